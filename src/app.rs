@@ -1,22 +1,52 @@
-use axum::Router;
+use axum::{middleware, Router};
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 
-use crate::modules::{health, users};
+use crate::modules::{
+    auth, health, impersonation, invitations, lockout, mfa, oauth, organizations, permissions,
+    saml, scim, users, webauthn,
+};
 use crate::state::AppState;
 
 pub fn rust_saas(state: AppState) -> Router {
     Router::new()
-        .nest("/api", api_routes())
+        .nest("/api", api_routes(state.clone()))
+        .nest("/scim/v2", scim::routes::scim_routes())
+        .nest("/oauth", oauth::routes::oauth_routes())
+        .merge(oauth::routes::metadata_routes())
         .merge(health::routes::health_routes())
         .layer(
             ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    impersonation::middleware::track_impersonation,
+                ))
                 .into_inner(),
         )
         .with_state(state)
 }
 
-fn api_routes() -> Router<AppState> {
-    Router::new().nest("/users", users::routes::user_routes())
+fn api_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .nest("/auth", auth::routes::auth_routes())
+        .nest("/users", users::routes::user_routes(state.clone()))
+        .nest("/mfa", mfa::routes::mfa_routes(state.clone()))
+        .nest(
+            "/webauthn",
+            webauthn::routes::webauthn_routes(state.clone()),
+        )
+        .nest(
+            "/orgs",
+            organizations::routes::organization_routes(state.clone()),
+        )
+        .nest("/invitations", invitations::routes::invitation_routes())
+        .nest("/saml", saml::routes::saml_routes())
+        .nest("/permissions", permissions::routes::permission_routes())
+        .nest("/oauth", oauth::routes::authorization_routes(state.clone()))
+        .nest(
+            "/admin/impersonations",
+            impersonation::routes::impersonation_routes(state.clone()),
+        )
+        .nest("/admin/users", lockout::routes::admin_unlock_routes(state))
 }
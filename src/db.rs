@@ -1,9 +1,19 @@
-use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use sea_orm::{
+    ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DatabaseTransaction, DbBackend,
+    Statement, TransactionTrait,
+};
 use sea_orm_migration::MigratorTrait;
 use tracing::info;
+use uuid::Uuid;
 
 use crate::migration::Migrator;
 
+/// Postgres setting read by the row-level security policies
+pub const CURRENT_ORG_SETTING: &str = "app.current_org";
+
+/// Role the row-level security policies apply to
+pub const TENANT_ROLE: &str = "app_tenant";
+
 pub async fn connect_database(database_url: &str) -> Result<DatabaseConnection, sea_orm::DbErr> {
     let mut opt = ConnectOptions::new(database_url);
     opt.max_connections(100)
@@ -18,3 +28,31 @@ pub async fn connect_database(database_url: &str) -> Result<DatabaseConnection,
 
     Ok(db)
 }
+
+/// Begin a transaction scoped to one organization
+///
+/// `app.current_org` is set for the lifetime of the transaction. With
+/// `enforce_rls` the transaction also switches to the `app_tenant` role, so
+/// queries on tenant-owned tables only ever see rows of that organization,
+/// even when a `WHERE org_id = ?` filter is forgotten.
+pub async fn begin_tenant_transaction(
+    db: &DatabaseConnection,
+    org_id: Uuid,
+    enforce_rls: bool,
+) -> Result<DatabaseTransaction, sea_orm::DbErr> {
+    let txn = db.begin().await?;
+
+    if enforce_rls {
+        txn.execute_unprepared(&format!("SET LOCAL ROLE {}", TENANT_ROLE))
+            .await?;
+    }
+
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT set_config($1, $2, true)",
+        [CURRENT_ORG_SETTING.into(), org_id.to_string().into()],
+    ))
+    .await?;
+
+    Ok(txn)
+}
@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -13,7 +13,7 @@ use tracing::error;
 ///
 /// # Usage
 ///
-/// ```rust
+/// ```rust,ignore
 /// // Return error directly (AppError implements IntoResponse)
 /// pub async fn handler() -> AppError {
 ///     AppError::NotFound("User not found".to_string())
@@ -46,6 +46,10 @@ pub enum AppError {
     #[error("Forbidden: {0}")]
     Forbidden(String),
 
+    /// 403 Forbidden for lacking a permission, which is named in `details`
+    #[error("Forbidden: missing permission {permission}")]
+    MissingPermission { permission: &'static str },
+
     /// 404 Not Found
     #[error("Not found: {0}")]
     NotFound(String),
@@ -58,6 +62,14 @@ pub enum AppError {
     #[error("Validation error: {0}")]
     ValidationError(String),
 
+    /// 429 Too Many Requests, telling the client when to try again in a
+    /// `Retry-After` header
+    #[error("Too many requests: {message}")]
+    TooManyRequests {
+        message: String,
+        retry_after_secs: u64,
+    },
+
     /// 500 Internal Server Error
     /// Can wrap anyhow::Error to preserve error chains
     #[error("Internal server error: {0}")]
@@ -92,9 +104,11 @@ impl AppError {
             AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
             AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
             AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::MissingPermission { .. } => StatusCode::FORBIDDEN,
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
             AppError::Conflict(_) => StatusCode::CONFLICT,
             AppError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -108,9 +122,11 @@ impl AppError {
             AppError::BadRequest(_) => "BAD_REQUEST",
             AppError::Unauthorized(_) => "UNAUTHORIZED",
             AppError::Forbidden(_) => "FORBIDDEN",
+            AppError::MissingPermission { .. } => "FORBIDDEN",
             AppError::NotFound(_) => "NOT_FOUND",
             AppError::Conflict(_) => "CONFLICT",
             AppError::ValidationError(_) => "VALIDATION_ERROR",
+            AppError::TooManyRequests { .. } => "TOO_MANY_REQUESTS",
             AppError::Internal(_) => "INTERNAL_ERROR",
             AppError::Database(_) => "DATABASE_ERROR",
             AppError::Config(_) => "CONFIG_ERROR",
@@ -118,6 +134,19 @@ impl AppError {
         }
     }
 
+    /// Machine-readable context for the client, if the error has any
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            AppError::MissingPermission { permission } => {
+                Some(serde_json::json!({ "missing_permission": permission }))
+            }
+            AppError::TooManyRequests {
+                retry_after_secs, ..
+            } => Some(serde_json::json!({ "retry_after": retry_after_secs })),
+            _ => None,
+        }
+    }
+
     /// Log the error appropriately
     /// Errors are always logged, regardless of environment
     pub fn log_error(&self) {
@@ -142,13 +171,25 @@ impl IntoResponse for AppError {
         self.log_error();
 
         let status = self.status_code();
+        let retry_after_secs = match &self {
+            AppError::TooManyRequests {
+                retry_after_secs, ..
+            } => Some(*retry_after_secs),
+            _ => None,
+        };
         let error_response = ErrorResponse {
             error: self.error_code().to_string(),
             message: self.to_string(),
-            details: None,
+            details: self.details(),
         };
 
-        (status, Json(error_response)).into_response()
+        let mut response = (status, Json(error_response)).into_response();
+        if let Some(secs) = retry_after_secs {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
     }
 }
 
@@ -10,7 +10,7 @@
 //! # async {
 //! let config = AppConfig::from_env();
 //! let db = connect_database(&config.database_url).await.unwrap();
-//! let state = AppState::new(db);
+//! let state = AppState::new(db, config);
 //! let app = create_app(state);
 //! # };
 //! ```
@@ -25,9 +25,12 @@ pub mod app;
 pub mod config;
 pub mod db;
 pub mod error;
+pub mod mailer;
 pub mod migration;
 pub mod modules;
 pub mod state;
+#[cfg(test)]
+mod test_support;
 
 pub use app::rust_saas;
 pub use config::AppConfig;
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+
+use super::{build_message, parse_sender, Email, Mailer};
+
+/// Writes each message as an `.eml` file, for inspecting mail in development
+pub struct FileMailer {
+    from: Mailbox,
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(from: &str, dir: &str) -> AppResult<Self> {
+        Ok(Self {
+            from: parse_sender(from)?,
+            dir: PathBuf::from(dir),
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> AppResult<()> {
+        let message = build_message(&self.from, &email)?;
+        let path = self.dir.join(format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+            Uuid::new_v4()
+        ));
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| AppError::internal(format!("Failed to create mail directory: {}", e)))?;
+        tokio::fs::write(&path, message.formatted())
+            .await
+            .map_err(|e| AppError::internal(format!("Failed to write email: {}", e)))?;
+
+        tracing::info!("Email to {} written to {}", email.to, path.display());
+        Ok(())
+    }
+}
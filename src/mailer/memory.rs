@@ -0,0 +1,54 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::error::AppResult;
+
+use super::{Email, Mailer};
+
+/// Keeps sent messages in memory so tests can assert on them
+///
+/// ```rust,ignore
+/// let mailer = Arc::new(InMemoryMailer::new());
+/// let state = AppState::new(db, config).with_mailer(mailer.clone());
+/// // ... exercise the app ...
+/// let email = mailer.last_to("user@example.com").unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct InMemoryMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl InMemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All messages sent so far, oldest first
+    /// Part of public API for library users
+    #[allow(dead_code)]
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().expect("mailer lock poisoned").clone()
+    }
+
+    /// The most recent message sent to `to`
+    /// Part of public API for library users
+    #[allow(dead_code)]
+    pub fn last_to(&self, to: &str) -> Option<Email> {
+        self.sent
+            .lock()
+            .expect("mailer lock poisoned")
+            .iter()
+            .rev()
+            .find(|email| email.to.eq_ignore_ascii_case(to))
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl Mailer for InMemoryMailer {
+    async fn send(&self, email: Email) -> AppResult<()> {
+        self.sent.lock().expect("mailer lock poisoned").push(email);
+        Ok(())
+    }
+}
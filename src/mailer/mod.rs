@@ -0,0 +1,128 @@
+//! Outgoing email
+//!
+//! Code that sends mail depends only on the [`Mailer`] trait; the transport is
+//! picked from `MAIL_TRANSPORT` at startup:
+//!
+//! - `log` (default) writes messages to the log
+//! - `smtp` delivers through an SMTP server, e.g. a local catcher like Mailpit
+//! - `file` drops `.eml` files into `MAIL_DIR` for development
+//! - `memory` keeps messages in memory so tests can inspect them
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox, MultiPart, SinglePart},
+    Message,
+};
+use tracing::info;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+
+mod file;
+mod memory;
+mod smtp;
+pub mod templates;
+
+pub use file::FileMailer;
+pub use memory::InMemoryMailer;
+pub use smtp::SmtpMailer;
+
+/// An outgoing email message
+#[derive(Clone, Debug)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: Option<String>,
+}
+
+/// Transport used to deliver outgoing email
+///
+/// Stored in `AppState` as `Arc<dyn Mailer>` so deployments and tests can
+/// swap the implementation without touching the code that sends mail.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> AppResult<()>;
+}
+
+/// Mailer that writes messages to the log instead of delivering them
+#[derive(Clone, Debug, Default)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> AppResult<()> {
+        info!(
+            "Email to {} - {}\n{}",
+            email.to, email.subject, email.text_body
+        );
+        Ok(())
+    }
+}
+
+/// Build the mailer selected by `MAIL_TRANSPORT`
+pub fn from_config(config: &AppConfig) -> AppResult<Arc<dyn Mailer>> {
+    let mailer: Arc<dyn Mailer> = match config.mail_transport.to_lowercase().as_str() {
+        "log" => Arc::new(LogMailer),
+        "smtp" => Arc::new(SmtpMailer::from_config(config)?),
+        "file" => Arc::new(FileMailer::new(&config.mail_from, &config.mail_dir)?),
+        "memory" => Arc::new(InMemoryMailer::new()),
+        other => {
+            return Err(config_error(format!(
+                "Unknown mail transport '{}', expected log, smtp, file or memory",
+                other
+            )))
+        }
+    };
+
+    Ok(mailer)
+}
+
+/// Parse an address such as `Acme <no-reply@acme.io>`
+fn parse_mailbox(address: &str) -> AppResult<Mailbox> {
+    address.parse().map_err(|e| {
+        AppError::ValidationError(format!("Invalid email address '{}': {}", address, e))
+    })
+}
+
+/// Parse the configured sender address
+fn parse_sender(address: &str) -> AppResult<Mailbox> {
+    address
+        .parse()
+        .map_err(|e| config_error(format!("Invalid MAIL_FROM address '{}': {}", address, e)))
+}
+
+fn config_error(message: String) -> AppError {
+    AppError::Config(config::ConfigError::Message(message))
+}
+
+/// Build a MIME message, as multipart/alternative when there is an HTML body
+fn build_message(from: &Mailbox, email: &Email) -> AppResult<Message> {
+    let builder = Message::builder()
+        .from(from.clone())
+        .to(parse_mailbox(&email.to)?)
+        .subject(email.subject.clone());
+
+    let message = match &email.html_body {
+        Some(html) => builder.multipart(
+            MultiPart::alternative()
+                .singlepart(
+                    SinglePart::builder()
+                        .header(ContentType::TEXT_PLAIN)
+                        .body(email.text_body.clone()),
+                )
+                .singlepart(
+                    SinglePart::builder()
+                        .header(ContentType::TEXT_HTML)
+                        .body(html.clone()),
+                ),
+        ),
+        None => builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.text_body.clone()),
+    };
+
+    message.map_err(|e| AppError::internal(format!("Failed to build email: {}", e)))
+}
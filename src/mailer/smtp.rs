@@ -0,0 +1,182 @@
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Tokio1Executor,
+};
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+
+use super::{build_message, config_error, parse_sender, Email, Mailer};
+
+/// Delivers email through an SMTP server
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn from_config(config: &AppConfig) -> AppResult<Self> {
+        let host = config.smtp_host.as_str();
+        let builder = match config.smtp_tls.to_lowercase().as_str() {
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .map_err(|e| config_error(format!("Invalid SMTP relay: {}", e)))?,
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .map_err(|e| config_error(format!("Invalid SMTP relay: {}", e)))?,
+            other => {
+                return Err(config_error(format!(
+                    "Unknown SMTP TLS mode '{}', expected none, starttls or tls",
+                    other
+                )))
+            }
+        };
+
+        let builder = builder.port(config.smtp_port);
+        let builder = match (&config.smtp_username, &config.smtp_password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            _ => builder,
+        };
+
+        Ok(Self {
+            from: parse_sender(&config.mail_from)?,
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> AppResult<()> {
+        let message = build_message(&self.from, &email)?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| AppError::internal(format!("Failed to send email: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    use crate::mailer::templates::{app_link, ActionEmail};
+    use crate::test_support::test_config;
+
+    /// What an SMTP client handed over in one session
+    struct Delivery {
+        recipients: Vec<String>,
+        data: String,
+    }
+
+    /// Accept a single SMTP session on a local port, answering every command
+    /// with success and recording the envelope recipients and message data
+    async fn smtp_sink() -> (u16, JoinHandle<Delivery>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut delivery = Delivery {
+                recipients: Vec::new(),
+                data: String::new(),
+            };
+
+            writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("RCPT TO:") {
+                    delivery.recipients.push(line[8..].trim().to_string());
+                    b"250 OK\r\n"
+                } else if command == "DATA" {
+                    writer.write_all(b"354 End data with .\r\n").await.unwrap();
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        if line == "." {
+                            break;
+                        }
+                        delivery.data.push_str(&line);
+                        delivery.data.push('\n');
+                    }
+                    b"250 Queued\r\n"
+                } else if command == "QUIT" {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+
+            delivery
+        });
+
+        (port, handle)
+    }
+
+    /// Undo the quoted-printable encoding of long body lines
+    fn decode_quoted_printable(encoded: &str) -> String {
+        let joined = encoded.replace("=\n", "");
+        let mut decoded = Vec::with_capacity(joined.len());
+        let bytes = joined.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            match (bytes[i], bytes.get(i + 1..i + 3)) {
+                (b'=', Some(hex)) if hex.iter().all(u8::is_ascii_hexdigit) => {
+                    let hex = std::str::from_utf8(hex).unwrap();
+                    decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                    i += 3;
+                }
+                (byte, _) => {
+                    decoded.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8(decoded).unwrap()
+    }
+
+    #[tokio::test]
+    async fn delivers_rendered_email_over_smtp() {
+        let (port, sink) = smtp_sink().await;
+        let mut config = test_config();
+        config.mail_transport = "smtp".to_string();
+        config.mail_from = "Acme <no-reply@acme.test>".to_string();
+        config.smtp_host = "127.0.0.1".to_string();
+        config.smtp_port = port;
+        config.smtp_tls = "none".to_string();
+        config.smtp_username = None;
+        config.smtp_password = None;
+        let mailer = crate::mailer::from_config(&config).unwrap();
+
+        let link = app_link(&config.app_base_url, "/verify-email", "raw-token-123");
+        let email = ActionEmail {
+            subject: "Confirm your email".to_string(),
+            heading: "Confirm your email",
+            intro: "Please confirm your address.".to_string(),
+            action_label: "Confirm email",
+            action_url: link.clone(),
+            outro: "If you did not sign up, ignore this email.".to_string(),
+        }
+        .render("alice@example.com");
+        mailer.send(email).await.unwrap();
+
+        let delivery = sink.await.unwrap();
+        assert_eq!(delivery.recipients, vec!["<alice@example.com>"]);
+        assert!(delivery.data.contains("From: Acme <no-reply@acme.test>\n"));
+        assert!(delivery.data.contains("To: alice@example.com\n"));
+        assert!(delivery.data.contains("Subject: Confirm your email\n"));
+
+        let body = decode_quoted_printable(&delivery.data);
+        assert!(body.contains(&format!("Confirm email: {}", link)));
+        assert!(body.contains(&format!("<a href=\"{}\"", link)));
+    }
+}
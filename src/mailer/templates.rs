@@ -0,0 +1,83 @@
+//! Templates for transactional email
+//!
+//! Every message is rendered twice, as plain text and as HTML, from the same
+//! content so the two parts never drift apart.
+
+use super::Email;
+
+/// A transactional message built around a single call to action, such as
+/// "Accept invitation" or "Confirm email"
+pub struct ActionEmail<'a> {
+    pub subject: String,
+    pub heading: &'a str,
+    pub intro: String,
+    pub action_label: &'a str,
+    pub action_url: String,
+    pub outro: String,
+}
+
+impl ActionEmail<'_> {
+    pub fn render(&self, to: &str) -> Email {
+        let text_body = format!(
+            "{heading}\n\n{intro}\n\n{label}: {url}\n\n{outro}\n",
+            heading = self.heading,
+            intro = self.intro,
+            label = self.action_label,
+            url = self.action_url,
+            outro = self.outro,
+        );
+
+        let html_body = format!(
+            r#"<!DOCTYPE html>
+<html>
+  <body style="margin:0;padding:24px;background:#f4f4f5;font-family:-apple-system,Segoe UI,Helvetica,Arial,sans-serif;color:#18181b;">
+    <table role="presentation" width="100%" style="max-width:560px;margin:0 auto;background:#ffffff;border-radius:8px;padding:32px;">
+      <tr><td>
+        <h1 style="font-size:20px;margin:0 0 16px;">{heading}</h1>
+        <p style="font-size:15px;line-height:1.5;margin:0 0 24px;">{intro}</p>
+        <p style="margin:0 0 24px;">
+          <a href="{url}" style="display:inline-block;background:#2563eb;color:#ffffff;text-decoration:none;padding:12px 20px;border-radius:6px;font-weight:600;">{label}</a>
+        </p>
+        <p style="font-size:13px;line-height:1.5;color:#71717a;margin:0 0 8px;">If the button does not work, copy this link into your browser:<br><a href="{url}" style="color:#2563eb;word-break:break-all;">{url}</a></p>
+        <p style="font-size:13px;line-height:1.5;color:#71717a;margin:0;">{outro}</p>
+      </td></tr>
+    </table>
+  </body>
+</html>
+"#,
+            heading = escape_html(self.heading),
+            intro = escape_html(&self.intro),
+            label = escape_html(self.action_label),
+            url = escape_html(&self.action_url),
+            outro = escape_html(&self.outro),
+        );
+
+        Email {
+            to: to.to_string(),
+            subject: self.subject.clone(),
+            text_body,
+            html_body: Some(html_body),
+        }
+    }
+}
+
+/// Escape text for inclusion in HTML element content or attribute values
+pub fn escape_html(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Build an absolute link into the web app
+pub fn app_link(base_url: &str, path: &str, token: &str) -> String {
+    format!("{}{}?token={}", base_url.trim_end_matches('/'), path, token)
+}
@@ -1,3 +1,5 @@
+use std::net::SocketAddr;
+
 use anyhow::Result;
 use tokio::signal;
 use tracing::info;
@@ -7,9 +9,12 @@ mod app;
 mod config;
 mod db;
 mod error;
+mod mailer;
 mod migration;
 mod modules;
 mod state;
+#[cfg(test)]
+mod test_support;
 
 use app::rust_saas;
 use config::AppConfig;
@@ -36,8 +41,8 @@ async fn main() -> Result<()> {
         .expect("Failed to connect to database");
     info!("Database connected successfully");
 
-    let state = AppState::new(db);
     let addr = config.server_addr();
+    let state = AppState::new(db, config);
     let app = rust_saas(state);
 
     info!("Server starting on http://{}", addr);
@@ -47,9 +52,13 @@ async fn main() -> Result<()> {
 
     info!("Press Ctrl+C to shutdown gracefully");
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown)
-        .await?;
+    // Peer addresses are recorded on sessions
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown)
+    .await?;
 
     info!("Server shutdown complete");
     Ok(())
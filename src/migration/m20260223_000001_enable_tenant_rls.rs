@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+
+use super::tenant::{disable_tenant_rls, enable_tenant_rls};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Role that tenant transactions run as; it cannot log in and only
+        // exists so RLS policies apply even when the app connects as the
+        // table owner or a superuser
+        db.execute_unprepared(
+            "DO $$
+            BEGIN
+                IF NOT EXISTS (SELECT FROM pg_roles WHERE rolname = 'app_tenant') THEN
+                    CREATE ROLE app_tenant NOLOGIN;
+                END IF;
+            END
+            $$",
+        )
+        .await?;
+        db.execute_unprepared("GRANT app_tenant TO CURRENT_USER")
+            .await?;
+        db.execute_unprepared("GRANT USAGE ON SCHEMA public TO app_tenant")
+            .await?;
+        db.execute_unprepared(
+            "GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public TO app_tenant",
+        )
+        .await?;
+        db.execute_unprepared(
+            "ALTER DEFAULT PRIVILEGES IN SCHEMA public \
+             GRANT SELECT, INSERT, UPDATE, DELETE ON TABLES TO app_tenant",
+        )
+        .await?;
+
+        enable_tenant_rls(manager, "organizations", "id").await?;
+        enable_tenant_rls(manager, "organization_members", "organization_id").await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        disable_tenant_rls(manager, "organization_members").await?;
+        disable_tenant_rls(manager, "organizations").await?;
+
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "ALTER DEFAULT PRIVILEGES IN SCHEMA public \
+             REVOKE SELECT, INSERT, UPDATE, DELETE ON TABLES FROM app_tenant",
+        )
+        .await?;
+        db.execute_unprepared(
+            "REVOKE SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public FROM app_tenant",
+        )
+        .await?;
+        db.execute_unprepared("REVOKE USAGE ON SCHEMA public FROM app_tenant")
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use sea_orm_migration::prelude::*;
+
+use super::tenant::{disable_tenant_rls, enable_tenant_rls};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Invitations::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Invitations::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Invitations::OrganizationId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Invitations::Email)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Invitations::Role).string_len(16).not_null())
+                    .col(
+                        ColumnDef::new(Invitations::TokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Invitations::InvitedBy).uuid())
+                    .col(
+                        ColumnDef::new(Invitations::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Invitations::AcceptedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(Invitations::DeclinedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(Invitations::RevokedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(Invitations::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitations_organization_id")
+                            .from(Invitations::Table, Invitations::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitations_invited_by")
+                            .from(Invitations::Table, Invitations::InvitedBy)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_invitations_organization_id_email")
+                    .table(Invitations::Table)
+                    .col(Invitations::OrganizationId)
+                    .col(Invitations::Email)
+                    .to_owned(),
+            )
+            .await?;
+
+        enable_tenant_rls(manager, "invitations", "organization_id").await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        disable_tenant_rls(manager, "invitations").await?;
+
+        manager
+            .drop_table(Table::drop().table(Invitations::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Invitations {
+    Table,
+    Id,
+    OrganizationId,
+    Email,
+    Role,
+    TokenHash,
+    InvitedBy,
+    ExpiresAt,
+    AcceptedAt,
+    DeclinedAt,
+    RevokedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
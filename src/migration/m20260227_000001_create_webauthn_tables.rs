@@ -0,0 +1,194 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Passkey-only accounts have no password
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .modify_column(ColumnDef::new(Users::PasswordHash).string_len(255).null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebauthnCredentials::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WebauthnCredentials::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredentials::UserId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredentials::CredentialId)
+                            .string_len(1024)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredentials::PublicKey)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredentials::Algorithm)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredentials::SignCount)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredentials::Name)
+                            .string_len(100)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnCredentials::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(WebauthnCredentials::LastUsedAt).timestamp_with_time_zone())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_webauthn_credentials_user_id")
+                            .from(WebauthnCredentials::Table, WebauthnCredentials::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webauthn_credentials_user_id")
+                    .table(WebauthnCredentials::Table)
+                    .col(WebauthnCredentials::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebauthnChallenges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WebauthnChallenges::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnChallenges::ChallengeHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnChallenges::Ceremony)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(WebauthnChallenges::UserId).uuid())
+                    .col(ColumnDef::new(WebauthnChallenges::Email).string_len(255))
+                    .col(ColumnDef::new(WebauthnChallenges::Name).string_len(255))
+                    .col(
+                        ColumnDef::new(WebauthnChallenges::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebauthnChallenges::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_webauthn_challenges_user_id")
+                            .from(WebauthnChallenges::Table, WebauthnChallenges::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebauthnChallenges::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(WebauthnCredentials::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .modify_column(
+                        ColumnDef::new(Users::PasswordHash)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum WebauthnCredentials {
+    Table,
+    Id,
+    UserId,
+    CredentialId,
+    PublicKey,
+    Algorithm,
+    SignCount,
+    Name,
+    CreatedAt,
+    LastUsedAt,
+}
+
+#[derive(DeriveIden)]
+enum WebauthnChallenges {
+    Table,
+    Id,
+    ChallengeHash,
+    Ceremony,
+    UserId,
+    Email,
+    Name,
+    ExpiresAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+    PasswordHash,
+}
@@ -0,0 +1,169 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Identities::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Identities::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Identities::UserId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(Identities::Provider)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Identities::Subject)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Identities::Email).string_len(255))
+                    .col(
+                        ColumnDef::new(Identities::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(Identities::LastUsedAt).timestamp_with_time_zone())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_identities_user_id")
+                            .from(Identities::Table, Identities::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // A provider's subject identifies one external account
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_identities_provider_subject")
+                    .table(Identities::Table)
+                    .col(Identities::Provider)
+                    .col(Identities::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_identities_user_id")
+                    .table(Identities::Table)
+                    .col(Identities::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OidcAuthRequests::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OidcAuthRequests::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OidcAuthRequests::StateHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OidcAuthRequests::Provider)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OidcAuthRequests::UserId).uuid())
+                    .col(
+                        ColumnDef::new(OidcAuthRequests::Nonce)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OidcAuthRequests::CodeVerifier)
+                            .string_len(128)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OidcAuthRequests::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OidcAuthRequests::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oidc_auth_requests_user_id")
+                            .from(OidcAuthRequests::Table, OidcAuthRequests::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OidcAuthRequests::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Identities::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Identities {
+    Table,
+    Id,
+    UserId,
+    Provider,
+    Subject,
+    Email,
+    CreatedAt,
+    LastUsedAt,
+}
+
+#[derive(DeriveIden)]
+enum OidcAuthRequests {
+    Table,
+    Id,
+    StateHash,
+    Provider,
+    UserId,
+    Nonce,
+    CodeVerifier,
+    ExpiresAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,208 @@
+use sea_orm_migration::prelude::*;
+
+use super::tenant::{disable_tenant_rls, enable_tenant_rls};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SamlConnections::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SamlConnections::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlConnections::OrganizationId)
+                            .uuid()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlConnections::IdpEntityId)
+                            .string_len(1024)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlConnections::IdpSsoUrl)
+                            .string_len(2048)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlConnections::IdpCertificates)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlConnections::DefaultRole)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlConnections::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(SamlConnections::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_saml_connections_organization_id")
+                            .from(SamlConnections::Table, SamlConnections::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SamlAuthRequests::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SamlAuthRequests::Id)
+                            .string_len(64)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlAuthRequests::OrganizationId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlAuthRequests::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlAuthRequests::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_saml_auth_requests_organization_id")
+                            .from(SamlAuthRequests::Table, SamlAuthRequests::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Assertion ids are chosen by the identity provider, so they are
+        // only unique per organization
+        manager
+            .create_table(
+                Table::create()
+                    .table(SamlUsedAssertions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SamlUsedAssertions::OrganizationId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlUsedAssertions::AssertionId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SamlUsedAssertions::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(SamlUsedAssertions::OrganizationId)
+                            .col(SamlUsedAssertions::AssertionId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_saml_used_assertions_organization_id")
+                            .from(
+                                SamlUsedAssertions::Table,
+                                SamlUsedAssertions::OrganizationId,
+                            )
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        enable_tenant_rls(manager, "saml_connections", "organization_id").await?;
+        enable_tenant_rls(manager, "saml_auth_requests", "organization_id").await?;
+        enable_tenant_rls(manager, "saml_used_assertions", "organization_id").await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        disable_tenant_rls(manager, "saml_used_assertions").await?;
+        disable_tenant_rls(manager, "saml_auth_requests").await?;
+        disable_tenant_rls(manager, "saml_connections").await?;
+
+        manager
+            .drop_table(Table::drop().table(SamlUsedAssertions::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(SamlAuthRequests::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(SamlConnections::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SamlConnections {
+    Table,
+    Id,
+    OrganizationId,
+    IdpEntityId,
+    IdpSsoUrl,
+    IdpCertificates,
+    DefaultRole,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum SamlAuthRequests {
+    Table,
+    Id,
+    OrganizationId,
+    ExpiresAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum SamlUsedAssertions {
+    Table,
+    OrganizationId,
+    AssertionId,
+    ExpiresAt,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+}
@@ -0,0 +1,274 @@
+use sea_orm_migration::prelude::*;
+
+use super::tenant::{disable_tenant_rls, enable_tenant_rls};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ScimTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ScimTokens::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ScimTokens::OrganizationId).uuid().not_null())
+                    .col(ColumnDef::new(ScimTokens::Name).string_len(100).not_null())
+                    .col(
+                        ColumnDef::new(ScimTokens::TokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ScimTokens::CreatedBy).uuid())
+                    .col(ColumnDef::new(ScimTokens::LastUsedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(ScimTokens::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scim_tokens_organization_id")
+                            .from(ScimTokens::Table, ScimTokens::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scim_tokens_created_by")
+                            .from(ScimTokens::Table, ScimTokens::CreatedBy)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_scim_tokens_organization_id")
+                    .table(ScimTokens::Table)
+                    .col(ScimTokens::OrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Users provisioned by an organization's directory; the row outlives
+        // the membership so a deprovisioned user can be provisioned again
+        manager
+            .create_table(
+                Table::create()
+                    .table(ScimUsers::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ScimUsers::OrganizationId).uuid().not_null())
+                    .col(ColumnDef::new(ScimUsers::UserId).uuid().not_null())
+                    .col(ColumnDef::new(ScimUsers::ExternalId).string_len(255))
+                    .col(
+                        ColumnDef::new(ScimUsers::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(ScimUsers::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(ScimUsers::OrganizationId)
+                            .col(ScimUsers::UserId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scim_users_organization_id")
+                            .from(ScimUsers::Table, ScimUsers::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scim_users_user_id")
+                            .from(ScimUsers::Table, ScimUsers::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ScimGroups::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ScimGroups::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ScimGroups::OrganizationId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(ScimGroups::DisplayName)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ScimGroups::ExternalId).string_len(255))
+                    .col(ColumnDef::new(ScimGroups::Role).string_len(16))
+                    .col(
+                        ColumnDef::new(ScimGroups::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(ScimGroups::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scim_groups_organization_id")
+                            .from(ScimGroups::Table, ScimGroups::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_scim_groups_organization_id_display_name")
+                    .table(ScimGroups::Table)
+                    .col(ScimGroups::OrganizationId)
+                    .col(ScimGroups::DisplayName)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ScimGroupMembers::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ScimGroupMembers::GroupId).uuid().not_null())
+                    .col(ColumnDef::new(ScimGroupMembers::UserId).uuid().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(ScimGroupMembers::GroupId)
+                            .col(ScimGroupMembers::UserId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scim_group_members_group_id")
+                            .from(ScimGroupMembers::Table, ScimGroupMembers::GroupId)
+                            .to(ScimGroups::Table, ScimGroups::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scim_group_members_user_id")
+                            .from(ScimGroupMembers::Table, ScimGroupMembers::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        enable_tenant_rls(manager, "scim_tokens", "organization_id").await?;
+        enable_tenant_rls(manager, "scim_users", "organization_id").await?;
+        enable_tenant_rls(manager, "scim_groups", "organization_id").await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        disable_tenant_rls(manager, "scim_groups").await?;
+        disable_tenant_rls(manager, "scim_users").await?;
+        disable_tenant_rls(manager, "scim_tokens").await?;
+
+        manager
+            .drop_table(Table::drop().table(ScimGroupMembers::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ScimGroups::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ScimUsers::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ScimTokens::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ScimTokens {
+    Table,
+    Id,
+    OrganizationId,
+    Name,
+    TokenHash,
+    CreatedBy,
+    LastUsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ScimUsers {
+    Table,
+    OrganizationId,
+    UserId,
+    ExternalId,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ScimGroups {
+    Table,
+    Id,
+    OrganizationId,
+    DisplayName,
+    ExternalId,
+    Role,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ScimGroupMembers {
+    Table,
+    GroupId,
+    UserId,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,135 @@
+use sea_orm_migration::prelude::*;
+
+use super::tenant::{disable_tenant_rls, enable_tenant_rls};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // A key belongs to either a user or an organization, never both
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiKeys::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ApiKeys::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(ApiKeys::Name).string_len(100).not_null())
+                    .col(
+                        ColumnDef::new(ApiKeys::Prefix)
+                            .string_len(32)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ApiKeys::KeyHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ApiKeys::UserId).uuid())
+                    .col(ColumnDef::new(ApiKeys::OrganizationId).uuid())
+                    .col(ColumnDef::new(ApiKeys::CreatedBy).uuid())
+                    .col(ColumnDef::new(ApiKeys::Scopes).string_len(255).not_null())
+                    .col(ColumnDef::new(ApiKeys::ExpiresAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(ApiKeys::LastUsedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(ApiKeys::RevokedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(ApiKeys::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .check(
+                        Expr::col(ApiKeys::UserId)
+                            .is_null()
+                            .ne(Expr::col(ApiKeys::OrganizationId).is_null()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_api_keys_user_id")
+                            .from(ApiKeys::Table, ApiKeys::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_api_keys_organization_id")
+                            .from(ApiKeys::Table, ApiKeys::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_api_keys_created_by")
+                            .from(ApiKeys::Table, ApiKeys::CreatedBy)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_api_keys_user_id")
+                    .table(ApiKeys::Table)
+                    .col(ApiKeys::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_api_keys_organization_id")
+                    .table(ApiKeys::Table)
+                    .col(ApiKeys::OrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Personal keys have no organization, so tenant transactions never
+        // see them; they are only managed through the regular connection
+        enable_tenant_rls(manager, "api_keys", "organization_id").await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        disable_tenant_rls(manager, "api_keys").await?;
+
+        manager
+            .drop_table(Table::drop().table(ApiKeys::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ApiKeys {
+    Table,
+    Id,
+    Name,
+    Prefix,
+    KeyHash,
+    UserId,
+    OrganizationId,
+    CreatedBy,
+    Scopes,
+    ExpiresAt,
+    LastUsedAt,
+    RevokedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,84 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // No foreign keys: the trail has to outlive the users and
+        // organizations it mentions
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditEvents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditEvents::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AuditEvents::Action)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditEvents::ActorId).uuid())
+                    .col(ColumnDef::new(AuditEvents::OrganizationId).uuid())
+                    .col(ColumnDef::new(AuditEvents::TargetType).string_len(32))
+                    .col(ColumnDef::new(AuditEvents::TargetId).uuid())
+                    .col(ColumnDef::new(AuditEvents::IpAddress).string_len(45))
+                    .col(ColumnDef::new(AuditEvents::Details).json_binary())
+                    .col(
+                        ColumnDef::new(AuditEvents::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_events_organization_id_created_at")
+                    .table(AuditEvents::Table)
+                    .col(AuditEvents::OrganizationId)
+                    .col(AuditEvents::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_events_target_id")
+                    .table(AuditEvents::Table)
+                    .col(AuditEvents::TargetId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditEvents::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuditEvents {
+    Table,
+    Id,
+    Action,
+    ActorId,
+    OrganizationId,
+    TargetType,
+    TargetId,
+    IpAddress,
+    Details,
+    CreatedAt,
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Failed sign-ins per account (normalized email, whether or not it
+        // is registered) and per client address
+        manager
+            .create_table(
+                Table::create()
+                    .table(LoginThrottles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(LoginThrottles::Scope)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LoginThrottles::Key)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LoginThrottles::Failures)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LoginThrottles::LastFailureAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(LoginThrottles::LockedUntil).timestamp_with_time_zone())
+                    .primary_key(
+                        Index::create()
+                            .col(LoginThrottles::Scope)
+                            .col(LoginThrottles::Key),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LoginThrottles::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LoginThrottles {
+    Table,
+    Scope,
+    Key,
+    Failures,
+    LastFailureAt,
+    LockedUntil,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+use super::tenant::{disable_tenant_rls, enable_tenant_rls};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PasswordPolicies::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PasswordPolicies::OrganizationId)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(PasswordPolicies::MinLength).integer())
+                    .col(ColumnDef::new(PasswordPolicies::MinScore).small_integer())
+                    .col(
+                        ColumnDef::new(PasswordPolicies::BannedWords)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(PasswordPolicies::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_password_policies_organization_id")
+                            .from(PasswordPolicies::Table, PasswordPolicies::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        enable_tenant_rls(manager, "password_policies", "organization_id").await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        disable_tenant_rls(manager, "password_policies").await?;
+
+        manager
+            .drop_table(Table::drop().table(PasswordPolicies::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum PasswordPolicies {
+    Table,
+    OrganizationId,
+    MinLength,
+    MinScore,
+    BannedWords,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+}
@@ -0,0 +1,119 @@
+use sea_orm_migration::prelude::*;
+
+use super::tenant::{disable_tenant_rls, enable_tenant_rls};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OrgRoles::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(OrgRoles::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(OrgRoles::OrganizationId).uuid().not_null())
+                    .col(ColumnDef::new(OrgRoles::Name).string_len(64).not_null())
+                    .col(ColumnDef::new(OrgRoles::Description).string_len(255))
+                    .col(ColumnDef::new(OrgRoles::Permissions).text().not_null())
+                    .col(
+                        ColumnDef::new(OrgRoles::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(OrgRoles::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_org_roles_organization_id")
+                            .from(OrgRoles::Table, OrgRoles::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_org_roles_organization_id_name")
+                    .table(OrgRoles::Table)
+                    .col(OrgRoles::OrganizationId)
+                    .col(OrgRoles::Name)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // A member holds a built-in role and at most one custom role on top
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(OrganizationMembers::Table)
+                    .add_column(ColumnDef::new(OrganizationMembers::CustomRoleId).uuid())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_organization_members_custom_role_id")
+                            .from_tbl(OrganizationMembers::Table)
+                            .from_col(OrganizationMembers::CustomRoleId)
+                            .to_tbl(OrgRoles::Table)
+                            .to_col(OrgRoles::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        enable_tenant_rls(manager, "org_roles", "organization_id").await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        disable_tenant_rls(manager, "org_roles").await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(OrganizationMembers::Table)
+                    .drop_foreign_key(Alias::new("fk_organization_members_custom_role_id"))
+                    .drop_column(OrganizationMembers::CustomRoleId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(OrgRoles::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum OrgRoles {
+    Table,
+    Id,
+    OrganizationId,
+    Name,
+    Description,
+    Permissions,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum OrganizationMembers {
+    Table,
+    CustomRoleId,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+}
@@ -0,0 +1,119 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(
+                        ColumnDef::new(Users::IsPlatformAdmin)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Impersonations::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Impersonations::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Impersonations::AdminId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(Impersonations::TargetUserId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Impersonations::Reason).string_len(255))
+                    .col(ColumnDef::new(Impersonations::IpAddress).string_len(45))
+                    .col(ColumnDef::new(Impersonations::UserAgent).string_len(512))
+                    .col(
+                        ColumnDef::new(Impersonations::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Impersonations::EndedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(Impersonations::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_impersonations_admin_id")
+                            .from(Impersonations::Table, Impersonations::AdminId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_impersonations_target_user_id")
+                            .from(Impersonations::Table, Impersonations::TargetUserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_impersonations_admin_id")
+                    .table(Impersonations::Table)
+                    .col(Impersonations::AdminId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Impersonations::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::IsPlatformAdmin)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Impersonations {
+    Table,
+    Id,
+    AdminId,
+    TargetUserId,
+    Reason,
+    IpAddress,
+    UserAgent,
+    ExpiresAt,
+    EndedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+    IsPlatformAdmin,
+}
@@ -0,0 +1,95 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::ServiceAccountOrganizationId).uuid())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_users_service_account_organization_id")
+                            .from_tbl(Users::Table)
+                            .from_col(Users::ServiceAccountOrganizationId)
+                            .to_tbl(Organizations::Table)
+                            .to_col(Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_users_service_account_organization_id")
+                    .table(Users::Table)
+                    .col(Users::ServiceAccountOrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuditEvents::Table)
+                    .add_column(ColumnDef::new(AuditEvents::ActorType).string_len(32))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AuditEvents::Table)
+                    .drop_column(AuditEvents::ActorType)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Service accounts cannot exist without the column that marks them
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(Users::Table)
+                    .and_where(Expr::col(Users::ServiceAccountOrganizationId).is_not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_foreign_key(Alias::new("fk_users_service_account_organization_id"))
+                    .drop_column(Users::ServiceAccountOrganizationId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    ServiceAccountOrganizationId,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum AuditEvents {
+    Table,
+    ActorType,
+}
@@ -0,0 +1,408 @@
+use sea_orm_migration::prelude::*;
+
+use super::tenant::{disable_tenant_rls, enable_tenant_rls};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthClients::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OauthClients::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthClients::OrganizationId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthClients::Name)
+                            .string_len(100)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OauthClients::SecretHash).string_len(64))
+                    .col(ColumnDef::new(OauthClients::RedirectUris).text().not_null())
+                    .col(
+                        ColumnDef::new(OauthClients::Scopes)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OauthClients::ServiceAccountId).uuid())
+                    .col(ColumnDef::new(OauthClients::CreatedBy).uuid())
+                    .col(
+                        ColumnDef::new(OauthClients::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(OauthClients::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_clients_organization_id")
+                            .from(OauthClients::Table, OauthClients::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_clients_service_account_id")
+                            .from(OauthClients::Table, OauthClients::ServiceAccountId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_clients_created_by")
+                            .from(OauthClients::Table, OauthClients::CreatedBy)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_oauth_clients_organization_id")
+                    .table(OauthClients::Table)
+                    .col(OauthClients::OrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthConsents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OauthConsents::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(OauthConsents::UserId).uuid().not_null())
+                    .col(ColumnDef::new(OauthConsents::ClientId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(OauthConsents::Scopes)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthConsents::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(OauthConsents::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_consents_user_id")
+                            .from(OauthConsents::Table, OauthConsents::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_consents_client_id")
+                            .from(OauthConsents::Table, OauthConsents::ClientId)
+                            .to(OauthClients::Table, OauthClients::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // A user has one consent per client, widened as more is granted
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_oauth_consents_user_id_client_id")
+                    .table(OauthConsents::Table)
+                    .col(OauthConsents::UserId)
+                    .col(OauthConsents::ClientId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthAuthorizationCodes::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::CodeHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::ClientId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::UserId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::RedirectUri)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::Scopes)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::CodeChallenge)
+                            .string_len(128)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OauthAuthorizationCodes::UsedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(OauthAuthorizationCodes::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_authorization_codes_client_id")
+                            .from(
+                                OauthAuthorizationCodes::Table,
+                                OauthAuthorizationCodes::ClientId,
+                            )
+                            .to(OauthClients::Table, OauthClients::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_authorization_codes_user_id")
+                            .from(
+                                OauthAuthorizationCodes::Table,
+                                OauthAuthorizationCodes::UserId,
+                            )
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OauthTokens::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(OauthTokens::ClientId).uuid().not_null())
+                    .col(ColumnDef::new(OauthTokens::UserId).uuid().not_null())
+                    .col(ColumnDef::new(OauthTokens::FamilyId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(OauthTokens::AccessTokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthTokens::RefreshTokenHash)
+                            .string_len(64)
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthTokens::Scopes)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OauthTokens::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OauthTokens::RefreshExpiresAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(OauthTokens::RevokedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(OauthTokens::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_tokens_client_id")
+                            .from(OauthTokens::Table, OauthTokens::ClientId)
+                            .to(OauthClients::Table, OauthClients::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_oauth_tokens_user_id")
+                            .from(OauthTokens::Table, OauthTokens::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_oauth_tokens_family_id")
+                    .table(OauthTokens::Table)
+                    .col(OauthTokens::FamilyId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_oauth_tokens_user_id_client_id")
+                    .table(OauthTokens::Table)
+                    .col(OauthTokens::UserId)
+                    .col(OauthTokens::ClientId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Consents, authorization codes and tokens are left out: they have no
+        // organization column, belong to the user who granted them and are
+        // only read by the token endpoint and the user's own settings, never
+        // inside a tenant transaction
+        enable_tenant_rls(manager, "oauth_clients", "organization_id").await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        disable_tenant_rls(manager, "oauth_clients").await?;
+
+        manager
+            .drop_table(Table::drop().table(OauthTokens::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(OauthAuthorizationCodes::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(OauthConsents::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(OauthClients::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum OauthClients {
+    Table,
+    Id,
+    OrganizationId,
+    Name,
+    SecretHash,
+    RedirectUris,
+    Scopes,
+    ServiceAccountId,
+    CreatedBy,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum OauthConsents {
+    Table,
+    Id,
+    UserId,
+    ClientId,
+    Scopes,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum OauthAuthorizationCodes {
+    Table,
+    Id,
+    CodeHash,
+    ClientId,
+    UserId,
+    RedirectUri,
+    Scopes,
+    CodeChallenge,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum OauthTokens {
+    Table,
+    Id,
+    ClientId,
+    UserId,
+    FamilyId,
+    AccessTokenHash,
+    RefreshTokenHash,
+    Scopes,
+    ExpiresAt,
+    RefreshExpiresAt,
+    RevokedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
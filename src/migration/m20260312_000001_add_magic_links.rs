@@ -0,0 +1,79 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Organizations::Table)
+                    .add_column(
+                        ColumnDef::new(Organizations::AllowMagicLinks)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Sign-in links requested per normalized email, whether or not it is
+        // registered, in the current window
+        manager
+            .create_table(
+                Table::create()
+                    .table(MagicLinkThrottles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MagicLinkThrottles::Email)
+                            .string_len(255)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(MagicLinkThrottles::Requests)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(MagicLinkThrottles::WindowStartedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MagicLinkThrottles::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Organizations::Table)
+                    .drop_column(Organizations::AllowMagicLinks)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    AllowMagicLinks,
+}
+
+#[derive(DeriveIden)]
+enum MagicLinkThrottles {
+    Table,
+    Email,
+    Requests,
+    WindowStartedAt,
+}
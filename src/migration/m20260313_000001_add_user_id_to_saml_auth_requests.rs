@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Set when a signed-in member links their identity provider account
+        // rather than signing in
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SamlAuthRequests::Table)
+                    .add_column(ColumnDef::new(SamlAuthRequests::UserId).uuid())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_saml_auth_requests_user_id")
+                            .from_tbl(SamlAuthRequests::Table)
+                            .from_col(SamlAuthRequests::UserId)
+                            .to_tbl(Users::Table)
+                            .to_col(Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SamlAuthRequests::Table)
+                    .drop_foreign_key(Alias::new("fk_saml_auth_requests_user_id"))
+                    .drop_column(SamlAuthRequests::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SamlAuthRequests {
+    Table,
+    UserId,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
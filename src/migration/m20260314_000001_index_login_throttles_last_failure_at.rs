@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Stale failures are pruned by age on every failed sign-in
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_login_throttles_last_failure_at")
+                    .table(LoginThrottles::Table)
+                    .col(LoginThrottles::LastFailureAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_login_throttles_last_failure_at")
+                    .table(LoginThrottles::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LoginThrottles {
+    Table,
+    LastFailureAt,
+}
@@ -1,12 +1,61 @@
 use sea_orm_migration::prelude::*;
 
 mod m20260219_000001_create_users_table;
+mod m20260220_000001_create_refresh_tokens_table;
+mod m20260221_000001_create_user_tokens_table;
+mod m20260222_000001_create_organizations_tables;
+mod m20260223_000001_enable_tenant_rls;
+mod m20260224_000001_create_invitations_table;
+mod m20260225_000001_add_email_verified_at_to_users;
+mod m20260226_000001_create_mfa_tables;
+mod m20260227_000001_create_webauthn_tables;
+mod m20260228_000001_create_identities_tables;
+mod m20260301_000001_create_saml_tables;
+mod m20260302_000001_create_scim_tables;
+mod m20260303_000001_create_api_keys_table;
+mod m20260304_000001_create_sessions_table;
+mod m20260305_000001_create_audit_events_table;
+mod m20260306_000001_create_login_throttles_table;
+mod m20260307_000001_create_password_policies_table;
+mod m20260308_000001_create_org_roles_table;
+mod m20260309_000001_create_impersonations_table;
+mod m20260310_000001_add_service_accounts;
+mod m20260311_000001_create_oauth_tables;
+mod m20260312_000001_add_magic_links;
+mod m20260313_000001_add_user_id_to_saml_auth_requests;
+mod m20260314_000001_index_login_throttles_last_failure_at;
+mod tenant;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20260219_000001_create_users_table::Migration)]
+        vec![
+            Box::new(m20260219_000001_create_users_table::Migration),
+            Box::new(m20260220_000001_create_refresh_tokens_table::Migration),
+            Box::new(m20260221_000001_create_user_tokens_table::Migration),
+            Box::new(m20260222_000001_create_organizations_tables::Migration),
+            Box::new(m20260223_000001_enable_tenant_rls::Migration),
+            Box::new(m20260224_000001_create_invitations_table::Migration),
+            Box::new(m20260225_000001_add_email_verified_at_to_users::Migration),
+            Box::new(m20260226_000001_create_mfa_tables::Migration),
+            Box::new(m20260227_000001_create_webauthn_tables::Migration),
+            Box::new(m20260228_000001_create_identities_tables::Migration),
+            Box::new(m20260301_000001_create_saml_tables::Migration),
+            Box::new(m20260302_000001_create_scim_tables::Migration),
+            Box::new(m20260303_000001_create_api_keys_table::Migration),
+            Box::new(m20260304_000001_create_sessions_table::Migration),
+            Box::new(m20260305_000001_create_audit_events_table::Migration),
+            Box::new(m20260306_000001_create_login_throttles_table::Migration),
+            Box::new(m20260307_000001_create_password_policies_table::Migration),
+            Box::new(m20260308_000001_create_org_roles_table::Migration),
+            Box::new(m20260309_000001_create_impersonations_table::Migration),
+            Box::new(m20260310_000001_add_service_accounts::Migration),
+            Box::new(m20260311_000001_create_oauth_tables::Migration),
+            Box::new(m20260312_000001_add_magic_links::Migration),
+            Box::new(m20260313_000001_add_user_id_to_saml_auth_requests::Migration),
+            Box::new(m20260314_000001_index_login_throttles_last_failure_at::Migration),
+        ]
     }
 }
@@ -0,0 +1,44 @@
+//! Helpers for installing row-level security on tenant-owned tables
+//!
+//! Policies apply to the `app_tenant` role only. Tenant transactions switch to
+//! it with `SET LOCAL ROLE` (see `db::begin_tenant_transaction`), so rows of
+//! other organizations are invisible there, while the regular connection used
+//! for cross-tenant work such as login and migrations is unaffected.
+
+use sea_orm_migration::prelude::*;
+
+/// Enable row-level security on `table`, restricting `app_tenant` to rows whose
+/// `org_column` matches the `app.current_org` setting
+pub async fn enable_tenant_rls(
+    manager: &SchemaManager<'_>,
+    table: &str,
+    org_column: &str,
+) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+
+    db.execute_unprepared(&format!("ALTER TABLE {table} ENABLE ROW LEVEL SECURITY"))
+        .await?;
+
+    db.execute_unprepared(&format!(
+        "CREATE POLICY tenant_isolation ON {table} TO app_tenant \
+         USING ({org_column} = NULLIF(current_setting('app.current_org', true), '')::uuid) \
+         WITH CHECK ({org_column} = NULLIF(current_setting('app.current_org', true), '')::uuid)"
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Undo [`enable_tenant_rls`]
+pub async fn disable_tenant_rls(manager: &SchemaManager<'_>, table: &str) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+
+    db.execute_unprepared(&format!(
+        "DROP POLICY IF EXISTS tenant_isolation ON {table}"
+    ))
+    .await?;
+    db.execute_unprepared(&format!("ALTER TABLE {table} DISABLE ROW LEVEL SECURITY"))
+        .await?;
+
+    Ok(())
+}
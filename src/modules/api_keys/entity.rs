@@ -0,0 +1,76 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A long-lived credential for scripts and integrations, owned by a user or
+/// an organization and stored as a SHA-256 hash
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "api_keys")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub name: String,
+    /// Start of the key, kept in clear so owners can tell their keys apart
+    #[sea_orm(unique)]
+    pub prefix: String,
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    /// The user or service account requests made with the key act as
+    pub user_id: Option<Uuid>,
+    pub organization_id: Option<Uuid>,
+    /// Admin who created an organization or service account key; requests
+    /// made with an organization key act as this user
+    pub created_by: Option<Uuid>,
+    /// Space-separated, e.g. `users:read orgs:write`
+    pub scopes: String,
+    pub expires_at: Option<DateTimeWithTimeZone>,
+    pub last_used_at: Option<DateTimeWithTimeZone>,
+    pub revoked_at: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+impl Model {
+    pub fn scopes(&self) -> Vec<String> {
+        self.scopes.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// Not revoked and not expired
+    pub fn is_usable(&self) -> bool {
+        self.revoked_at.is_none()
+            && self
+                .expires_at
+                .is_none_or(|expires_at| expires_at > chrono::Utc::now())
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::modules::users::entity::Entity",
+        from = "Column::UserId",
+        to = "crate::modules::users::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    User,
+    #[sea_orm(
+        belongs_to = "crate::modules::organizations::entity::Entity",
+        from = "Column::OrganizationId",
+        to = "crate::modules::organizations::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Organization,
+}
+
+impl Related<crate::modules::users::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl Related<crate::modules::organizations::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,211 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::error::AppResult;
+use crate::modules::auth::extractor::AuthUser;
+use crate::modules::organizations::context::{Admin, OrgGuard};
+use crate::modules::service_accounts::service as service_accounts;
+use crate::state::AppState;
+
+use super::entity;
+use super::service::{self, NewApiKey, Owner};
+
+#[derive(serde::Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// e.g. `["users:read", "orgs:write"]`
+    pub scopes: Vec<String>,
+    /// Never expires when left out
+    pub expires_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+impl From<CreateApiKeyRequest> for NewApiKey {
+    fn from(payload: CreateApiKeyRequest) -> Self {
+        Self {
+            name: payload.name,
+            scopes: payload.scopes,
+            expires_at: payload.expires_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_by: Option<Uuid>,
+    pub expires_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub revoked_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl From<entity::Model> for ApiKeyResponse {
+    fn from(model: entity::Model) -> Self {
+        Self {
+            scopes: model.scopes(),
+            id: model.id,
+            name: model.name,
+            prefix: model.prefix,
+            created_by: model.created_by,
+            expires_at: model.expires_at,
+            last_used_at: model.last_used_at,
+            revoked_at: model.revoked_at,
+            created_at: model.created_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    pub key: ApiKeyResponse,
+    /// Shown only once
+    pub secret: String,
+}
+
+fn created(key: entity::Model, secret: String) -> impl IntoResponse {
+    (
+        StatusCode::CREATED,
+        Json(CreatedApiKeyResponse {
+            key: key.into(),
+            secret,
+        }),
+    )
+}
+
+fn listed(keys: Vec<entity::Model>) -> impl IntoResponse {
+    Json(
+        keys.into_iter()
+            .map(ApiKeyResponse::from)
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// GET /api/users/me/api-keys
+pub async fn list_my_api_keys(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let keys = service::list(&state, Owner::User(auth.user.id)).await?;
+    Ok(listed(keys))
+}
+
+/// POST /api/users/me/api-keys
+pub async fn create_my_api_key(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let (key, secret) = service::create(&state, Owner::User(auth.user.id), payload.into()).await?;
+    Ok(created(key, secret))
+}
+
+/// DELETE /api/users/me/api-keys/:key_id
+pub async fn revoke_my_api_key(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(key_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::revoke(&state, Owner::User(auth.user.id), key_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/orgs/:org_id/api-keys
+pub async fn list_org_api_keys(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+) -> AppResult<impl IntoResponse> {
+    let keys = service::list(&state, org_owner(&org)).await?;
+    Ok(listed(keys))
+}
+
+/// POST /api/orgs/:org_id/api-keys
+pub async fn create_org_api_key(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    auth: AuthUser,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let (key, secret) = service::create(&state, org_owner(&org), payload.into()).await?;
+    Ok(created(key, secret))
+}
+
+/// DELETE /api/orgs/:org_id/api-keys/:key_id
+pub async fn revoke_org_api_key(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    auth: AuthUser,
+    Path((_, key_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::revoke(&state, org_owner(&org), key_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/orgs/:org_id/service-accounts/:service_account_id/api-keys
+pub async fn list_service_account_api_keys(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    Path((_, service_account_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let owner = service_account_owner(&state, &org, service_account_id).await?;
+    let keys = service::list(&state, owner).await?;
+    Ok(listed(keys))
+}
+
+/// POST /api/orgs/:org_id/service-accounts/:service_account_id/api-keys
+pub async fn create_service_account_api_key(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    auth: AuthUser,
+    Path((_, service_account_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let owner = service_account_owner(&state, &org, service_account_id).await?;
+    let (key, secret) = service::create(&state, owner, payload.into()).await?;
+    Ok(created(key, secret))
+}
+
+/// DELETE /api/orgs/:org_id/service-accounts/:service_account_id/api-keys/:key_id
+pub async fn revoke_service_account_api_key(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    auth: AuthUser,
+    Path((_, service_account_id, key_id)): Path<(Uuid, Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let owner = service_account_owner(&state, &org, service_account_id).await?;
+    service::revoke(&state, owner, key_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn org_owner(org: &OrgGuard<Admin>) -> Owner {
+    Owner::Organization {
+        id: org.org_id,
+        created_by: org.user_id,
+    }
+}
+
+async fn service_account_owner(
+    state: &AppState,
+    org: &OrgGuard<Admin>,
+    service_account_id: Uuid,
+) -> AppResult<Owner> {
+    let (account, _) = service_accounts::find(state, org.org_id, service_account_id).await?;
+    Ok(Owner::ServiceAccount {
+        id: account.id,
+        created_by: org.user_id,
+    })
+}
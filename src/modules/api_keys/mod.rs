@@ -0,0 +1,5 @@
+pub mod entity;
+pub mod handler;
+pub mod routes;
+pub mod scope;
+pub mod service;
@@ -0,0 +1,40 @@
+use axum::{
+    routing::{delete, get},
+    Router,
+};
+
+use crate::state::AppState;
+
+use super::handler;
+
+/// The caller's own keys, nested under `/api/users/me/api-keys`
+pub fn user_api_key_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(handler::list_my_api_keys).post(handler::create_my_api_key),
+        )
+        .route("/{key_id}", delete(handler::revoke_my_api_key))
+}
+
+/// Keys owned by an organization, nested under `/api/orgs/{org_id}/api-keys`
+pub fn org_api_key_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(handler::list_org_api_keys).post(handler::create_org_api_key),
+        )
+        .route("/{key_id}", delete(handler::revoke_org_api_key))
+}
+
+/// Keys of a service account, nested under
+/// `/api/orgs/{org_id}/service-accounts/{service_account_id}/api-keys`
+pub fn service_account_api_key_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(handler::list_service_account_api_keys)
+                .post(handler::create_service_account_api_key),
+        )
+        .route("/{key_id}", delete(handler::revoke_service_account_api_key))
+}
@@ -0,0 +1,261 @@
+//! What an API key may do
+//!
+//! Scopes are `<resource>:read` or `<resource>:write`, and write implies
+//! read. Routes opt in to API keys by declaring their resource with a
+//! [`ScopedResource`] extension layered outside `require_auth`; every other
+//! route refuses them, so account settings, second factors and key
+//! management stay behind an interactive sign-in.
+
+use axum::{
+    extract::{FromRequestParts, RawPathParams},
+    http::{request::Parts, Method},
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::organizations::context::ORG_ID_PATH_PARAM;
+use crate::modules::permissions::permission::PermissionSet;
+use crate::state::AppState;
+
+/// Resources API keys can be granted access to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScopedResource {
+    Users,
+    Organizations,
+}
+
+impl ScopedResource {
+    pub const ALL: [ScopedResource; 2] = [ScopedResource::Users, ScopedResource::Organizations];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ScopedResource::Users => "users",
+            ScopedResource::Organizations => "orgs",
+        }
+    }
+}
+
+/// Whether `scope` is one a key can be given
+pub fn is_known(scope: &str) -> bool {
+    scope.split_once(':').is_some_and(|(resource, access)| {
+        ScopedResource::ALL
+            .iter()
+            .any(|known| known.as_str() == resource)
+            && matches!(access, "read" | "write")
+    })
+}
+
+/// The API key or OAuth access token a request was authenticated with
+///
+/// OAuth access tokens are authorized like API keys, their scopes opening
+/// the same routes.
+#[derive(Clone, Debug)]
+#[allow(dead_code)] // Fields are part of public API and read by handlers as they are added
+pub struct ApiKeyGrant {
+    pub id: Uuid,
+    /// Set for organization keys, which only reach that organization
+    pub organization_id: Option<Uuid>,
+    pub scopes: Vec<String>,
+    /// Set for OAuth access tokens: the most the user's roles may grant
+    /// while the client acts for them
+    pub permissions: Option<PermissionSet>,
+}
+
+impl ApiKeyGrant {
+    pub fn allows(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+            || scope.strip_suffix(":read").is_some_and(|resource| {
+                self.scopes
+                    .iter()
+                    .any(|granted| granted.strip_suffix(":write") == Some(resource))
+            })
+    }
+
+    /// Fail with Forbidden unless the key may call the route being requested
+    pub async fn authorize(&self, parts: &mut Parts, state: &AppState) -> AppResult<()> {
+        let resource =
+            parts
+                .extensions
+                .get::<ScopedResource>()
+                .copied()
+                .ok_or(AppError::Forbidden(
+                    "API keys cannot be used for this endpoint".to_string(),
+                ))?;
+
+        let access = if matches!(parts.method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            "read"
+        } else {
+            "write"
+        };
+        let scope = format!("{}:{}", resource.as_str(), access);
+        if !self.allows(&scope) {
+            return Err(AppError::Forbidden(format!(
+                "API key is missing the {} scope",
+                scope
+            )));
+        }
+
+        if let Some(organization_id) = self.organization_id {
+            let target = RawPathParams::from_request_parts(parts, state)
+                .await
+                .ok()
+                .and_then(|params| {
+                    params
+                        .iter()
+                        .find(|(key, _)| *key == ORG_ID_PATH_PARAM)
+                        .and_then(|(_, value)| Uuid::parse_str(value).ok())
+                });
+            if target != Some(organization_id) {
+                return Err(AppError::Forbidden(
+                    "Organization API keys can only be used on their organization".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        extract::{Request, State},
+        http::StatusCode,
+        routing::any,
+        Extension, Router,
+    };
+    use sea_orm::DatabaseConnection;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_support::test_config;
+
+    fn grant(organization_id: Option<Uuid>, scopes: &[&str]) -> ApiKeyGrant {
+        ApiKeyGrant {
+            id: Uuid::new_v4(),
+            organization_id,
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            permissions: None,
+        }
+    }
+
+    /// Status of `method path` for `grant` on a route for `resource`, or on
+    /// a route that does not accept API keys
+    async fn status(
+        grant: ApiKeyGrant,
+        resource: Option<ScopedResource>,
+        method: Method,
+        path: &str,
+    ) -> StatusCode {
+        async fn handler(
+            State(state): State<AppState>,
+            Extension(grant): Extension<ApiKeyGrant>,
+            request: Request,
+        ) -> StatusCode {
+            let (mut parts, _) = request.into_parts();
+            match grant.authorize(&mut parts, &state).await {
+                Ok(()) => StatusCode::OK,
+                Err(_) => StatusCode::FORBIDDEN,
+            }
+        }
+
+        let state = AppState::new(DatabaseConnection::Disconnected, test_config());
+        let mut app = Router::new()
+            .route("/orgs/{org_id}/members", any(handler))
+            .route("/users/me", any(handler));
+        if let Some(resource) = resource {
+            app = app.layer(Extension(resource));
+        }
+        let app = app.layer(Extension(grant)).with_state(state);
+
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[test]
+    fn knows_resource_scopes() {
+        for scope in ["users:read", "users:write", "orgs:read", "orgs:write"] {
+            assert!(is_known(scope), "{}", scope);
+        }
+        for scope in ["users", "users:admin", "billing:read", "users:read:x", ""] {
+            assert!(!is_known(scope), "{}", scope);
+        }
+    }
+
+    #[test]
+    fn write_implies_read() {
+        let writer = grant(None, &["users:write"]);
+        assert!(writer.allows("users:write"));
+        assert!(writer.allows("users:read"));
+        assert!(!writer.allows("orgs:read"));
+
+        let reader = grant(None, &["users:read"]);
+        assert!(reader.allows("users:read"));
+        assert!(!reader.allows("users:write"));
+
+        let lookalike = grant(None, &["users:writer", "user:write"]);
+        assert!(!lookalike.allows("users:read"));
+        assert!(!lookalike.allows("users:write"));
+    }
+
+    #[tokio::test]
+    async fn needs_the_scope_of_the_method() {
+        let users = Some(ScopedResource::Users);
+        let reader = || grant(None, &["users:read"]);
+
+        assert_eq!(
+            status(reader(), users, Method::GET, "/users/me").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(reader(), users, Method::PATCH, "/users/me").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(
+                grant(None, &["users:write"]),
+                users,
+                Method::PATCH,
+                "/users/me"
+            )
+            .await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_routes_without_a_resource() {
+        let everything = grant(None, &["users:write", "orgs:write"]);
+
+        assert_eq!(
+            status(everything, None, Method::GET, "/users/me").await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_organization_keys_in_their_organization() {
+        let orgs = Some(ScopedResource::Organizations);
+        let organization_id = Uuid::new_v4();
+        let key = || grant(Some(organization_id), &["orgs:read"]);
+
+        let own = format!("/orgs/{}/members", organization_id);
+        assert_eq!(status(key(), orgs, Method::GET, &own).await, StatusCode::OK);
+
+        let other = format!("/orgs/{}/members", Uuid::new_v4());
+        assert_eq!(
+            status(key(), orgs, Method::GET, &other).await,
+            StatusCode::FORBIDDEN
+        );
+        // Routes without an organization are out of reach too
+        assert_eq!(
+            status(key(), orgs, Method::GET, "/users/me").await,
+            StatusCode::FORBIDDEN
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use rand::{rngs::OsRng, RngCore};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter,
+    QueryOrder, Set,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::token::{generate_token, hash_token};
+use crate::modules::users::entity::{self as users, Entity as Users};
+use crate::state::AppState;
+
+use super::entity::{self, Entity as ApiKeys};
+use super::scope::{self, ScopedResource};
+
+/// Every key starts with this, which is how bearer tokens are told apart
+/// from access tokens
+pub const KEY_PREFIX: &str = "sk_";
+
+/// How stale `last_used_at` may get before a request updates it, so busy
+/// keys do not write on every call
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+/// Who a key belongs to
+#[derive(Clone, Copy, Debug)]
+pub enum Owner {
+    User(Uuid),
+    /// Requests made with the key act as the admin who created it
+    Organization {
+        id: Uuid,
+        created_by: Uuid,
+    },
+    /// Requests made with the key act as the service account
+    ServiceAccount {
+        id: Uuid,
+        created_by: Uuid,
+    },
+}
+
+pub struct NewApiKey {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTimeWithTimeZone>,
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(KEY_PREFIX)
+}
+
+pub async fn list(state: &AppState, owner: Owner) -> AppResult<Vec<entity::Model>> {
+    ApiKeys::find()
+        .filter(owner_condition(owner))
+        .order_by_asc(entity::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Create a key, returning it with the raw value, which is not stored and
+/// cannot be shown again
+pub async fn create(
+    state: &AppState,
+    owner: Owner,
+    input: NewApiKey,
+) -> AppResult<(entity::Model, String)> {
+    let name = input.name.trim();
+    if name.is_empty() || name.len() > 100 {
+        return Err(AppError::ValidationError(
+            "Name must be between 1 and 100 characters".to_string(),
+        ));
+    }
+
+    let scopes = validate_scopes(owner, input.scopes)?;
+
+    let now = chrono::Utc::now().fixed_offset();
+    if input.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(AppError::ValidationError(
+            "Expiry must be in the future".to_string(),
+        ));
+    }
+
+    let mut id = [0u8; 6];
+    OsRng.fill_bytes(&mut id);
+    let prefix = format!("{}{}", KEY_PREFIX, hex::encode(id));
+    let raw_key = format!("{}_{}", prefix, generate_token());
+
+    let (user_id, organization_id, created_by) = match owner {
+        Owner::User(user_id) => (Some(user_id), None, Some(user_id)),
+        Owner::Organization { id, created_by } => (None, Some(id), Some(created_by)),
+        Owner::ServiceAccount { id, created_by } => (Some(id), None, Some(created_by)),
+    };
+
+    let key = entity::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        name: Set(name.to_string()),
+        prefix: Set(prefix),
+        key_hash: Set(hash_token(&raw_key)),
+        user_id: Set(user_id),
+        organization_id: Set(organization_id),
+        created_by: Set(created_by),
+        scopes: Set(scopes.join(" ")),
+        expires_at: Set(input.expires_at),
+        last_used_at: Set(None),
+        revoked_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(AppError::from)?;
+
+    Ok((key, raw_key))
+}
+
+/// Stop a key from working; revoked keys stay listed for reference
+pub async fn revoke(state: &AppState, owner: Owner, key_id: Uuid) -> AppResult<()> {
+    let key = ApiKeys::find_by_id(key_id)
+        .filter(owner_condition(owner))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or(AppError::NotFound(format!(
+            "API key with id {} not found",
+            key_id
+        )))?;
+
+    if key.revoked_at.is_some() {
+        return Ok(());
+    }
+
+    let mut active: entity::ActiveModel = key.into();
+    active.revoked_at = Set(Some(chrono::Utc::now().fixed_offset()));
+    active.update(&state.db).await.map_err(AppError::from)?;
+    Ok(())
+}
+
+/// Resolve a raw key to itself and the user requests made with it act as
+pub async fn authenticate(
+    state: &AppState,
+    raw_key: &str,
+) -> AppResult<(entity::Model, users::Model)> {
+    let key = ApiKeys::find()
+        .filter(entity::Column::KeyHash.eq(hash_token(raw_key)))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .filter(entity::Model::is_usable)
+        .ok_or(AppError::Unauthorized("Invalid API key".to_string()))?;
+
+    let user_id = key
+        .user_id
+        .or(key.created_by)
+        .ok_or(AppError::Unauthorized("Invalid API key".to_string()))?;
+    let user = Users::find_by_id(user_id)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .filter(|user| user.is_active)
+        .ok_or(AppError::Unauthorized(
+            "Account is not available".to_string(),
+        ))?;
+
+    let now = chrono::Utc::now();
+    let stale = key.last_used_at.is_none_or(|last_used_at| {
+        (now - last_used_at.to_utc()).num_seconds() >= LAST_USED_RESOLUTION_SECS
+    });
+    if stale {
+        ApiKeys::update_many()
+            .col_expr(
+                entity::Column::LastUsedAt,
+                sea_orm::sea_query::Expr::value(now.fixed_offset()),
+            )
+            .filter(entity::Column::Id.eq(key.id))
+            .exec(&state.db)
+            .await
+            .map_err(AppError::from)?;
+    }
+
+    Ok((key, user))
+}
+
+fn owner_condition(owner: Owner) -> sea_orm::sea_query::SimpleExpr {
+    match owner {
+        Owner::User(user_id) => entity::Column::UserId.eq(user_id),
+        Owner::Organization { id, .. } => entity::Column::OrganizationId.eq(id),
+        Owner::ServiceAccount { id, .. } => entity::Column::UserId.eq(id),
+    }
+}
+
+/// Check requested scopes and return them sorted and deduplicated
+///
+/// Organization keys act on one organization, so they only get `orgs` scopes.
+/// Service account keys can have any scope; the account's membership keeps
+/// them within its organization.
+fn validate_scopes(owner: Owner, mut scopes: Vec<String>) -> AppResult<Vec<String>> {
+    scopes.sort();
+    scopes.dedup();
+
+    if scopes.is_empty() {
+        return Err(AppError::ValidationError(
+            "At least one scope is required".to_string(),
+        ));
+    }
+
+    if let Some(unknown) = scopes.iter().find(|scope| !scope::is_known(scope)) {
+        return Err(AppError::ValidationError(format!(
+            "Unknown scope {}",
+            unknown
+        )));
+    }
+
+    if matches!(owner, Owner::Organization { .. }) {
+        let organizations = format!("{}:", ScopedResource::Organizations.as_str());
+        if let Some(scope) = scopes
+            .iter()
+            .find(|scope| !scope.starts_with(&organizations))
+        {
+            return Err(AppError::ValidationError(format!(
+                "Organization keys cannot have the {} scope",
+                scope
+            )));
+        }
+    }
+
+    Ok(scopes)
+}
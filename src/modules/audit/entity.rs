@@ -0,0 +1,28 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Something security-relevant that happened, kept for later review
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "audit_events")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    /// Dotted name, e.g. `account.locked`
+    pub action: String,
+    /// User who caused the event; absent when the system did
+    pub actor_id: Option<Uuid>,
+    /// `user` or `service_account`, so automations stand out from people
+    pub actor_type: Option<String>,
+    pub organization_id: Option<Uuid>,
+    /// Kind of the thing acted on, e.g. `user`
+    pub target_type: Option<String>,
+    pub target_id: Option<Uuid>,
+    pub ip_address: Option<String>,
+    pub details: Option<Json>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,102 @@
+use std::net::IpAddr;
+
+use sea_orm::{ActiveModelTrait, ConnectionTrait, EntityTrait, QuerySelect, Set};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::users::entity::{self as users, Entity as Users};
+
+use super::entity;
+
+/// An event to record; only `action` is required
+#[derive(Clone, Debug, Default)]
+pub struct AuditEvent {
+    pub action: &'static str,
+    pub actor_id: Option<Uuid>,
+    pub organization_id: Option<Uuid>,
+    pub target: Option<(&'static str, Uuid)>,
+    pub ip_address: Option<IpAddr>,
+    pub details: Option<Value>,
+}
+
+impl AuditEvent {
+    pub fn new(action: &'static str) -> Self {
+        Self {
+            action,
+            ..Self::default()
+        }
+    }
+
+    pub fn actor(mut self, user_id: Uuid) -> Self {
+        self.actor_id = Some(user_id);
+        self
+    }
+
+    pub fn organization(mut self, organization_id: Uuid) -> Self {
+        self.organization_id = Some(organization_id);
+        self
+    }
+
+    pub fn target_user(mut self, user_id: Uuid) -> Self {
+        self.target = Some(("user", user_id));
+        self
+    }
+
+    pub fn target_service_account(mut self, service_account_id: Uuid) -> Self {
+        self.target = Some(("service_account", service_account_id));
+        self
+    }
+
+    pub fn ip_address(mut self, ip_address: Option<IpAddr>) -> Self {
+        self.ip_address = ip_address;
+        self
+    }
+
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+/// Append an event to the audit trail
+pub async fn record<C: ConnectionTrait>(db: &C, event: AuditEvent) -> AppResult<()> {
+    let actor_type = match event.actor_id {
+        Some(actor_id) => Some(actor_type(db, actor_id).await?),
+        None => None,
+    };
+
+    entity::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        action: Set(event.action.to_string()),
+        actor_id: Set(event.actor_id),
+        actor_type: Set(actor_type.map(str::to_string)),
+        organization_id: Set(event.organization_id),
+        target_type: Set(event.target.map(|(kind, _)| kind.to_string())),
+        target_id: Set(event.target.map(|(_, id)| id)),
+        ip_address: Set(event.ip_address.map(|ip| ip.to_string())),
+        details: Set(event.details),
+        created_at: Set(chrono::Utc::now().fixed_offset()),
+    }
+    .insert(db)
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+/// Whether an actor is a person or a service account
+async fn actor_type<C: ConnectionTrait>(db: &C, actor_id: Uuid) -> AppResult<&'static str> {
+    let organization_id: Option<Option<Uuid>> = Users::find_by_id(actor_id)
+        .select_only()
+        .column(users::Column::ServiceAccountOrganizationId)
+        .into_tuple()
+        .one(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(match organization_id.flatten() {
+        Some(_) => "service_account",
+        None => "user",
+    })
+}
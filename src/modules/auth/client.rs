@@ -0,0 +1,62 @@
+use std::{convert::Infallible, net::IpAddr, net::SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts},
+};
+
+use crate::state::AppState;
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Longest user agent kept; some clients send kilobytes of it
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// Where a request comes from
+///
+/// The address is the peer of the connection, or with `trust_proxy_headers`
+/// the last `X-Forwarded-For` entry, which the proxy in front of the API
+/// appended. Either may be missing, e.g. when the app is driven in-process.
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequestParts<AppState> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Infallible> {
+        let forwarded = || {
+            parts
+                .headers
+                .get(FORWARDED_FOR)?
+                .to_str()
+                .ok()?
+                .rsplit(',')
+                .next()?
+                .trim()
+                .parse()
+                .ok()
+        };
+        let peer = || {
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip())
+        };
+        let ip = if state.config.trust_proxy_headers {
+            forwarded()
+        } else {
+            peer()
+        };
+
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LEN).collect());
+
+        Ok(Self { ip, user_agent })
+    }
+}
@@ -0,0 +1,342 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, Method},
+};
+use sea_orm::EntityTrait;
+use uuid::Uuid;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::modules::api_keys::{scope::ApiKeyGrant, service as api_keys};
+use crate::modules::impersonation::service as impersonation;
+use crate::modules::oauth::{scope as oauth_scope, service as oauth};
+use crate::modules::sessions::{cookies as session_cookies, service as sessions};
+use crate::modules::users::entity::{self as users, Entity as Users};
+use crate::state::AppState;
+
+use super::jwt::{self, Claims};
+use super::verification;
+
+/// The authenticated caller of a request
+///
+/// Use as a handler argument to require authentication:
+///
+/// ```rust,ignore
+/// pub async fn handler(auth: AuthUser) -> AppResult<impl IntoResponse> {
+///     Ok(Json(UserResponse::from(auth.user)))
+/// }
+/// ```
+///
+/// When the route is wrapped in [`require_auth`](super::middleware::require_auth)
+/// the user resolved by the middleware is reused instead of being loaded twice.
+///
+/// The bearer token is an access token, an API key or an OAuth access token.
+/// API keys and OAuth access tokens are refused unless the route accepts
+/// their scope, see [`scope`](crate::modules::api_keys::scope). Without a bearer token the
+/// session cookie is used, and unsafe requests must carry its CSRF token.
+///
+/// While a platform admin impersonates someone, `user` is the impersonated
+/// user and [`impersonator`](Self::impersonator) the admin.
+#[derive(Clone, Debug)]
+#[allow(dead_code)] // Fields are part of public API and read by handlers as they are added
+pub struct AuthUser {
+    pub user: users::Model,
+    /// For API keys, OAuth access tokens and sessions, claims standing in for
+    /// an access token: `jti` is the key, token or session id, and `org` an
+    /// API key's organization
+    pub claims: Claims,
+    pub api_key: Option<ApiKeyGrant>,
+    pub session_id: Option<Uuid>,
+}
+
+impl AuthUser {
+    /// Fail with Forbidden unless the caller's email address is verified
+    /// (or verification is turned off for this deployment)
+    pub fn require_verified_email(&self, config: &AppConfig) -> AppResult<()> {
+        verification::require_verified_email(config, &self.user)
+    }
+
+    /// The platform admin behind an impersonation token
+    pub fn impersonator(&self) -> Option<Uuid> {
+        self.claims.act.map(|actor| actor.sub)
+    }
+
+    /// Fail with Forbidden while impersonating, for actions that would
+    /// take over or lock out the account, such as changing its password
+    pub fn forbid_impersonation(&self) -> AppResult<()> {
+        if self.impersonator().is_some() {
+            return Err(AppError::Forbidden(
+                "This action is not allowed while impersonating".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> AppResult<Self> {
+        let auth = match parts.extensions.get::<AuthUser>() {
+            Some(auth) => auth.clone(),
+            None => {
+                let auth = authenticate(parts, state).await?;
+                parts.extensions.insert(auth.clone());
+                auth
+            }
+        };
+
+        if let Some(grant) = &auth.api_key {
+            grant.authorize(parts, state).await?;
+        }
+
+        Ok(auth)
+    }
+}
+
+/// Resolve the caller from the `Authorization: Bearer <token>` header, or
+/// the session cookie when there is none
+async fn authenticate(parts: &Parts, state: &AppState) -> AppResult<AuthUser> {
+    let Some(token) = bearer_token(&parts.headers) else {
+        return match session_cookies::session_token(&state.config, &parts.headers) {
+            Some(session_token) => authenticate_session(parts, state, &session_token).await,
+            None => Err(AppError::Unauthorized("Missing bearer token".to_string())),
+        };
+    };
+
+    if api_keys::is_api_key(token) {
+        return authenticate_api_key(state, token).await;
+    }
+
+    if oauth::is_access_token(token) {
+        return authenticate_oauth_token(state, token).await;
+    }
+
+    let claims = jwt::decode_access_token(&state.config, token)?;
+    if let Some(actor) = claims.act {
+        impersonation::verify(state, &claims, actor).await?;
+    }
+
+    let user = Users::find_by_id(claims.sub)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .filter(|user| user.is_active)
+        .ok_or(AppError::Unauthorized(
+            "Account is not available".to_string(),
+        ))?;
+
+    Ok(AuthUser {
+        user,
+        claims,
+        api_key: None,
+        session_id: None,
+    })
+}
+
+async fn authenticate_api_key(state: &AppState, raw_key: &str) -> AppResult<AuthUser> {
+    let (key, user) = api_keys::authenticate(state, raw_key).await?;
+
+    let claims = Claims {
+        sub: user.id,
+        iss: state.config.jwt_issuer.clone(),
+        iat: key.created_at.timestamp(),
+        exp: key
+            .expires_at
+            .map_or(i64::MAX, |expires_at| expires_at.timestamp()),
+        jti: key.id,
+        org: key.organization_id.or(user.service_account_organization_id),
+        act: None,
+    };
+    let api_key = ApiKeyGrant {
+        id: key.id,
+        organization_id: key.organization_id,
+        scopes: key.scopes(),
+        permissions: None,
+    };
+
+    Ok(AuthUser {
+        user,
+        claims,
+        api_key: Some(api_key),
+        session_id: None,
+    })
+}
+
+async fn authenticate_oauth_token(state: &AppState, access_token: &str) -> AppResult<AuthUser> {
+    let (token, user) = oauth::authenticate(state, access_token).await?;
+
+    let claims = Claims {
+        sub: user.id,
+        iss: state.config.jwt_issuer.clone(),
+        iat: token.created_at.timestamp(),
+        exp: token.expires_at.timestamp(),
+        jti: token.id,
+        org: user.service_account_organization_id,
+        act: None,
+    };
+    let scopes = token.scopes();
+    let grant = ApiKeyGrant {
+        id: token.id,
+        organization_id: None,
+        permissions: Some(oauth_scope::permissions(&scopes)),
+        scopes,
+    };
+
+    Ok(AuthUser {
+        user,
+        claims,
+        api_key: Some(grant),
+        session_id: None,
+    })
+}
+
+async fn authenticate_session(
+    parts: &Parts,
+    state: &AppState,
+    session_token: &str,
+) -> AppResult<AuthUser> {
+    let (session, user) = sessions::authenticate(state, session_token).await?;
+
+    if !matches!(parts.method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        let csrf_token = parts
+            .headers
+            .get(session_cookies::CSRF_HEADER)
+            .and_then(|value| value.to_str().ok());
+        sessions::verify_csrf(&session, csrf_token)?;
+    }
+
+    let claims = Claims {
+        sub: user.id,
+        iss: state.config.jwt_issuer.clone(),
+        iat: session.created_at.timestamp(),
+        exp: session.expires_at.timestamp(),
+        jti: session.id,
+        org: None,
+        act: None,
+    };
+
+    Ok(AuthUser {
+        user,
+        claims,
+        api_key: None,
+        session_id: Some(session.id),
+    })
+}
+
+/// The token of an `Authorization: Bearer <token>` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+
+    if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() {
+        Some(token.trim())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{header::COOKIE, StatusCode},
+        routing::any,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::modules::auth::client::ClientInfo;
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_state, unique_email};
+
+    /// Status of `method /` for a request signed in with a session cookie
+    /// and the given CSRF header
+    async fn status(
+        state: &AppState,
+        method: Method,
+        session: &str,
+        csrf: Option<&str>,
+    ) -> StatusCode {
+        async fn handler(_auth: AuthUser) -> StatusCode {
+            StatusCode::OK
+        }
+
+        let app = Router::new()
+            .route("/", any(handler))
+            .with_state(state.clone());
+        let mut request = Request::builder().method(method).uri("/").header(
+            COOKIE,
+            format!("{}={}", state.config.session_cookie_name, session),
+        );
+        if let Some(csrf) = csrf {
+            request = request.header(session_cookies::CSRF_HEADER, csrf);
+        }
+
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[test]
+    fn reads_bearer_token() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+
+        assert_eq!(bearer_token(&headers("Bearer abc")), Some("abc"));
+        assert_eq!(bearer_token(&headers("bearer  abc ")), Some("abc"));
+        assert_eq!(bearer_token(&headers("Basic abc")), None);
+        assert_eq!(bearer_token(&headers("Bearer ")), None);
+        assert_eq!(bearer_token(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn session_requests_need_csrf_token_unless_safe() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap();
+        let client = ClientInfo {
+            ip: None,
+            user_agent: None,
+        };
+        let session = sessions::create(&state, user.id, &client).await.unwrap();
+        let token = session.token.as_str();
+        let csrf = session.csrf_token.as_str();
+
+        assert_eq!(
+            status(&state, Method::GET, token, None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(&state, Method::POST, token, None).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(&state, Method::DELETE, token, Some(token)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(&state, Method::POST, token, Some(csrf)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(&state, Method::GET, "made-up", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
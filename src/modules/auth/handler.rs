@@ -0,0 +1,394 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::lockout::service::{self as lockout, LoginAttempt};
+use crate::modules::mfa::service::{self as mfa_service, SecondFactor};
+use crate::modules::sessions::{
+    cookies::session_cookies, handler::SessionResponse, mode::SignInMode,
+    service as sessions_service,
+};
+use crate::modules::users::{
+    entity as users, handler::UserResponse, password, service as users_service,
+};
+use crate::modules::webauthn::ceremony::AuthenticationCredential;
+use crate::state::AppState;
+
+use super::client::ClientInfo;
+use super::password_reset;
+use super::service::{self, TokenPair};
+use super::verification;
+
+#[derive(serde::Deserialize)]
+pub struct RegisterRequest {
+    pub email: String,
+    pub name: String,
+    pub password: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct MfaLoginRequest {
+    pub mfa_token: String,
+    /// A code from the authenticator app or an unused recovery code
+    pub code: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct MfaPasskeyOptionsRequest {
+    pub mfa_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct MfaPasskeyLoginRequest {
+    pub mfa_token: String,
+    pub credential: AuthenticationCredential,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+}
+
+impl From<TokenPair> for TokenResponse {
+    fn from(tokens: TokenPair) -> Self {
+        Self {
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+            token_type: "Bearer",
+            expires_in: tokens.expires_in,
+        }
+    }
+}
+
+/// Returned by login instead of tokens when a second factor is required
+#[derive(serde::Serialize)]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    /// What the challenge can be completed with, at `POST /api/auth/mfa`
+    /// or `POST /api/auth/mfa/passkey`
+    pub methods: Vec<SecondFactor>,
+    pub expires_in: i64,
+}
+
+impl MfaChallengeResponse {
+    /// Start the second step of a login whose first step succeeded
+    pub async fn start(state: &AppState, user_id: Uuid) -> AppResult<Self> {
+        Ok(Self {
+            mfa_required: true,
+            mfa_token: mfa_service::start_challenge(state, user_id).await?,
+            methods: mfa_service::challenge_methods(&state.db, user_id).await?,
+            expires_in: state.config.mfa_challenge_ttl_secs,
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct AuthResponse {
+    pub user: UserResponse,
+    /// Absent in session mode, and when the account must verify its email
+    /// before signing in
+    #[serde(flatten)]
+    pub tokens: Option<TokenResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionResponse>,
+}
+
+impl AuthResponse {
+    /// Sign the user in the way the client asked for, returning the cookies
+    /// to set alongside the response, which are none in token mode
+    pub async fn sign_in(
+        state: &AppState,
+        mode: &SignInMode,
+        user: users::Model,
+    ) -> AppResult<(CookieJar, Self)> {
+        match mode {
+            SignInMode::Tokens => {
+                let tokens = service::issue_tokens(state, &user).await?;
+                Ok((
+                    CookieJar::new(),
+                    Self {
+                        user: UserResponse::from(user),
+                        tokens: Some(tokens.into()),
+                        session: None,
+                    },
+                ))
+            }
+            SignInMode::Session(client) => {
+                let session = sessions_service::create(state, user.id, client).await?;
+                Ok((
+                    session_cookies(&state.config, &session),
+                    Self {
+                        user: UserResponse::from(user),
+                        tokens: None,
+                        session: Some(SessionResponse::from(&session)),
+                    },
+                ))
+            }
+        }
+    }
+
+    /// A new account that cannot sign in until its email is verified
+    pub fn pending(user: users::Model) -> Self {
+        Self {
+            user: UserResponse::from(user),
+            tokens: None,
+            session: None,
+        }
+    }
+}
+
+/// POST /api/auth/register
+pub async fn register(
+    State(state): State<AppState>,
+    mode: SignInMode,
+    Json(payload): Json<RegisterRequest>,
+) -> AppResult<impl IntoResponse> {
+    let user =
+        users_service::create_user(&state, &payload.email, &payload.name, payload.password).await?;
+
+    // The account exists either way; a failed send can be retried through
+    // the resend endpoint
+    if let Err(err) = verification::send_verification_email(&state, &user).await {
+        tracing::warn!(
+            "Failed to send verification email to user {}: {}",
+            user.id,
+            err
+        );
+    }
+
+    let (cookies, response) = match verification::ensure_sign_in_allowed(&state.config, &user) {
+        Ok(()) => AuthResponse::sign_in(&state, &mode, user).await?,
+        Err(_) => (CookieJar::new(), AuthResponse::pending(user)),
+    };
+
+    Ok((StatusCode::CREATED, cookies, Json(response)))
+}
+
+/// POST /api/auth/login
+///
+/// Accounts with MFA enabled, or relying on a passkey for an organization
+/// that requires MFA, get an [`MfaChallengeResponse`] to finish at
+/// `POST /api/auth/mfa` or `POST /api/auth/mfa/passkey` instead of tokens.
+pub async fn login(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    mode: SignInMode,
+    Json(payload): Json<LoginRequest>,
+) -> AppResult<Response> {
+    let invalid = || AppError::Unauthorized("Invalid email or password".to_string());
+
+    let attempt = LoginAttempt::new(&payload.email, &client);
+    lockout::check(&state, &attempt).await?;
+
+    let Some(user) = users_service::find_by_email(&state, &payload.email).await? else {
+        // Spend the same time as a real verification so response timing
+        // does not reveal which emails are registered
+        state.password_hasher.hash_async(payload.password).await?;
+        lockout::record_failure(&state, &attempt).await?;
+        return Err(invalid());
+    };
+
+    let Some(user) = password::verify_user_password(&state, user, &payload.password).await? else {
+        lockout::record_failure(&state, &attempt).await?;
+        return Err(invalid());
+    };
+
+    if !user.is_active {
+        return Err(AppError::Forbidden("Account is disabled".to_string()));
+    }
+    verification::ensure_sign_in_allowed(&state.config, &user)?;
+
+    if mfa_service::requires_challenge(&state.db, user.id).await? {
+        return Ok(Json(MfaChallengeResponse::start(&state, user.id).await?).into_response());
+    }
+
+    // With MFA the failures are forgotten only once the second step succeeds,
+    // so knowing the password does not reset the count on wrong codes
+    lockout::record_success(&state, &attempt).await?;
+    let (cookies, response) = AuthResponse::sign_in(&state, &mode, user).await?;
+    Ok((cookies, Json(response)).into_response())
+}
+
+/// POST /api/auth/mfa
+///
+/// Second login step: trades the token from `/api/auth/login` and a code
+/// for access and refresh tokens, or a session in session mode.
+pub async fn login_mfa(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    mode: SignInMode,
+    Json(payload): Json<MfaLoginRequest>,
+) -> AppResult<impl IntoResponse> {
+    // Wrong codes count against the account like wrong passwords do
+    let user = mfa_service::challenge_user(&state, &payload.mfa_token).await?;
+    let attempt = LoginAttempt::new(&user.email, &client);
+    lockout::check(&state, &attempt).await?;
+
+    let user =
+        match mfa_service::complete_challenge(&state, &payload.mfa_token, &payload.code).await {
+            Ok(user) => user,
+            Err(err @ AppError::Unauthorized(_)) => {
+                lockout::record_failure(&state, &attempt).await?;
+                return Err(err);
+            }
+            Err(err) => return Err(err),
+        };
+    lockout::record_success(&state, &attempt).await?;
+
+    if !user.is_active {
+        return Err(AppError::Forbidden("Account is disabled".to_string()));
+    }
+    verification::ensure_sign_in_allowed(&state.config, &user)?;
+
+    let (cookies, response) = AuthResponse::sign_in(&state, &mode, user).await?;
+    Ok((cookies, Json(response)))
+}
+
+/// POST /api/auth/mfa/passkey/options
+pub async fn mfa_passkey_options(
+    State(state): State<AppState>,
+    Json(payload): Json<MfaPasskeyOptionsRequest>,
+) -> AppResult<impl IntoResponse> {
+    let options = mfa_service::passkey_challenge_options(&state, &payload.mfa_token).await?;
+    Ok(Json(options))
+}
+
+/// POST /api/auth/mfa/passkey
+///
+/// Second login step with one of the user's passkeys instead of a code.
+pub async fn login_mfa_passkey(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    mode: SignInMode,
+    Json(payload): Json<MfaPasskeyLoginRequest>,
+) -> AppResult<impl IntoResponse> {
+    let user = mfa_service::challenge_user(&state, &payload.mfa_token).await?;
+    let attempt = LoginAttempt::new(&user.email, &client);
+    lockout::check(&state, &attempt).await?;
+
+    let user = match mfa_service::complete_passkey_challenge(
+        &state,
+        &payload.mfa_token,
+        &payload.credential,
+    )
+    .await
+    {
+        Ok(user) => user,
+        Err(err @ AppError::Unauthorized(_)) => {
+            lockout::record_failure(&state, &attempt).await?;
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
+    lockout::record_success(&state, &attempt).await?;
+
+    if !user.is_active {
+        return Err(AppError::Forbidden("Account is disabled".to_string()));
+    }
+    verification::ensure_sign_in_allowed(&state.config, &user)?;
+
+    let (cookies, response) = AuthResponse::sign_in(&state, &mode, user).await?;
+    Ok((cookies, Json(response)))
+}
+
+/// POST /api/auth/refresh
+pub async fn refresh(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshRequest>,
+) -> AppResult<impl IntoResponse> {
+    let (_, tokens) = service::rotate_refresh_token(&state, &payload.refresh_token).await?;
+    Ok(Json(TokenResponse::from(tokens)))
+}
+
+/// POST /api/auth/logout
+pub async fn logout(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshRequest>,
+) -> AppResult<impl IntoResponse> {
+    service::revoke_refresh_token(&state, &payload.refresh_token).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/auth/verify-email
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Json(payload): Json<VerifyEmailRequest>,
+) -> AppResult<impl IntoResponse> {
+    let user = verification::verify_email(&state, &payload.token).await?;
+    Ok(Json(UserResponse::from(user)))
+}
+
+/// POST /api/auth/resend-verification
+///
+/// Always answers 202 so the response does not reveal whether the email is
+/// registered or already verified.
+pub async fn resend_verification(
+    State(state): State<AppState>,
+    Json(payload): Json<ResendVerificationRequest>,
+) -> AppResult<impl IntoResponse> {
+    verification::resend_verification_email(&state, &payload.email).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// POST /api/auth/forgot-password
+///
+/// Always answers 202 so the response does not reveal whether the email is
+/// registered.
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> AppResult<impl IntoResponse> {
+    password_reset::request_password_reset(&state, &payload.email).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// POST /api/auth/reset-password
+pub async fn reset_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> AppResult<impl IntoResponse> {
+    password_reset::reset_password(&state, &payload.token, payload.password).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
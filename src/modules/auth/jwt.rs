@@ -0,0 +1,182 @@
+use jsonwebtoken::{
+    decode, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+
+/// Claims carried by an access token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// User id
+    pub sub: Uuid,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: Uuid,
+    /// Active organization the token is scoped to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<Uuid>,
+    /// The platform admin acting as `sub` while impersonating them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+/// Who is really behind a token, as in RFC 8693's `act` claim
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: Uuid,
+}
+
+impl Claims {
+    /// Claims for a new access token expiring after the configured lifetime
+    pub fn new(config: &AppConfig, user_id: Uuid) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        Self {
+            sub: user_id,
+            iss: config.jwt_issuer.clone(),
+            iat: now,
+            exp: now + config.access_token_ttl_secs,
+            jti: Uuid::new_v4(),
+            org: None,
+            act: None,
+        }
+    }
+
+    /// Scope the token to an organization
+    pub fn with_org(mut self, organization_id: Uuid) -> Self {
+        self.org = Some(organization_id);
+        self
+    }
+}
+
+/// Sign a short-lived access token for a user
+pub fn issue_access_token(config: &AppConfig, user_id: Uuid) -> AppResult<String> {
+    encode_claims(config, &Claims::new(config, user_id))
+}
+
+/// Sign an access token carrying the given claims
+pub fn encode_claims(config: &AppConfig, claims: &Claims) -> AppResult<String> {
+    encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+    )
+    .map_err(|e| AppError::internal(format!("Failed to sign access token: {}", e)))
+}
+
+/// Validate an access token's signature, issuer and expiry and return its claims
+pub fn decode_access_token(config: &AppConfig, token: &str) -> AppResult<Claims> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_issuer(&[&config.jwt_issuer]);
+
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
+        &validation,
+    )
+    .map(|data| data.claims)
+    .map_err(|e| match e.kind() {
+        ErrorKind::ExpiredSignature => {
+            AppError::Unauthorized("Access token has expired".to_string())
+        }
+        _ => AppError::Unauthorized("Invalid access token".to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_config;
+
+    fn expired_claims(config: &AppConfig) -> Claims {
+        let mut claims = Claims::new(config, Uuid::new_v4());
+        // Past the default 60 second leeway
+        claims.iat -= 3600;
+        claims.exp = claims.iat + 60;
+        claims
+    }
+
+    #[test]
+    fn decodes_issued_token() {
+        let config = test_config();
+        let organization_id = Uuid::new_v4();
+        let claims = Claims::new(&config, Uuid::new_v4()).with_org(organization_id);
+
+        let decoded =
+            decode_access_token(&config, &encode_claims(&config, &claims).unwrap()).unwrap();
+
+        assert_eq!(decoded.sub, claims.sub);
+        assert_eq!(decoded.jti, claims.jti);
+        assert_eq!(decoded.org, Some(organization_id));
+        assert_eq!(decoded.exp - decoded.iat, config.access_token_ttl_secs);
+    }
+
+    #[test]
+    fn rejects_expired_token() {
+        let config = test_config();
+        let token = encode_claims(&config, &expired_claims(&config)).unwrap();
+
+        let err = decode_access_token(&config, &token).unwrap_err();
+
+        assert!(matches!(err, AppError::Unauthorized(message) if message.contains("expired")));
+    }
+
+    #[test]
+    fn rejects_token_signed_with_another_secret() {
+        let config = test_config();
+        let mut other = test_config();
+        other.jwt_secret = "another secret".to_string();
+        let token = issue_access_token(&other, Uuid::new_v4()).unwrap();
+
+        assert!(matches!(
+            decode_access_token(&config, &token),
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_token_of_another_issuer() {
+        let config = test_config();
+        let mut other = test_config();
+        other.jwt_issuer = "another-issuer".to_string();
+        let token = issue_access_token(&other, Uuid::new_v4()).unwrap();
+
+        assert!(matches!(
+            decode_access_token(&config, &token),
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_tampered_claims() {
+        let config = test_config();
+        let token = issue_access_token(&config, Uuid::new_v4()).unwrap();
+        let forged = issue_access_token(&config, Uuid::new_v4()).unwrap();
+
+        // Someone else's payload under this token's signature
+        let mut parts: Vec<&str> = token.split('.').collect();
+        parts[1] = forged.split('.').nth(1).unwrap();
+
+        assert!(matches!(
+            decode_access_token(&config, &parts.join(".")),
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unsigned_token() {
+        let config = test_config();
+        let token = issue_access_token(&config, Uuid::new_v4()).unwrap();
+        let payload = token.split('.').nth(1).unwrap();
+        // {"alg":"none","typ":"JWT"}
+        let unsigned = format!("eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.{}.", payload);
+
+        assert!(matches!(
+            decode_access_token(&config, &unsigned),
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+}
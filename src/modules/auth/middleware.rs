@@ -0,0 +1,14 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+
+use super::extractor::AuthUser;
+
+/// Reject unauthenticated requests with `401 Unauthorized`
+///
+/// Apply to a router with
+/// `.route_layer(axum::middleware::from_fn_with_state(state, require_auth))`.
+/// The resolved [`AuthUser`] is stored in the request extensions so handlers
+/// extracting it do not hit the database again.
+pub async fn require_auth(auth: AuthUser, mut req: Request, next: Next) -> Response {
+    req.extensions_mut().insert(auth);
+    next.run(req).await
+}
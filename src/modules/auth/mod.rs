@@ -0,0 +1,11 @@
+pub mod client;
+pub mod entity;
+pub mod extractor;
+pub mod handler;
+pub mod jwt;
+pub mod middleware;
+pub mod password_reset;
+pub mod routes;
+pub mod service;
+pub mod token;
+pub mod verification;
@@ -0,0 +1,253 @@
+use sea_orm::{ActiveModelTrait, Set, TransactionTrait};
+
+use crate::error::{AppError, AppResult};
+use crate::mailer::templates::{app_link, ActionEmail};
+use crate::modules::password_policy::service::{self as password_policy, PasswordOwner};
+use crate::modules::user_tokens::{entity::TokenPurpose, service as user_tokens};
+use crate::modules::users::{entity as users, service as users_service};
+use crate::state::AppState;
+
+use super::service as auth_service;
+
+/// Mail a password reset link if the address belongs to an active account
+///
+/// Unknown and disabled accounts are ignored silently, so the response does
+/// not reveal whether the email is registered. Delivery happens in the
+/// background to keep slow mail servers out of the timing, though storing the
+/// token still makes real accounts slightly slower.
+pub async fn request_password_reset(state: &AppState, email: &str) -> AppResult<()> {
+    let Some(user) = users_service::find_by_email(state, email).await? else {
+        return Ok(());
+    };
+    if !user.is_active {
+        return Ok(());
+    }
+
+    let token = user_tokens::issue(
+        &state.db,
+        user.id,
+        TokenPurpose::PasswordReset,
+        None,
+        state.config.password_reset_token_ttl_secs,
+    )
+    .await?;
+
+    let email = ActionEmail {
+        subject: "Reset your password".to_string(),
+        heading: "Reset your password",
+        intro: format!(
+            "Hi {}, we received a request to reset the password for your account.",
+            user.name
+        ),
+        action_label: "Choose a new password",
+        action_url: app_link(&state.config.app_base_url, "/reset-password", &token),
+        outro:
+            "If you did not ask for this, you can ignore this email; your password will not change."
+                .to_string(),
+    }
+    .render(&user.email);
+
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(err) = mailer.send(email).await {
+            tracing::warn!(
+                "Failed to send password reset email to user {}: {}",
+                user.id,
+                err
+            );
+        }
+    });
+
+    Ok(())
+}
+
+/// Redeem a reset token, set the new password and sign the user out
+/// everywhere
+pub async fn reset_password(
+    state: &AppState,
+    raw_token: &str,
+    new_password: String,
+) -> AppResult<users::Model> {
+    // Check the password before spending the token, so a rejected one can
+    // be corrected with the same link
+    let pending =
+        user_tokens::find_valid(&state.db, TokenPurpose::PasswordReset, raw_token).await?;
+    let owner = users_service::find_by_id(state, pending.user_id).await?;
+    password_policy::validate(state, &new_password, &PasswordOwner::user(&owner)).await?;
+
+    let password_hash = state.password_hasher.hash_async(new_password).await?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    let token = user_tokens::consume(&txn, TokenPurpose::PasswordReset, raw_token).await?;
+    let user = users_service::find_by_id(state, token.user_id).await?;
+    if !user.is_active {
+        return Err(AppError::BadRequest("Invalid or expired token".to_string()));
+    }
+
+    let now = chrono::Utc::now().fixed_offset();
+    let mut active: users::ActiveModel = user.into();
+    active.password_hash = Set(Some(password_hash));
+    active.updated_at = Set(now);
+    let user = active.update(&txn).await.map_err(AppError::from)?;
+
+    // The reset link was delivered to this address
+    let user = users_service::mark_email_verified(&txn, user).await?;
+    auth_service::revoke_all_for_user(&txn, user.id).await?;
+
+    txn.commit().await.map_err(AppError::from)?;
+
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mailer::InMemoryMailer;
+    use crate::modules::auth::client::ClientInfo;
+    use crate::modules::sessions::service as sessions;
+    use crate::modules::users::password::PasswordVerification;
+    use crate::test_support::{link_token, mail_after, test_state, unique_email};
+
+    const OLD_PASSWORD: &str = "an old and long passphrase";
+    const NEW_PASSWORD: &str = "a brand new long passphrase";
+
+    async fn setup() -> Option<(AppState, Arc<InMemoryMailer>, users::Model)> {
+        let mailer = Arc::new(InMemoryMailer::new());
+        let state = test_state().await?.with_mailer(mailer.clone());
+        let user =
+            users_service::create_user(&state, &unique_email(), "Test", OLD_PASSWORD.to_string())
+                .await
+                .unwrap();
+        Some((state, mailer, user))
+    }
+
+    /// Request a reset for `user` and return the mailed token
+    async fn request(state: &AppState, mailer: &InMemoryMailer, user: &users::Model) -> String {
+        let sent = mailer.sent().len();
+        request_password_reset(state, &user.email).await.unwrap();
+        link_token(&mail_after(mailer, sent, &user.email).await)
+    }
+
+    fn verifies(state: &AppState, user: &users::Model, password: &str) -> bool {
+        let hash = user.password_hash.as_deref().unwrap();
+        state.password_hasher.verify(password, hash).unwrap() == PasswordVerification::Valid
+    }
+
+    fn is_invalid(result: AppResult<users::Model>) -> bool {
+        matches!(result, Err(AppError::BadRequest(_)))
+    }
+
+    #[tokio::test]
+    async fn resets_the_password_once() {
+        let Some((state, mailer, user)) = setup().await else {
+            return;
+        };
+        let token = request(&state, &mailer, &user).await;
+        assert_eq!(
+            mailer.last_to(&user.email).unwrap().subject,
+            "Reset your password"
+        );
+
+        let reset = reset_password(&state, &token, NEW_PASSWORD.to_string())
+            .await
+            .unwrap();
+        assert!(verifies(&state, &reset, NEW_PASSWORD));
+        assert!(!verifies(&state, &reset, OLD_PASSWORD));
+        assert!(reset.email_verified_at.is_some());
+
+        let again = reset_password(&state, &token, OLD_PASSWORD.to_string()).await;
+        assert!(is_invalid(again));
+    }
+
+    #[tokio::test]
+    async fn a_rejected_password_keeps_the_token() {
+        let Some((state, mailer, user)) = setup().await else {
+            return;
+        };
+        let token = request(&state, &mailer, &user).await;
+
+        let weak = reset_password(&state, &token, "short".to_string()).await;
+        assert!(matches!(weak, Err(AppError::ValidationError(_))));
+
+        reset_password(&state, &token, NEW_PASSWORD.to_string())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_and_superseded_tokens() {
+        let Some((state, mailer, user)) = setup().await else {
+            return;
+        };
+
+        let expired = user_tokens::issue(&state.db, user.id, TokenPurpose::PasswordReset, None, -1)
+            .await
+            .unwrap();
+        let result = reset_password(&state, &expired, NEW_PASSWORD.to_string()).await;
+        assert!(is_invalid(result));
+
+        // Only the latest link works
+        let first = request(&state, &mailer, &user).await;
+        let second = request(&state, &mailer, &user).await;
+        let result = reset_password(&state, &first, NEW_PASSWORD.to_string()).await;
+        assert!(is_invalid(result));
+        reset_password(&state, &second, NEW_PASSWORD.to_string())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn signs_the_user_out_everywhere() {
+        let Some((state, mailer, user)) = setup().await else {
+            return;
+        };
+        let tokens = auth_service::issue_tokens(&state, &user).await.unwrap();
+        let client = ClientInfo {
+            ip: None,
+            user_agent: None,
+        };
+        let session = sessions::create(&state, user.id, &client).await.unwrap();
+        let token = request(&state, &mailer, &user).await;
+
+        reset_password(&state, &token, NEW_PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let refreshed = auth_service::rotate_refresh_token(&state, &tokens.refresh_token).await;
+        assert!(matches!(refreshed, Err(AppError::Unauthorized(_))));
+        let resumed = sessions::authenticate(&state, &session.token).await;
+        assert!(matches!(resumed, Err(AppError::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn ignores_unknown_and_disabled_accounts() {
+        let Some((state, mailer, user)) = setup().await else {
+            return;
+        };
+
+        request_password_reset(&state, &unique_email())
+            .await
+            .unwrap();
+
+        let mut disabled: users::ActiveModel = user.clone().into();
+        disabled.is_active = Set(false);
+        disabled.update(&state.db).await.unwrap();
+        request_password_reset(&state, &user.email).await.unwrap();
+
+        let issued = user_tokens::last_issued_at(&state.db, user.id, TokenPurpose::PasswordReset)
+            .await
+            .unwrap();
+        assert!(issued.is_none());
+        assert!(mailer.sent().is_empty());
+
+        // A token issued before the account was disabled no longer works
+        let token = user_tokens::issue(&state.db, user.id, TokenPurpose::PasswordReset, None, 600)
+            .await
+            .unwrap();
+        let result = reset_password(&state, &token, NEW_PASSWORD.to_string()).await;
+        assert!(is_invalid(result));
+    }
+}
@@ -0,0 +1,32 @@
+use axum::{routing::post, Router};
+
+use crate::modules::lockout::routes::unlock_auth_routes;
+use crate::modules::magic_links::routes::magic_link_auth_routes;
+use crate::modules::oidc::routes::oidc_auth_routes;
+use crate::modules::saml::routes::sso_auth_routes;
+use crate::modules::sessions::routes::session_auth_routes;
+use crate::modules::webauthn::routes::passkey_auth_routes;
+use crate::state::AppState;
+
+use super::handler;
+
+pub fn auth_routes() -> Router<AppState> {
+    Router::new()
+        .route("/register", post(handler::register))
+        .route("/login", post(handler::login))
+        .route("/mfa", post(handler::login_mfa))
+        .route("/mfa/passkey", post(handler::login_mfa_passkey))
+        .route("/mfa/passkey/options", post(handler::mfa_passkey_options))
+        .route("/refresh", post(handler::refresh))
+        .route("/logout", post(handler::logout))
+        .route("/verify-email", post(handler::verify_email))
+        .route("/resend-verification", post(handler::resend_verification))
+        .route("/forgot-password", post(handler::forgot_password))
+        .route("/reset-password", post(handler::reset_password))
+        .nest("/unlock", unlock_auth_routes())
+        .nest("/magic-link", magic_link_auth_routes())
+        .nest("/passkey", passkey_auth_routes())
+        .nest("/oidc", oidc_auth_routes())
+        .nest("/sso", sso_auth_routes())
+        .nest("/session", session_auth_routes())
+}
@@ -0,0 +1,324 @@
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::sessions::service as sessions;
+use crate::modules::users::entity::{self as users, Entity as Users};
+use crate::state::AppState;
+
+use super::entity::{self as refresh_tokens, Entity as RefreshTokens};
+use super::{jwt, token, verification};
+
+/// A freshly issued access/refresh token pair
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+/// Issue a token pair for a user, starting a new refresh token family
+pub async fn issue_tokens(state: &AppState, user: &users::Model) -> AppResult<TokenPair> {
+    let refresh_token = insert_refresh_token(state, &state.db, user.id, Uuid::new_v4()).await?;
+
+    Ok(TokenPair {
+        access_token: jwt::issue_access_token(&state.config, user.id)?,
+        refresh_token,
+        expires_in: state.config.access_token_ttl_secs,
+    })
+}
+
+/// Exchange a refresh token for a new token pair
+///
+/// The presented token is revoked and replaced by a successor in the same
+/// family. Presenting a token that was already revoked is treated as theft
+/// and revokes every token in its family.
+pub async fn rotate_refresh_token(
+    state: &AppState,
+    raw_token: &str,
+) -> AppResult<(users::Model, TokenPair)> {
+    let current = find_refresh_token(state, raw_token).await?;
+    let now = chrono::Utc::now().fixed_offset();
+
+    if current.revoked_at.is_some() {
+        revoke_family(&state.db, current.family_id).await?;
+        tracing::warn!(
+            "Refresh token reuse detected for user {}, revoked family {}",
+            current.user_id,
+            current.family_id
+        );
+        return Err(AppError::Unauthorized(
+            "Refresh token has been revoked".to_string(),
+        ));
+    }
+
+    if current.expires_at < now {
+        return Err(AppError::Unauthorized(
+            "Refresh token has expired".to_string(),
+        ));
+    }
+
+    let user = Users::find_by_id(current.user_id)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .filter(|user| user.is_active)
+        .ok_or(AppError::Unauthorized(
+            "Account is not available".to_string(),
+        ))?;
+    verification::ensure_sign_in_allowed(&state.config, &user)?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    // Only one concurrent refresh can win the conditional update; the loser
+    // sees zero affected rows and is handled like any other reuse.
+    let successor_id = Uuid::new_v4();
+    let result = RefreshTokens::update_many()
+        .col_expr(refresh_tokens::Column::RevokedAt, Expr::value(now))
+        .col_expr(
+            refresh_tokens::Column::ReplacedBy,
+            Expr::value(successor_id),
+        )
+        .filter(refresh_tokens::Column::Id.eq(current.id))
+        .filter(refresh_tokens::Column::RevokedAt.is_null())
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+
+    if result.rows_affected == 0 {
+        txn.rollback().await.map_err(AppError::from)?;
+        revoke_family(&state.db, current.family_id).await?;
+        return Err(AppError::Unauthorized(
+            "Refresh token has been revoked".to_string(),
+        ));
+    }
+
+    let refresh_token =
+        insert_refresh_token_with_id(state, &txn, successor_id, user.id, current.family_id).await?;
+    txn.commit().await.map_err(AppError::from)?;
+
+    let tokens = TokenPair {
+        access_token: jwt::issue_access_token(&state.config, user.id)?,
+        refresh_token,
+        expires_in: state.config.access_token_ttl_secs,
+    };
+
+    Ok((user, tokens))
+}
+
+/// Revoke the family of the given refresh token (logout)
+pub async fn revoke_refresh_token(state: &AppState, raw_token: &str) -> AppResult<()> {
+    let current = find_refresh_token(state, raw_token).await?;
+    revoke_family(&state.db, current.family_id).await
+}
+
+/// Revoke every still-active token in a refresh token family
+pub async fn revoke_family<C: ConnectionTrait>(db: &C, family_id: Uuid) -> AppResult<()> {
+    RefreshTokens::update_many()
+        .col_expr(
+            refresh_tokens::Column::RevokedAt,
+            Expr::value(chrono::Utc::now().fixed_offset()),
+        )
+        .filter(refresh_tokens::Column::FamilyId.eq(family_id))
+        .filter(refresh_tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+/// Revoke every active refresh token of a user and end their sessions,
+/// signing them out everywhere
+pub async fn revoke_all_for_user<C: ConnectionTrait>(db: &C, user_id: Uuid) -> AppResult<()> {
+    sessions::revoke_all_for_user(db, user_id).await?;
+
+    RefreshTokens::update_many()
+        .col_expr(
+            refresh_tokens::Column::RevokedAt,
+            Expr::value(chrono::Utc::now().fixed_offset()),
+        )
+        .filter(refresh_tokens::Column::UserId.eq(user_id))
+        .filter(refresh_tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+async fn find_refresh_token(state: &AppState, raw_token: &str) -> AppResult<refresh_tokens::Model> {
+    RefreshTokens::find()
+        .filter(refresh_tokens::Column::TokenHash.eq(token::hash_token(raw_token)))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or(AppError::Unauthorized("Invalid refresh token".to_string()))
+}
+
+async fn insert_refresh_token<C: ConnectionTrait>(
+    state: &AppState,
+    db: &C,
+    user_id: Uuid,
+    family_id: Uuid,
+) -> AppResult<String> {
+    insert_refresh_token_with_id(state, db, Uuid::new_v4(), user_id, family_id).await
+}
+
+async fn insert_refresh_token_with_id<C: ConnectionTrait>(
+    state: &AppState,
+    db: &C,
+    id: Uuid,
+    user_id: Uuid,
+    family_id: Uuid,
+) -> AppResult<String> {
+    let raw_token = token::generate_token();
+    let now = chrono::Utc::now().fixed_offset();
+
+    refresh_tokens::ActiveModel {
+        id: Set(id),
+        user_id: Set(user_id),
+        family_id: Set(family_id),
+        token_hash: Set(token::hash_token(&raw_token)),
+        expires_at: Set(now + chrono::Duration::seconds(state.config.refresh_token_ttl_secs)),
+        revoked_at: Set(None),
+        replaced_by: Set(None),
+        created_at: Set(now),
+    }
+    .insert(db)
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(raw_token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_state, unique_email};
+
+    async fn create_user(state: &AppState) -> users::Model {
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap();
+        users_service::mark_email_verified(&state.db, user)
+            .await
+            .unwrap()
+    }
+
+    fn is_unauthorized(result: AppResult<(users::Model, TokenPair)>) -> bool {
+        matches!(result, Err(AppError::Unauthorized(_)))
+    }
+
+    #[tokio::test]
+    async fn rotates_refresh_token() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = create_user(&state).await;
+        let first = issue_tokens(&state, &user).await.unwrap();
+
+        let (refreshed_user, second) = rotate_refresh_token(&state, &first.refresh_token)
+            .await
+            .unwrap();
+        assert_eq!(refreshed_user.id, user.id);
+        assert_ne!(second.refresh_token, first.refresh_token);
+
+        let old = find_refresh_token(&state, &first.refresh_token)
+            .await
+            .unwrap();
+        let new = find_refresh_token(&state, &second.refresh_token)
+            .await
+            .unwrap();
+        assert!(old.revoked_at.is_some());
+        assert_eq!(old.replaced_by, Some(new.id));
+        assert_eq!(new.family_id, old.family_id);
+        assert!(new.revoked_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn reuse_revokes_the_whole_family() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = create_user(&state).await;
+        let stolen = issue_tokens(&state, &user).await.unwrap();
+        let other_device = issue_tokens(&state, &user).await.unwrap();
+        let (_, current) = rotate_refresh_token(&state, &stolen.refresh_token)
+            .await
+            .unwrap();
+
+        // The thief replays the token the owner already rotated
+        assert!(is_unauthorized(
+            rotate_refresh_token(&state, &stolen.refresh_token).await
+        ));
+        // which also logs out the owner's current token in that family
+        assert!(is_unauthorized(
+            rotate_refresh_token(&state, &current.refresh_token).await
+        ));
+        // but not other sign-ins
+        rotate_refresh_token(&state, &other_device.refresh_token)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn logout_revokes_the_family() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = create_user(&state).await;
+        let first = issue_tokens(&state, &user).await.unwrap();
+        let (_, second) = rotate_refresh_token(&state, &first.refresh_token)
+            .await
+            .unwrap();
+
+        revoke_refresh_token(&state, &second.refresh_token)
+            .await
+            .unwrap();
+
+        assert!(is_unauthorized(
+            rotate_refresh_token(&state, &second.refresh_token).await
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_expired_or_deactivated() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = create_user(&state).await;
+
+        assert!(is_unauthorized(
+            rotate_refresh_token(&state, "made-up").await
+        ));
+
+        let expired = issue_tokens(&state, &user).await.unwrap();
+        let mut active: refresh_tokens::ActiveModel =
+            find_refresh_token(&state, &expired.refresh_token)
+                .await
+                .unwrap()
+                .into();
+        active.expires_at = Set(chrono::Utc::now().fixed_offset() - chrono::Duration::seconds(1));
+        active.update(&state.db).await.unwrap();
+        assert!(is_unauthorized(
+            rotate_refresh_token(&state, &expired.refresh_token).await
+        ));
+
+        let tokens = issue_tokens(&state, &user).await.unwrap();
+        let mut active: users::ActiveModel = user.into();
+        active.is_active = Set(false);
+        active.update(&state.db).await.unwrap();
+        assert!(is_unauthorized(
+            rotate_refresh_token(&state, &tokens.refresh_token).await
+        ));
+    }
+}
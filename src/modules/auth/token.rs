@@ -0,0 +1,18 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Generate a random, URL-safe opaque token with 256 bits of entropy
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hash an opaque token for storage
+///
+/// Tokens are high-entropy random values, so a fast unsalted hash is enough:
+/// it only has to make a leaked table useless, not resist guessing.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
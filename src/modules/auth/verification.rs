@@ -0,0 +1,303 @@
+use crate::config::{AppConfig, EmailVerificationPolicy};
+use crate::error::{AppError, AppResult};
+use crate::mailer::templates::{app_link, ActionEmail};
+use crate::modules::user_tokens::{entity::TokenPurpose, service as user_tokens};
+use crate::modules::users::{entity as users, service as users_service};
+use crate::state::AppState;
+
+/// Mail a fresh verification link for the user's current email address
+pub async fn send_verification_email(state: &AppState, user: &users::Model) -> AppResult<()> {
+    let token = user_tokens::issue(
+        &state.db,
+        user.id,
+        TokenPurpose::EmailVerification,
+        Some(user.email.clone()),
+        state.config.email_verification_token_ttl_secs,
+    )
+    .await?;
+
+    let email = ActionEmail {
+        subject: "Verify your email address".to_string(),
+        heading: "Verify your email address",
+        intro: format!(
+            "Hi {}, please confirm that {} is your email address.",
+            user.name, user.email
+        ),
+        action_label: "Verify email",
+        action_url: app_link(&state.config.app_base_url, "/verify-email", &token),
+        outro: "If you did not create an account, you can ignore this email.".to_string(),
+    }
+    .render(&user.email);
+
+    state.mailer.send(email).await
+}
+
+/// Redeem a verification token
+pub async fn verify_email(state: &AppState, raw_token: &str) -> AppResult<users::Model> {
+    let token = user_tokens::consume(&state.db, TokenPurpose::EmailVerification, raw_token).await?;
+
+    let user = users_service::find_by_id(state, token.user_id).await?;
+
+    // The token only vouches for the address it was sent to
+    if token.payload.as_deref() != Some(user.email.as_str()) {
+        return Err(AppError::BadRequest("Invalid or expired token".to_string()));
+    }
+
+    users_service::mark_email_verified(&state.db, user).await
+}
+
+/// Send another verification email unless one was sent very recently
+///
+/// Unknown and already verified addresses are ignored silently so the
+/// endpoint does not reveal which emails have accounts.
+pub async fn resend_verification_email(state: &AppState, email: &str) -> AppResult<()> {
+    let Some(user) = users_service::find_by_email(state, email).await? else {
+        return Ok(());
+    };
+    if user.email_verified_at.is_some() || !user.is_active {
+        return Ok(());
+    }
+
+    let cooldown = chrono::Duration::seconds(state.config.email_verification_resend_cooldown_secs);
+    let last_sent =
+        user_tokens::last_issued_at(&state.db, user.id, TokenPurpose::EmailVerification).await?;
+    if let Some(last_sent) = last_sent {
+        if chrono::Utc::now().fixed_offset() - last_sent < cooldown {
+            tracing::info!("Throttled verification email for user {}", user.id);
+            return Ok(());
+        }
+    }
+
+    send_verification_email(state, &user).await
+}
+
+/// Reject sign-in for unverified accounts under the `block` policy, and for
+/// service accounts, which only ever use API keys
+pub fn ensure_sign_in_allowed(config: &AppConfig, user: &users::Model) -> AppResult<()> {
+    if user.is_service_account() {
+        return Err(AppError::Forbidden(
+            "Service accounts cannot sign in".to_string(),
+        ));
+    }
+    if config.email_verification_policy == EmailVerificationPolicy::Block
+        && user.email_verified_at.is_none()
+    {
+        return Err(AppError::Forbidden(
+            "Email address has not been verified".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Require a verified email address for actions unverified accounts are
+/// limited from, unless verification is turned off
+pub fn require_verified_email(config: &AppConfig, user: &users::Model) -> AppResult<()> {
+    if config.email_verification_policy != EmailVerificationPolicy::Off
+        && user.email_verified_at.is_none()
+    {
+        return Err(AppError::Forbidden(
+            "Verify your email address before performing this action".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{header, Method, StatusCode},
+    };
+    use sea_orm::{ActiveModelTrait, Set};
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::mailer::InMemoryMailer;
+    use crate::modules::auth::jwt::{self, Claims};
+    use crate::test_support::{link_token, test_config, test_db, test_state, unique_email};
+
+    fn user(verified: bool, service_account: bool) -> users::Model {
+        let now = chrono::Utc::now().fixed_offset();
+        users::Model {
+            id: Uuid::new_v4(),
+            email: unique_email(),
+            name: "Test".to_string(),
+            password_hash: None,
+            is_active: true,
+            is_platform_admin: false,
+            service_account_organization_id: service_account.then(Uuid::new_v4),
+            email_verified_at: verified.then_some(now),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn config_with(policy: EmailVerificationPolicy) -> AppConfig {
+        let mut config = test_config();
+        config.email_verification_policy = policy;
+        config
+    }
+
+    async fn create_user(state: &AppState) -> users::Model {
+        users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn policies_gate_sign_in_and_limited_actions() {
+        use EmailVerificationPolicy::*;
+
+        let verified = user(true, false);
+        let unverified = user(false, false);
+        for (policy, signs_in, acts) in [
+            (Off, true, true),
+            (Limit, true, false),
+            (Block, false, false),
+        ] {
+            let config = config_with(policy);
+            assert!(ensure_sign_in_allowed(&config, &verified).is_ok());
+            assert!(require_verified_email(&config, &verified).is_ok());
+            assert_eq!(
+                ensure_sign_in_allowed(&config, &unverified).is_ok(),
+                signs_in,
+                "{:?}",
+                policy
+            );
+            assert_eq!(
+                require_verified_email(&config, &unverified).is_ok(),
+                acts,
+                "{:?}",
+                policy
+            );
+        }
+
+        let result = ensure_sign_in_allowed(&config_with(Off), &user(true, true));
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn verifies_with_the_mailed_token_once() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let mailer = Arc::new(InMemoryMailer::new());
+        let state = state.with_mailer(mailer.clone());
+        let user = create_user(&state).await;
+
+        send_verification_email(&state, &user).await.unwrap();
+        let email = mailer.last_to(&user.email).unwrap();
+        assert_eq!(email.subject, "Verify your email address");
+        let token = link_token(&email);
+
+        let verified = verify_email(&state, &token).await.unwrap();
+        assert!(verified.email_verified_at.is_some());
+        let again = verify_email(&state, &token).await;
+        assert!(matches!(again, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn tokens_only_verify_the_address_they_were_sent_to() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let mailer = Arc::new(InMemoryMailer::new());
+        let state = state.with_mailer(mailer.clone());
+        let user = create_user(&state).await;
+        send_verification_email(&state, &user).await.unwrap();
+        let token = link_token(&mailer.last_to(&user.email).unwrap());
+
+        let mut changed: users::ActiveModel = user.into();
+        changed.email = Set(unique_email());
+        changed.update(&state.db).await.unwrap();
+
+        let result = verify_email(&state, &token).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_do_not_verify() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let mut config = test_config();
+        config.email_verification_token_ttl_secs = -1;
+        let mailer = Arc::new(InMemoryMailer::new());
+        let state = AppState::new(db, config).with_mailer(mailer.clone());
+        let user = create_user(&state).await;
+        send_verification_email(&state, &user).await.unwrap();
+        let token = link_token(&mailer.last_to(&user.email).unwrap());
+
+        let result = verify_email(&state, &token).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn resends_after_the_cooldown_only() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let mailer = Arc::new(InMemoryMailer::new());
+        let state = state.with_mailer(mailer.clone());
+        let user = create_user(&state).await;
+
+        resend_verification_email(&state, &user.email)
+            .await
+            .unwrap();
+        resend_verification_email(&state, &user.email)
+            .await
+            .unwrap();
+        resend_verification_email(&state, &unique_email())
+            .await
+            .unwrap();
+        assert_eq!(mailer.sent().len(), 1);
+
+        let token = link_token(&mailer.last_to(&user.email).unwrap());
+        verify_email(&state, &token).await.unwrap();
+
+        // Verified accounts get nothing more
+        resend_verification_email(&state, &user.email)
+            .await
+            .unwrap();
+        assert_eq!(mailer.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn unverified_users_create_organizations_only_with_verification_off() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+
+        for (policy, expected) in [
+            (EmailVerificationPolicy::Limit, StatusCode::FORBIDDEN),
+            (EmailVerificationPolicy::Off, StatusCode::CREATED),
+        ] {
+            let state = AppState::new(db.clone(), config_with(policy));
+            let user = create_user(&state).await;
+            let token =
+                jwt::encode_claims(&state.config, &Claims::new(&state.config, user.id)).unwrap();
+            let name = format!("Unverified {}", Uuid::new_v4().simple());
+
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("/api/orgs")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::json!({ "name": name }).to_string()))
+                .unwrap();
+            let response = crate::app::rust_saas(state).oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected, "{:?}", policy);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A platform admin acting as another user; its id is the `jti` of the
+/// impersonation token
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "impersonations")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub admin_id: Uuid,
+    pub target_user_id: Uuid,
+    /// Why support needed to see the account, e.g. a ticket reference
+    pub reason: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    /// Fixed when the impersonation starts; never pushed back
+    pub expires_at: DateTimeWithTimeZone,
+    /// Set when stopped before it expired
+    pub ended_at: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::modules::users::entity::Entity",
+        from = "Column::TargetUserId",
+        to = "crate::modules::users::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    TargetUser,
+}
+
+impl Related<crate::modules::users::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TargetUser.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,71 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::error::AppResult;
+use crate::modules::auth::{client::ClientInfo, extractor::AuthUser};
+use crate::modules::users::handler::UserResponse;
+use crate::state::AppState;
+
+use super::service;
+
+#[derive(serde::Deserialize)]
+pub struct StartImpersonationRequest {
+    pub user_id: Uuid,
+    /// Why the account needs to be seen, e.g. a support ticket reference
+    pub reason: String,
+}
+
+/// Returned when an impersonation starts; there is no refresh token
+#[derive(serde::Serialize)]
+pub struct ImpersonationResponse {
+    pub impersonation_id: Uuid,
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    pub expires_at: chrono::DateTime<chrono::FixedOffset>,
+    pub user: UserResponse,
+}
+
+/// POST /api/admin/impersonations
+///
+/// Platform admins only. Requests made with the returned token act as the
+/// user and are marked with the `X-Impersonated-By` response header.
+pub async fn start_impersonation(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    client: ClientInfo,
+    Json(payload): Json<StartImpersonationRequest>,
+) -> AppResult<impl IntoResponse> {
+    let started = service::start(&state, &auth, &client, payload.user_id, &payload.reason).await?;
+    let expires_at = started.impersonation.expires_at;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ImpersonationResponse {
+            impersonation_id: started.impersonation.id,
+            access_token: started.access_token,
+            token_type: "Bearer",
+            expires_in: (expires_at - chrono::Utc::now().fixed_offset()).num_seconds(),
+            expires_at,
+            user: UserResponse::from(started.target),
+        }),
+    ))
+}
+
+/// DELETE /api/admin/impersonations/:impersonation_id
+///
+/// Made with the impersonation token itself or by the admin who started it.
+pub async fn stop_impersonation(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    client: ClientInfo,
+    Path(impersonation_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    service::stop(&state, &auth, &client, impersonation_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
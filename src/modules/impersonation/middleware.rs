@@ -0,0 +1,54 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::modules::auth::{client::ClientInfo, extractor::bearer_token, jwt};
+use crate::state::AppState;
+
+use super::service;
+
+/// Header marking responses to impersonated requests, holding the admin's id
+pub const IMPERSONATED_BY_HEADER: &str = "x-impersonated-by";
+
+/// Mark responses to requests made with an impersonation token and audit
+/// every mutating one
+///
+/// Applied to the whole app, outside routing, so no route can be missed.
+/// Only the token's signature and expiry are checked here; whether the
+/// impersonation is still running is up to authentication, and requests it
+/// refuses are recorded all the same.
+pub async fn track_impersonation(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    req: Request,
+    next: Next,
+) -> Response {
+    let impersonation = bearer_token(req.headers())
+        .and_then(|token| jwt::decode_access_token(&state.config, token).ok())
+        .and_then(|claims| claims.act.map(|actor| (claims, actor)));
+    let Some((claims, actor)) = impersonation else {
+        return next.run(req).await;
+    };
+
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let mut response = next.run(req).await;
+
+    if let Ok(value) = HeaderValue::from_str(&actor.sub.to_string()) {
+        response.headers_mut().insert(IMPERSONATED_BY_HEADER, value);
+    }
+
+    if !matches!(method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        let status = response.status().as_u16();
+        if let Err(err) =
+            service::record_request(&state, &claims, actor, &method, &path, status, &client).await
+        {
+            tracing::error!("Failed to audit impersonated request: {}", err);
+        }
+    }
+
+    response
+}
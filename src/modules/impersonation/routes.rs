@@ -0,0 +1,19 @@
+use axum::{
+    middleware,
+    routing::{delete, post},
+    Router,
+};
+
+use crate::modules::auth::middleware::require_auth;
+use crate::state::AppState;
+
+use super::handler;
+
+pub fn impersonation_routes(state: AppState) -> Router<AppState> {
+    let auth = middleware::from_fn_with_state(state, require_auth);
+
+    Router::new()
+        .route("/", post(handler::start_impersonation))
+        .route("/{impersonation_id}", delete(handler::stop_impersonation))
+        .route_layer(auth)
+}
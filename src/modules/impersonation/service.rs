@@ -0,0 +1,570 @@
+use axum::http::Method;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::audit::service::{self as audit, AuditEvent};
+use crate::modules::auth::{
+    client::ClientInfo,
+    extractor::AuthUser,
+    jwt::{self, Actor, Claims},
+};
+use crate::modules::users::{entity as users, service as users_service};
+use crate::state::AppState;
+
+use super::entity::{self as impersonation, Entity as Impersonations};
+
+/// Longest reason kept for an impersonation
+const MAX_REASON_LEN: usize = 255;
+
+/// A started impersonation and the token to act with
+pub struct StartedImpersonation {
+    pub impersonation: impersonation::Model,
+    pub target: users::Model,
+    pub access_token: String,
+}
+
+/// Let a platform admin act as `target_id` until the impersonation expires
+/// or is stopped
+///
+/// The token carries the target as `sub` and the admin in `act`. Other
+/// platform admins cannot be impersonated, and neither can inactive users.
+pub async fn start(
+    state: &AppState,
+    admin: &AuthUser,
+    client: &ClientInfo,
+    target_id: Uuid,
+    reason: &str,
+) -> AppResult<StartedImpersonation> {
+    if admin.impersonator().is_some() {
+        return Err(AppError::Forbidden(
+            "Stop impersonating before starting another impersonation".to_string(),
+        ));
+    }
+    if !admin.user.is_platform_admin || admin.api_key.is_some() {
+        return Err(AppError::Forbidden(
+            "Only platform admins can impersonate users".to_string(),
+        ));
+    }
+    if admin.user.id == target_id {
+        return Err(AppError::BadRequest(
+            "You cannot impersonate yourself".to_string(),
+        ));
+    }
+
+    let reason = reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LEN {
+        return Err(AppError::ValidationError(format!(
+            "A reason of 1 to {} characters is required",
+            MAX_REASON_LEN
+        )));
+    }
+
+    let target = users_service::find_by_id(state, target_id).await?;
+    if !target.is_active {
+        return Err(AppError::BadRequest(
+            "Inactive users cannot be impersonated".to_string(),
+        ));
+    }
+    if target.is_service_account() {
+        return Err(AppError::BadRequest(
+            "Service accounts cannot be impersonated".to_string(),
+        ));
+    }
+    if target.is_platform_admin {
+        return Err(AppError::Forbidden(
+            "Platform admins cannot be impersonated".to_string(),
+        ));
+    }
+
+    let now = chrono::Utc::now().fixed_offset();
+    let expires_at = now + chrono::Duration::seconds(state.config.impersonation_ttl_secs);
+
+    let impersonation = impersonation::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        admin_id: Set(admin.user.id),
+        target_user_id: Set(target.id),
+        reason: Set(Some(reason.to_string())),
+        ip_address: Set(client.ip.map(|ip| ip.to_string())),
+        user_agent: Set(client.user_agent.clone()),
+        expires_at: Set(expires_at),
+        ended_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(AppError::from)?;
+
+    let claims = Claims {
+        sub: target.id,
+        iss: state.config.jwt_issuer.clone(),
+        iat: now.timestamp(),
+        exp: expires_at.timestamp(),
+        jti: impersonation.id,
+        org: None,
+        act: Some(Actor { sub: admin.user.id }),
+    };
+    let access_token = jwt::encode_claims(&state.config, &claims)?;
+
+    audit::record(
+        &state.db,
+        AuditEvent::new("impersonation.started")
+            .actor(admin.user.id)
+            .target_user(target.id)
+            .ip_address(client.ip)
+            .details(serde_json::json!({
+                "impersonation_id": impersonation.id,
+                "reason": reason,
+                "expires_at": expires_at,
+            })),
+    )
+    .await?;
+
+    Ok(StartedImpersonation {
+        impersonation,
+        target,
+        access_token,
+    })
+}
+
+/// Check that the impersonation behind a token is still running and its
+/// admin still allowed to impersonate
+pub async fn verify(state: &AppState, claims: &Claims, actor: Actor) -> AppResult<()> {
+    let ended = || AppError::Unauthorized("Impersonation has ended".to_string());
+
+    Impersonations::find_by_id(claims.jti)
+        .filter(impersonation::Column::AdminId.eq(actor.sub))
+        .filter(impersonation::Column::TargetUserId.eq(claims.sub))
+        .filter(impersonation::Column::EndedAt.is_null())
+        .filter(impersonation::Column::ExpiresAt.gt(chrono::Utc::now().fixed_offset()))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(ended)?;
+
+    let admin = users::Entity::find_by_id(actor.sub)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?;
+    if !admin.is_some_and(|admin| admin.is_active && admin.is_platform_admin) {
+        return Err(ended());
+    }
+
+    Ok(())
+}
+
+/// End an impersonation, either with its own token or by the admin who
+/// started it
+///
+/// Stopping one that already ended or expired succeeds without another
+/// audit record.
+pub async fn stop(
+    state: &AppState,
+    auth: &AuthUser,
+    client: &ClientInfo,
+    impersonation_id: Uuid,
+) -> AppResult<()> {
+    let admin_id = auth.impersonator().unwrap_or(auth.user.id);
+    let impersonation = Impersonations::find_by_id(impersonation_id)
+        .filter(impersonation::Column::AdminId.eq(admin_id))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| {
+            AppError::NotFound(format!(
+                "Impersonation with id {} not found",
+                impersonation_id
+            ))
+        })?;
+
+    let now = chrono::Utc::now().fixed_offset();
+    if impersonation.ended_at.is_some() || impersonation.expires_at <= now {
+        return Ok(());
+    }
+
+    let target_user_id = impersonation.target_user_id;
+    let mut active: impersonation::ActiveModel = impersonation.into();
+    active.ended_at = Set(Some(now));
+    active.update(&state.db).await.map_err(AppError::from)?;
+
+    audit::record(
+        &state.db,
+        AuditEvent::new("impersonation.stopped")
+            .actor(admin_id)
+            .target_user(target_user_id)
+            .ip_address(client.ip)
+            .details(serde_json::json!({ "impersonation_id": impersonation_id })),
+    )
+    .await
+}
+
+/// Record a mutating request made while impersonating, whatever its outcome
+pub async fn record_request(
+    state: &AppState,
+    claims: &Claims,
+    actor: Actor,
+    method: &Method,
+    path: &str,
+    status: u16,
+    client: &ClientInfo,
+) -> AppResult<()> {
+    audit::record(
+        &state.db,
+        AuditEvent::new("impersonation.request")
+            .actor(actor.sub)
+            .target_user(claims.sub)
+            .ip_address(client.ip)
+            .details(serde_json::json!({
+                "impersonation_id": claims.jti,
+                "method": method.as_str(),
+                "path": path,
+                "status": status,
+            })),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{header, StatusCode},
+        response::Response,
+    };
+    use sea_orm::QueryOrder;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::modules::audit::entity as audit_log;
+    use crate::modules::impersonation::middleware::IMPERSONATED_BY_HEADER;
+    use crate::modules::users::password::PasswordVerification;
+    use crate::test_support::{test_state, unique_email};
+
+    const PASSWORD: &str = "a long enough passphrase";
+
+    async fn create_user(state: &AppState, platform_admin: bool) -> users::Model {
+        let user = users_service::create_user(state, &unique_email(), "Test", PASSWORD.to_string())
+            .await
+            .unwrap();
+        let user = users_service::mark_email_verified(&state.db, user)
+            .await
+            .unwrap();
+        let mut active: users::ActiveModel = user.into();
+        active.is_platform_admin = Set(platform_admin);
+        active.update(&state.db).await.unwrap()
+    }
+
+    fn signed_in(state: &AppState, user: &users::Model) -> AuthUser {
+        AuthUser {
+            user: user.clone(),
+            claims: Claims::new(&state.config, user.id),
+            api_key: None,
+            session_id: None,
+        }
+    }
+
+    fn client() -> ClientInfo {
+        ClientInfo::default()
+    }
+
+    /// A platform admin impersonating a fresh user
+    async fn impersonate(state: &AppState) -> (users::Model, StartedImpersonation) {
+        let admin = create_user(state, true).await;
+        let target = create_user(state, false).await;
+        let started = start(
+            state,
+            &signed_in(state, &admin),
+            &client(),
+            target.id,
+            "Ticket #42",
+        )
+        .await
+        .unwrap();
+        (admin, started)
+    }
+
+    fn claims_of(state: &AppState, started: &StartedImpersonation) -> Claims {
+        jwt::decode_access_token(&state.config, &started.access_token).unwrap()
+    }
+
+    async fn send(
+        state: &AppState,
+        method: Method,
+        uri: &str,
+        token: &str,
+        body: Option<Value>,
+    ) -> Response {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token));
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+
+        crate::app::rust_saas(state.clone())
+            .oneshot(request.unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn audit_events(
+        state: &AppState,
+        action: &str,
+        target_user_id: Uuid,
+    ) -> Vec<audit_log::Model> {
+        audit_log::Entity::find()
+            .filter(audit_log::Column::Action.eq(action))
+            .filter(audit_log::Column::TargetId.eq(target_user_id))
+            .order_by_asc(audit_log::Column::CreatedAt)
+            .all(&state.db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn only_platform_admins_impersonate_other_users() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let admin = signed_in(&state, &create_user(&state, true).await);
+        let target = create_user(&state, false).await;
+
+        let by_user = signed_in(&state, &create_user(&state, false).await);
+        let result = start(&state, &by_user, &client(), target.id, "Ticket").await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+
+        let other_admin = create_user(&state, true).await;
+        let result = start(&state, &admin, &client(), other_admin.id, "Ticket").await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+
+        let result = start(&state, &admin, &client(), admin.user.id, "Ticket").await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        let result = start(&state, &admin, &client(), target.id, "  ").await;
+        assert!(matches!(result, Err(AppError::ValidationError(_))));
+
+        let mut inactive: users::ActiveModel = create_user(&state, false).await.into();
+        inactive.is_active = Set(false);
+        let inactive = inactive.update(&state.db).await.unwrap();
+        let result = start(&state, &admin, &client(), inactive.id, "Ticket").await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        // Impersonations do not nest
+        let started = start(&state, &admin, &client(), target.id, "Ticket")
+            .await
+            .unwrap();
+        let as_target = AuthUser {
+            claims: claims_of(&state, &started),
+            ..signed_in(&state, &started.target)
+        };
+        let result = start(&state, &as_target, &client(), target.id, "Ticket").await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn tokens_last_only_the_time_limit() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (admin, started) = impersonate(&state).await;
+
+        let claims = claims_of(&state, &started);
+        assert_eq!(claims.sub, started.target.id);
+        assert_eq!(claims.act.map(|actor| actor.sub), Some(admin.id));
+        assert_eq!(claims.jti, started.impersonation.id);
+        assert_eq!(claims.exp - claims.iat, state.config.impersonation_ttl_secs);
+        assert_eq!(claims.exp, started.impersonation.expires_at.timestamp());
+
+        let started_events = audit_events(&state, "impersonation.started", started.target.id).await;
+        assert_eq!(started_events.len(), 1);
+        assert_eq!(started_events[0].actor_id, Some(admin.id));
+        assert_eq!(
+            started_events[0].details.as_ref().unwrap()["reason"],
+            "Ticket #42"
+        );
+
+        // Expired in the database while the JWT itself would still pass
+        let mut expired: impersonation::ActiveModel = started.impersonation.clone().into();
+        expired.expires_at = Set(chrono::Utc::now().fixed_offset() - chrono::Duration::seconds(1));
+        expired.update(&state.db).await.unwrap();
+
+        let result = verify(&state, &claims, Actor { sub: admin.id }).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+        let response = send(
+            &state,
+            Method::GET,
+            "/api/users/me",
+            &started.access_token,
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn stopping_or_demoting_the_admin_ends_it() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (admin, started) = impersonate(&state).await;
+        let claims = claims_of(&state, &started);
+        let actor = Actor { sub: admin.id };
+        verify(&state, &claims, actor).await.unwrap();
+
+        let other_admin = signed_in(&state, &create_user(&state, true).await);
+        let result = stop(&state, &other_admin, &client(), started.impersonation.id).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+
+        // With the impersonation token itself, then again as a no-op
+        let as_target = AuthUser {
+            claims: claims.clone(),
+            ..signed_in(&state, &started.target)
+        };
+        stop(&state, &as_target, &client(), started.impersonation.id)
+            .await
+            .unwrap();
+        stop(
+            &state,
+            &signed_in(&state, &admin),
+            &client(),
+            started.impersonation.id,
+        )
+        .await
+        .unwrap();
+        let result = verify(&state, &claims, actor).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+        let stopped = audit_events(&state, "impersonation.stopped", started.target.id).await;
+        assert_eq!(stopped.len(), 1);
+
+        let (admin, started) = impersonate(&state).await;
+        let mut demoted: users::ActiveModel = admin.clone().into();
+        demoted.is_platform_admin = Set(false);
+        demoted.update(&state.db).await.unwrap();
+        let result = verify(
+            &state,
+            &claims_of(&state, &started),
+            Actor { sub: admin.id },
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn marks_responses_and_audits_mutating_requests() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (admin, started) = impersonate(&state).await;
+        let token = started.access_token.as_str();
+        let target_id = started.target.id;
+
+        let response = send(&state, Method::GET, "/api/users/me", token, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[IMPERSONATED_BY_HEADER],
+            admin.id.to_string().as_str()
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let me: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(me["id"], target_id.to_string());
+        assert!(audit_events(&state, "impersonation.request", target_id)
+            .await
+            .is_empty());
+
+        let rename = json!({ "name": "Renamed" });
+        let response = send(&state, Method::PATCH, "/api/users/me", token, Some(rename)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(IMPERSONATED_BY_HEADER));
+
+        let requests = audit_events(&state, "impersonation.request", target_id).await;
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].actor_id, Some(admin.id));
+        assert_eq!(
+            requests[0].details,
+            Some(json!({
+                "impersonation_id": started.impersonation.id,
+                "method": "PATCH",
+                "path": "/api/users/me",
+                "status": 200,
+            }))
+        );
+
+        // Ordinary tokens are neither marked nor audited
+        let own =
+            jwt::encode_claims(&state.config, &Claims::new(&state.config, target_id)).unwrap();
+        let response = send(
+            &state,
+            Method::PATCH,
+            "/api/users/me",
+            &own,
+            Some(json!({})),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(IMPERSONATED_BY_HEADER));
+        assert_eq!(
+            audit_events(&state, "impersonation.request", target_id)
+                .await
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_account_takeover_actions() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, started) = impersonate(&state).await;
+        let token = started.access_token.as_str();
+        let target_id = started.target.id;
+
+        let change_password = json!({
+            "current_password": PASSWORD,
+            "new_password": "a different long passphrase",
+        });
+        let response = send(
+            &state,
+            Method::PATCH,
+            "/api/users/me",
+            token,
+            Some(change_password),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let change_email = json!({ "email": unique_email(), "current_password": PASSWORD });
+        let response = send(
+            &state,
+            Method::POST,
+            "/api/users/me/email",
+            token,
+            Some(change_email),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let user = users_service::find_by_id(&state, target_id).await.unwrap();
+        let hash = user.password_hash.unwrap();
+        assert_eq!(
+            state.password_hasher.verify(PASSWORD, &hash).unwrap(),
+            PasswordVerification::Valid
+        );
+
+        // Refused requests are audited too
+        let statuses: Vec<_> = audit_events(&state, "impersonation.request", target_id)
+            .await
+            .into_iter()
+            .map(|event| event.details.unwrap()["status"].clone())
+            .collect();
+        assert_eq!(statuses, [json!(403), json!(403)]);
+    }
+}
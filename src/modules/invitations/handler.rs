@@ -0,0 +1,148 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::error::AppResult;
+use crate::modules::auth::extractor::AuthUser;
+use crate::modules::organizations::{
+    context::{Admin, OrgGuard},
+    membership::OrgRole,
+    tenant::TenantDb,
+};
+use crate::modules::users::handler::UserResponse;
+use crate::state::AppState;
+
+use super::entity;
+use super::service::{self, NewAccount};
+
+#[derive(serde::Deserialize)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    #[serde(default = "default_invitation_role")]
+    pub role: OrgRole,
+}
+
+fn default_invitation_role() -> OrgRole {
+    OrgRole::Member
+}
+
+#[derive(serde::Deserialize)]
+pub struct AcceptInvitationRequest {
+    pub token: String,
+    /// Required when no account exists for the invited email
+    pub name: Option<String>,
+    /// Required when no account exists for the invited email
+    pub password: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeclineInvitationRequest {
+    pub token: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct InvitationResponse {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub email: String,
+    pub role: OrgRole,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: chrono::DateTime<chrono::FixedOffset>,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl From<entity::Model> for InvitationResponse {
+    fn from(model: entity::Model) -> Self {
+        Self {
+            id: model.id,
+            organization_id: model.organization_id,
+            email: model.email,
+            role: model.role,
+            invited_by: model.invited_by,
+            expires_at: model.expires_at,
+            created_at: model.created_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct AcceptInvitationResponse {
+    pub organization_id: Uuid,
+    pub organization_name: String,
+    pub role: OrgRole,
+    pub user: UserResponse,
+}
+
+/// POST /api/orgs/:org_id/invitations
+pub async fn create_invitation(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    org: OrgGuard<Admin>,
+    Json(payload): Json<CreateInvitationRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.require_verified_email(&state.config)?;
+
+    let invitation = service::create_invitation(&state, &org, &payload.email, payload.role).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(InvitationResponse::from(invitation)),
+    ))
+}
+
+/// GET /api/orgs/:org_id/invitations
+pub async fn list_invitations(tenant: TenantDb) -> AppResult<impl IntoResponse> {
+    tenant.org.require(OrgRole::Admin)?;
+    let invitations = service::list_pending(&*tenant, tenant.org.org_id).await?;
+    let responses: Vec<InvitationResponse> = invitations
+        .into_iter()
+        .map(InvitationResponse::from)
+        .collect();
+
+    Ok(Json(responses))
+}
+
+/// DELETE /api/orgs/:org_id/invitations/:invitation_id
+pub async fn revoke_invitation(
+    tenant: TenantDb,
+    Path((_, invitation_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    tenant.org.require(OrgRole::Admin)?;
+    service::revoke(&*tenant, tenant.org.org_id, invitation_id).await?;
+    tenant.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/invitations/accept
+pub async fn accept_invitation(
+    State(state): State<AppState>,
+    Json(payload): Json<AcceptInvitationRequest>,
+) -> AppResult<impl IntoResponse> {
+    let new_account = match (payload.name, payload.password) {
+        (Some(name), Some(password)) => Some(NewAccount { name, password }),
+        _ => None,
+    };
+
+    let (organization, membership, user) =
+        service::accept(&state, &payload.token, new_account).await?;
+
+    Ok(Json(AcceptInvitationResponse {
+        organization_id: organization.id,
+        organization_name: organization.name,
+        role: membership.role,
+        user: UserResponse::from(user),
+    }))
+}
+
+/// POST /api/invitations/decline
+pub async fn decline_invitation(
+    State(state): State<AppState>,
+    Json(payload): Json<DeclineInvitationRequest>,
+) -> AppResult<impl IntoResponse> {
+    service::decline(&state, &payload.token).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,25 @@
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+
+use crate::state::AppState;
+
+use super::handler;
+
+/// Invitation management, nested under `/api/orgs/{org_id}/invitations`
+pub fn org_invitation_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(handler::list_invitations).post(handler::create_invitation),
+        )
+        .route("/{invitation_id}", delete(handler::revoke_invitation))
+}
+
+/// Public endpoints used by invitees, nested under `/api/invitations`
+pub fn invitation_routes() -> Router<AppState> {
+    Router::new()
+        .route("/accept", post(handler::accept_invitation))
+        .route("/decline", post(handler::decline_invitation))
+}
@@ -0,0 +1,543 @@
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::mailer::{
+    templates::{app_link, ActionEmail},
+    Email,
+};
+use crate::modules::auth::token;
+use crate::modules::organizations::{
+    context::OrgContext,
+    entity as organizations,
+    membership::{self, OrgRole},
+    service as organizations_service,
+};
+use crate::modules::users::{entity as users, service as users_service};
+use crate::state::AppState;
+
+use super::entity::{self as invitations, Entity as Invitations};
+
+/// Details for creating an account while accepting an invitation
+pub struct NewAccount {
+    pub name: String,
+    pub password: String,
+}
+
+/// Invite an email address to the actor's organization and mail the token
+///
+/// A pending invitation for the same address is revoked and replaced, which
+/// doubles as "resend".
+pub async fn create_invitation(
+    state: &AppState,
+    actor: &OrgContext,
+    email: &str,
+    role: OrgRole,
+) -> AppResult<invitations::Model> {
+    organizations_service::ensure_can_grant(actor, role)?;
+
+    let email = users_service::validate_email(email)?;
+
+    if let Some(user) = users_service::find_by_email(state, &email).await? {
+        if organizations_service::find_membership(&state.db, actor.org_id, user.id)
+            .await?
+            .is_some()
+        {
+            return Err(AppError::Conflict(
+                "User is already a member of this organization".to_string(),
+            ));
+        }
+    }
+
+    let organization = organizations_service::get_organization(&state.db, actor.org_id).await?;
+    let raw_token = token::generate_token();
+    let now = chrono::Utc::now().fixed_offset();
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    Invitations::update_many()
+        .col_expr(invitations::Column::RevokedAt, Expr::value(now))
+        .filter(invitations::Column::OrganizationId.eq(actor.org_id))
+        .filter(invitations::Column::Email.eq(email.clone()))
+        .filter(invitations::Column::AcceptedAt.is_null())
+        .filter(invitations::Column::DeclinedAt.is_null())
+        .filter(invitations::Column::RevokedAt.is_null())
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+
+    let invitation = invitations::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        organization_id: Set(actor.org_id),
+        email: Set(email),
+        role: Set(role),
+        token_hash: Set(token::hash_token(&raw_token)),
+        invited_by: Set(Some(actor.user_id)),
+        expires_at: Set(now + chrono::Duration::seconds(state.config.invitation_ttl_secs)),
+        accepted_at: Set(None),
+        declined_at: Set(None),
+        revoked_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(&txn)
+    .await
+    .map_err(AppError::from)?;
+
+    txn.commit().await.map_err(AppError::from)?;
+
+    state
+        .mailer
+        .send(invitation_email(
+            state,
+            &organization,
+            &invitation,
+            &raw_token,
+        ))
+        .await?;
+
+    Ok(invitation)
+}
+
+/// Pending (unanswered, unrevoked, unexpired) invitations of an organization
+pub async fn list_pending<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+) -> AppResult<Vec<invitations::Model>> {
+    Invitations::find()
+        .filter(invitations::Column::OrganizationId.eq(organization_id))
+        .filter(invitations::Column::AcceptedAt.is_null())
+        .filter(invitations::Column::DeclinedAt.is_null())
+        .filter(invitations::Column::RevokedAt.is_null())
+        .filter(invitations::Column::ExpiresAt.gt(chrono::Utc::now().fixed_offset()))
+        .order_by_desc(invitations::Column::CreatedAt)
+        .all(db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Revoke a pending invitation so its token can no longer be used
+pub async fn revoke<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    invitation_id: Uuid,
+) -> AppResult<()> {
+    let result = Invitations::update_many()
+        .col_expr(
+            invitations::Column::RevokedAt,
+            Expr::value(chrono::Utc::now().fixed_offset()),
+        )
+        .filter(invitations::Column::Id.eq(invitation_id))
+        .filter(invitations::Column::OrganizationId.eq(organization_id))
+        .filter(invitations::Column::AcceptedAt.is_null())
+        .filter(invitations::Column::DeclinedAt.is_null())
+        .filter(invitations::Column::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    if result.rows_affected == 0 {
+        return Err(AppError::NotFound(format!(
+            "Pending invitation with id {} not found",
+            invitation_id
+        )));
+    }
+
+    Ok(())
+}
+
+/// Accept an invitation, joining the organization
+///
+/// The invited address is attached to its existing account, or a new account
+/// is created from `new_account` when there is none.
+pub async fn accept(
+    state: &AppState,
+    raw_token: &str,
+    new_account: Option<NewAccount>,
+) -> AppResult<(organizations::Model, membership::Model, users::Model)> {
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let invitation = find_pending_for_update(&txn, raw_token).await?;
+
+    let user = match users_service::find_by_email_in(&txn, &invitation.email).await? {
+        Some(user) => user,
+        None => {
+            let account = new_account.ok_or(AppError::BadRequest(
+                "name and password are required to create an account".to_string(),
+            ))?;
+            let user = users_service::create_user_in(
+                state,
+                &txn,
+                &invitation.email,
+                &account.name,
+                account.password,
+                Some(invitation.organization_id),
+            )
+            .await?;
+
+            // The invitation token was mailed to this address
+            users_service::mark_email_verified(&txn, user).await?
+        }
+    };
+
+    if organizations_service::find_membership(&txn, invitation.organization_id, user.id)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Conflict(
+            "You are already a member of this organization".to_string(),
+        ));
+    }
+
+    let membership = organizations_service::insert_membership(
+        &txn,
+        invitation.organization_id,
+        user.id,
+        invitation.role,
+    )
+    .await?;
+    let organization =
+        organizations_service::get_organization(&txn, invitation.organization_id).await?;
+
+    let mut active: invitations::ActiveModel = invitation.into();
+    active.accepted_at = Set(Some(chrono::Utc::now().fixed_offset()));
+    active.update(&txn).await.map_err(AppError::from)?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok((organization, membership, user))
+}
+
+/// Decline an invitation
+pub async fn decline(state: &AppState, raw_token: &str) -> AppResult<()> {
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let invitation = find_pending_for_update(&txn, raw_token).await?;
+
+    let mut active: invitations::ActiveModel = invitation.into();
+    active.declined_at = Set(Some(chrono::Utc::now().fixed_offset()));
+    active.update(&txn).await.map_err(AppError::from)?;
+
+    txn.commit().await.map_err(AppError::from)
+}
+
+/// Find a pending invitation by token, locking it for the rest of the
+/// transaction so it cannot be answered twice concurrently
+async fn find_pending_for_update<C: ConnectionTrait>(
+    db: &C,
+    raw_token: &str,
+) -> AppResult<invitations::Model> {
+    let invalid = || AppError::BadRequest("Invalid or expired invitation".to_string());
+
+    let invitation = Invitations::find()
+        .filter(invitations::Column::TokenHash.eq(token::hash_token(raw_token)))
+        .lock_exclusive()
+        .one(db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(invalid)?;
+
+    if !invitation.is_pending(chrono::Utc::now().fixed_offset()) {
+        return Err(invalid());
+    }
+
+    Ok(invitation)
+}
+
+fn invitation_email(
+    state: &AppState,
+    organization: &organizations::Model,
+    invitation: &invitations::Model,
+    raw_token: &str,
+) -> Email {
+    ActionEmail {
+        subject: format!("You have been invited to join {}", organization.name),
+        heading: "You have been invited",
+        intro: format!(
+            "You have been invited to join {} as {:?}.",
+            organization.name, invitation.role
+        ),
+        action_label: "Accept invitation",
+        action_url: app_link(&state.config.app_base_url, "/invitations/accept", raw_token),
+        outro: format!(
+            "This invitation expires on {}. If you were not expecting it, you can ignore this email.",
+            invitation.expires_at.format("%B %-d, %Y")
+        ),
+    }
+    .render(&invitation.email)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mailer::InMemoryMailer;
+    use crate::test_support::{link_token, test_state, unique_email};
+
+    /// State mailing into memory, an organization and its owner's context, or
+    /// `None` without a test database
+    async fn setup() -> Option<(AppState, Arc<InMemoryMailer>, OrgContext)> {
+        let mailer = Arc::new(InMemoryMailer::new());
+        let state = test_state().await?.with_mailer(mailer.clone());
+        let owner = create_user(&state, &unique_email()).await;
+        let name = format!("Invitations {}", Uuid::new_v4().simple());
+        let (organization, _) =
+            organizations_service::create_organization(&state, &owner, &name, None)
+                .await
+                .unwrap();
+        let owner = OrgContext {
+            org_id: organization.id,
+            user_id: owner.id,
+            role: OrgRole::Owner,
+            custom_role_id: None,
+        };
+        Some((state, mailer, owner))
+    }
+
+    async fn create_user(state: &AppState, email: &str) -> users::Model {
+        users_service::create_passwordless_user_in(&state.db, Uuid::new_v4(), email, "Test")
+            .await
+            .unwrap()
+    }
+
+    /// Invite `email` and return the token mailed to it
+    async fn invite(
+        state: &AppState,
+        mailer: &InMemoryMailer,
+        actor: &OrgContext,
+        email: &str,
+        role: OrgRole,
+    ) -> String {
+        create_invitation(state, actor, email, role).await.unwrap();
+        link_token(&mailer.last_to(email).unwrap())
+    }
+
+    fn new_account() -> Option<NewAccount> {
+        Some(NewAccount {
+            name: "Invitee".to_string(),
+            password: "correct horse battery staple".to_string(),
+        })
+    }
+
+    fn is_invalid<T>(result: AppResult<T>) -> bool {
+        matches!(result, Err(AppError::BadRequest(message)) if message.contains("invitation"))
+    }
+
+    #[tokio::test]
+    async fn mails_a_pending_invitation() {
+        let Some((state, mailer, owner)) = setup().await else {
+            return;
+        };
+        let email = unique_email();
+
+        let invitation = create_invitation(&state, &owner, &format!(" {} ", email), OrgRole::Admin)
+            .await
+            .unwrap();
+        assert_eq!(invitation.email, email);
+        assert_eq!(invitation.role, OrgRole::Admin);
+        assert_eq!(invitation.invited_by, Some(owner.user_id));
+
+        let sent = mailer.last_to(&email).unwrap();
+        let organization = organizations_service::get_organization(&state.db, owner.org_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            sent.subject,
+            format!("You have been invited to join {}", organization.name)
+        );
+        assert_eq!(invitation.token_hash, token::hash_token(&link_token(&sent)));
+
+        let pending = list_pending(&state.db, owner.org_id).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, invitation.id);
+    }
+
+    #[tokio::test]
+    async fn refuses_invalid_email_addresses() {
+        let Some((state, mailer, owner)) = setup().await else {
+            return;
+        };
+
+        for email in ["@example.com", "invitee@localhost", "in vitee@example.com"] {
+            let result = create_invitation(&state, &owner, email, OrgRole::Member).await;
+            assert!(
+                matches!(result, Err(AppError::ValidationError(_))),
+                "{:?}",
+                email
+            );
+        }
+        assert!(mailer.sent().is_empty());
+        assert!(list_pending(&state.db, owner.org_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_admins_invite_and_only_owners_invite_owners() {
+        let Some((state, _, owner)) = setup().await else {
+            return;
+        };
+        let admin = OrgContext {
+            role: OrgRole::Admin,
+            ..owner.clone()
+        };
+        let member = OrgContext {
+            role: OrgRole::Member,
+            ..owner.clone()
+        };
+
+        let by_member = create_invitation(&state, &member, &unique_email(), OrgRole::Member).await;
+        assert!(matches!(by_member, Err(AppError::Forbidden(_))));
+        let owner_by_admin =
+            create_invitation(&state, &admin, &unique_email(), OrgRole::Owner).await;
+        assert!(matches!(owner_by_admin, Err(AppError::Forbidden(_))));
+        create_invitation(&state, &owner, &unique_email(), OrgRole::Owner)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn resending_revokes_the_previous_invitation() {
+        let Some((state, mailer, owner)) = setup().await else {
+            return;
+        };
+        let email = unique_email();
+
+        let first = invite(&state, &mailer, &owner, &email, OrgRole::Member).await;
+        let second = invite(&state, &mailer, &owner, &email, OrgRole::Member).await;
+        assert_ne!(first, second);
+        assert_eq!(
+            list_pending(&state.db, owner.org_id).await.unwrap().len(),
+            1
+        );
+
+        assert!(is_invalid(accept(&state, &first, new_account()).await));
+        accept(&state, &second, new_account()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn accepting_joins_an_existing_account() {
+        let Some((state, mailer, owner)) = setup().await else {
+            return;
+        };
+        let email = unique_email();
+        let user = create_user(&state, &email).await;
+        let token = invite(&state, &mailer, &owner, &email, OrgRole::Admin).await;
+
+        let (organization, membership, joined) = accept(&state, &token, None).await.unwrap();
+        assert_eq!(organization.id, owner.org_id);
+        assert_eq!(joined.id, user.id);
+        assert_eq!(membership.user_id, user.id);
+        assert_eq!(membership.role, OrgRole::Admin);
+        assert!(list_pending(&state.db, owner.org_id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Members cannot be invited again
+        let again = create_invitation(&state, &owner, &email, OrgRole::Member).await;
+        assert!(matches!(again, Err(AppError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn accepting_creates_a_verified_account() {
+        let Some((state, mailer, owner)) = setup().await else {
+            return;
+        };
+        let email = unique_email();
+        let token = invite(&state, &mailer, &owner, &email, OrgRole::Member).await;
+
+        // Without account details nothing changes and the token stays usable
+        let missing = accept(&state, &token, None).await;
+        assert!(matches!(missing, Err(AppError::BadRequest(_))));
+        assert!(users_service::find_by_email(&state, &email)
+            .await
+            .unwrap()
+            .is_none());
+
+        let (_, membership, user) = accept(&state, &token, new_account()).await.unwrap();
+        assert_eq!(user.email, email);
+        assert_eq!(user.name, "Invitee");
+        assert!(user.password_hash.is_some());
+        assert!(user.email_verified_at.is_some());
+        assert_eq!(membership.role, OrgRole::Member);
+
+        assert!(is_invalid(accept(&state, &token, None).await));
+    }
+
+    #[tokio::test]
+    async fn declined_and_revoked_invitations_cannot_be_used() {
+        let Some((state, mailer, owner)) = setup().await else {
+            return;
+        };
+
+        let email = unique_email();
+        let token = invite(&state, &mailer, &owner, &email, OrgRole::Member).await;
+        decline(&state, &token).await.unwrap();
+        assert!(is_invalid(accept(&state, &token, new_account()).await));
+        assert!(is_invalid(decline(&state, &token).await));
+
+        let email = unique_email();
+        let invitation = create_invitation(&state, &owner, &email, OrgRole::Member)
+            .await
+            .unwrap();
+        let token = link_token(&mailer.last_to(&email).unwrap());
+        let other_org = revoke(&state.db, Uuid::new_v4(), invitation.id).await;
+        assert!(matches!(other_org, Err(AppError::NotFound(_))));
+        revoke(&state.db, owner.org_id, invitation.id)
+            .await
+            .unwrap();
+        assert!(is_invalid(accept(&state, &token, new_account()).await));
+
+        assert!(is_invalid(accept(&state, "made-up", new_account()).await));
+    }
+
+    #[tokio::test]
+    async fn expired_invitations_cannot_be_used() {
+        let Some((state, mailer, owner)) = setup().await else {
+            return;
+        };
+        let email = unique_email();
+        let invitation = create_invitation(&state, &owner, &email, OrgRole::Member)
+            .await
+            .unwrap();
+        let token = link_token(&mailer.last_to(&email).unwrap());
+
+        let mut expired: invitations::ActiveModel = invitation.into();
+        expired.expires_at = Set(chrono::Utc::now().fixed_offset() - chrono::Duration::seconds(1));
+        expired.update(&state.db).await.unwrap();
+
+        assert!(list_pending(&state.db, owner.org_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(is_invalid(accept(&state, &token, new_account()).await));
+    }
+
+    #[tokio::test]
+    async fn concurrent_accepts_join_once() {
+        let Some((state, mailer, owner)) = setup().await else {
+            return;
+        };
+        let email = unique_email();
+        let user = create_user(&state, &email).await;
+        let token = invite(&state, &mailer, &owner, &email, OrgRole::Member).await;
+
+        // The row lock makes the second accept wait and then see the first
+        let (a, b) = tokio::join!(accept(&state, &token, None), accept(&state, &token, None));
+        assert_eq!([&a, &b].iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(is_invalid(if a.is_ok() { b } else { a }));
+
+        let members = organizations_service::list_members(&state.db, owner.org_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            members
+                .iter()
+                .filter(|(membership, _)| membership.user_id == user.id)
+                .count(),
+            1
+        );
+    }
+}
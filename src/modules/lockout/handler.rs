@@ -0,0 +1,43 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::error::AppResult;
+use crate::modules::auth::{client::ClientInfo, extractor::AuthUser};
+use crate::state::AppState;
+
+use super::service;
+
+#[derive(serde::Deserialize)]
+pub struct UnlockAccountRequest {
+    pub token: String,
+}
+
+/// POST /api/auth/unlock
+///
+/// Redeems the link mailed when an account is locked.
+pub async fn unlock_account(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    Json(payload): Json<UnlockAccountRequest>,
+) -> AppResult<impl IntoResponse> {
+    service::unlock_with_token(&state, &payload.token, &client).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/admin/users/:user_id/unlock
+///
+/// Platform admins only.
+pub async fn unlock_user(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    client: ClientInfo,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    service::unlock_user(&state, &auth, user_id, &client).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,21 @@
+use axum::{middleware, routing::post, Router};
+
+use crate::modules::auth::middleware::require_auth;
+use crate::state::AppState;
+
+use super::handler;
+
+/// Unlocking with the emailed link, nested under `/api/auth/unlock`
+pub fn unlock_auth_routes() -> Router<AppState> {
+    Router::new().route("/", post(handler::unlock_account))
+}
+
+/// Unlocking an account as a platform admin, nested under
+/// `/api/admin/users`
+pub fn admin_unlock_routes(state: AppState) -> Router<AppState> {
+    let auth = middleware::from_fn_with_state(state, require_auth);
+
+    Router::new()
+        .route("/{user_id}/unlock", post(handler::unlock_user))
+        .route_layer(auth)
+}
@@ -0,0 +1,657 @@
+use std::net::IpAddr;
+
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, sea_query::Expr, ColumnTrait, Condition, ConnectionTrait,
+    DbBackend, EntityTrait, QueryFilter, Statement, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::mailer::templates::{app_link, ActionEmail};
+use crate::modules::audit::service::{self as audit, AuditEvent};
+use crate::modules::auth::{client::ClientInfo, extractor::AuthUser};
+use crate::modules::user_tokens::{entity::TokenPurpose, service as user_tokens};
+use crate::modules::users::{entity as users, service as users_service};
+use crate::state::AppState;
+
+use super::entity::{self as throttle, Entity as Throttles, ThrottleScope};
+
+/// Longest delay expressed as a power of two, well past any sane maximum
+const MAX_DELAY_EXPONENT: u32 = 20;
+
+/// A sign-in attempt, counted against the account it targets and the
+/// address it comes from
+#[derive(Clone, Debug)]
+pub struct LoginAttempt {
+    account: String,
+    ip: Option<IpAddr>,
+}
+
+impl LoginAttempt {
+    pub fn new(email: &str, client: &ClientInfo) -> Self {
+        Self {
+            account: users_service::normalize_email(email),
+            ip: client.ip,
+        }
+    }
+
+    fn keys(&self) -> impl Iterator<Item = (ThrottleScope, String)> + '_ {
+        std::iter::once((ThrottleScope::Account, self.account.clone()))
+            .chain(self.ip.map(|ip| (ThrottleScope::Ip, ip.to_string())))
+    }
+}
+
+fn too_many_attempts(retry_after_secs: i64) -> AppError {
+    AppError::TooManyRequests {
+        message: "Too many failed sign-in attempts, try again later".to_string(),
+        retry_after_secs: retry_after_secs.max(1) as u64,
+    }
+}
+
+/// Whole seconds until `until`, rounded up
+fn secs_until(until: DateTimeWithTimeZone, now: DateTimeWithTimeZone) -> i64 {
+    ((until - now).num_milliseconds() + 999) / 1000
+}
+
+/// How long an account has to wait after its latest failure, doubling with
+/// each failure past the delay threshold
+fn delay_secs(state: &AppState, failures: i32) -> Option<i64> {
+    let threshold = state.config.login_delay_threshold as i32;
+    if threshold == 0 || failures < threshold {
+        return None;
+    }
+    let exponent = ((failures - threshold) as u32).min(MAX_DELAY_EXPONENT);
+    Some((1i64 << exponent).min(state.config.login_max_delay_secs))
+}
+
+fn lockout_threshold(state: &AppState, scope: ThrottleScope) -> u32 {
+    match scope {
+        ThrottleScope::Account => state.config.login_lockout_threshold,
+        ThrottleScope::Ip => state.config.login_ip_lockout_threshold,
+    }
+}
+
+/// Fail with `429 Too Many Requests` while the account or address is locked,
+/// or the account has to wait out a delay after its latest failure
+///
+/// Runs before credentials are checked, the same way for registered and
+/// unknown emails.
+pub async fn check(state: &AppState, attempt: &LoginAttempt) -> AppResult<()> {
+    let now = chrono::Utc::now().fixed_offset();
+    let window_start = now - chrono::Duration::seconds(state.config.login_failure_window_secs);
+
+    let rows = Throttles::find()
+        .filter(
+            attempt
+                .keys()
+                .fold(Condition::any(), |condition, (scope, key)| {
+                    condition.add(
+                        Condition::all()
+                            .add(throttle::Column::Scope.eq(scope))
+                            .add(throttle::Column::Key.eq(key)),
+                    )
+                }),
+        )
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    let mut retry_after = 0;
+    for row in rows {
+        match row.locked_until {
+            Some(locked_until) if locked_until > now => {
+                retry_after = retry_after.max(secs_until(locked_until, now));
+            }
+            // The lock has run out; the next failure starts counting afresh
+            Some(_) => {}
+            None if row.scope == ThrottleScope::Account && row.last_failure_at >= window_start => {
+                if let Some(delay) = delay_secs(state, row.failures) {
+                    let wait_until = row.last_failure_at + chrono::Duration::seconds(delay);
+                    if wait_until > now {
+                        retry_after = retry_after.max(secs_until(wait_until, now));
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    if retry_after > 0 {
+        return Err(too_many_attempts(retry_after));
+    }
+    Ok(())
+}
+
+/// Count a failed sign-in against the account and address, locking either
+/// once it reaches its threshold
+pub async fn record_failure(state: &AppState, attempt: &LoginAttempt) -> AppResult<()> {
+    let now = chrono::Utc::now().fixed_offset();
+    let window_start = now - chrono::Duration::seconds(state.config.login_failure_window_secs);
+
+    // Any email can be tried, so rows are created for addresses nobody owns;
+    // those that no longer count are cleaned up here rather than by a job
+    Throttles::delete_many()
+        .filter(throttle::Column::LastFailureAt.lt(window_start))
+        .filter(
+            Condition::any()
+                .add(throttle::Column::LockedUntil.is_null())
+                .add(throttle::Column::LockedUntil.lte(now)),
+        )
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    for (scope, key) in attempt.keys() {
+        // Failures older than the window, or from before a lapsed lock, are
+        // forgotten rather than added to
+        let row = Throttles::find()
+            .from_raw_sql(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                r#"INSERT INTO login_throttles (scope, key, failures, last_failure_at, locked_until)
+                VALUES ($1, $2, 1, $3, NULL)
+                ON CONFLICT (scope, key) DO UPDATE SET
+                    failures = CASE
+                        WHEN login_throttles.last_failure_at < $4
+                            OR login_throttles.locked_until <= $3 THEN 1
+                        ELSE login_throttles.failures + 1
+                    END,
+                    locked_until = CASE
+                        WHEN login_throttles.locked_until <= $3 THEN NULL
+                        ELSE login_throttles.locked_until
+                    END,
+                    last_failure_at = $3
+                RETURNING *"#,
+                [
+                    scope.into(),
+                    key.clone().into(),
+                    now.into(),
+                    window_start.into(),
+                ],
+            ))
+            .one(&state.db)
+            .await
+            .map_err(AppError::from)?
+            .ok_or_else(|| AppError::Internal(anyhow::anyhow!("upsert returned no row")))?;
+
+        let threshold = lockout_threshold(state, scope);
+        if threshold == 0 || row.failures < threshold as i32 || row.locked_until.is_some() {
+            continue;
+        }
+
+        let locked_until = now + chrono::Duration::seconds(state.config.login_lockout_secs);
+        let result = Throttles::update_many()
+            .col_expr(throttle::Column::LockedUntil, Expr::value(locked_until))
+            .filter(throttle::Column::Scope.eq(scope))
+            .filter(throttle::Column::Key.eq(key.as_str()))
+            .filter(throttle::Column::LockedUntil.is_null())
+            .exec(&state.db)
+            .await
+            .map_err(AppError::from)?;
+
+        // Concurrent failures may race to the threshold; only one reports it
+        if result.rows_affected == 1 {
+            match scope {
+                ThrottleScope::Account => account_locked(state, attempt, row.failures).await?,
+                ThrottleScope::Ip => {
+                    audit::record(
+                        &state.db,
+                        AuditEvent::new("ip.blocked")
+                            .ip_address(attempt.ip)
+                            .details(serde_json::json!({ "failures": row.failures })),
+                    )
+                    .await?
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Forget the account's failures after a successful sign-in
+///
+/// Failures from the address are left to expire, so one valid account does
+/// not clear the way for guessing at others.
+pub async fn record_success(state: &AppState, attempt: &LoginAttempt) -> AppResult<()> {
+    clear_account(&state.db, &attempt.account).await
+}
+
+/// Audit a locked account and, if it is registered, mail its owner a link
+/// to unlock it early
+async fn account_locked(state: &AppState, attempt: &LoginAttempt, failures: i32) -> AppResult<()> {
+    let user = users_service::find_by_email(state, &attempt.account).await?;
+
+    let mut event = AuditEvent::new("account.locked")
+        .ip_address(attempt.ip)
+        .details(serde_json::json!({
+            "email": attempt.account,
+            "failures": failures,
+        }));
+    if let Some(user) = &user {
+        event = event.target_user(user.id);
+    }
+    audit::record(&state.db, event).await?;
+
+    let Some(user) = user.filter(|user| user.is_active) else {
+        return Ok(());
+    };
+    send_unlock_email(state, user).await
+}
+
+async fn send_unlock_email(state: &AppState, user: users::Model) -> AppResult<()> {
+    let token = user_tokens::issue(
+        &state.db,
+        user.id,
+        TokenPurpose::AccountUnlock,
+        None,
+        state.config.account_unlock_token_ttl_secs,
+    )
+    .await?;
+
+    let email = ActionEmail {
+        subject: "Your account has been locked".to_string(),
+        heading: "Your account has been locked",
+        intro: format!(
+            "Hi {}, we locked your account after too many failed sign-in attempts. \
+             It unlocks by itself in {} minutes, or right away with the link below.",
+            user.name,
+            (state.config.login_lockout_secs + 59) / 60
+        ),
+        action_label: "Unlock my account",
+        action_url: app_link(&state.config.app_base_url, "/unlock-account", &token),
+        outro: "If these attempts were not yours, consider changing your password once you are back in."
+            .to_string(),
+    }
+    .render(&user.email);
+
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(err) = mailer.send(email).await {
+            tracing::warn!(
+                "Failed to send account unlock email to user {}: {}",
+                user.id,
+                err
+            );
+        }
+    });
+
+    Ok(())
+}
+
+async fn clear_account<C: ConnectionTrait>(db: &C, account: &str) -> AppResult<()> {
+    Throttles::delete_many()
+        .filter(throttle::Column::Scope.eq(ThrottleScope::Account))
+        .filter(throttle::Column::Key.eq(account))
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+/// Redeem an unlock link from the lockout email
+pub async fn unlock_with_token(
+    state: &AppState,
+    raw_token: &str,
+    client: &ClientInfo,
+) -> AppResult<()> {
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    let token = user_tokens::consume(&txn, TokenPurpose::AccountUnlock, raw_token).await?;
+    let user = users_service::find_by_id(state, token.user_id).await?;
+
+    clear_account(&txn, &users_service::normalize_email(&user.email)).await?;
+    audit::record(
+        &txn,
+        AuditEvent::new("account.unlocked")
+            .actor(user.id)
+            .target_user(user.id)
+            .ip_address(client.ip)
+            .details(serde_json::json!({ "via": "email" })),
+    )
+    .await?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok(())
+}
+
+/// Unlock an account on behalf of a platform admin
+///
+/// Lockout protects the account everywhere it is used, so no single
+/// organization may lift it; owners of the account use the emailed link.
+pub async fn unlock_user(
+    state: &AppState,
+    admin: &AuthUser,
+    user_id: Uuid,
+    client: &ClientInfo,
+) -> AppResult<()> {
+    admin.forbid_impersonation()?;
+    if !admin.user.is_platform_admin || admin.api_key.is_some() {
+        return Err(AppError::Forbidden(
+            "Only platform admins can unlock accounts".to_string(),
+        ));
+    }
+    let user = users_service::find_by_id(state, user_id).await?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    clear_account(&txn, &users_service::normalize_email(&user.email)).await?;
+    audit::record(
+        &txn,
+        AuditEvent::new("account.unlocked")
+            .actor(admin.user.id)
+            .target_user(user.id)
+            .ip_address(client.ip)
+            .details(serde_json::json!({ "via": "admin" })),
+    )
+    .await?;
+    txn.commit().await.map_err(AppError::from)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use sea_orm::{ActiveModelTrait, DatabaseConnection, Set};
+
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::modules::auth::jwt::{Actor, Claims};
+    use crate::test_support::{test_config, test_db, unique_email};
+
+    fn state_with(configure: impl FnOnce(&mut AppConfig)) -> AppState {
+        let mut config = test_config();
+        configure(&mut config);
+        AppState::new(DatabaseConnection::Disconnected, config)
+    }
+
+    /// State on the test database with delays off and the given lockout
+    /// thresholds, or `None` without one
+    async fn lockout_state(account_threshold: u32, ip_threshold: u32) -> Option<AppState> {
+        let mut config = test_config();
+        config.login_delay_threshold = 0;
+        config.login_lockout_threshold = account_threshold;
+        config.login_ip_lockout_threshold = ip_threshold;
+        Some(AppState::new(test_db().await?, config))
+    }
+
+    /// An address no other test signs in from
+    fn unique_ip() -> IpAddr {
+        let [a, b, c, ..] = *Uuid::new_v4().as_bytes();
+        IpAddr::V4(Ipv4Addr::new(10, a, b, c))
+    }
+
+    fn attempt(email: &str, ip: Option<IpAddr>) -> LoginAttempt {
+        LoginAttempt::new(
+            email,
+            &ClientInfo {
+                ip,
+                user_agent: None,
+            },
+        )
+    }
+
+    fn retry_after(result: AppResult<()>) -> u64 {
+        match result {
+            Err(AppError::TooManyRequests {
+                retry_after_secs, ..
+            }) => retry_after_secs,
+            other => panic!("expected TooManyRequests, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn doubles_delay_past_the_threshold() {
+        let state = state_with(|config| {
+            config.login_delay_threshold = 3;
+            config.login_max_delay_secs = 60;
+        });
+
+        let delays: Vec<_> = (0..=10)
+            .map(|failures| delay_secs(&state, failures))
+            .collect();
+        assert_eq!(
+            delays,
+            [
+                None,
+                None,
+                None,
+                Some(1),
+                Some(2),
+                Some(4),
+                Some(8),
+                Some(16),
+                Some(32),
+                Some(60),
+                Some(60)
+            ]
+        );
+    }
+
+    #[test]
+    fn caps_the_exponent_for_long_runs_of_failures() {
+        let state = state_with(|config| {
+            config.login_delay_threshold = 1;
+            config.login_max_delay_secs = i64::MAX;
+        });
+
+        assert_eq!(delay_secs(&state, 1_000), Some(1 << MAX_DELAY_EXPONENT));
+        assert_eq!(delay_secs(&state, i32::MAX), Some(1 << MAX_DELAY_EXPONENT));
+    }
+
+    #[test]
+    fn zero_threshold_disables_delays() {
+        let state = state_with(|config| config.login_delay_threshold = 0);
+
+        assert_eq!(delay_secs(&state, 0), None);
+        assert_eq!(delay_secs(&state, 100), None);
+    }
+
+    #[test]
+    fn rounds_waits_up_to_whole_seconds() {
+        let now = chrono::Utc::now().fixed_offset();
+
+        assert_eq!(secs_until(now + chrono::Duration::seconds(2), now), 2);
+        assert_eq!(secs_until(now + chrono::Duration::milliseconds(1), now), 1);
+        assert_eq!(
+            secs_until(now + chrono::Duration::milliseconds(1001), now),
+            2
+        );
+        assert_eq!(secs_until(now, now), 0);
+    }
+
+    #[test]
+    fn counts_attempts_against_the_normalized_account() {
+        let ip = unique_ip();
+        let keys: Vec<_> = attempt("  Someone@Example.COM ", Some(ip)).keys().collect();
+
+        assert_eq!(
+            keys,
+            [
+                (ThrottleScope::Account, "someone@example.com".to_string()),
+                (ThrottleScope::Ip, ip.to_string()),
+            ]
+        );
+        assert_eq!(attempt("someone@example.com", None).keys().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn delays_the_account_after_a_failure() {
+        let mut config = test_config();
+        config.login_delay_threshold = 1;
+        config.login_lockout_threshold = 0;
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let state = AppState::new(db, config);
+        let attempt = attempt(&unique_email(), None);
+
+        check(&state, &attempt).await.unwrap();
+        record_failure(&state, &attempt).await.unwrap();
+
+        assert_eq!(retry_after(check(&state, &attempt).await), 1);
+    }
+
+    #[tokio::test]
+    async fn locks_the_account_at_its_threshold() {
+        let Some(state) = lockout_state(3, 0).await else {
+            return;
+        };
+        let attempt = attempt(&unique_email(), None);
+
+        for _ in 0..2 {
+            record_failure(&state, &attempt).await.unwrap();
+            check(&state, &attempt).await.unwrap();
+        }
+        record_failure(&state, &attempt).await.unwrap();
+
+        let retry_after = retry_after(check(&state, &attempt).await);
+        assert!(
+            retry_after > 0 && retry_after <= state.config.login_lockout_secs as u64,
+            "{}",
+            retry_after
+        );
+    }
+
+    #[tokio::test]
+    async fn success_clears_the_account_but_not_the_address() {
+        let Some(state) = lockout_state(2, 2).await else {
+            return;
+        };
+        let email = unique_email();
+        let ip = unique_ip();
+
+        record_failure(&state, &attempt(&email, Some(ip)))
+            .await
+            .unwrap();
+        record_success(&state, &attempt(&email, Some(ip)))
+            .await
+            .unwrap();
+        // The account starts counting afresh, the address does not
+        record_failure(&state, &attempt(&email, None))
+            .await
+            .unwrap();
+        check(&state, &attempt(&email, None)).await.unwrap();
+
+        record_failure(&state, &attempt(&unique_email(), Some(ip)))
+            .await
+            .unwrap();
+        retry_after(check(&state, &attempt(&unique_email(), Some(ip))).await);
+    }
+
+    #[tokio::test]
+    async fn locks_an_address_guessing_at_many_accounts() {
+        let Some(state) = lockout_state(0, 3).await else {
+            return;
+        };
+        let ip = unique_ip();
+
+        for _ in 0..3 {
+            let attempt = attempt(&unique_email(), Some(ip));
+            check(&state, &attempt).await.unwrap();
+            record_failure(&state, &attempt).await.unwrap();
+        }
+
+        retry_after(check(&state, &attempt(&unique_email(), Some(ip))).await);
+        check(&state, &attempt(&unique_email(), Some(unique_ip())))
+            .await
+            .unwrap();
+    }
+
+    /// A signed-in user, made a platform admin if `platform_admin`
+    async fn signed_in(state: &AppState, platform_admin: bool) -> AuthUser {
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "User",
+        )
+        .await
+        .unwrap();
+        let mut active: users::ActiveModel = user.into();
+        active.is_platform_admin = Set(platform_admin);
+        let user = active.update(&state.db).await.unwrap();
+
+        AuthUser {
+            claims: Claims::new(&state.config, user.id),
+            user,
+            api_key: None,
+            session_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn prunes_failures_that_no_longer_count() {
+        let Some(state) = lockout_state(0, 0).await else {
+            return;
+        };
+        let now = chrono::Utc::now().fixed_offset();
+        let long_ago = now - chrono::Duration::seconds(state.config.login_failure_window_secs + 1);
+        let row = |key: &str, locked_until| throttle::ActiveModel {
+            scope: Set(ThrottleScope::Account),
+            key: Set(key.to_string()),
+            failures: Set(1),
+            last_failure_at: Set(long_ago),
+            locked_until: Set(locked_until),
+        };
+        let (stale, expired_lock, locked) = (unique_email(), unique_email(), unique_email());
+        row(&stale, None).insert(&state.db).await.unwrap();
+        row(&expired_lock, Some(now - chrono::Duration::seconds(1)))
+            .insert(&state.db)
+            .await
+            .unwrap();
+        row(&locked, Some(now + chrono::Duration::hours(1)))
+            .insert(&state.db)
+            .await
+            .unwrap();
+
+        record_failure(&state, &attempt(&unique_email(), None))
+            .await
+            .unwrap();
+
+        let remaining = Throttles::find()
+            .filter(throttle::Column::Key.is_in([stale, expired_lock, locked.clone()]))
+            .all(&state.db)
+            .await
+            .unwrap();
+        assert_eq!(
+            remaining.into_iter().map(|row| row.key).collect::<Vec<_>>(),
+            [locked]
+        );
+    }
+
+    #[tokio::test]
+    async fn only_platform_admins_unlock_accounts() {
+        let Some(state) = lockout_state(1, 0).await else {
+            return;
+        };
+        let client = ClientInfo {
+            ip: None,
+            user_agent: None,
+        };
+        let target = signed_in(&state, false).await;
+        let attempt = attempt(&target.user.email, None);
+        record_failure(&state, &attempt).await.unwrap();
+
+        let member = signed_in(&state, false).await;
+        let result = unlock_user(&state, &member, target.user.id, &client).await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+
+        // An admin impersonating someone acts as them, not as an admin
+        let admin = signed_in(&state, true).await;
+        let mut impersonating = signed_in(&state, true).await;
+        impersonating.claims.act = Some(Actor { sub: admin.user.id });
+        let result = unlock_user(&state, &impersonating, target.user.id, &client).await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+        retry_after(check(&state, &attempt).await);
+
+        unlock_user(&state, &admin, target.user.id, &client)
+            .await
+            .unwrap();
+        check(&state, &attempt).await.unwrap();
+
+        let result = unlock_user(&state, &admin, Uuid::new_v4(), &client).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+}
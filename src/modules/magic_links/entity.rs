@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Sign-in links requested for an email in the current window
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "magic_link_throttles")]
+pub struct Model {
+    /// Normalized email, registered or not
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub email: String,
+    pub requests: i32,
+    pub window_started_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,81 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::{
+    handler::{AuthResponse, MfaChallengeResponse},
+    verification,
+};
+use crate::modules::mfa::service as mfa_service;
+use crate::modules::sessions::mode::SignInMode;
+use crate::state::AppState;
+
+use super::service;
+
+#[derive(serde::Deserialize)]
+pub struct MagicLinkRequest {
+    pub email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct MagicLinkSignInRequest {
+    /// The token from the emailed link
+    pub token: String,
+    /// The nonce returned when the link was requested
+    pub nonce: String,
+}
+
+/// Returned for every request, whether or not a link was sent
+#[derive(serde::Serialize)]
+pub struct MagicLinkResponse {
+    /// Keep in the browser and send back with the link's token; the link
+    /// does not work without it
+    pub nonce: String,
+    pub expires_in: i64,
+}
+
+/// POST /api/auth/magic-link
+///
+/// Always answers 202 so the response does not reveal whether the email is
+/// registered, or 429 once the email has requested too many links.
+pub async fn request_link(
+    State(state): State<AppState>,
+    Json(payload): Json<MagicLinkRequest>,
+) -> AppResult<impl IntoResponse> {
+    let nonce = service::request_link(&state, &payload.email).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(MagicLinkResponse {
+            nonce,
+            expires_in: state.config.magic_link_ttl_secs,
+        }),
+    ))
+}
+
+/// POST /api/auth/magic-link/verify
+///
+/// Accounts that need a second factor get an [`MfaChallengeResponse`] like
+/// a password login.
+pub async fn sign_in(
+    State(state): State<AppState>,
+    mode: SignInMode,
+    Json(payload): Json<MagicLinkSignInRequest>,
+) -> AppResult<Response> {
+    let user = service::redeem(&state, &payload.token, &payload.nonce).await?;
+
+    if !user.is_active {
+        return Err(AppError::Forbidden("Account is disabled".to_string()));
+    }
+    verification::ensure_sign_in_allowed(&state.config, &user)?;
+
+    if mfa_service::requires_challenge(&state.db, user.id).await? {
+        return Ok(Json(MfaChallengeResponse::start(&state, user.id).await?).into_response());
+    }
+
+    let (cookies, response) = AuthResponse::sign_in(&state, &mode, user).await?;
+    Ok((cookies, Json(response)).into_response())
+}
@@ -0,0 +1,13 @@
+use axum::{routing::post, Router};
+
+use crate::state::AppState;
+
+use super::handler;
+
+/// Passwordless sign-in with an emailed link, nested under
+/// `/api/auth/magic-link`
+pub fn magic_link_auth_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", post(handler::request_link))
+        .route("/verify", post(handler::sign_in))
+}
@@ -0,0 +1,362 @@
+//! Passwordless sign-in with a link mailed to the account's address
+//!
+//! Each request hands the browser a random nonce and mails a single-use link
+//! whose token only signs in together with that nonce, so a link that leaks
+//! or is opened elsewhere is useless. The deployment turns the feature on,
+//! and any organization can turn it off for its members.
+
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DbBackend, EntityTrait, JoinType, PaginatorTrait, QueryFilter,
+    QuerySelect, RelationTrait, Statement, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::mailer::templates::{app_link, ActionEmail};
+use crate::modules::auth::token;
+use crate::modules::organizations::{
+    entity::{self as organizations, Entity as Organizations},
+    membership,
+};
+use crate::modules::user_tokens::{entity::TokenPurpose, service as user_tokens};
+use crate::modules::users::{entity as users, service as users_service};
+use crate::state::AppState;
+
+use super::entity::Entity as Throttles;
+
+/// Ask for a sign-in link to be mailed to `email`, returning the nonce the
+/// requesting browser must present with the link's token
+///
+/// Requests are counted per email whether or not it is registered, and
+/// unknown, disabled and service accounts, as well as members of
+/// organizations that turned the feature off, get no email, so the response
+/// does not reveal which emails have accounts. Its timing may: only real
+/// accounts are checked against their organizations and get a token stored.
+pub async fn request_link(state: &AppState, email: &str) -> AppResult<String> {
+    ensure_enabled(state)?;
+    count_request(state, email).await?;
+
+    let nonce = token::generate_token();
+
+    let Some(user) = users_service::find_by_email(state, email).await? else {
+        return Ok(nonce);
+    };
+    if !user.is_active || user.is_service_account() {
+        return Ok(nonce);
+    }
+    if !allowed_for_user(&state.db, user.id).await? {
+        tracing::info!(
+            "Not sending a sign-in link to user {}: an organization turned them off",
+            user.id
+        );
+        return Ok(nonce);
+    }
+
+    // Issuing invalidates the user's previous link
+    let token = user_tokens::issue(
+        &state.db,
+        user.id,
+        TokenPurpose::MagicLink,
+        Some(token::hash_token(&nonce)),
+        state.config.magic_link_ttl_secs,
+    )
+    .await?;
+
+    let email = ActionEmail {
+        subject: "Your sign-in link".to_string(),
+        heading: "Sign in to your account",
+        intro: format!(
+            "Hi {}, use the link below to sign in. It works once, for {} minutes, \
+             in the browser where you asked for it.",
+            user.name,
+            (state.config.magic_link_ttl_secs + 59) / 60
+        ),
+        action_label: "Sign in",
+        action_url: app_link(&state.config.app_base_url, "/auth/magic-link", &token),
+        outro: "If you did not ask for this, you can ignore this email; nobody can sign in without opening the link."
+            .to_string(),
+    }
+    .render(&user.email);
+
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(err) = mailer.send(email).await {
+            tracing::warn!("Failed to send sign-in link to user {}: {}", user.id, err);
+        }
+    });
+
+    Ok(nonce)
+}
+
+/// Redeem a sign-in link, returning the user it signs in
+///
+/// A token presented with the wrong nonce is left unused, so opening the
+/// link in another browser does not burn it. Redeeming it proves the user
+/// received mail at their address, which counts as verifying it.
+pub async fn redeem(state: &AppState, raw_token: &str, nonce: &str) -> AppResult<users::Model> {
+    ensure_enabled(state)?;
+
+    let pending = user_tokens::find_valid(&state.db, TokenPurpose::MagicLink, raw_token).await?;
+    if pending.payload.as_deref() != Some(token::hash_token(nonce).as_str()) {
+        return Err(AppError::BadRequest(
+            "Open the sign-in link in the browser you requested it from".to_string(),
+        ));
+    }
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    let token = user_tokens::consume(&txn, TokenPurpose::MagicLink, raw_token).await?;
+    let user = users_service::find_by_id(state, token.user_id).await?;
+
+    // An organization may have turned sign-in links off since this one was
+    // mailed
+    if !allowed_for_user(&txn, user.id).await? {
+        return Err(AppError::Forbidden(
+            "Your organization does not allow signing in with an email link".to_string(),
+        ));
+    }
+
+    let user = users_service::mark_email_verified(&txn, user).await?;
+    txn.commit().await.map_err(AppError::from)?;
+
+    Ok(user)
+}
+
+/// Whether none of the user's organizations turned sign-in links off
+pub async fn allowed_for_user<C: ConnectionTrait>(db: &C, user_id: Uuid) -> AppResult<bool> {
+    let count = Organizations::find()
+        .join(JoinType::InnerJoin, organizations::Relation::Members.def())
+        .filter(membership::Column::UserId.eq(user_id))
+        .filter(organizations::Column::AllowMagicLinks.eq(false))
+        .count(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(count == 0)
+}
+
+fn ensure_enabled(state: &AppState) -> AppResult<()> {
+    if !state.config.magic_link_login {
+        return Err(AppError::Forbidden(
+            "Signing in with an email link is not enabled".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Count a request against its email, failing with `429 Too Many Requests`
+/// once the email has used up its window
+async fn count_request(state: &AppState, email: &str) -> AppResult<()> {
+    let max_requests = state.config.magic_link_max_requests;
+    if max_requests == 0 {
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now().fixed_offset();
+    let window = chrono::Duration::seconds(state.config.magic_link_window_secs);
+
+    // A lapsed window starts counting afresh
+    let row = Throttles::find()
+        .from_raw_sql(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"INSERT INTO magic_link_throttles (email, requests, window_started_at)
+            VALUES ($1, 1, $2)
+            ON CONFLICT (email) DO UPDATE SET
+                requests = CASE
+                    WHEN magic_link_throttles.window_started_at <= $3 THEN 1
+                    ELSE magic_link_throttles.requests + 1
+                END,
+                window_started_at = CASE
+                    WHEN magic_link_throttles.window_started_at <= $3 THEN $2
+                    ELSE magic_link_throttles.window_started_at
+                END
+            RETURNING *"#,
+            [
+                users_service::normalize_email(email).into(),
+                now.into(),
+                (now - window).into(),
+            ],
+        ))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| AppError::Internal(anyhow::anyhow!("upsert returned no row")))?;
+
+    if row.requests > max_requests as i32 {
+        let retry_after = (row.window_started_at + window - now).num_seconds() + 1;
+        return Err(AppError::TooManyRequests {
+            message: "Too many sign-in links requested for this email, try again later".to_string(),
+            retry_after_secs: retry_after.max(1) as u64,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sea_orm::{ActiveModelTrait, Set};
+
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::mailer::InMemoryMailer;
+    use crate::modules::organizations::service as organizations_service;
+    use crate::test_support::{link_token, mail_after, test_config, test_db, unique_email};
+
+    /// State with sign-in links on and mailing into memory, or `None`
+    /// without a test database
+    async fn setup_with(
+        configure: impl FnOnce(&mut AppConfig),
+    ) -> Option<(AppState, Arc<InMemoryMailer>)> {
+        let mut config = test_config();
+        config.magic_link_login = true;
+        configure(&mut config);
+        let mailer = Arc::new(InMemoryMailer::new());
+        let state = AppState::new(test_db().await?, config).with_mailer(mailer.clone());
+        Some((state, mailer))
+    }
+
+    async fn setup() -> Option<(AppState, Arc<InMemoryMailer>)> {
+        setup_with(|_| {}).await
+    }
+
+    async fn create_user(state: &AppState) -> users::Model {
+        users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Request a link for `user`, returning the nonce and the mailed token
+    async fn request(
+        state: &AppState,
+        mailer: &InMemoryMailer,
+        user: &users::Model,
+    ) -> (String, String) {
+        let sent = mailer.sent().len();
+        let nonce = request_link(state, &user.email).await.unwrap();
+        let token = link_token(&mail_after(mailer, sent, &user.email).await);
+        (nonce, token)
+    }
+
+    #[tokio::test]
+    async fn signs_in_only_with_the_requesting_browsers_nonce() {
+        let Some((state, mailer)) = setup().await else {
+            return;
+        };
+        let user = create_user(&state).await;
+        let (nonce, token) = request(&state, &mailer, &user).await;
+        assert_eq!(
+            mailer.last_to(&user.email).unwrap().subject,
+            "Your sign-in link"
+        );
+
+        // Another browser neither signs in nor burns the link
+        let elsewhere = redeem(&state, &token, "another-browser").await;
+        assert!(matches!(elsewhere, Err(AppError::BadRequest(_))));
+
+        let signed_in = redeem(&state, &token, &nonce).await.unwrap();
+        assert_eq!(signed_in.id, user.id);
+        assert!(signed_in.email_verified_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn links_work_once_and_only_the_latest() {
+        let Some((state, mailer)) = setup().await else {
+            return;
+        };
+        let user = create_user(&state).await;
+
+        let (first_nonce, first) = request(&state, &mailer, &user).await;
+        let (nonce, token) = request(&state, &mailer, &user).await;
+        let superseded = redeem(&state, &first, &first_nonce).await;
+        assert!(matches!(superseded, Err(AppError::BadRequest(_))));
+
+        redeem(&state, &token, &nonce).await.unwrap();
+        let reused = redeem(&state, &token, &nonce).await;
+        assert!(matches!(reused, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn throttles_requests_per_email_whether_registered_or_not() {
+        let Some((state, _)) = setup_with(|config| {
+            config.magic_link_max_requests = 2;
+            config.magic_link_window_secs = 900;
+        })
+        .await
+        else {
+            return;
+        };
+        let registered = create_user(&state).await.email;
+        let unknown = unique_email();
+
+        for email in [&registered, &unknown] {
+            request_link(&state, email).await.unwrap();
+            request_link(&state, &email.to_uppercase()).await.unwrap();
+            let result = request_link(&state, email).await;
+            assert!(
+                matches!(
+                    result,
+                    Err(AppError::TooManyRequests { retry_after_secs, .. })
+                        if (1..=901).contains(&retry_after_secs)
+                ),
+                "{}",
+                email
+            );
+        }
+
+        // Other emails keep their own count
+        request_link(&state, &unique_email()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn organizations_can_turn_links_off() {
+        let Some((state, mailer)) = setup().await else {
+            return;
+        };
+        let user = create_user(&state).await;
+        let name = format!("No links {}", Uuid::new_v4().simple());
+        let (organization, _) =
+            organizations_service::create_organization(&state, &user, &name, None)
+                .await
+                .unwrap();
+
+        // A link mailed before the organization turned them off stops working
+        let (nonce, token) = request(&state, &mailer, &user).await;
+        let mut active: organizations::ActiveModel = organization.into();
+        active.allow_magic_links = Set(false);
+        active.update(&state.db).await.unwrap();
+
+        assert!(!allowed_for_user(&state.db, user.id).await.unwrap());
+        let result = redeem(&state, &token, &nonce).await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+
+        // and no new one is issued, though the response looks the same
+        let last_issued =
+            || user_tokens::last_issued_at(&state.db, user.id, TokenPurpose::MagicLink);
+        let before = last_issued().await.unwrap();
+        let sent = mailer.sent().len();
+        request_link(&state, &user.email).await.unwrap();
+        assert_eq!(last_issued().await.unwrap(), before);
+        assert_eq!(mailer.sent().len(), sent);
+    }
+
+    #[tokio::test]
+    async fn deployment_can_turn_links_off() {
+        let Some((state, _)) = setup_with(|config| config.magic_link_login = false).await else {
+            return;
+        };
+
+        let result = request_link(&state, &unique_email()).await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+        let result = redeem(&state, "token", "nonce").await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+    }
+}
@@ -0,0 +1,42 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A user's TOTP authenticator; it only counts as enabled once confirmed
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "user_totp")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: Uuid,
+    /// Base32-encoded shared secret
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub confirmed_at: Option<DateTimeWithTimeZone>,
+    /// Time step of the last accepted code, so a code cannot be replayed
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+impl Model {
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::modules::users::entity::Entity",
+        from = "Column::UserId",
+        to = "crate::modules::users::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<crate::modules::users::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
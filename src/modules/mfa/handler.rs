@@ -0,0 +1,103 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+
+use crate::error::AppResult;
+use crate::modules::auth::extractor::AuthUser;
+use crate::state::AppState;
+
+use super::service;
+
+#[derive(serde::Deserialize)]
+pub struct CodeRequest {
+    pub code: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DisableMfaRequest {
+    pub current_password: String,
+    pub code: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct MfaStatusResponse {
+    pub enabled: bool,
+    pub recovery_codes_remaining: u64,
+}
+
+impl From<service::MfaStatus> for MfaStatusResponse {
+    fn from(status: service::MfaStatus) -> Self {
+        Self {
+            enabled: status.enabled,
+            recovery_codes_remaining: status.recovery_codes_remaining,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct EnrollmentResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+/// GET /api/mfa
+pub async fn get_status(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let status = service::status(&state, auth.user.id).await?;
+    Ok(Json(MfaStatusResponse::from(status)))
+}
+
+/// POST /api/mfa/totp
+///
+/// Returns the secret and an `otpauth://` URI for QR display; MFA is not
+/// enabled until confirmed with a code.
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let enrollment = service::begin_enrollment(&state, &auth.user).await?;
+    Ok(Json(EnrollmentResponse {
+        secret: enrollment.secret,
+        otpauth_uri: enrollment.otpauth_uri,
+    }))
+}
+
+/// POST /api/mfa/totp/confirm
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<CodeRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let recovery_codes = service::confirm_enrollment(&state, &auth.user, &payload.code).await?;
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// POST /api/mfa/disable
+pub async fn disable(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<DisableMfaRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::disable(&state, auth.user, &payload.current_password, &payload.code).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/mfa/recovery-codes
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<CodeRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let recovery_codes =
+        service::regenerate_recovery_codes(&state, &auth.user, &payload.code).await?;
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
@@ -0,0 +1,6 @@
+pub mod entity;
+pub mod handler;
+pub mod recovery_code;
+pub mod routes;
+pub mod service;
+pub mod totp;
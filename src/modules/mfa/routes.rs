@@ -0,0 +1,20 @@
+use axum::{
+    middleware,
+    routing::{get, post},
+    Router,
+};
+
+use crate::modules::auth::middleware::require_auth;
+use crate::state::AppState;
+
+use super::handler;
+
+pub fn mfa_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/", get(handler::get_status))
+        .route("/totp", post(handler::enroll_totp))
+        .route("/totp/confirm", post(handler::confirm_totp))
+        .route("/disable", post(handler::disable))
+        .route("/recovery-codes", post(handler::regenerate_recovery_codes))
+        .route_layer(middleware::from_fn_with_state(state, require_auth))
+}
@@ -0,0 +1,612 @@
+use data_encoding::BASE32_NOPAD;
+use rand::{rngs::OsRng, RngCore};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, EntityTrait,
+    JoinType, PaginatorTrait, QueryFilter, QuerySelect, RelationTrait, Set, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::token;
+use crate::modules::organizations::{
+    entity::{self as organizations, Entity as Organizations},
+    membership,
+};
+use crate::modules::user_tokens::{entity::TokenPurpose, service as user_tokens};
+use crate::modules::users::{entity as users, password, service as users_service};
+use crate::modules::webauthn::{ceremony::AuthenticationCredential, service as webauthn_service};
+use crate::state::AppState;
+
+use super::entity::{self as user_totp, Entity as UserTotp};
+use super::recovery_code::{self, Entity as RecoveryCodes};
+use super::totp;
+
+/// Number of recovery codes handed out at a time
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// A pending TOTP enrollment, to be confirmed with a code from the app
+pub struct Enrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// A way to complete the second step of a login
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecondFactor {
+    /// A code from the authenticator app, or a recovery code
+    Totp,
+    Passkey,
+}
+
+/// Whether a user has MFA turned on and how many recovery codes are left
+pub struct MfaStatus {
+    pub enabled: bool,
+    pub recovery_codes_remaining: u64,
+}
+
+pub async fn find_totp<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+) -> AppResult<Option<user_totp::Model>> {
+    UserTotp::find_by_id(user_id)
+        .one(db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Whether the user has a confirmed second factor
+pub async fn is_enabled<C: ConnectionTrait>(db: &C, user_id: Uuid) -> AppResult<bool> {
+    Ok(find_totp(db, user_id)
+        .await?
+        .is_some_and(|totp| totp.is_confirmed()))
+}
+
+/// Whether the user has any second factor: a confirmed TOTP authenticator
+/// or a passkey, which is verified on the device
+pub async fn has_second_factor<C: ConnectionTrait>(db: &C, user_id: Uuid) -> AppResult<bool> {
+    Ok(is_enabled(db, user_id).await? || webauthn_service::has_credentials(db, user_id).await?)
+}
+
+/// Whether a sign-in that proved one factor has to be completed with a
+/// second: the user turned TOTP on, or an organization requires MFA and
+/// they rely on a passkey for it
+///
+/// Signing in with the passkey itself needs no second step.
+pub async fn requires_challenge<C: ConnectionTrait>(db: &C, user_id: Uuid) -> AppResult<bool> {
+    if is_enabled(db, user_id).await? {
+        return Ok(true);
+    }
+
+    Ok(webauthn_service::has_credentials(db, user_id).await?
+        && required_by_organization(db, user_id).await?)
+}
+
+/// How a pending login challenge can be completed
+pub async fn challenge_methods<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+) -> AppResult<Vec<SecondFactor>> {
+    let mut methods = Vec::new();
+    if is_enabled(db, user_id).await? {
+        methods.push(SecondFactor::Totp);
+    }
+    if webauthn_service::has_credentials(db, user_id).await? {
+        methods.push(SecondFactor::Passkey);
+    }
+
+    Ok(methods)
+}
+
+pub async fn status(state: &AppState, user_id: Uuid) -> AppResult<MfaStatus> {
+    let enabled = is_enabled(&state.db, user_id).await?;
+    let recovery_codes_remaining = RecoveryCodes::find()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .filter(recovery_code::Column::UsedAt.is_null())
+        .count(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(MfaStatus {
+        enabled,
+        recovery_codes_remaining,
+    })
+}
+
+/// Start TOTP enrollment with a fresh secret, replacing any earlier
+/// unconfirmed attempt
+pub async fn begin_enrollment(state: &AppState, user: &users::Model) -> AppResult<Enrollment> {
+    if is_enabled(&state.db, user.id).await? {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let secret = totp::generate_secret();
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    UserTotp::delete_by_id(user.id)
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+
+    user_totp::ActiveModel {
+        user_id: Set(user.id),
+        secret: Set(secret.clone()),
+        confirmed_at: Set(None),
+        last_used_step: Set(None),
+        created_at: Set(chrono::Utc::now().fixed_offset()),
+    }
+    .insert(&txn)
+    .await
+    .map_err(AppError::from)?;
+
+    txn.commit().await.map_err(AppError::from)?;
+
+    let otpauth_uri = totp::provisioning_uri(&state.config.totp_issuer, &user.email, &secret);
+    Ok(Enrollment {
+        secret,
+        otpauth_uri,
+    })
+}
+
+/// Finish enrollment with a code from the authenticator app
+///
+/// Returns the plaintext recovery codes; they are never shown again.
+pub async fn confirm_enrollment(
+    state: &AppState,
+    user: &users::Model,
+    code: &str,
+) -> AppResult<Vec<String>> {
+    let totp = find_totp(&state.db, user.id)
+        .await?
+        .filter(|totp| !totp.is_confirmed())
+        .ok_or(AppError::BadRequest(
+            "No two-factor enrollment is in progress".to_string(),
+        ))?;
+
+    let step = totp::verify(&totp.secret, code, chrono::Utc::now().timestamp())
+        .ok_or_else(invalid_code)?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    let mut active: user_totp::ActiveModel = totp.into();
+    active.confirmed_at = Set(Some(chrono::Utc::now().fixed_offset()));
+    active.last_used_step = Set(Some(step));
+    active.update(&txn).await.map_err(AppError::from)?;
+
+    let codes = replace_recovery_codes(&txn, user.id).await?;
+    txn.commit().await.map_err(AppError::from)?;
+
+    Ok(codes)
+}
+
+/// Turn off MFA after re-checking the password and a current code
+pub async fn disable(
+    state: &AppState,
+    user: users::Model,
+    current_password: &str,
+    code: &str,
+) -> AppResult<()> {
+    let user = password::verify_user_password(state, user, current_password)
+        .await?
+        .ok_or(AppError::Unauthorized(
+            "Current password is incorrect".to_string(),
+        ))?;
+
+    if required_by_organization(&state.db, user.id).await?
+        && !webauthn_service::has_credentials(&state.db, user.id).await?
+    {
+        return Err(AppError::Forbidden(
+            "An organization you belong to requires two-factor authentication".to_string(),
+        ));
+    }
+
+    if !verify_second_factor(&state.db, user.id, code).await? {
+        return Err(invalid_code());
+    }
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    UserTotp::delete_by_id(user.id)
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+    RecoveryCodes::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user.id))
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+    txn.commit().await.map_err(AppError::from)
+}
+
+/// Replace all recovery codes after checking a current code
+pub async fn regenerate_recovery_codes(
+    state: &AppState,
+    user: &users::Model,
+    code: &str,
+) -> AppResult<Vec<String>> {
+    if !is_enabled(&state.db, user.id).await? {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is not enabled".to_string(),
+        ));
+    }
+    if !verify_second_factor(&state.db, user.id, code).await? {
+        return Err(invalid_code());
+    }
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let codes = replace_recovery_codes(&txn, user.id).await?;
+    txn.commit().await.map_err(AppError::from)?;
+
+    Ok(codes)
+}
+
+/// Check a TOTP code or an unused recovery code, consuming it on success
+///
+/// Each TOTP time step is accepted at most once and each recovery code
+/// only once; both are enforced with conditional updates so concurrent
+/// attempts cannot reuse the same code.
+pub async fn verify_second_factor<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    code: &str,
+) -> AppResult<bool> {
+    let Some(totp) = find_totp(db, user_id)
+        .await?
+        .filter(|totp| totp.is_confirmed())
+    else {
+        return Ok(false);
+    };
+
+    if let Some(step) = totp::verify(&totp.secret, code, chrono::Utc::now().timestamp()) {
+        let result = UserTotp::update_many()
+            .col_expr(user_totp::Column::LastUsedStep, Expr::value(step))
+            .filter(user_totp::Column::UserId.eq(user_id))
+            .filter(
+                Condition::any()
+                    .add(user_totp::Column::LastUsedStep.is_null())
+                    .add(user_totp::Column::LastUsedStep.lt(step)),
+            )
+            .exec(db)
+            .await
+            .map_err(AppError::from)?;
+
+        return Ok(result.rows_affected == 1);
+    }
+
+    let result = RecoveryCodes::update_many()
+        .col_expr(
+            recovery_code::Column::UsedAt,
+            Expr::value(chrono::Utc::now().fixed_offset()),
+        )
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .filter(recovery_code::Column::CodeHash.eq(hash_recovery_code(code)))
+        .filter(recovery_code::Column::UsedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Whether any organization the user belongs to requires MFA
+pub async fn required_by_organization<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+) -> AppResult<bool> {
+    let count = Organizations::find()
+        .join(JoinType::InnerJoin, organizations::Relation::Members.def())
+        .filter(membership::Column::UserId.eq(user_id))
+        .filter(organizations::Column::RequireMfa.eq(true))
+        .count(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(count > 0)
+}
+
+/// Fail with Forbidden if the organization requires MFA and the user has
+/// no second factor
+pub async fn enforce_organization_policy(
+    state: &AppState,
+    organization_id: Uuid,
+    user_id: Uuid,
+) -> AppResult<()> {
+    let requires_mfa = Organizations::find_by_id(organization_id)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .is_some_and(|organization| organization.require_mfa);
+
+    if requires_mfa && !has_second_factor(&state.db, user_id).await? {
+        return Err(AppError::Forbidden(
+            "This organization requires two-factor authentication; enable it to continue"
+                .to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Issue the intermediate token for a login whose password step succeeded
+pub async fn start_challenge(state: &AppState, user_id: Uuid) -> AppResult<String> {
+    user_tokens::issue(
+        &state.db,
+        user_id,
+        TokenPurpose::MfaChallenge,
+        None,
+        state.config.mfa_challenge_ttl_secs,
+    )
+    .await
+}
+
+/// The user a pending challenge belongs to
+pub async fn challenge_user(state: &AppState, mfa_token: &str) -> AppResult<users::Model> {
+    let challenge = user_tokens::find_valid(&state.db, TokenPurpose::MfaChallenge, mfa_token)
+        .await
+        .map_err(|_| invalid_token())?;
+
+    users_service::find_by_id(state, challenge.user_id).await
+}
+
+/// Complete a login with the intermediate token and a second factor
+///
+/// A wrong code leaves the token usable until it expires; a correct one
+/// consumes it.
+pub async fn complete_challenge(
+    state: &AppState,
+    mfa_token: &str,
+    code: &str,
+) -> AppResult<users::Model> {
+    let challenge = user_tokens::find_valid(&state.db, TokenPurpose::MfaChallenge, mfa_token)
+        .await
+        .map_err(|_| invalid_token())?;
+
+    if !verify_second_factor(&state.db, challenge.user_id, code).await? {
+        return Err(invalid_code());
+    }
+
+    user_tokens::consume(&state.db, TokenPurpose::MfaChallenge, mfa_token)
+        .await
+        .map_err(|_| invalid_token())?;
+
+    users_service::find_by_id(state, challenge.user_id).await
+}
+
+/// Request options for completing a login with one of the user's passkeys
+pub async fn passkey_challenge_options(
+    state: &AppState,
+    mfa_token: &str,
+) -> AppResult<serde_json::Value> {
+    let challenge = user_tokens::find_valid(&state.db, TokenPurpose::MfaChallenge, mfa_token)
+        .await
+        .map_err(|_| invalid_token())?;
+
+    webauthn_service::second_factor_options(state, challenge.user_id).await
+}
+
+/// Complete a login with the intermediate token and a passkey assertion
+///
+/// Like a code, a failed assertion leaves the token usable until it
+/// expires.
+pub async fn complete_passkey_challenge(
+    state: &AppState,
+    mfa_token: &str,
+    credential: &AuthenticationCredential,
+) -> AppResult<users::Model> {
+    let challenge = user_tokens::find_valid(&state.db, TokenPurpose::MfaChallenge, mfa_token)
+        .await
+        .map_err(|_| invalid_token())?;
+
+    let user = webauthn_service::authenticate(state, credential).await?;
+    if user.id != challenge.user_id {
+        return Err(AppError::Unauthorized(
+            "Passkey verification failed".to_string(),
+        ));
+    }
+
+    user_tokens::consume(&state.db, TokenPurpose::MfaChallenge, mfa_token)
+        .await
+        .map_err(|_| invalid_token())?;
+
+    Ok(user)
+}
+
+async fn replace_recovery_codes<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+) -> AppResult<Vec<String>> {
+    RecoveryCodes::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    let now = chrono::Utc::now().fixed_offset();
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+
+    RecoveryCodes::insert_many(codes.iter().map(|code| recovery_code::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        code_hash: Set(hash_recovery_code(code)),
+        used_at: Set(None),
+        created_at: Set(now),
+    }))
+    .exec(db)
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(codes)
+}
+
+/// A 50-bit code formatted for reading aloud, e.g. `k3v9q-x2m7d`
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 7];
+    OsRng.fill_bytes(&mut bytes);
+    let encoded = BASE32_NOPAD.encode(&bytes).to_lowercase();
+    format!("{}-{}", &encoded[..5], &encoded[5..10])
+}
+
+/// Hash a recovery code, ignoring case, spaces and dashes
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    token::hash_token(&normalized)
+}
+
+fn invalid_code() -> AppError {
+    AppError::Unauthorized("Invalid authentication code".to_string())
+}
+
+fn invalid_token() -> AppError {
+    AppError::Unauthorized("Invalid or expired MFA token".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_state, unique_email};
+
+    fn current_step() -> i64 {
+        totp::step_at(chrono::Utc::now().timestamp())
+    }
+
+    /// The code the user's authenticator app shows at a time step
+    fn code(secret: &str, step: i64) -> String {
+        let secret = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        totp::code_at(&secret, step)
+    }
+
+    /// A user with MFA turned on, their secret, recovery codes and the time
+    /// step of the code they enrolled with
+    async fn enrolled_user(state: &AppState) -> (users::Model, String, Vec<String>, i64) {
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap();
+        let enrollment = begin_enrollment(state, &user).await.unwrap();
+        let step = current_step();
+        let codes = confirm_enrollment(state, &user, &code(&enrollment.secret, step))
+            .await
+            .unwrap();
+        (user, enrollment.secret, codes, step)
+    }
+
+    #[tokio::test]
+    async fn enrollment_needs_a_valid_code() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap();
+        let enrollment = begin_enrollment(&state, &user).await.unwrap();
+        assert!(enrollment.otpauth_uri.contains(&enrollment.secret));
+
+        let stale = code(&enrollment.secret, current_step() - 5);
+        let wrong = confirm_enrollment(&state, &user, &stale).await;
+        assert!(matches!(wrong, Err(AppError::Unauthorized(_))));
+        assert!(!is_enabled(&state.db, user.id).await.unwrap());
+
+        let codes = confirm_enrollment(&state, &user, &code(&enrollment.secret, current_step()))
+            .await
+            .unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(is_enabled(&state.db, user.id).await.unwrap());
+
+        let again = begin_enrollment(&state, &user).await;
+        assert!(matches!(again, Err(AppError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn accepts_each_time_step_once() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (user, secret, _, step) = enrolled_user(&state).await;
+
+        // The enrollment code's step is used up
+        let replayed = code(&secret, step);
+        assert!(!verify_second_factor(&state.db, user.id, &replayed)
+            .await
+            .unwrap());
+
+        let next = code(&secret, step + 1);
+        assert!(verify_second_factor(&state.db, user.id, &next)
+            .await
+            .unwrap());
+        assert!(!verify_second_factor(&state.db, user.id, &next)
+            .await
+            .unwrap());
+        // Nor is an earlier step accepted after a later one
+        let previous = code(&secret, step - 1);
+        assert!(!verify_second_factor(&state.db, user.id, &previous)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn accepts_each_recovery_code_once() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (user, _, codes, _) = enrolled_user(&state).await;
+
+        // Case, spaces and dashes do not matter
+        let typed = codes[0].to_uppercase().replace('-', " ");
+        assert!(verify_second_factor(&state.db, user.id, &typed)
+            .await
+            .unwrap());
+        assert!(!verify_second_factor(&state.db, user.id, &codes[0])
+            .await
+            .unwrap());
+        assert!(!verify_second_factor(&state.db, user.id, "aaaaa-bbbbb")
+            .await
+            .unwrap());
+
+        let status = status(&state, user.id).await.unwrap();
+        assert_eq!(
+            status.recovery_codes_remaining,
+            RECOVERY_CODE_COUNT as u64 - 1
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_codes_of_unconfirmed_enrollment() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap();
+        let enrollment = begin_enrollment(&state, &user).await.unwrap();
+
+        assert!(!verify_second_factor(
+            &state.db,
+            user.id,
+            &code(&enrollment.secret, current_step())
+        )
+        .await
+        .unwrap());
+        assert!(!requires_challenge(&state.db, user.id).await.unwrap());
+    }
+}
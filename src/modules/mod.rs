@@ -1,2 +1,21 @@
+pub mod api_keys;
+pub mod audit;
+pub mod auth;
 pub mod health;
+pub mod impersonation;
+pub mod invitations;
+pub mod lockout;
+pub mod magic_links;
+pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod organizations;
+pub mod password_policy;
+pub mod permissions;
+pub mod saml;
+pub mod scim;
+pub mod service_accounts;
+pub mod sessions;
+pub mod user_tokens;
 pub mod users;
+pub mod webauthn;
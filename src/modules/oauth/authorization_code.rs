@@ -0,0 +1,47 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A code handed to a client through the user's browser, exchanged once for
+/// tokens and looked up by its SHA-256 hash
+///
+/// Tokens issued for it use its id as their `family_id`, so they can all be
+/// revoked if the code is presented again.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "oauth_authorization_codes")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub code_hash: String,
+    pub client_id: Uuid,
+    pub user_id: Uuid,
+    /// Must be sent again, unchanged, with the code
+    pub redirect_uri: String,
+    /// Space-separated
+    pub scopes: String,
+    /// PKCE `S256` challenge the code verifier must match
+    pub code_challenge: String,
+    pub expires_at: DateTimeWithTimeZone,
+    pub used_at: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::client::Entity",
+        from = "Column::ClientId",
+        to = "super::client::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Client,
+}
+
+impl Related<super::client::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Client.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
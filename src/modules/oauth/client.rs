@@ -0,0 +1,76 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// An application registered by an organization to act on behalf of users
+/// who authorize it; its id is the OAuth `client_id`
+///
+/// Confidential clients have a secret, stored as a SHA-256 hash. Public
+/// clients, such as mobile and single-page apps, have none and rely on PKCE.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "oauth_clients")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub secret_hash: Option<String>,
+    /// Space-separated; the authorization code grant needs at least one
+    pub redirect_uris: String,
+    /// Space-separated scopes the client may request
+    pub scopes: String,
+    /// Service account client credentials tokens act as; without one the
+    /// client cannot use that grant
+    pub service_account_id: Option<Uuid>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl Model {
+    pub fn redirect_uris(&self) -> Vec<String> {
+        self.redirect_uris
+            .split_whitespace()
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn scopes(&self) -> Vec<String> {
+        self.scopes.split_whitespace().map(str::to_string).collect()
+    }
+
+    pub fn is_confidential(&self) -> bool {
+        self.secret_hash.is_some()
+    }
+
+    /// Grant types the client may use at the token endpoint
+    pub fn grant_types(&self) -> Vec<&'static str> {
+        let mut grant_types = Vec::new();
+        if !self.redirect_uris.trim().is_empty() {
+            grant_types.extend(["authorization_code", "refresh_token"]);
+        }
+        if self.is_confidential() && self.service_account_id.is_some() {
+            grant_types.push("client_credentials");
+        }
+        grant_types
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::modules::organizations::entity::Entity",
+        from = "Column::OrganizationId",
+        to = "crate::modules::organizations::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Organization,
+}
+
+impl Related<crate::modules::organizations::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
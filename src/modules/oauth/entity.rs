@@ -0,0 +1,84 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// An opaque access token issued to a client, with the refresh token it can
+/// be renewed with, both stored as SHA-256 hashes
+///
+/// Refreshing revokes the row and issues a successor in the same family.
+/// Presenting a refresh token that was already revoked means it was stolen,
+/// and revokes the whole family.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "oauth_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub client_id: Uuid,
+    /// The user who authorized the client, or its service account for
+    /// client credentials tokens
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub access_token_hash: String,
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub refresh_token_hash: Option<String>,
+    /// Space-separated
+    pub scopes: String,
+    /// When the access token expires
+    pub expires_at: DateTimeWithTimeZone,
+    pub refresh_expires_at: Option<DateTimeWithTimeZone>,
+    pub revoked_at: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+impl Model {
+    pub fn scopes(&self) -> Vec<String> {
+        self.scopes.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// Whether the access token still works
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > chrono::Utc::now()
+    }
+
+    /// Whether the refresh token still works
+    pub fn is_refreshable(&self) -> bool {
+        self.revoked_at.is_none()
+            && self
+                .refresh_expires_at
+                .is_some_and(|expires_at| expires_at > chrono::Utc::now())
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::client::Entity",
+        from = "Column::ClientId",
+        to = "super::client::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Client,
+    #[sea_orm(
+        belongs_to = "crate::modules::users::entity::Entity",
+        from = "Column::UserId",
+        to = "crate::modules::users::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<super::client::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Client.def()
+    }
+}
+
+impl Related<crate::modules::users::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,593 @@
+use axum::{
+    extract::{rejection::FormRejection, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Form, Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Deserializer};
+use uuid::Uuid;
+
+use crate::error::AppResult;
+use crate::modules::auth::{client::ClientInfo, extractor::AuthUser};
+use crate::modules::organizations::context::{Admin, OrgGuard};
+use crate::state::AppState;
+
+use super::client;
+use super::error::{OAuthError, OAuthResult};
+use super::registration::{self, ClientChanges, NewClient};
+use super::scope;
+use super::service::{self, AuthorizationRequest, IntrospectedToken, IssuedTokens};
+
+#[derive(Deserialize)]
+pub struct CreateClientRequest {
+    pub name: String,
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    /// Public clients, such as mobile and single-page apps, cannot keep a
+    /// secret
+    #[serde(default = "default_confidential")]
+    pub confidential: bool,
+    pub service_account_id: Option<Uuid>,
+}
+
+fn default_confidential() -> bool {
+    true
+}
+
+impl From<CreateClientRequest> for NewClient {
+    fn from(payload: CreateClientRequest) -> Self {
+        Self {
+            name: payload.name,
+            redirect_uris: payload.redirect_uris,
+            scopes: payload.scopes,
+            confidential: payload.confidential,
+            service_account_id: payload.service_account_id,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateClientRequest {
+    pub name: Option<String>,
+    pub redirect_uris: Option<Vec<String>>,
+    pub scopes: Option<Vec<String>>,
+    /// `null` removes the service account
+    #[serde(default, deserialize_with = "present")]
+    pub service_account_id: Option<Option<Uuid>>,
+}
+
+/// Tell a field set to `null` from one left out
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl From<UpdateClientRequest> for ClientChanges {
+    fn from(payload: UpdateClientRequest) -> Self {
+        Self {
+            name: payload.name,
+            redirect_uris: payload.redirect_uris,
+            scopes: payload.scopes,
+            service_account_id: payload.service_account_id,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ClientResponse {
+    pub client_id: Uuid,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub grant_types: Vec<&'static str>,
+    pub confidential: bool,
+    pub service_account_id: Option<Uuid>,
+    pub created_by: Option<Uuid>,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+    pub updated_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl From<client::Model> for ClientResponse {
+    fn from(model: client::Model) -> Self {
+        Self {
+            redirect_uris: model.redirect_uris(),
+            scopes: model.scopes(),
+            grant_types: model.grant_types(),
+            confidential: model.is_confidential(),
+            client_id: model.id,
+            name: model.name,
+            service_account_id: model.service_account_id,
+            created_by: model.created_by,
+            created_at: model.created_at,
+            updated_at: model.updated_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ClientWithSecretResponse {
+    #[serde(flatten)]
+    pub client: ClientResponse,
+    /// Shown only once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+/// Query of `GET /api/oauth/authorize`, as the client put it in the URL of
+/// the authorization page
+#[derive(Deserialize)]
+pub struct AuthorizeParams {
+    pub response_type: String,
+    pub client_id: Uuid,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+impl From<AuthorizeParams> for AuthorizationRequest {
+    fn from(params: AuthorizeParams) -> Self {
+        Self {
+            response_type: params.response_type,
+            client_id: params.client_id,
+            redirect_uri: params.redirect_uri,
+            scope: params.scope,
+            state: params.state,
+            code_challenge: params.code_challenge,
+            code_challenge_method: params.code_challenge_method,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AuthorizeDecisionRequest {
+    #[serde(flatten)]
+    pub params: AuthorizeParams,
+    /// Whether the user allowed the client
+    pub approve: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct ScopeResponse {
+    pub scope: String,
+    pub description: &'static str,
+}
+
+/// What the authorization page shows the user
+#[derive(serde::Serialize)]
+pub struct AuthorizationPromptResponse {
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<ScopeResponse>,
+    /// The user already allowed every requested scope, so the page can
+    /// approve without asking
+    pub consented: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct AuthorizationDecisionResponse {
+    /// Where to send the browser: the client's redirect URI with the code,
+    /// or with `error=access_denied`
+    pub redirect_to: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentResponse {
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub scopes: Vec<String>,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+    pub updated_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+/// Body of the token endpoint; every field is optional so missing ones are
+/// reported in the OAuth error format
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub scope: String,
+}
+
+impl From<IssuedTokens> for TokenResponse {
+    fn from(tokens: IssuedTokens) -> Self {
+        Self {
+            access_token: tokens.access_token,
+            token_type: "Bearer",
+            expires_in: tokens.expires_in,
+            refresh_token: tokens.refresh_token,
+            scope: tokens.scopes.join(" "),
+        }
+    }
+}
+
+/// Body of the introspection and revocation endpoints
+#[derive(Deserialize)]
+pub struct TokenLookupRequest {
+    pub token: Option<String>,
+    /// Accepted but not needed: tokens are told apart by their prefix
+    #[allow(dead_code)]
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// RFC 7662 response; only `active` is set for inactive tokens
+#[derive(Default, serde::Serialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+}
+
+/// GET /api/orgs/:org_id/oauth-clients
+pub async fn list_clients(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+) -> AppResult<impl IntoResponse> {
+    let clients = registration::list(&state, org.org_id).await?;
+    Ok(Json(
+        clients
+            .into_iter()
+            .map(ClientResponse::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// POST /api/orgs/:org_id/oauth-clients
+pub async fn create_client(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    client_info: ClientInfo,
+    Json(payload): Json<CreateClientRequest>,
+) -> AppResult<impl IntoResponse> {
+    let (client, client_secret) =
+        registration::create(&state, &org, &client_info, payload.into()).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(ClientWithSecretResponse {
+            client: client.into(),
+            client_secret,
+        }),
+    ))
+}
+
+/// PATCH /api/orgs/:org_id/oauth-clients/:client_id
+pub async fn update_client(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    client_info: ClientInfo,
+    Path((_, client_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<UpdateClientRequest>,
+) -> AppResult<impl IntoResponse> {
+    let client =
+        registration::update(&state, &org, &client_info, client_id, payload.into()).await?;
+    Ok(Json(ClientResponse::from(client)))
+}
+
+/// POST /api/orgs/:org_id/oauth-clients/:client_id/secret
+pub async fn rotate_client_secret(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    client_info: ClientInfo,
+    Path((_, client_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let (client, client_secret) =
+        registration::rotate_secret(&state, &org, &client_info, client_id).await?;
+    Ok(Json(ClientWithSecretResponse {
+        client: client.into(),
+        client_secret: Some(client_secret),
+    }))
+}
+
+/// DELETE /api/orgs/:org_id/oauth-clients/:client_id
+pub async fn delete_client(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    client_info: ClientInfo,
+    Path((_, client_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    registration::delete(&state, &org, &client_info, client_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/oauth/authorize
+///
+/// Checks the request the client sent the user with and describes it for
+/// the authorization page.
+pub async fn get_authorization(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(params): Query<AuthorizeParams>,
+) -> AppResult<impl IntoResponse> {
+    let authorization = service::validate_authorization(&state, params.into()).await?;
+    let consented = service::find_consent(&state.db, auth.user.id, authorization.client.id)
+        .await?
+        .is_some_and(|consent| consent.covers(&authorization.scopes));
+
+    Ok(Json(AuthorizationPromptResponse {
+        client_id: authorization.client.id,
+        client_name: authorization.client.name,
+        redirect_uri: authorization.redirect_uri,
+        scopes: authorization
+            .scopes
+            .into_iter()
+            .map(|s| ScopeResponse {
+                description: scope::describe(&s),
+                scope: s,
+            })
+            .collect(),
+        consented,
+    }))
+}
+
+/// POST /api/oauth/authorize
+pub async fn decide_authorization(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    client_info: ClientInfo,
+    Json(payload): Json<AuthorizeDecisionRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let authorization = service::validate_authorization(&state, payload.params.into()).await?;
+
+    let redirect_to = if payload.approve {
+        service::approve(&state, &auth.user, &client_info, authorization).await?
+    } else {
+        service::deny(&authorization)?
+    };
+
+    Ok(Json(AuthorizationDecisionResponse { redirect_to }))
+}
+
+/// GET /api/users/me/oauth-consents
+pub async fn list_my_consents(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let consents = service::list_consents(&state, auth.user.id).await?;
+    Ok(Json(
+        consents
+            .into_iter()
+            .map(|(consent, client)| ConsentResponse {
+                scopes: consent.scopes(),
+                client_id: client.id,
+                client_name: client.name,
+                created_at: consent.created_at,
+                updated_at: consent.updated_at,
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// DELETE /api/users/me/oauth-consents/:client_id
+pub async fn revoke_my_consent(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    client_info: ClientInfo,
+    Path(client_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    service::revoke_consent(&state, &auth.user, &client_info, client_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /oauth/token
+pub async fn token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    form: Result<Form<TokenRequest>, FormRejection>,
+) -> OAuthResult<impl IntoResponse> {
+    let Form(request) = form.map_err(|e| OAuthError::invalid_request(e.body_text()))?;
+    let grant_type = request.grant_type.as_deref().unwrap_or_default();
+
+    // Only confidential clients can get tokens without a user
+    let require_secret = grant_type == "client_credentials";
+    let (client_id, client_secret) =
+        client_credentials(&headers, request.client_id, request.client_secret)?;
+    let client =
+        service::authenticate_client(&state, &client_id, client_secret.as_deref(), require_secret)
+            .await?;
+
+    let tokens = match grant_type {
+        "authorization_code" => {
+            service::exchange_code(
+                &state,
+                &client,
+                &required(request.code, "code")?,
+                &required(request.redirect_uri, "redirect_uri")?,
+                &required(request.code_verifier, "code_verifier")?,
+            )
+            .await?
+        }
+        "refresh_token" => {
+            service::refresh(
+                &state,
+                &client,
+                &required(request.refresh_token, "refresh_token")?,
+                request.scope.as_deref(),
+            )
+            .await?
+        }
+        "client_credentials" => {
+            service::client_credentials(&state, &client, request.scope.as_deref()).await?
+        }
+        "" => return Err(OAuthError::invalid_request("Missing grant_type")),
+        _ => return Err(OAuthError::unsupported_grant_type()),
+    };
+
+    Ok((
+        [
+            (header::CACHE_CONTROL, "no-store"),
+            (header::PRAGMA, "no-cache"),
+        ],
+        Json(TokenResponse::from(tokens)),
+    ))
+}
+
+/// POST /oauth/introspect
+pub async fn introspect(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    form: Result<Form<TokenLookupRequest>, FormRejection>,
+) -> OAuthResult<impl IntoResponse> {
+    let Form(request) = form.map_err(|e| OAuthError::invalid_request(e.body_text()))?;
+    let (client_id, client_secret) =
+        client_credentials(&headers, request.client_id, request.client_secret)?;
+    let client =
+        service::authenticate_client(&state, &client_id, client_secret.as_deref(), true).await?;
+    let raw_token = required(request.token, "token")?;
+
+    let response = match service::introspect(&state, &client, &raw_token).await? {
+        None => IntrospectionResponse::default(),
+        Some(introspected) => {
+            let (token, user, token_type, exp) = match introspected {
+                IntrospectedToken::Access(token, user) => {
+                    let exp = token.expires_at;
+                    (token, user, "Bearer", exp)
+                }
+                IntrospectedToken::Refresh(token, user) => {
+                    let exp = token.refresh_expires_at.unwrap_or(token.expires_at);
+                    (token, user, "refresh_token", exp)
+                }
+            };
+            IntrospectionResponse {
+                active: true,
+                scope: Some(token.scopes),
+                client_id: Some(token.client_id),
+                username: Some(user.email),
+                token_type: Some(token_type),
+                exp: Some(exp.timestamp()),
+                iat: Some(token.created_at.timestamp()),
+                sub: Some(user.id),
+                iss: Some(state.config.public_api_url().to_string()),
+            }
+        }
+    };
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+/// POST /oauth/revoke
+pub async fn revoke(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    form: Result<Form<TokenLookupRequest>, FormRejection>,
+) -> OAuthResult<impl IntoResponse> {
+    let Form(request) = form.map_err(|e| OAuthError::invalid_request(e.body_text()))?;
+    let (client_id, client_secret) =
+        client_credentials(&headers, request.client_id, request.client_secret)?;
+    let client =
+        service::authenticate_client(&state, &client_id, client_secret.as_deref(), false).await?;
+    let raw_token = required(request.token, "token")?;
+
+    service::revoke(&state, &client, &raw_token).await?;
+    Ok(StatusCode::OK)
+}
+
+/// GET /.well-known/oauth-authorization-server
+pub async fn metadata(State(state): State<AppState>) -> impl IntoResponse {
+    let api_url = state.config.public_api_url();
+    Json(serde_json::json!({
+        "issuer": api_url,
+        "authorization_endpoint": state.config.oauth_authorization_endpoint(),
+        "token_endpoint": format!("{}/oauth/token", api_url),
+        "introspection_endpoint": format!("{}/oauth/introspect", api_url),
+        "revocation_endpoint": format!("{}/oauth/revoke", api_url),
+        "scopes_supported": scope::supported(),
+        "response_types_supported": ["code"],
+        "response_modes_supported": ["query"],
+        "grant_types_supported": ["authorization_code", "refresh_token", "client_credentials"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "introspection_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post"],
+        "revocation_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+/// The client's id and secret, from HTTP Basic authentication or the body,
+/// but not both (RFC 6749, section 2.3.1)
+fn client_credentials(
+    headers: &HeaderMap,
+    body_id: Option<String>,
+    body_secret: Option<String>,
+) -> OAuthResult<(String, Option<String>)> {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"));
+
+    match (basic, body_id) {
+        (Some(_), Some(_)) => Err(OAuthError::invalid_request(
+            "Use only one client authentication method",
+        )),
+        (Some((_, encoded)), None) => {
+            if body_secret.is_some() {
+                return Err(OAuthError::invalid_request(
+                    "Use only one client authentication method",
+                ));
+            }
+            let decoded = STANDARD
+                .decode(encoded.trim())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .ok_or_else(OAuthError::invalid_client)?;
+            let (id, secret) = decoded
+                .split_once(':')
+                .ok_or_else(OAuthError::invalid_client)?;
+            // Both parts are form-encoded before being joined
+            let decode = |value: &str| {
+                percent_decode_str(&value.replace('+', " "))
+                    .decode_utf8()
+                    .map(|value| value.into_owned())
+                    .map_err(|_| OAuthError::invalid_client())
+            };
+            Ok((decode(id)?, Some(decode(secret)?)))
+        }
+        (None, Some(id)) => Ok((id, body_secret)),
+        (None, None) => Err(OAuthError::invalid_client()),
+    }
+}
+
+fn required(value: Option<String>, name: &str) -> OAuthResult<String> {
+    value
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| OAuthError::invalid_request(format!("Missing {}", name)))
+}
@@ -0,0 +1,10 @@
+pub mod authorization_code;
+pub mod client;
+pub mod consent;
+pub mod entity;
+pub mod error;
+pub mod handler;
+pub mod registration;
+pub mod routes;
+pub mod scope;
+pub mod service;
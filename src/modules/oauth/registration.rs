@@ -0,0 +1,376 @@
+//! OAuth clients an organization registers for its integrations
+//!
+//! A client needs a redirect URI to use the authorization code grant, and a
+//! secret and a service account of its organization to use client
+//! credentials. Narrowing a client's scopes or changing its service account
+//! revokes the tokens it holds.
+
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set,
+    TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::audit::service::{self as audit, AuditEvent};
+use crate::modules::auth::{client::ClientInfo, token};
+use crate::modules::organizations::context::OrgContext;
+use crate::modules::service_accounts::service as service_accounts;
+use crate::state::AppState;
+
+use super::client::{self, Entity as Clients};
+use super::entity::{self as tokens, Entity as Tokens};
+use super::scope;
+
+/// Longest client name
+const MAX_NAME_LEN: usize = 100;
+
+/// Most redirect URIs a client can register
+const MAX_REDIRECT_URIS: usize = 10;
+
+/// A client to register
+pub struct NewClient {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    /// Public clients get no secret and can only use the authorization code
+    /// grant
+    pub confidential: bool,
+    pub service_account_id: Option<Uuid>,
+}
+
+/// Changes to a client; unset fields are left alone
+pub struct ClientChanges {
+    pub name: Option<String>,
+    pub redirect_uris: Option<Vec<String>>,
+    pub scopes: Option<Vec<String>>,
+    /// `Some(None)` stops the client using client credentials
+    pub service_account_id: Option<Option<Uuid>>,
+}
+
+pub async fn list(state: &AppState, organization_id: Uuid) -> AppResult<Vec<client::Model>> {
+    Clients::find()
+        .filter(client::Column::OrganizationId.eq(organization_id))
+        .order_by_asc(client::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// A client of the organization, or `404`
+pub async fn find(
+    state: &AppState,
+    organization_id: Uuid,
+    client_id: Uuid,
+) -> AppResult<client::Model> {
+    Clients::find_by_id(client_id)
+        .filter(client::Column::OrganizationId.eq(organization_id))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| AppError::NotFound(format!("OAuth client with id {} not found", client_id)))
+}
+
+/// Register a client, returning it with its secret, if confidential, which
+/// is not stored and cannot be shown again
+pub async fn create(
+    state: &AppState,
+    actor: &OrgContext,
+    client_info: &ClientInfo,
+    input: NewClient,
+) -> AppResult<(client::Model, Option<String>)> {
+    let name = validate_name(&input.name)?;
+    let redirect_uris = validate_redirect_uris(input.redirect_uris)?;
+    let scopes = validate_scopes(input.scopes)?;
+    if let Some(service_account_id) = input.service_account_id {
+        ensure_service_account(state, actor, input.confidential, service_account_id).await?;
+    }
+    if redirect_uris.is_empty() && input.service_account_id.is_none() {
+        return Err(AppError::ValidationError(
+            "A client needs a redirect URI or a service account".to_string(),
+        ));
+    }
+
+    let secret = input.confidential.then(token::generate_token);
+    let now = chrono::Utc::now().fixed_offset();
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    let client = client::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        organization_id: Set(actor.org_id),
+        name: Set(name),
+        secret_hash: Set(secret.as_deref().map(token::hash_token)),
+        redirect_uris: Set(redirect_uris.join(" ")),
+        scopes: Set(scopes.join(" ")),
+        service_account_id: Set(input.service_account_id),
+        created_by: Set(Some(actor.user_id)),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(&txn)
+    .await
+    .map_err(AppError::from)?;
+
+    audit::record(
+        &txn,
+        AuditEvent::new("oauth_client.created")
+            .actor(actor.user_id)
+            .organization(actor.org_id)
+            .ip_address(client_info.ip)
+            .details(details(&client)),
+    )
+    .await?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok((client, secret))
+}
+
+pub async fn update(
+    state: &AppState,
+    actor: &OrgContext,
+    client_info: &ClientInfo,
+    client_id: Uuid,
+    changes: ClientChanges,
+) -> AppResult<client::Model> {
+    let current = find(state, actor.org_id, client_id).await?;
+    let name = changes.name.as_deref().map(validate_name).transpose()?;
+    let redirect_uris = changes
+        .redirect_uris
+        .map(validate_redirect_uris)
+        .transpose()?;
+    let scopes = changes.scopes.map(validate_scopes).transpose()?;
+    if let Some(Some(service_account_id)) = changes.service_account_id {
+        ensure_service_account(state, actor, current.is_confidential(), service_account_id).await?;
+    }
+
+    let has_redirect_uri = redirect_uris
+        .as_ref()
+        .map_or(!current.redirect_uris.is_empty(), |uris| !uris.is_empty());
+    let service_account_id = changes
+        .service_account_id
+        .unwrap_or(current.service_account_id);
+    if !has_redirect_uri && service_account_id.is_none() {
+        return Err(AppError::ValidationError(
+            "A client needs a redirect URI or a service account".to_string(),
+        ));
+    }
+
+    let narrowed = scopes.as_ref().is_some_and(|scopes| {
+        current
+            .scopes()
+            .iter()
+            .any(|granted| !scopes.contains(granted))
+    });
+    let service_account_changed = service_account_id != current.service_account_id;
+
+    let now = chrono::Utc::now().fixed_offset();
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    let mut active: client::ActiveModel = current.into();
+    if let Some(name) = name {
+        active.name = Set(name);
+    }
+    if let Some(redirect_uris) = redirect_uris {
+        active.redirect_uris = Set(redirect_uris.join(" "));
+    }
+    if let Some(scopes) = scopes {
+        active.scopes = Set(scopes.join(" "));
+    }
+    active.service_account_id = Set(service_account_id);
+    active.updated_at = Set(now);
+    let client = active.update(&txn).await.map_err(AppError::from)?;
+
+    if narrowed || service_account_changed {
+        revoke_tokens(&txn, client.id).await?;
+    }
+
+    audit::record(
+        &txn,
+        AuditEvent::new("oauth_client.updated")
+            .actor(actor.user_id)
+            .organization(actor.org_id)
+            .ip_address(client_info.ip)
+            .details(details(&client)),
+    )
+    .await?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok(client)
+}
+
+/// Replace a confidential client's secret, returning the new one; the old
+/// one stops working at once
+pub async fn rotate_secret(
+    state: &AppState,
+    actor: &OrgContext,
+    client_info: &ClientInfo,
+    client_id: Uuid,
+) -> AppResult<(client::Model, String)> {
+    let current = find(state, actor.org_id, client_id).await?;
+    if !current.is_confidential() {
+        return Err(AppError::BadRequest(
+            "Public clients have no secret".to_string(),
+        ));
+    }
+
+    let secret = token::generate_token();
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    let mut active: client::ActiveModel = current.into();
+    active.secret_hash = Set(Some(token::hash_token(&secret)));
+    active.updated_at = Set(chrono::Utc::now().fixed_offset());
+    let client = active.update(&txn).await.map_err(AppError::from)?;
+
+    audit::record(
+        &txn,
+        AuditEvent::new("oauth_client.secret_rotated")
+            .actor(actor.user_id)
+            .organization(actor.org_id)
+            .ip_address(client_info.ip)
+            .details(serde_json::json!({ "client_id": client.id })),
+    )
+    .await?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok((client, secret))
+}
+
+/// Delete a client along with its consents, codes and tokens
+pub async fn delete(
+    state: &AppState,
+    actor: &OrgContext,
+    client_info: &ClientInfo,
+    client_id: Uuid,
+) -> AppResult<()> {
+    let client = find(state, actor.org_id, client_id).await?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    Clients::delete_by_id(client.id)
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+
+    audit::record(
+        &txn,
+        AuditEvent::new("oauth_client.deleted")
+            .actor(actor.user_id)
+            .organization(actor.org_id)
+            .ip_address(client_info.ip)
+            .details(serde_json::json!({ "client_id": client.id, "name": client.name })),
+    )
+    .await?;
+
+    txn.commit().await.map_err(AppError::from)
+}
+
+async fn revoke_tokens<C: sea_orm::ConnectionTrait>(db: &C, client_id: Uuid) -> AppResult<()> {
+    Tokens::update_many()
+        .col_expr(
+            tokens::Column::RevokedAt,
+            Expr::value(chrono::Utc::now().fixed_offset()),
+        )
+        .filter(tokens::Column::ClientId.eq(client_id))
+        .filter(tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+fn details(client: &client::Model) -> serde_json::Value {
+    serde_json::json!({
+        "client_id": client.id,
+        "name": client.name,
+        "redirect_uris": client.redirect_uris(),
+        "scopes": client.scopes(),
+        "service_account_id": client.service_account_id,
+    })
+}
+
+fn validate_name(name: &str) -> AppResult<String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::ValidationError(format!(
+            "Name must be between 1 and {} characters",
+            MAX_NAME_LEN
+        )));
+    }
+
+    Ok(name.to_string())
+}
+
+/// Check redirect URIs and return them without duplicates
+///
+/// They must be absolute, without a fragment, and use HTTPS, plain HTTP on
+/// the loopback interface, or a private-use scheme such as
+/// `com.example.app:/callback` for native apps (RFC 8252).
+fn validate_redirect_uris(uris: Vec<String>) -> AppResult<Vec<String>> {
+    let mut redirect_uris: Vec<String> = Vec::new();
+    for uri in uris {
+        if !redirect_uris.contains(&uri) {
+            redirect_uris.push(uri);
+        }
+    }
+    if redirect_uris.len() > MAX_REDIRECT_URIS {
+        return Err(AppError::ValidationError(format!(
+            "A client can have at most {} redirect URIs",
+            MAX_REDIRECT_URIS
+        )));
+    }
+
+    for redirect_uri in &redirect_uris {
+        let invalid =
+            || AppError::ValidationError(format!("Invalid redirect URI {}", redirect_uri));
+        let url = reqwest::Url::parse(redirect_uri).map_err(|_| invalid())?;
+        let allowed = match url.scheme() {
+            "https" => true,
+            "http" => matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]")),
+            scheme => scheme.contains('.'),
+        };
+        if !allowed || url.fragment().is_some() || redirect_uri.contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+    }
+
+    Ok(redirect_uris)
+}
+
+/// Check requested scopes and return them sorted and deduplicated
+fn validate_scopes(mut scopes: Vec<String>) -> AppResult<Vec<String>> {
+    scopes.sort();
+    scopes.dedup();
+
+    if scopes.is_empty() {
+        return Err(AppError::ValidationError(
+            "At least one scope is required".to_string(),
+        ));
+    }
+
+    if let Some(unknown) = scopes.iter().find(|s| !scope::is_supported(s)) {
+        return Err(AppError::ValidationError(format!(
+            "Unknown scope {}",
+            unknown
+        )));
+    }
+
+    Ok(scopes)
+}
+
+/// Client credentials tokens act as the service account, so only
+/// confidential clients can have one, and only of their organization
+async fn ensure_service_account(
+    state: &AppState,
+    actor: &OrgContext,
+    confidential: bool,
+    service_account_id: Uuid,
+) -> AppResult<()> {
+    if !confidential {
+        return Err(AppError::ValidationError(
+            "Only confidential clients can have a service account".to_string(),
+        ));
+    }
+
+    service_accounts::find(state, actor.org_id, service_account_id).await?;
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use axum::{
+    middleware,
+    routing::{delete, get, post},
+    Router,
+};
+
+use crate::modules::auth::middleware::require_auth;
+use crate::state::AppState;
+
+use super::handler;
+
+/// The token, introspection and revocation endpoints clients call, nested
+/// under `/oauth`
+pub fn oauth_routes() -> Router<AppState> {
+    Router::new()
+        .route("/token", post(handler::token))
+        .route("/introspect", post(handler::introspect))
+        .route("/revoke", post(handler::revoke))
+}
+
+/// Authorization server metadata (RFC 8414), merged at the root
+pub fn metadata_routes() -> Router<AppState> {
+    Router::new().route(
+        "/.well-known/oauth-authorization-server",
+        get(handler::metadata),
+    )
+}
+
+/// The API behind the authorization page, nested under `/api/oauth`
+pub fn authorization_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/authorize",
+            get(handler::get_authorization).post(handler::decide_authorization),
+        )
+        .route_layer(middleware::from_fn_with_state(state, require_auth))
+}
+
+/// Clients registered by an organization, nested under
+/// `/api/orgs/{org_id}/oauth-clients`
+pub fn org_oauth_client_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(handler::list_clients).post(handler::create_client))
+        .route(
+            "/{client_id}",
+            delete(handler::delete_client).patch(handler::update_client),
+        )
+        .route("/{client_id}/secret", post(handler::rotate_client_secret))
+}
+
+/// Clients the caller has authorized, nested under
+/// `/api/users/me/oauth-consents`
+pub fn user_consent_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(handler::list_my_consents))
+        .route("/{client_id}", delete(handler::revoke_my_consent))
+}
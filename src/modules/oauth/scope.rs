@@ -0,0 +1,61 @@
+//! What an OAuth client may do for a user
+//!
+//! Clients request the scopes API keys have, which open routes, and the
+//! names of permissions, which cap what the user's roles allow while the
+//! client acts for them. As for API keys, write implies read, so
+//! `users:write` also grants the `users:read` permission. `orgs` scopes open
+//! routes only.
+
+use crate::modules::api_keys::scope::{self as api_key_scope, ScopedResource};
+use crate::modules::permissions::permission::{Permission, PermissionSet};
+
+/// Every scope a client can be registered with, sorted
+pub fn supported() -> Vec<String> {
+    let mut scopes: Vec<String> = ScopedResource::ALL
+        .iter()
+        .flat_map(|resource| {
+            ["read", "write"].map(|access| format!("{}:{}", resource.as_str(), access))
+        })
+        .chain(Permission::ALL.iter().map(|p| p.as_str().to_string()))
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+    scopes
+}
+
+pub fn is_supported(scope: &str) -> bool {
+    api_key_scope::is_known(scope) || Permission::parse(scope).is_some()
+}
+
+/// Split a space-separated `scope` parameter, sorted and deduplicated
+pub fn parse(value: &str) -> Vec<String> {
+    let mut scopes: Vec<String> = value.split_whitespace().map(str::to_string).collect();
+    scopes.sort();
+    scopes.dedup();
+    scopes
+}
+
+/// What the user is asked to allow, for the consent screen
+pub fn describe(scope: &str) -> &'static str {
+    match scope {
+        "users:read" => "See the members of your organizations",
+        "users:write" => "Add, change and remove members of your organizations",
+        "orgs:read" => "See your organizations",
+        "orgs:write" => "Change your organizations and their settings",
+        "roles:manage" => "Create roles and assign them to members",
+        "billing:manage" => "Manage the billing of your organizations",
+        _ => "",
+    }
+}
+
+/// The permissions granted scopes leave the user's roles
+pub fn permissions(scopes: &[String]) -> PermissionSet {
+    scopes
+        .iter()
+        .flat_map(|scope| match Permission::parse(scope) {
+            Some(Permission::UsersWrite) => vec![Permission::UsersWrite, Permission::UsersRead],
+            Some(permission) => vec![permission],
+            None => Vec::new(),
+        })
+        .collect()
+}
@@ -0,0 +1,919 @@
+//! OAuth 2.0 authorization server for third-party integrations
+//!
+//! Users authorize clients with the authorization code grant, which always
+//! requires PKCE with `S256` (RFC 7636). What they allow is kept as a
+//! consent, which later authorizations within it skip asking for, and
+//! which tokens are checked against. Clients renew access with rotating
+//! refresh tokens, or use client credentials to act as their service
+//! account. Access tokens are opaque and authenticate like API keys.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::audit::service::{self as audit, AuditEvent};
+use crate::modules::auth::{client::ClientInfo, token, verification};
+use crate::modules::users::entity::{self as users, Entity as Users};
+use crate::state::AppState;
+
+use super::authorization_code::{self, Entity as AuthorizationCodes};
+use super::client::{self, Entity as Clients};
+use super::consent::{self, Entity as Consents};
+use super::entity::{self as tokens, Entity as Tokens};
+use super::error::{OAuthError, OAuthResult};
+
+/// Start of access tokens, telling them apart from API keys and JWTs
+pub const ACCESS_TOKEN_PREFIX: &str = "oat_";
+
+/// Start of refresh tokens
+pub const REFRESH_TOKEN_PREFIX: &str = "ort_";
+
+pub fn is_access_token(token: &str) -> bool {
+    token.starts_with(ACCESS_TOKEN_PREFIX)
+}
+
+/// Parameters of an authorization request, as the client sent them
+pub struct AuthorizationRequest {
+    pub response_type: String,
+    pub client_id: Uuid,
+    pub redirect_uri: String,
+    /// Space-separated; all of the client's scopes when left out
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+/// An authorization request that passed validation
+pub struct Authorization {
+    pub client: client::Model,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub state: Option<String>,
+    code_challenge: String,
+}
+
+/// Tokens issued by the token endpoint
+pub struct IssuedTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_in: i64,
+    pub scopes: Vec<String>,
+}
+
+/// A token found by introspection
+pub enum IntrospectedToken {
+    Access(tokens::Model, users::Model),
+    Refresh(tokens::Model, users::Model),
+}
+
+/// Check an authorization request before the user is asked to approve it
+///
+/// Requests with an unknown client or unregistered redirect URI must not
+/// send the browser back to the client, so every problem is reported to the
+/// user rather than to the redirect URI.
+pub async fn validate_authorization(
+    state: &AppState,
+    request: AuthorizationRequest,
+) -> AppResult<Authorization> {
+    let client = Clients::find_by_id(request.client_id)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| AppError::BadRequest("Unknown client".to_string()))?;
+
+    if !client.redirect_uris().contains(&request.redirect_uri) {
+        return Err(AppError::BadRequest(
+            "Redirect URI is not registered for this client".to_string(),
+        ));
+    }
+
+    if request.response_type != "code" {
+        return Err(AppError::BadRequest(
+            "Only the code response type is supported".to_string(),
+        ));
+    }
+
+    let scopes = match request.scope.as_deref() {
+        Some(scope) => super::scope::parse(scope),
+        None => client.scopes(),
+    };
+    let allowed = client.scopes();
+    if scopes.is_empty() {
+        return Err(AppError::BadRequest("No scope requested".to_string()));
+    }
+    if let Some(scope) = scopes.iter().find(|scope| !allowed.contains(scope)) {
+        return Err(AppError::BadRequest(format!(
+            "Client may not request the {} scope",
+            scope
+        )));
+    }
+
+    if request.code_challenge_method.as_deref() != Some("S256") {
+        return Err(AppError::BadRequest(
+            "PKCE with the S256 code challenge method is required".to_string(),
+        ));
+    }
+    let code_challenge = request
+        .code_challenge
+        .filter(|challenge| is_pkce_value(challenge))
+        .ok_or_else(|| AppError::BadRequest("Invalid code challenge".to_string()))?;
+
+    Ok(Authorization {
+        client,
+        redirect_uri: request.redirect_uri,
+        scopes,
+        state: request.state,
+        code_challenge,
+    })
+}
+
+/// The user's consent to a client, if any
+pub async fn find_consent<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    client_id: Uuid,
+) -> AppResult<Option<consent::Model>> {
+    Consents::find()
+        .filter(consent::Column::UserId.eq(user_id))
+        .filter(consent::Column::ClientId.eq(client_id))
+        .one(db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Record the user's consent and issue an authorization code, returning
+/// the URL to send the browser back to the client with
+pub async fn approve(
+    state: &AppState,
+    user: &users::Model,
+    client_info: &ClientInfo,
+    authorization: Authorization,
+) -> AppResult<String> {
+    let now = chrono::Utc::now().fixed_offset();
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    // Expired codes are cleaned up here rather than by a job
+    AuthorizationCodes::delete_many()
+        .filter(authorization_code::Column::ExpiresAt.lt(now))
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+
+    let client = &authorization.client;
+    let existing = find_consent(&txn, user.id, client.id).await?;
+    if !existing
+        .as_ref()
+        .is_some_and(|consent| consent.covers(&authorization.scopes))
+    {
+        let scopes = match &existing {
+            Some(consent) => super::scope::parse(&format!(
+                "{} {}",
+                consent.scopes,
+                authorization.scopes.join(" ")
+            )),
+            None => authorization.scopes.clone(),
+        };
+
+        match existing {
+            Some(consent) => {
+                let mut active: consent::ActiveModel = consent.into();
+                active.scopes = Set(scopes.join(" "));
+                active.updated_at = Set(now);
+                active.update(&txn).await.map_err(AppError::from)?;
+            }
+            None => {
+                consent::ActiveModel {
+                    id: Set(Uuid::new_v4()),
+                    user_id: Set(user.id),
+                    client_id: Set(client.id),
+                    scopes: Set(scopes.join(" ")),
+                    created_at: Set(now),
+                    updated_at: Set(now),
+                }
+                .insert(&txn)
+                .await
+                .map_err(AppError::from)?;
+            }
+        }
+
+        audit::record(
+            &txn,
+            AuditEvent::new("oauth.consent_granted")
+                .actor(user.id)
+                .ip_address(client_info.ip)
+                .details(serde_json::json!({
+                    "client_id": client.id,
+                    "client_name": client.name,
+                    "scopes": scopes,
+                })),
+        )
+        .await?;
+    }
+
+    let code = token::generate_token();
+    authorization_code::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        code_hash: Set(token::hash_token(&code)),
+        client_id: Set(client.id),
+        user_id: Set(user.id),
+        redirect_uri: Set(authorization.redirect_uri.clone()),
+        scopes: Set(authorization.scopes.join(" ")),
+        code_challenge: Set(authorization.code_challenge.clone()),
+        expires_at: Set(
+            now + chrono::Duration::seconds(state.config.oauth_authorization_code_ttl_secs)
+        ),
+        used_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(&txn)
+    .await
+    .map_err(AppError::from)?;
+
+    txn.commit().await.map_err(AppError::from)?;
+
+    redirect_url(&authorization, &[("code", &code)])
+}
+
+/// The URL to send the browser back to the client with when the user
+/// declines
+pub fn deny(authorization: &Authorization) -> AppResult<String> {
+    redirect_url(authorization, &[("error", "access_denied")])
+}
+
+fn redirect_url(authorization: &Authorization, params: &[(&str, &str)]) -> AppResult<String> {
+    let mut url = reqwest::Url::parse(&authorization.redirect_uri)
+        .map_err(|_| AppError::internal("Registered redirect URI is invalid"))?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.extend_pairs(params);
+        if let Some(state) = &authorization.state {
+            query.append_pair("state", state);
+        }
+    }
+
+    Ok(url.into())
+}
+
+/// Find a client by the credentials it sent to the token, introspection or
+/// revocation endpoint
+///
+/// Confidential clients must send their secret. Public clients send only
+/// their id, which is enough where `require_secret` is false.
+pub async fn authenticate_client(
+    state: &AppState,
+    client_id: &str,
+    client_secret: Option<&str>,
+    require_secret: bool,
+) -> OAuthResult<client::Model> {
+    let client_id = Uuid::parse_str(client_id).map_err(|_| OAuthError::invalid_client())?;
+    let client = Clients::find_by_id(client_id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(OAuthError::invalid_client)?;
+
+    let authenticated = match (&client.secret_hash, client_secret) {
+        (Some(secret_hash), Some(secret)) => token::hash_token(secret) == *secret_hash,
+        (None, None) => !require_secret,
+        _ => false,
+    };
+    if !authenticated {
+        return Err(OAuthError::invalid_client());
+    }
+
+    Ok(client)
+}
+
+/// Redeem an authorization code (`grant_type=authorization_code`)
+///
+/// A code presented twice was intercepted, so the tokens already issued for
+/// it are revoked.
+pub async fn exchange_code(
+    state: &AppState,
+    client: &client::Model,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: &str,
+) -> OAuthResult<IssuedTokens> {
+    ensure_grant_type(client, "authorization_code")?;
+
+    let invalid = || OAuthError::invalid_grant("Invalid authorization code");
+    let authorization = AuthorizationCodes::find()
+        .filter(authorization_code::Column::CodeHash.eq(token::hash_token(code)))
+        .one(&state.db)
+        .await?
+        .filter(|authorization| authorization.client_id == client.id)
+        .ok_or_else(invalid)?;
+
+    if authorization.used_at.is_some() {
+        revoke_family(&state.db, authorization.id).await?;
+        tracing::warn!(
+            "Authorization code reuse detected for client {}, revoked its tokens",
+            client.id
+        );
+        return Err(invalid());
+    }
+
+    let now = chrono::Utc::now().fixed_offset();
+    if authorization.expires_at < now {
+        return Err(OAuthError::invalid_grant("Authorization code has expired"));
+    }
+    if authorization.redirect_uri != redirect_uri {
+        return Err(OAuthError::invalid_grant("Redirect URI does not match"));
+    }
+    if !is_pkce_value(code_verifier)
+        || URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+            != authorization.code_challenge
+    {
+        return Err(OAuthError::invalid_grant("Code verifier does not match"));
+    }
+
+    let scopes = super::scope::parse(&authorization.scopes);
+    let user = find_authorizing_user(state, authorization.user_id, client, &scopes).await?;
+
+    let txn = state.db.begin().await?;
+
+    // Only one concurrent exchange can win the conditional update
+    let result = AuthorizationCodes::update_many()
+        .col_expr(authorization_code::Column::UsedAt, Expr::value(now))
+        .filter(authorization_code::Column::Id.eq(authorization.id))
+        .filter(authorization_code::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await?;
+    if result.rows_affected == 0 {
+        txn.rollback().await?;
+        revoke_family(&state.db, authorization.id).await?;
+        return Err(invalid());
+    }
+
+    let tokens =
+        insert_tokens(state, &txn, client, user.id, authorization.id, scopes, true).await?;
+    txn.commit().await?;
+
+    Ok(tokens)
+}
+
+/// Exchange a refresh token for new tokens (`grant_type=refresh_token`)
+///
+/// The presented token is revoked and replaced by a successor in the same
+/// family, optionally with fewer scopes. Presenting a token that was
+/// already revoked is treated as theft and revokes the whole family.
+pub async fn refresh(
+    state: &AppState,
+    client: &client::Model,
+    refresh_token: &str,
+    scope: Option<&str>,
+) -> OAuthResult<IssuedTokens> {
+    ensure_grant_type(client, "refresh_token")?;
+
+    let invalid = || OAuthError::invalid_grant("Invalid refresh token");
+    let current = Tokens::find()
+        .filter(tokens::Column::RefreshTokenHash.eq(token::hash_token(refresh_token)))
+        .one(&state.db)
+        .await?
+        .filter(|current| current.client_id == client.id)
+        .ok_or_else(invalid)?;
+
+    if current.revoked_at.is_some() {
+        revoke_family(&state.db, current.family_id).await?;
+        tracing::warn!(
+            "OAuth refresh token reuse detected for client {}, revoked family {}",
+            client.id,
+            current.family_id
+        );
+        return Err(invalid());
+    }
+    if !current.is_refreshable() {
+        return Err(OAuthError::invalid_grant("Refresh token has expired"));
+    }
+
+    let granted = current.scopes();
+    let scopes = match scope {
+        Some(scope) => super::scope::parse(scope),
+        None => granted.clone(),
+    };
+    if scopes.is_empty() || scopes.iter().any(|scope| !granted.contains(scope)) {
+        return Err(OAuthError::invalid_scope(
+            "Scopes must be among those originally granted",
+        ));
+    }
+
+    let user = find_authorizing_user(state, current.user_id, client, &scopes).await?;
+
+    let txn = state.db.begin().await?;
+
+    let result = Tokens::update_many()
+        .col_expr(
+            tokens::Column::RevokedAt,
+            Expr::value(chrono::Utc::now().fixed_offset()),
+        )
+        .filter(tokens::Column::Id.eq(current.id))
+        .filter(tokens::Column::RevokedAt.is_null())
+        .exec(&txn)
+        .await?;
+    if result.rows_affected == 0 {
+        txn.rollback().await?;
+        revoke_family(&state.db, current.family_id).await?;
+        return Err(invalid());
+    }
+
+    let tokens = insert_tokens(
+        state,
+        &txn,
+        client,
+        user.id,
+        current.family_id,
+        scopes,
+        true,
+    )
+    .await?;
+    txn.commit().await?;
+
+    Ok(tokens)
+}
+
+/// Issue a token acting as the client's service account
+/// (`grant_type=client_credentials`); it comes without a refresh token
+pub async fn client_credentials(
+    state: &AppState,
+    client: &client::Model,
+    scope: Option<&str>,
+) -> OAuthResult<IssuedTokens> {
+    ensure_grant_type(client, "client_credentials")?;
+
+    let allowed = client.scopes();
+    let scopes = match scope {
+        Some(scope) => super::scope::parse(scope),
+        None => allowed.clone(),
+    };
+    if scopes.is_empty() || scopes.iter().any(|scope| !allowed.contains(scope)) {
+        return Err(OAuthError::invalid_scope(
+            "Scopes must be among those registered for the client",
+        ));
+    }
+
+    let service_account = Users::find_by_id(client.service_account_id.unwrap_or_default())
+        .one(&state.db)
+        .await?
+        .filter(|user| user.is_active)
+        .ok_or_else(|| OAuthError::unauthorized_client("Service account is not available"))?;
+
+    insert_tokens(
+        state,
+        &state.db,
+        client,
+        service_account.id,
+        Uuid::new_v4(),
+        scopes,
+        false,
+    )
+    .await
+}
+
+/// Resolve an access token to itself and the user requests made with it
+/// act as
+pub async fn authenticate(
+    state: &AppState,
+    access_token: &str,
+) -> AppResult<(tokens::Model, users::Model)> {
+    let token = Tokens::find()
+        .filter(tokens::Column::AccessTokenHash.eq(token::hash_token(access_token)))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .filter(tokens::Model::is_active)
+        .ok_or(AppError::Unauthorized("Invalid access token".to_string()))?;
+
+    let user = Users::find_by_id(token.user_id)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .filter(|user| user.is_active)
+        .ok_or(AppError::Unauthorized(
+            "Account is not available".to_string(),
+        ))?;
+
+    Ok((token, user))
+}
+
+/// Look up an active token issued to the client (RFC 7662); tokens of other
+/// clients are reported as inactive, like unknown ones
+pub async fn introspect(
+    state: &AppState,
+    client: &client::Model,
+    raw_token: &str,
+) -> OAuthResult<Option<IntrospectedToken>> {
+    let Some((token, is_refresh)) = find_token(state, client, raw_token).await? else {
+        return Ok(None);
+    };
+    let active = if is_refresh {
+        token.is_refreshable()
+    } else {
+        token.is_active()
+    };
+    if !active {
+        return Ok(None);
+    }
+
+    let Some(user) = Users::find_by_id(token.user_id)
+        .one(&state.db)
+        .await?
+        .filter(|user| user.is_active)
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(if is_refresh {
+        IntrospectedToken::Refresh(token, user)
+    } else {
+        IntrospectedToken::Access(token, user)
+    }))
+}
+
+/// Revoke a token issued to the client (RFC 7009)
+///
+/// Revoking a refresh token also revokes every token issued from the same
+/// authorization. Unknown tokens are ignored, as the RFC requires.
+pub async fn revoke(state: &AppState, client: &client::Model, raw_token: &str) -> OAuthResult<()> {
+    let Some((token, is_refresh)) = find_token(state, client, raw_token).await? else {
+        return Ok(());
+    };
+
+    if is_refresh {
+        revoke_family(&state.db, token.family_id).await?;
+    } else {
+        Tokens::update_many()
+            .col_expr(
+                tokens::Column::RevokedAt,
+                Expr::value(chrono::Utc::now().fixed_offset()),
+            )
+            .filter(tokens::Column::Id.eq(token.id))
+            .filter(tokens::Column::RevokedAt.is_null())
+            .exec(&state.db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// The user's consents, with the clients they were given to
+pub async fn list_consents(
+    state: &AppState,
+    user_id: Uuid,
+) -> AppResult<Vec<(consent::Model, client::Model)>> {
+    let rows = Consents::find()
+        .filter(consent::Column::UserId.eq(user_id))
+        .find_also_related(Clients)
+        .order_by_asc(consent::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(consent, client)| client.map(|c| (consent, c)))
+        .collect())
+}
+
+/// Withdraw the user's consent to a client and revoke its tokens for them
+pub async fn revoke_consent(
+    state: &AppState,
+    user: &users::Model,
+    client_info: &ClientInfo,
+    client_id: Uuid,
+) -> AppResult<()> {
+    let (consent, client) = Consents::find()
+        .filter(consent::Column::UserId.eq(user.id))
+        .filter(consent::Column::ClientId.eq(client_id))
+        .find_also_related(Clients)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .and_then(|(consent, client)| client.map(|c| (consent, c)))
+        .ok_or_else(|| AppError::NotFound(format!("No consent given to client {}", client_id)))?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    Consents::delete_by_id(consent.id)
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+    Tokens::update_many()
+        .col_expr(
+            tokens::Column::RevokedAt,
+            Expr::value(chrono::Utc::now().fixed_offset()),
+        )
+        .filter(tokens::Column::UserId.eq(user.id))
+        .filter(tokens::Column::ClientId.eq(client.id))
+        .filter(tokens::Column::RevokedAt.is_null())
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+
+    audit::record(
+        &txn,
+        AuditEvent::new("oauth.consent_revoked")
+            .actor(user.id)
+            .ip_address(client_info.ip)
+            .details(serde_json::json!({
+                "client_id": client.id,
+                "client_name": client.name,
+            })),
+    )
+    .await?;
+
+    txn.commit().await.map_err(AppError::from)
+}
+
+/// Revoke every still-active token in a family
+async fn revoke_family<C: ConnectionTrait>(db: &C, family_id: Uuid) -> AppResult<()> {
+    Tokens::update_many()
+        .col_expr(
+            tokens::Column::RevokedAt,
+            Expr::value(chrono::Utc::now().fixed_offset()),
+        )
+        .filter(tokens::Column::FamilyId.eq(family_id))
+        .filter(tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+fn ensure_grant_type(client: &client::Model, grant_type: &str) -> OAuthResult<()> {
+    if !client.grant_types().contains(&grant_type) {
+        return Err(OAuthError::unauthorized_client(format!(
+            "Client may not use the {} grant",
+            grant_type
+        )));
+    }
+
+    Ok(())
+}
+
+/// The user a client acts for, as long as they can still sign in and have
+/// not withdrawn their consent to `scopes`
+async fn find_authorizing_user(
+    state: &AppState,
+    user_id: Uuid,
+    client: &client::Model,
+    scopes: &[String],
+) -> OAuthResult<users::Model> {
+    let user = Users::find_by_id(user_id)
+        .one(&state.db)
+        .await?
+        .filter(|user| user.is_active)
+        .filter(|user| verification::ensure_sign_in_allowed(&state.config, user).is_ok())
+        .ok_or_else(|| OAuthError::invalid_grant("Account is not available"))?;
+
+    let consented = find_consent(&state.db, user.id, client.id)
+        .await?
+        .is_some_and(|consent| consent.covers(scopes));
+    if !consented {
+        return Err(OAuthError::invalid_grant("Consent has been withdrawn"));
+    }
+
+    Ok(user)
+}
+
+/// A token of the client by its raw value, and whether it is a refresh token
+async fn find_token(
+    state: &AppState,
+    client: &client::Model,
+    raw_token: &str,
+) -> OAuthResult<Option<(tokens::Model, bool)>> {
+    let hash = token::hash_token(raw_token);
+    let is_refresh = raw_token.starts_with(REFRESH_TOKEN_PREFIX);
+    let column = if is_refresh {
+        tokens::Column::RefreshTokenHash
+    } else {
+        tokens::Column::AccessTokenHash
+    };
+
+    let token = Tokens::find()
+        .filter(column.eq(hash))
+        .one(&state.db)
+        .await?
+        .filter(|token| token.client_id == client.id);
+
+    Ok(token.map(|token| (token, is_refresh)))
+}
+
+async fn insert_tokens<C: ConnectionTrait>(
+    state: &AppState,
+    db: &C,
+    client: &client::Model,
+    user_id: Uuid,
+    family_id: Uuid,
+    scopes: Vec<String>,
+    with_refresh_token: bool,
+) -> OAuthResult<IssuedTokens> {
+    let now = chrono::Utc::now().fixed_offset();
+    let access_token = format!("{}{}", ACCESS_TOKEN_PREFIX, token::generate_token());
+    let refresh_token =
+        with_refresh_token.then(|| format!("{}{}", REFRESH_TOKEN_PREFIX, token::generate_token()));
+    let expires_in = state.config.access_token_ttl_secs;
+
+    tokens::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        client_id: Set(client.id),
+        user_id: Set(user_id),
+        family_id: Set(family_id),
+        access_token_hash: Set(token::hash_token(&access_token)),
+        refresh_token_hash: Set(refresh_token.as_deref().map(token::hash_token)),
+        scopes: Set(scopes.join(" ")),
+        expires_at: Set(now + chrono::Duration::seconds(expires_in)),
+        refresh_expires_at: Set(refresh_token
+            .as_ref()
+            .map(|_| now + chrono::Duration::seconds(state.config.refresh_token_ttl_secs))),
+        revoked_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(IssuedTokens {
+        access_token,
+        refresh_token,
+        expires_in,
+        scopes,
+    })
+}
+
+/// Whether a PKCE verifier or `S256` challenge has the allowed length and
+/// characters (RFC 7636, section 4.1)
+fn is_pkce_value(value: &str) -> bool {
+    (43..=128).contains(&value.len())
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::organizations::service as organizations_service;
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_state, unique_email};
+
+    /// Verifier and challenge from RFC 7636, appendix B
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+    const REDIRECT_URI: &str = "https://client.example.com/callback";
+
+    /// A user owning an organization with a public client
+    async fn public_client(state: &AppState) -> (users::Model, client::Model) {
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Owner",
+        )
+        .await
+        .unwrap();
+        let name = format!("OAuth {}", Uuid::new_v4().simple());
+        let (organization, _) =
+            organizations_service::create_organization(state, &user, &name, None)
+                .await
+                .unwrap();
+
+        let now = chrono::Utc::now().fixed_offset();
+        let client = client::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            organization_id: Set(organization.id),
+            name: Set("Test client".to_string()),
+            secret_hash: Set(None),
+            redirect_uris: Set(REDIRECT_URI.to_string()),
+            scopes: Set("users:read".to_string()),
+            service_account_id: Set(None),
+            created_by: Set(Some(user.id)),
+            created_at: Set(now),
+            updated_at: Set(now),
+        }
+        .insert(&state.db)
+        .await
+        .unwrap();
+
+        (user, client)
+    }
+
+    fn request(
+        client: &client::Model,
+        challenge: Option<&str>,
+        method: Option<&str>,
+    ) -> AuthorizationRequest {
+        AuthorizationRequest {
+            response_type: "code".to_string(),
+            client_id: client.id,
+            redirect_uri: REDIRECT_URI.to_string(),
+            scope: None,
+            state: Some("xyz".to_string()),
+            code_challenge: challenge.map(str::to_string),
+            code_challenge_method: method.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn accepts_pkce_values_of_the_allowed_length_and_alphabet() {
+        assert!(is_pkce_value(VERIFIER));
+        assert!(is_pkce_value(CHALLENGE));
+        assert!(is_pkce_value(&"a".repeat(43)));
+        assert!(is_pkce_value(&"Az09-._~".repeat(16)));
+
+        assert!(!is_pkce_value(&"a".repeat(42)));
+        assert!(!is_pkce_value(&"a".repeat(129)));
+        for invalid in ["+", "/", "=", " ", "é"] {
+            let value = format!("{}{}", "a".repeat(43), invalid);
+            assert!(!is_pkce_value(&value), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn derives_the_rfc_challenge_from_its_verifier() {
+        assert_eq!(
+            URL_SAFE_NO_PAD.encode(Sha256::digest(VERIFIER.as_bytes())),
+            CHALLENGE
+        );
+    }
+
+    #[tokio::test]
+    async fn requires_an_s256_challenge() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, client) = public_client(&state).await;
+
+        for (challenge, method) in [
+            (Some(CHALLENGE), None),
+            (Some(CHALLENGE), Some("plain")),
+            (None, Some("S256")),
+            (Some("too-short"), Some("S256")),
+        ] {
+            let result = validate_authorization(&state, request(&client, challenge, method)).await;
+            assert!(
+                matches!(result, Err(AppError::BadRequest(_))),
+                "{:?} {:?}",
+                challenge,
+                method
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn exchanges_a_code_only_with_its_verifier() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (user, client) = public_client(&state).await;
+        let client_info = ClientInfo {
+            ip: None,
+            user_agent: None,
+        };
+
+        let authorization =
+            validate_authorization(&state, request(&client, Some(CHALLENGE), Some("S256")))
+                .await
+                .unwrap();
+        let redirect = approve(&state, &user, &client_info, authorization)
+            .await
+            .unwrap();
+        let code = reqwest::Url::parse(&redirect)
+            .unwrap()
+            .query_pairs()
+            .find(|(name, _)| name == "code")
+            .unwrap()
+            .1
+            .into_owned();
+
+        let other_verifier = "a".repeat(43);
+        for verifier in [other_verifier.as_str(), "short", ""] {
+            let err = exchange_code(&state, &client, &code, REDIRECT_URI, verifier)
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(
+                (err.error, err.description.as_str()),
+                ("invalid_grant", "Code verifier does not match")
+            );
+        }
+
+        let tokens = exchange_code(&state, &client, &code, REDIRECT_URI, VERIFIER)
+            .await
+            .unwrap();
+        assert!(tokens.access_token.starts_with(ACCESS_TOKEN_PREFIX));
+        assert_eq!(tokens.scopes, ["users:read"]);
+
+        // A code is redeemed once, even with the right verifier
+        let err = exchange_code(&state, &client, &code, REDIRECT_URI, VERIFIER)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.error, "invalid_grant");
+    }
+}
@@ -0,0 +1,46 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A sign-in at a provider in progress, looked up by the SHA-256 hash of the
+/// `state` parameter when the browser comes back
+///
+/// Requests with a `user_id` link the provider account to that signed-in
+/// user; requests without one sign in or sign up.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "oidc_auth_requests")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub state_hash: String,
+    pub provider: String,
+    pub user_id: Option<Uuid>,
+    /// Expected `nonce` claim of the ID token
+    #[serde(skip_serializing)]
+    pub nonce: String,
+    /// PKCE verifier sent with the authorization code
+    #[serde(skip_serializing)]
+    pub code_verifier: String,
+    pub expires_at: DateTimeWithTimeZone,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::modules::users::entity::Entity",
+        from = "Column::UserId",
+        to = "crate::modules::users::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<crate::modules::users::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
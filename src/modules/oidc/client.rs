@@ -0,0 +1,556 @@
+//! Talking to OpenID Connect providers: discovery, signing keys, the token
+//! endpoint and ID token validation
+//!
+//! Discovery documents and key sets are cached per issuer. A token signed
+//! with a key id the cache does not know triggers one early key set refresh,
+//! so provider key rotation is picked up without waiting for the cache to
+//! expire.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use jsonwebtoken::{jwk::Jwk, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+use crate::config::OidcProviderConfig;
+use crate::error::{AppError, AppResult};
+
+/// Minimum time between two key set refreshes caused by unknown key ids,
+/// so tokens with made-up key ids cannot make us hammer the provider
+const MIN_JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// ID token signature algorithms accepted; HMAC is excluded since the
+/// client secret is not a key the provider should sign with here
+const ALLOWED_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// The parts of a provider's discovery document this client uses
+#[derive(Debug, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    #[serde(default)]
+    pub token_endpoint_auth_methods_supported: Option<Vec<String>>,
+}
+
+/// Claims read from a validated ID token
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub azp: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Some providers send this as a string
+    #[serde(default)]
+    pub email_verified: Option<serde_json::Value>,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl IdTokenClaims {
+    /// Whether the provider vouches for the email address
+    pub fn email_verified(&self) -> bool {
+        match &self.email_verified {
+            Some(serde_json::Value::Bool(verified)) => *verified,
+            Some(serde_json::Value::String(verified)) => verified == "true",
+            _ => false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenEndpointResponse {
+    id_token: Option<String>,
+}
+
+struct Cached<T> {
+    value: Arc<T>,
+    fetched_at: Instant,
+}
+
+/// HTTP client for OpenID Connect providers, shared through `AppState`
+pub struct OidcClient {
+    http: reqwest::Client,
+    cache_ttl: Duration,
+    metadata: RwLock<HashMap<String, Cached<ProviderMetadata>>>,
+    keys: RwLock<HashMap<String, Cached<Vec<Jwk>>>>,
+}
+
+impl OidcClient {
+    pub fn new(cache_ttl: Duration) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("failed to build HTTP client")?;
+
+        Ok(Self {
+            http,
+            cache_ttl,
+            metadata: RwLock::new(HashMap::new()),
+            keys: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// The provider's discovery document, fetched on first use and cached
+    pub async fn metadata(&self, issuer: &str) -> AppResult<Arc<ProviderMetadata>> {
+        if let Some(cached) = self.metadata.read().unwrap().get(issuer) {
+            if cached.fetched_at.elapsed() < self.cache_ttl {
+                return Ok(cached.value.clone());
+            }
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = self.get_json(&url).await?;
+        if metadata.issuer != issuer {
+            return Err(anyhow!(
+                "discovery document of {} names a different issuer: {}",
+                issuer,
+                metadata.issuer
+            )
+            .into());
+        }
+
+        let metadata = Arc::new(metadata);
+        self.metadata.write().unwrap().insert(
+            issuer.to_string(),
+            Cached {
+                value: metadata.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+
+        Ok(metadata)
+    }
+
+    /// Trade an authorization code for the provider's ID token
+    pub async fn exchange_code(
+        &self,
+        provider: &OidcProviderConfig,
+        metadata: &ProviderMetadata,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+    ) -> AppResult<String> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", code_verifier),
+            ("client_id", provider.client_id.as_str()),
+        ];
+
+        let mut request = self.http.post(&metadata.token_endpoint);
+        if let Some(secret) = &provider.client_secret {
+            // client_secret_basic is the default when the provider does not
+            // list its methods
+            let basic = metadata
+                .token_endpoint_auth_methods_supported
+                .as_ref()
+                .is_none_or(|methods| methods.iter().any(|m| m == "client_secret_basic"));
+            if basic {
+                request = request.basic_auth(&provider.client_id, Some(secret));
+            } else {
+                form.push(("client_secret", secret));
+            }
+        }
+
+        let response = request
+            .form(&form)
+            .send()
+            .await
+            .context("token request failed")?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            tracing::warn!(
+                "Token endpoint of {} answered {}: {}",
+                provider.issuer,
+                status,
+                body
+            );
+            return Err(sign_in_failed());
+        }
+
+        let tokens: TokenEndpointResponse = response
+            .json()
+            .await
+            .context("malformed token endpoint response")?;
+        tokens.id_token.ok_or_else(|| {
+            tracing::warn!("Token endpoint of {} returned no ID token", provider.issuer);
+            sign_in_failed()
+        })
+    }
+
+    /// Check an ID token's signature, issuer, audience, expiry and nonce
+    pub async fn validate_id_token(
+        &self,
+        provider: &OidcProviderConfig,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+        nonce: &str,
+    ) -> AppResult<IdTokenClaims> {
+        let invalid = |reason: &str| {
+            tracing::warn!("Rejected ID token from {}: {}", provider.issuer, reason);
+            sign_in_failed()
+        };
+
+        let header = jsonwebtoken::decode_header(id_token).map_err(|_| invalid("malformed"))?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(invalid("unsupported signature algorithm"));
+        }
+
+        let jwk = self
+            .signing_key(metadata, header.kid.as_deref())
+            .await?
+            .ok_or_else(|| invalid("unknown signing key"))?;
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| invalid("unusable signing key"))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|err| invalid(&err.to_string()))?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(invalid("nonce mismatch"));
+        }
+        if claims
+            .azp
+            .as_ref()
+            .is_some_and(|azp| azp != &provider.client_id)
+        {
+            return Err(invalid("authorized party mismatch"));
+        }
+
+        Ok(claims)
+    }
+
+    /// Find the key a token was signed with, refreshing the cached key set
+    /// once if the key id is not in it
+    async fn signing_key(
+        &self,
+        metadata: &ProviderMetadata,
+        kid: Option<&str>,
+    ) -> AppResult<Option<Jwk>> {
+        let cached = self
+            .keys
+            .read()
+            .unwrap()
+            .get(&metadata.jwks_uri)
+            .map(|cached| (cached.value.clone(), cached.fetched_at));
+
+        let refresh = match &cached {
+            None => true,
+            Some((_, fetched_at)) if fetched_at.elapsed() >= self.cache_ttl => true,
+            Some((keys, fetched_at)) => {
+                find_key(keys, kid).is_none() && fetched_at.elapsed() >= MIN_JWKS_REFRESH_INTERVAL
+            }
+        };
+        if !refresh {
+            return Ok(cached.and_then(|(keys, _)| find_key(&keys, kid)));
+        }
+
+        let keys = Arc::new(self.fetch_keys(&metadata.jwks_uri).await?);
+        self.keys.write().unwrap().insert(
+            metadata.jwks_uri.clone(),
+            Cached {
+                value: keys.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+
+        Ok(find_key(&keys, kid))
+    }
+
+    /// Fetch a key set, skipping keys of types this client cannot use
+    /// rather than failing on them
+    async fn fetch_keys(&self, jwks_uri: &str) -> AppResult<Vec<Jwk>> {
+        #[derive(Deserialize)]
+        struct KeySet {
+            keys: Vec<serde_json::Value>,
+        }
+
+        let set: KeySet = self.get_json(jwks_uri).await?;
+        Ok(set
+            .keys
+            .into_iter()
+            .filter_map(|key| serde_json::from_value::<Jwk>(key).ok())
+            .filter(|key| {
+                !matches!(
+                    key.algorithm,
+                    jsonwebtoken::jwk::AlgorithmParameters::OctetKey(_)
+                ) && !matches!(
+                    key.common.public_key_use,
+                    Some(jsonwebtoken::jwk::PublicKeyUse::Encryption)
+                )
+            })
+            .collect())
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> AppResult<T> {
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("failed to fetch {}", url))?;
+
+        Ok(response
+            .json()
+            .await
+            .with_context(|| format!("malformed JSON at {}", url))?)
+    }
+}
+
+/// The key with the given id, or the only key when the token names none
+fn find_key(keys: &[Jwk], kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => keys
+            .iter()
+            .find(|key| key.common.key_id.as_deref() == Some(kid))
+            .cloned(),
+        None if keys.len() == 1 => keys.first().cloned(),
+        None => None,
+    }
+}
+
+fn sign_in_failed() -> AppError {
+    AppError::Unauthorized("Sign-in with the identity provider failed".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+
+    use super::super::test_provider::{TestKey, TestProvider, CLIENT_SECRET};
+    use super::*;
+
+    const NONCE: &str = "test-nonce";
+    const REDIRECT_URI: &str = "http://localhost/auth/oidc/callback";
+
+    fn client() -> OidcClient {
+        OidcClient::new(Duration::from_secs(3600)).unwrap()
+    }
+
+    async fn validate(
+        client: &OidcClient,
+        provider: &TestProvider,
+        id_token: &str,
+    ) -> AppResult<IdTokenClaims> {
+        let metadata = client.metadata(&provider.issuer).await.unwrap();
+        client
+            .validate_id_token(&provider.config(), &metadata, id_token, NONCE)
+            .await
+    }
+
+    fn claims_for(sub: &str) -> Value {
+        json!({ "sub": sub, "nonce": NONCE, "email": "alice@example.com" })
+    }
+
+    /// Pretend the cached key set was fetched `age` ago
+    fn age_key_set(client: &OidcClient, jwks_uri: &str, age: Duration) {
+        let mut keys = client.keys.write().unwrap();
+        keys.get_mut(jwks_uri).unwrap().fetched_at = Instant::now().checked_sub(age).unwrap();
+    }
+
+    /// An authorization URL as the service builds it, for `code_verifier`
+    fn authorization_url(provider: &TestProvider, code_verifier: &str) -> String {
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+        reqwest::Url::parse_with_params(
+            &format!("{}/authorize", provider.issuer),
+            &[
+                ("response_type", "code"),
+                ("client_id", provider.config().client_id.as_str()),
+                ("redirect_uri", REDIRECT_URI),
+                ("state", "state"),
+                ("nonce", NONCE),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[tokio::test]
+    async fn caches_discovery_document() {
+        let provider = TestProvider::start().await;
+        let client = client();
+
+        let first = client.metadata(&provider.issuer).await.unwrap();
+        let second = client.metadata(&provider.issuer).await.unwrap();
+
+        assert_eq!(first.token_endpoint, format!("{}/token", provider.issuer));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(provider.discovery_fetches(), 1);
+    }
+
+    #[tokio::test]
+    async fn refetches_expired_discovery_document() {
+        let provider = TestProvider::start().await;
+        let client = OidcClient::new(Duration::ZERO).unwrap();
+
+        client.metadata(&provider.issuer).await.unwrap();
+        client.metadata(&provider.issuer).await.unwrap();
+
+        assert_eq!(provider.discovery_fetches(), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_discovery_document_of_another_issuer() {
+        let provider = TestProvider::start().await;
+        let issuer = format!("{}/", provider.issuer);
+
+        let result = client().metadata(&issuer).await;
+
+        assert!(matches!(result, Err(AppError::Internal(_))));
+    }
+
+    #[tokio::test]
+    async fn accepts_id_token_and_caches_key_set() {
+        let provider = TestProvider::start().await;
+        let client = client();
+
+        let claims = validate(&client, &provider, &provider.id_token(claims_for("1")))
+            .await
+            .unwrap();
+        validate(&client, &provider, &provider.id_token(claims_for("2")))
+            .await
+            .unwrap();
+
+        assert_eq!(claims.sub, "1");
+        assert_eq!(claims.email.as_deref(), Some("alice@example.com"));
+        assert_eq!(provider.key_set_fetches(), 1);
+    }
+
+    #[tokio::test]
+    async fn picks_up_rotated_key() {
+        let provider = TestProvider::start().await;
+        let client = client();
+        validate(&client, &provider, &provider.id_token(claims_for("1")))
+            .await
+            .unwrap();
+
+        provider.rotate_key();
+        let rotated = provider.id_token(claims_for("1"));
+
+        // Right after a fetch, unknown key ids do not trigger another
+        let result = validate(&client, &provider, &rotated).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+        assert_eq!(provider.key_set_fetches(), 1);
+
+        let jwks_uri = format!("{}/jwks", provider.issuer);
+        age_key_set(&client, &jwks_uri, MIN_JWKS_REFRESH_INTERVAL);
+        validate(&client, &provider, &rotated).await.unwrap();
+        assert_eq!(provider.key_set_fetches(), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_id_tokens() {
+        let provider = TestProvider::start().await;
+        let client = client();
+        let now = chrono::Utc::now().timestamp();
+
+        let with = |extra: Value| {
+            let mut claims = claims_for("1");
+            claims
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            provider.id_token(claims)
+        };
+        let forged_key = TestKey::generate(&provider.key_id());
+        let hmac = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(Algorithm::HS256),
+            &provider.claims(claims_for("1")),
+            &jsonwebtoken::EncodingKey::from_secret(CLIENT_SECRET.as_bytes()),
+        )
+        .unwrap();
+
+        let cases = [
+            ("wrong nonce", with(json!({ "nonce": "another" }))),
+            ("missing nonce", provider.id_token(json!({ "sub": "1" }))),
+            ("wrong audience", with(json!({ "aud": "another-client" }))),
+            (
+                "wrong issuer",
+                with(json!({ "iss": "https://evil.example.com" })),
+            ),
+            ("expired", with(json!({ "exp": now - 3600 }))),
+            (
+                "wrong authorized party",
+                with(json!({ "azp": "another-client" })),
+            ),
+            (
+                "missing subject",
+                provider.id_token(json!({ "nonce": NONCE })),
+            ),
+            (
+                "unpublished key",
+                forged_key.sign(&provider.claims(claims_for("1"))),
+            ),
+            ("symmetric signature", hmac),
+        ];
+
+        for (case, id_token) in cases {
+            let result = validate(&client, &provider, &id_token).await;
+            assert!(
+                matches!(result, Err(AppError::Unauthorized(_))),
+                "accepted ID token with {}",
+                case
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn exchanges_code_once_with_its_verifier() {
+        let provider = TestProvider::start().await;
+        let client = client();
+        let config = provider.config();
+        let metadata = client.metadata(&provider.issuer).await.unwrap();
+
+        let (_, code) =
+            provider.authorize(&authorization_url(&provider, "verifier"), claims_for("1"));
+        let id_token = client
+            .exchange_code(&config, &metadata, &code, REDIRECT_URI, "verifier")
+            .await
+            .unwrap();
+        validate(&client, &provider, &id_token).await.unwrap();
+
+        let reused = client
+            .exchange_code(&config, &metadata, &code, REDIRECT_URI, "verifier")
+            .await;
+        assert!(matches!(reused, Err(AppError::Unauthorized(_))));
+
+        let (_, code) =
+            provider.authorize(&authorization_url(&provider, "verifier"), claims_for("1"));
+        let stolen = client
+            .exchange_code(&config, &metadata, &code, REDIRECT_URI, "another")
+            .await;
+        assert!(matches!(stolen, Err(AppError::Unauthorized(_))));
+    }
+}
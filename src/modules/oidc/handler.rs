@@ -0,0 +1,221 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar};
+use uuid::Uuid;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::{
+    extractor::AuthUser,
+    handler::{AuthResponse, MfaChallengeResponse},
+    verification,
+};
+use crate::modules::mfa::service as mfa_service;
+use crate::modules::sessions::{cookies, mode::SignInMode};
+use crate::state::AppState;
+
+use super::entity;
+use super::service;
+
+/// HttpOnly cookie holding the browser binding of a sign-in in progress
+pub const LOGIN_COOKIE: &str = "oidc_login";
+
+#[derive(serde::Deserialize)]
+pub struct CallbackRequest {
+    /// The `state` and `code` query parameters the provider redirected with
+    pub state: String,
+    pub code: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ProviderResponse {
+    pub id: String,
+    pub name: String,
+}
+
+impl From<service::ProviderInfo> for ProviderResponse {
+    fn from(provider: service::ProviderInfo) -> Self {
+        Self {
+            id: provider.id,
+            name: provider.name,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct AuthorizationResponse {
+    pub authorization_url: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct IdentityResponse {
+    pub id: Uuid,
+    pub provider: String,
+    pub email: Option<String>,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+    pub last_used_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+impl From<entity::Model> for IdentityResponse {
+    fn from(model: entity::Model) -> Self {
+        Self {
+            id: model.id,
+            provider: model.provider,
+            email: model.email,
+            created_at: model.created_at,
+            last_used_at: model.last_used_at,
+        }
+    }
+}
+
+/// GET /api/auth/oidc/providers
+pub async fn list_providers(State(state): State<AppState>) -> impl IntoResponse {
+    Json(
+        service::providers(&state.config)
+            .into_iter()
+            .map(ProviderResponse::from)
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// POST /api/auth/oidc/:provider/authorize
+///
+/// Sets the [`LOGIN_COOKIE`] the callback has to come back with.
+pub async fn authorize(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let start = service::start_login(&state, &provider).await?;
+
+    let mut cookie = login_cookie(&state.config);
+    cookie.set_value(start.browser_binding);
+    cookie.set_http_only(true);
+    cookie.set_max_age(time::Duration::seconds(state.config.oidc_login_ttl_secs));
+
+    Ok((
+        CookieJar::new().add(cookie),
+        Json(AuthorizationResponse {
+            authorization_url: start.authorization_url,
+        }),
+    ))
+}
+
+/// POST /api/auth/oidc/callback
+///
+/// Signs in, or signs up when the provider account is new (201). Accounts
+/// that need a second factor get an [`MfaChallengeResponse`] like a password
+/// login. Only the browser holding the [`LOGIN_COOKIE`] of the sign-in can
+/// finish it; the cookie is cleared once it has been used.
+pub async fn callback(
+    State(state): State<AppState>,
+    mode: SignInMode,
+    jar: CookieJar,
+    Json(payload): Json<CallbackRequest>,
+) -> AppResult<Response> {
+    let binding = jar.get(LOGIN_COOKIE).map(|cookie| cookie.value());
+    let sign_in = service::complete_login(&state, &payload.state, &payload.code, binding).await?;
+
+    let mut cleared = login_cookie(&state.config);
+    cleared.make_removal();
+    let response = signed_in(&state, mode, sign_in).await?;
+    Ok((CookieJar::new().add(cleared), response).into_response())
+}
+
+async fn signed_in(
+    state: &AppState,
+    mode: SignInMode,
+    sign_in: service::ProviderSignIn,
+) -> AppResult<Response> {
+    let user = sign_in.user;
+
+    if !user.is_active {
+        return Err(AppError::Forbidden("Account is disabled".to_string()));
+    }
+
+    if sign_in.created {
+        if user.email_verified_at.is_none() {
+            if let Err(err) = verification::send_verification_email(state, &user).await {
+                tracing::warn!(
+                    "Failed to send verification email to user {}: {}",
+                    user.id,
+                    err
+                );
+            }
+        }
+
+        let (cookies, response) = match verification::ensure_sign_in_allowed(&state.config, &user) {
+            Ok(()) => AuthResponse::sign_in(state, &mode, user).await?,
+            Err(_) => (CookieJar::new(), AuthResponse::pending(user)),
+        };
+
+        return Ok((StatusCode::CREATED, cookies, Json(response)).into_response());
+    }
+
+    verification::ensure_sign_in_allowed(&state.config, &user)?;
+
+    if mfa_service::requires_challenge(&state.db, user.id).await? {
+        return Ok(Json(MfaChallengeResponse::start(state, user.id).await?).into_response());
+    }
+
+    let (cookies, response) = AuthResponse::sign_in(state, &mode, user).await?;
+    Ok((cookies, Json(response)).into_response())
+}
+
+/// GET /api/users/me/identities
+pub async fn list_identities(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let identities = service::list_identities(&state, auth.user.id).await?;
+    Ok(Json(
+        identities
+            .into_iter()
+            .map(IdentityResponse::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// POST /api/users/me/identities/:provider/authorize
+pub async fn authorize_link(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(provider): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let authorization_url = service::start_link(&state, &auth.user, &provider).await?;
+    Ok(Json(AuthorizationResponse { authorization_url }))
+}
+
+/// POST /api/users/me/identities
+///
+/// Completes a link started with the authorize endpoint above; the body is
+/// the provider's callback parameters.
+pub async fn link_identity(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<CallbackRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let identity =
+        service::complete_link(&state, &auth.user, &payload.state, &payload.code).await?;
+    Ok((StatusCode::CREATED, Json(IdentityResponse::from(identity))))
+}
+
+/// DELETE /api/users/me/identities/:id
+pub async fn unlink_identity(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::unlink(&state, &auth.user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn login_cookie(config: &AppConfig) -> Cookie<'static> {
+    cookies::cookie(config, LOGIN_COOKIE.to_string())
+}
@@ -0,0 +1,8 @@
+pub mod auth_request;
+pub mod client;
+pub mod entity;
+pub mod handler;
+pub mod routes;
+pub mod service;
+#[cfg(test)]
+mod test_provider;
@@ -0,0 +1,28 @@
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+
+use crate::state::AppState;
+
+use super::handler;
+
+/// Public sign-in with an identity provider, nested under `/api/auth/oidc`
+pub fn oidc_auth_routes() -> Router<AppState> {
+    Router::new()
+        .route("/providers", get(handler::list_providers))
+        .route("/{provider}/authorize", post(handler::authorize))
+        .route("/callback", post(handler::callback))
+}
+
+/// Linked provider accounts of the signed-in user, nested under
+/// `/api/users/me/identities` behind the users router's auth layer
+pub fn identity_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(handler::list_identities).post(handler::link_identity),
+        )
+        .route("/{provider}/authorize", post(handler::authorize_link))
+        .route("/{id}", delete(handler::unlink_identity))
+}
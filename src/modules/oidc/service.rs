@@ -0,0 +1,633 @@
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::config::{AppConfig, OidcProviderConfig};
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::token;
+use crate::modules::users::{entity as users, service as users_service};
+use crate::modules::webauthn::service as webauthn_service;
+use crate::state::AppState;
+
+use super::auth_request::{self, Entity as AuthRequests};
+use super::client::IdTokenClaims;
+use super::entity::{self as identities, Entity as Identities};
+
+/// A configured provider as shown on the sign-in page
+pub struct ProviderInfo {
+    pub id: String,
+    pub name: String,
+}
+
+/// Outcome of a sign-in through a provider
+pub struct ProviderSignIn {
+    pub user: users::Model,
+    /// Whether the sign-in created the account
+    pub created: bool,
+}
+
+/// A sign-in started with a provider
+pub struct LoginStart {
+    /// Where to send the browser
+    pub authorization_url: String,
+    /// Kept by the browser and presented again with the callback, so only the
+    /// browser that started the sign-in can finish it
+    pub browser_binding: String,
+}
+
+/// Configured providers, ordered by id
+pub fn providers(config: &AppConfig) -> Vec<ProviderInfo> {
+    let mut providers: Vec<ProviderInfo> = config
+        .oidc_providers
+        .iter()
+        .map(|(id, provider)| ProviderInfo {
+            id: id.clone(),
+            name: provider.display_name.clone().unwrap_or_else(|| id.clone()),
+        })
+        .collect();
+    providers.sort_by(|a, b| a.id.cmp(&b.id));
+    providers
+}
+
+/// Start signing in with a provider
+///
+/// The browser binding is the hash of the `state` parameter: the raw value
+/// travels through the provider, the hash stays in the browser.
+pub async fn start_login(state: &AppState, provider_id: &str) -> AppResult<LoginStart> {
+    let (authorization_url, raw_state) = start_authorization(state, provider_id, None).await?;
+    Ok(LoginStart {
+        authorization_url,
+        browser_binding: token::hash_token(&raw_state),
+    })
+}
+
+/// Start linking a provider account to a signed-in user
+pub async fn start_link(
+    state: &AppState,
+    user: &users::Model,
+    provider_id: &str,
+) -> AppResult<String> {
+    let (authorization_url, _) = start_authorization(state, provider_id, Some(user.id)).await?;
+    Ok(authorization_url)
+}
+
+/// Finish a sign-in once the provider redirected back with a code
+///
+/// A known provider account signs in its user. An unknown one creates a
+/// passwordless account, unless its email address already belongs to an
+/// account: a provider asserting an address is not allowed to take over an
+/// existing account, whose owner has to sign in and link the provider instead.
+///
+/// A callback without the binding of the browser that started the sign-in is
+/// rejected before the request is used, so an attacker cannot sign a victim's
+/// browser in to the attacker's account by sending it the attacker's callback.
+pub async fn complete_login(
+    state: &AppState,
+    raw_state: &str,
+    code: &str,
+    browser_binding: Option<&str>,
+) -> AppResult<ProviderSignIn> {
+    if browser_binding != Some(token::hash_token(raw_state).as_str()) {
+        return Err(invalid_state());
+    }
+
+    let request = consume_request(&state.db, raw_state).await?;
+    if request.user_id.is_some() {
+        return Err(invalid_state());
+    }
+
+    let claims = verify_callback(state, &request, code).await?;
+
+    if let Some(identity) = find_identity(&state.db, &request.provider, &claims.sub).await? {
+        let user_id = identity.user_id;
+        touch_identity(&state.db, identity, &claims).await?;
+        return Ok(ProviderSignIn {
+            user: users_service::find_by_id(state, user_id).await?,
+            created: false,
+        });
+    }
+
+    let email = claims.email.as_deref().ok_or(AppError::BadRequest(
+        "The identity provider did not share an email address".to_string(),
+    ))?;
+    if users_service::find_by_email(state, email).await?.is_some() {
+        return Err(AppError::Conflict(
+            "An account with this email already exists; sign in and link the provider from your account settings"
+                .to_string(),
+        ));
+    }
+
+    let name = claims
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| email.split('@').next().unwrap_or(email));
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let mut user =
+        users_service::create_passwordless_user_in(&txn, Uuid::new_v4(), email, name).await?;
+    if claims.email_verified() {
+        user = users_service::mark_email_verified(&txn, user).await?;
+    }
+    insert_identity(&txn, user.id, &request.provider, &claims).await?;
+    txn.commit().await.map_err(AppError::from)?;
+
+    Ok(ProviderSignIn {
+        user,
+        created: true,
+    })
+}
+
+/// Finish linking a provider account to the signed-in user who started it
+pub async fn complete_link(
+    state: &AppState,
+    user: &users::Model,
+    raw_state: &str,
+    code: &str,
+) -> AppResult<identities::Model> {
+    let request = consume_request(&state.db, raw_state).await?;
+    if request.user_id != Some(user.id) {
+        return Err(invalid_state());
+    }
+
+    let claims = verify_callback(state, &request, code).await?;
+
+    if let Some(identity) = find_identity(&state.db, &request.provider, &claims.sub).await? {
+        return Err(AppError::Conflict(if identity.user_id == user.id {
+            "This account is already linked".to_string()
+        } else {
+            "This account is linked to another user".to_string()
+        }));
+    }
+
+    insert_identity(&state.db, user.id, &request.provider, &claims).await
+}
+
+pub async fn list_identities(state: &AppState, user_id: Uuid) -> AppResult<Vec<identities::Model>> {
+    Identities::find()
+        .filter(identities::Column::UserId.eq(user_id))
+        .order_by_asc(identities::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Whether the user can sign in through at least one provider
+pub async fn has_identities<C: ConnectionTrait>(db: &C, user_id: Uuid) -> AppResult<bool> {
+    let count = Identities::find()
+        .filter(identities::Column::UserId.eq(user_id))
+        .count(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(count > 0)
+}
+
+/// Unlink a provider account, keeping at least one way to sign in
+pub async fn unlink(state: &AppState, user: &users::Model, identity_id: Uuid) -> AppResult<()> {
+    let identity = Identities::find_by_id(identity_id)
+        .filter(identities::Column::UserId.eq(user.id))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or(AppError::NotFound(format!(
+            "Linked account with id {} not found",
+            identity_id
+        )))?;
+
+    let others = Identities::find()
+        .filter(identities::Column::UserId.eq(user.id))
+        .filter(identities::Column::Id.ne(identity.id))
+        .count(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    if others == 0
+        && user.password_hash.is_none()
+        && !webauthn_service::has_credentials(&state.db, user.id).await?
+    {
+        return Err(AppError::BadRequest(
+            "Cannot unlink the only way to sign in to this account".to_string(),
+        ));
+    }
+
+    Identities::delete_by_id(identity.id)
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+fn find_provider<'a>(
+    config: &'a AppConfig,
+    provider_id: &str,
+) -> AppResult<&'a OidcProviderConfig> {
+    config
+        .oidc_providers
+        .get(provider_id)
+        .ok_or(AppError::NotFound(format!(
+            "Sign-in provider {} not found",
+            provider_id
+        )))
+}
+
+/// Store a pending request and build the provider's authorization URL,
+/// returned with the raw `state`
+///
+/// The `state` parameter ties the callback to this request, the `nonce` ties
+/// the ID token to it, and the PKCE verifier makes a stolen code useless.
+async fn start_authorization(
+    state: &AppState,
+    provider_id: &str,
+    user_id: Option<Uuid>,
+) -> AppResult<(String, String)> {
+    let provider = find_provider(&state.config, provider_id)?;
+    let metadata = state.oidc.metadata(&provider.issuer).await?;
+    let now = chrono::Utc::now().fixed_offset();
+
+    // Abandoned sign-ins are cleaned up here rather than by a job
+    AuthRequests::delete_many()
+        .filter(auth_request::Column::ExpiresAt.lt(now))
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    let raw_state = token::generate_token();
+    let nonce = token::generate_token();
+    let code_verifier = token::generate_token();
+
+    auth_request::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        state_hash: Set(token::hash_token(&raw_state)),
+        provider: Set(provider_id.to_string()),
+        user_id: Set(user_id),
+        nonce: Set(nonce.clone()),
+        code_verifier: Set(code_verifier.clone()),
+        expires_at: Set(now + chrono::Duration::seconds(state.config.oidc_login_ttl_secs)),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(AppError::from)?;
+
+    let mut scopes: Vec<&str> = provider.scopes.split_whitespace().collect();
+    if !scopes.contains(&"openid") {
+        scopes.insert(0, "openid");
+    }
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+    let url = reqwest::Url::parse_with_params(
+        &metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", provider.client_id.as_str()),
+            ("redirect_uri", state.config.oidc_callback_url().as_str()),
+            ("scope", scopes.join(" ").as_str()),
+            ("state", raw_state.as_str()),
+            ("nonce", nonce.as_str()),
+            ("code_challenge", code_challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .context("provider has an invalid authorization endpoint")?;
+
+    Ok((url.into(), raw_state))
+}
+
+/// Redeem the authorization code of a pending request and validate the ID
+/// token it returns
+async fn verify_callback(
+    state: &AppState,
+    request: &auth_request::Model,
+    code: &str,
+) -> AppResult<IdTokenClaims> {
+    let provider = find_provider(&state.config, &request.provider)?;
+    let metadata = state.oidc.metadata(&provider.issuer).await?;
+
+    let id_token = state
+        .oidc
+        .exchange_code(
+            provider,
+            &metadata,
+            code,
+            &state.config.oidc_callback_url(),
+            &request.code_verifier,
+        )
+        .await?;
+
+    state
+        .oidc
+        .validate_id_token(provider, &metadata, &id_token, &request.nonce)
+        .await
+}
+
+/// Take a pending request out of the store so its state cannot be reused
+async fn consume_request<C: ConnectionTrait>(
+    db: &C,
+    raw_state: &str,
+) -> AppResult<auth_request::Model> {
+    let request = AuthRequests::find()
+        .filter(auth_request::Column::StateHash.eq(token::hash_token(raw_state)))
+        .filter(auth_request::Column::ExpiresAt.gt(chrono::Utc::now().fixed_offset()))
+        .one(db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(invalid_state)?;
+
+    let result = AuthRequests::delete_by_id(request.id)
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+    if result.rows_affected == 0 {
+        return Err(invalid_state());
+    }
+
+    Ok(request)
+}
+
+async fn find_identity<C: ConnectionTrait>(
+    db: &C,
+    provider: &str,
+    subject: &str,
+) -> AppResult<Option<identities::Model>> {
+    Identities::find()
+        .filter(identities::Column::Provider.eq(provider))
+        .filter(identities::Column::Subject.eq(subject))
+        .one(db)
+        .await
+        .map_err(AppError::from)
+}
+
+async fn insert_identity<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    provider: &str,
+    claims: &IdTokenClaims,
+) -> AppResult<identities::Model> {
+    let now = chrono::Utc::now().fixed_offset();
+
+    identities::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        provider: Set(provider.to_string()),
+        subject: Set(claims.sub.clone()),
+        email: Set(claims.email.clone()),
+        created_at: Set(now),
+        last_used_at: Set(Some(now)),
+    }
+    .insert(db)
+    .await
+    .map_err(|err| match AppError::from(err) {
+        AppError::Conflict(_) => {
+            AppError::Conflict("This account is linked to another user".to_string())
+        }
+        err => err,
+    })
+}
+
+/// Record a sign-in and the email address the provider reports now
+async fn touch_identity<C: ConnectionTrait>(
+    db: &C,
+    identity: identities::Model,
+    claims: &IdTokenClaims,
+) -> AppResult<()> {
+    let mut active: identities::ActiveModel = identity.into();
+    active.email = Set(claims.email.clone());
+    active.last_used_at = Set(Some(chrono::Utc::now().fixed_offset()));
+    active.update(db).await.map_err(AppError::from)?;
+
+    Ok(())
+}
+
+fn invalid_state() -> AppError {
+    AppError::BadRequest("Invalid or expired sign-in request".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::super::test_provider::TestProvider;
+    use super::*;
+    use crate::test_support::{test_config, test_db, unique_email};
+
+    const PROVIDER: &str = "test";
+
+    /// State with `provider` configured as [`PROVIDER`], or `None` without a
+    /// test database
+    async fn state_with(provider: &TestProvider) -> Option<AppState> {
+        let mut config = test_config();
+        config
+            .oidc_providers
+            .insert(PROVIDER.to_string(), provider.config());
+        Some(AppState::new(test_db().await?, config))
+    }
+
+    async fn create_user(state: &AppState, email: &str) -> users::Model {
+        users_service::create_passwordless_user_in(&state.db, Uuid::new_v4(), email, "Test")
+            .await
+            .unwrap()
+    }
+
+    fn account(email: &str) -> Value {
+        json!({
+            "sub": Uuid::new_v4().to_string(),
+            "email": email,
+            "email_verified": true,
+            "name": "Alice",
+        })
+    }
+
+    async fn sign_in(
+        state: &AppState,
+        provider: &TestProvider,
+        account: &Value,
+    ) -> AppResult<ProviderSignIn> {
+        let start = start_login(state, PROVIDER).await?;
+        let (raw_state, code) = provider.authorize(&start.authorization_url, account.clone());
+        complete_login(state, &raw_state, &code, Some(&start.browser_binding)).await
+    }
+
+    #[tokio::test]
+    async fn creates_account_then_signs_it_in() {
+        let provider = TestProvider::start().await;
+        let Some(state) = state_with(&provider).await else {
+            return;
+        };
+        let email = unique_email();
+        let account = account(&email);
+
+        let created = sign_in(&state, &provider, &account).await.unwrap();
+        assert!(created.created);
+        assert_eq!(created.user.email, email);
+        assert!(created.user.password_hash.is_none());
+        assert!(created.user.email_verified_at.is_some());
+
+        let again = sign_in(&state, &provider, &account).await.unwrap();
+        assert!(!again.created);
+        assert_eq!(again.user.id, created.user.id);
+        // Discovery is cached across sign-ins
+        assert_eq!(provider.discovery_fetches(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_reused_or_unknown_state() {
+        let provider = TestProvider::start().await;
+        let Some(state) = state_with(&provider).await else {
+            return;
+        };
+
+        let start = start_login(&state, PROVIDER).await.unwrap();
+        let binding = Some(start.browser_binding.as_str());
+        let (raw_state, code) =
+            provider.authorize(&start.authorization_url, account(&unique_email()));
+        complete_login(&state, &raw_state, &code, binding)
+            .await
+            .unwrap();
+
+        let reused = complete_login(&state, &raw_state, &code, binding).await;
+        assert!(matches!(reused, Err(AppError::BadRequest(_))));
+
+        let made_up = token::hash_token("made-up");
+        let unknown = complete_login(&state, "made-up", &code, Some(&made_up)).await;
+        assert!(matches!(unknown, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_callback_in_another_browser() {
+        let provider = TestProvider::start().await;
+        let Some(state) = state_with(&provider).await else {
+            return;
+        };
+
+        // The attacker starts a sign-in and hands the callback to a victim,
+        // whose browser has no binding or the binding of its own sign-in
+        let attacker = start_login(&state, PROVIDER).await.unwrap();
+        let (raw_state, code) =
+            provider.authorize(&attacker.authorization_url, account(&unique_email()));
+        let victim = start_login(&state, PROVIDER).await.unwrap();
+
+        let without = complete_login(&state, &raw_state, &code, None).await;
+        assert!(matches!(without, Err(AppError::BadRequest(_))));
+        let other = complete_login(&state, &raw_state, &code, Some(&victim.browser_binding)).await;
+        assert!(matches!(other, Err(AppError::BadRequest(_))));
+
+        // The rejected attempts leave the request usable by its own browser
+        complete_login(&state, &raw_state, &code, Some(&attacker.browser_binding))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_id_token_for_another_request() {
+        let provider = TestProvider::start().await;
+        let Some(state) = state_with(&provider).await else {
+            return;
+        };
+
+        let start = start_login(&state, PROVIDER).await.unwrap();
+        let mut account = account(&unique_email());
+        account["nonce"] = json!("nonce-of-another-request");
+        let (raw_state, code) = provider.authorize(&start.authorization_url, account);
+
+        let result = complete_login(&state, &raw_state, &code, Some(&start.browser_binding)).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_code_injected_into_another_request() {
+        let provider = TestProvider::start().await;
+        let Some(state) = state_with(&provider).await else {
+            return;
+        };
+
+        // The victim's code, redeemed in the attacker's request, goes to the
+        // provider with a verifier that does not match its challenge
+        let victim = start_login(&state, PROVIDER).await.unwrap();
+        let (_, code) = provider.authorize(&victim.authorization_url, account(&unique_email()));
+        let attacker = start_login(&state, PROVIDER).await.unwrap();
+        let (raw_state, _) =
+            provider.authorize(&attacker.authorization_url, account(&unique_email()));
+
+        let result =
+            complete_login(&state, &raw_state, &code, Some(&attacker.browser_binding)).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn refuses_to_take_over_existing_account() {
+        let provider = TestProvider::start().await;
+        let Some(state) = state_with(&provider).await else {
+            return;
+        };
+        let email = unique_email();
+        create_user(&state, &email).await;
+
+        let result = sign_in(&state, &provider, &account(&email)).await;
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+        let user = users_service::find_by_email(&state, &email)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!has_identities(&state.db, user.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn links_and_unlinks_provider_account() {
+        let provider = TestProvider::start().await;
+        let Some(state) = state_with(&provider).await else {
+            return;
+        };
+        let email = unique_email();
+        let user = create_user(&state, &email).await;
+        let account = account(&email);
+
+        // A link request cannot be finished as a sign-in, nor by someone else
+        let url = start_link(&state, &user, PROVIDER).await.unwrap();
+        let (raw_state, code) = provider.authorize(&url, account.clone());
+        let binding = token::hash_token(&raw_state);
+        let as_login = complete_login(&state, &raw_state, &code, Some(&binding)).await;
+        assert!(matches!(as_login, Err(AppError::BadRequest(_))));
+
+        let url = start_link(&state, &user, PROVIDER).await.unwrap();
+        let (raw_state, code) = provider.authorize(&url, account.clone());
+        let other = create_user(&state, &unique_email()).await;
+        let by_other = complete_link(&state, &other, &raw_state, &code).await;
+        assert!(matches!(by_other, Err(AppError::BadRequest(_))));
+
+        let url = start_link(&state, &user, PROVIDER).await.unwrap();
+        let (raw_state, code) = provider.authorize(&url, account.clone());
+        let identity = complete_link(&state, &user, &raw_state, &code)
+            .await
+            .unwrap();
+        assert_eq!(identity.subject, account["sub"]);
+
+        let signed_in = sign_in(&state, &provider, &account).await.unwrap();
+        assert_eq!(signed_in.user.id, user.id);
+
+        // The same provider account cannot be linked twice
+        let url = start_link(&state, &other, PROVIDER).await.unwrap();
+        let (raw_state, code) = provider.authorize(&url, account.clone());
+        let taken = complete_link(&state, &other, &raw_state, &code).await;
+        assert!(matches!(taken, Err(AppError::Conflict(_))));
+
+        // It is the only way this passwordless user signs in
+        let only = unlink(&state, &user, identity.id).await;
+        assert!(matches!(only, Err(AppError::BadRequest(_))));
+        let not_theirs = unlink(&state, &other, identity.id).await;
+        assert!(matches!(not_theirs, Err(AppError::NotFound(_))));
+
+        let mut with_password: users::ActiveModel = user.into();
+        with_password.password_hash = Set(Some("hash".to_string()));
+        let user = with_password.update(&state.db).await.unwrap();
+        unlink(&state, &user, identity.id).await.unwrap();
+        assert!(list_identities(&state, user.id).await.unwrap().is_empty());
+    }
+}
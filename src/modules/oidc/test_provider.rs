@@ -0,0 +1,289 @@
+//! An OpenID Connect provider for tests, served on a local port
+//!
+//! ```rust,ignore
+//! let provider = TestProvider::start().await;
+//! // configure provider.config() under some id, then
+//! let start = service::start_login(&state, "test").await?;
+//! let account = json!({ "sub": "1", "email": email });
+//! let (raw_state, code) = provider.authorize(&start.authorization_url, account);
+//! service::complete_login(&state, &raw_state, &code, Some(&start.browser_binding)).await?;
+//! ```
+//!
+//! It publishes one ES256 key at a time and counts how often its discovery
+//! document and key set are fetched, so tests can check what is cached.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::config::OidcProviderConfig;
+
+pub const CLIENT_ID: &str = "test-client";
+pub const CLIENT_SECRET: &str = "test-secret";
+
+/// An ES256 signing key
+pub struct TestKey {
+    pub kid: String,
+    pkcs8: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+impl TestKey {
+    pub fn generate(kid: &str) -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .expect("generating a P-256 key")
+            .as_ref()
+            .to_vec();
+        let public_key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, &rng)
+            .expect("parsing the generated key")
+            .public_key()
+            .as_ref()
+            .to_vec();
+
+        Self {
+            kid: kid.to_string(),
+            pkcs8,
+            public_key,
+        }
+    }
+
+    /// Sign claims into a compact JWT naming this key
+    pub fn sign(&self, claims: &Value) -> String {
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(self.kid.clone());
+        jsonwebtoken::encode(&header, claims, &EncodingKey::from_ec_der(&self.pkcs8))
+            .expect("signing a test token")
+    }
+
+    fn jwk(&self) -> Value {
+        // An uncompressed point: 0x04, then x and y
+        let (x, y) = self.public_key[1..].split_at(32);
+        json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "use": "sig",
+            "alg": "ES256",
+            "kid": self.kid,
+            "x": URL_SAFE_NO_PAD.encode(x),
+            "y": URL_SAFE_NO_PAD.encode(y),
+        })
+    }
+}
+
+/// An authorization code waiting to be redeemed
+struct Grant {
+    claims: Value,
+    redirect_uri: String,
+    code_challenge: String,
+}
+
+struct Shared {
+    issuer: String,
+    key: TestKey,
+    grants: HashMap<String, Grant>,
+    discovery_fetches: usize,
+    key_set_fetches: usize,
+}
+
+pub struct TestProvider {
+    pub issuer: String,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl TestProvider {
+    /// Serve a provider on an ephemeral port for as long as the test's
+    /// runtime lives
+    pub async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("binding the test provider");
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let shared = Arc::new(Mutex::new(Shared {
+            issuer: issuer.clone(),
+            key: TestKey::generate(&Uuid::new_v4().to_string()),
+            grants: HashMap::new(),
+            discovery_fetches: 0,
+            key_set_fetches: 0,
+        }));
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(key_set))
+            .route("/token", post(token))
+            .with_state(shared.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Self { issuer, shared }
+    }
+
+    /// Settings for the app to use this provider with
+    pub fn config(&self) -> OidcProviderConfig {
+        OidcProviderConfig {
+            issuer: self.issuer.clone(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: Some(CLIENT_SECRET.to_string()),
+            scopes: "openid email profile".to_string(),
+            display_name: None,
+        }
+    }
+
+    /// Id of the key tokens are currently signed with
+    pub fn key_id(&self) -> String {
+        self.shared.lock().unwrap().key.kid.clone()
+    }
+
+    /// Replace the published key with a new one
+    pub fn rotate_key(&self) {
+        self.shared.lock().unwrap().key = TestKey::generate(&Uuid::new_v4().to_string());
+    }
+
+    pub fn discovery_fetches(&self) -> usize {
+        self.shared.lock().unwrap().discovery_fetches
+    }
+
+    pub fn key_set_fetches(&self) -> usize {
+        self.shared.lock().unwrap().key_set_fetches
+    }
+
+    /// ID token claims valid for an hour, with `claims` added or overriding
+    pub fn claims(&self, claims: Value) -> Value {
+        let now = chrono::Utc::now().timestamp();
+        let mut defaults = json!({
+            "iss": self.issuer,
+            "aud": CLIENT_ID,
+            "iat": now,
+            "exp": now + 3600,
+        });
+        defaults
+            .as_object_mut()
+            .unwrap()
+            .extend(claims.as_object().expect("claims object").clone());
+        defaults
+    }
+
+    /// An ID token signed with the published key
+    pub fn id_token(&self, claims: Value) -> String {
+        let claims = self.claims(claims);
+        self.shared.lock().unwrap().key.sign(&claims)
+    }
+
+    /// Sign the user in at the authorization URL the app sent them to,
+    /// returning the `state` and `code` the provider redirects back with
+    ///
+    /// The ID token carries the request's nonce unless `claims` sets one.
+    pub fn authorize(&self, authorization_url: &str, claims: Value) -> (String, String) {
+        let url = reqwest::Url::parse(authorization_url).expect("authorization URL");
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["response_type"], "code");
+        assert_eq!(params["code_challenge_method"], "S256");
+
+        let mut with_nonce = json!({ "nonce": params["nonce"] });
+        with_nonce
+            .as_object_mut()
+            .unwrap()
+            .extend(claims.as_object().expect("claims object").clone());
+        let claims = self.claims(with_nonce);
+
+        let code = Uuid::new_v4().simple().to_string();
+        self.shared.lock().unwrap().grants.insert(
+            code.clone(),
+            Grant {
+                claims,
+                redirect_uri: params["redirect_uri"].clone(),
+                code_challenge: params["code_challenge"].clone(),
+            },
+        );
+
+        (params["state"].clone(), code)
+    }
+}
+
+async fn discovery(State(shared): State<Arc<Mutex<Shared>>>) -> Json<Value> {
+    let mut shared = shared.lock().unwrap();
+    shared.discovery_fetches += 1;
+    let issuer = &shared.issuer;
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer),
+        "token_endpoint": format!("{}/token", issuer),
+        "jwks_uri": format!("{}/jwks", issuer),
+    }))
+}
+
+async fn key_set(State(shared): State<Arc<Mutex<Shared>>>) -> Json<Value> {
+    let mut shared = shared.lock().unwrap();
+    shared.key_set_fetches += 1;
+    // Keys the app cannot use must be skipped rather than break the set
+    Json(json!({
+        "keys": [
+            { "kty": "oct", "kid": "symmetric", "k": "c2VjcmV0" },
+            shared.key.jwk(),
+        ]
+    }))
+}
+
+/// Redeem a code once, for the client that holds its PKCE verifier
+async fn token(
+    State(shared): State<Arc<Mutex<Shared>>>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let invalid_grant = || {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "invalid_grant" })),
+        )
+    };
+
+    let basic = format!(
+        "Basic {}",
+        STANDARD.encode(format!("{}:{}", CLIENT_ID, CLIENT_SECRET))
+    );
+    if headers.get("authorization").and_then(|h| h.to_str().ok()) != Some(basic.as_str()) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "invalid_client" })),
+        ));
+    }
+
+    let mut shared = shared.lock().unwrap();
+    let field = |name: &str| form.get(name).map(String::as_str).unwrap_or_default();
+    if field("grant_type") != "authorization_code" {
+        return Err(invalid_grant());
+    }
+    let grant = shared
+        .grants
+        .remove(field("code"))
+        .ok_or_else(invalid_grant)?;
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(field("code_verifier").as_bytes()));
+    if grant.redirect_uri != field("redirect_uri") || grant.code_challenge != challenge {
+        return Err(invalid_grant());
+    }
+
+    Ok(Json(json!({
+        "access_token": Uuid::new_v4().simple().to_string(),
+        "token_type": "Bearer",
+        "id_token": shared.key.sign(&grant.claims),
+    })))
+}
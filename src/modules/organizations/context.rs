@@ -0,0 +1,369 @@
+use std::{marker::PhantomData, ops::Deref};
+
+use axum::{
+    extract::{FromRequestParts, RawPathParams},
+    http::request::Parts,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::extractor::AuthUser;
+use crate::modules::mfa::service as mfa_service;
+use crate::state::AppState;
+
+use super::membership::OrgRole;
+use super::service;
+
+/// Header clients can use to select the active organization
+pub const ORG_ID_HEADER: &str = "x-org-id";
+
+/// Path parameter that selects the active organization
+pub const ORG_ID_PATH_PARAM: &str = "org_id";
+
+/// The organization a request acts on and the caller's role in it
+///
+/// The organization is resolved, in order of precedence, from the `{org_id}`
+/// path parameter, the `X-Org-Id` header, or the `org` claim of the access
+/// token. The caller's membership is always checked against the database.
+#[derive(Clone, Debug)]
+pub struct OrgContext {
+    pub org_id: Uuid,
+    pub user_id: Uuid,
+    pub role: OrgRole,
+    /// Custom role granting permissions on top of `role`
+    pub custom_role_id: Option<Uuid>,
+}
+
+impl OrgContext {
+    /// Fail with `403 Forbidden` unless the caller has at least `min_role`
+    pub fn require(&self, min_role: OrgRole) -> AppResult<()> {
+        if self.role < min_role {
+            return Err(AppError::Forbidden(format!(
+                "This action requires the {:?} role or higher",
+                min_role
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromRequestParts<AppState> for OrgContext {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> AppResult<Self> {
+        if let Some(context) = parts.extensions.get::<OrgContext>() {
+            return Ok(context.clone());
+        }
+
+        let auth = AuthUser::from_request_parts(parts, state).await?;
+        let org_id = selected_org_id(parts, state, &auth)
+            .await?
+            .ok_or(AppError::BadRequest(
+                "No organization selected; use the X-Org-Id header or an organization-scoped token"
+                    .to_string(),
+            ))?;
+        let membership =
+            service::require_role(state, org_id, auth.user.id, OrgRole::Member).await?;
+        // Service accounts have no second factor to enroll; their API keys
+        // are the organization's to manage
+        if !auth.user.is_service_account() {
+            mfa_service::enforce_organization_policy(state, org_id, auth.user.id).await?;
+        }
+
+        let context = OrgContext {
+            org_id,
+            user_id: auth.user.id,
+            role: membership.role,
+            custom_role_id: membership.custom_role_id,
+        };
+        parts.extensions.insert(context.clone());
+        Ok(context)
+    }
+}
+
+/// The organization the request selects, if any, without checking
+/// membership
+pub async fn selected_org_id(
+    parts: &mut Parts,
+    state: &AppState,
+    auth: &AuthUser,
+) -> AppResult<Option<Uuid>> {
+    let invalid = || AppError::BadRequest("Invalid organization id".to_string());
+
+    if let Ok(params) = RawPathParams::from_request_parts(parts, state).await {
+        if let Some((_, value)) = params.iter().find(|(key, _)| *key == ORG_ID_PATH_PARAM) {
+            return Uuid::parse_str(value).map(Some).map_err(|_| invalid());
+        }
+    }
+
+    if let Some(value) = parts.headers.get(ORG_ID_HEADER) {
+        let value = value.to_str().map_err(|_| invalid())?;
+        return Uuid::parse_str(value.trim())
+            .map(Some)
+            .map_err(|_| invalid());
+    }
+
+    Ok(auth.claims.org)
+}
+
+/// Minimum organization role required by an [`OrgGuard`]
+pub trait MinRole: Send + Sync {
+    const ROLE: OrgRole;
+}
+
+/// Marker for [`OrgGuard`]: any member
+pub struct Member;
+/// Marker for [`OrgGuard`]: admins and owners
+pub struct Admin;
+/// Marker for [`OrgGuard`]: owners only
+pub struct Owner;
+
+impl MinRole for Member {
+    const ROLE: OrgRole = OrgRole::Member;
+}
+
+impl MinRole for Admin {
+    const ROLE: OrgRole = OrgRole::Admin;
+}
+
+impl MinRole for Owner {
+    const ROLE: OrgRole = OrgRole::Owner;
+}
+
+/// An [`OrgContext`] whose role is at least `R`, otherwise `403 Forbidden`
+///
+/// ```rust,ignore
+/// pub async fn handler(org: OrgGuard<Admin>) -> AppResult<impl IntoResponse> {
+///     Ok(Json(org.org_id))
+/// }
+/// ```
+pub struct OrgGuard<R: MinRole>(pub OrgContext, PhantomData<R>);
+
+impl<R: MinRole> Deref for OrgGuard<R> {
+    type Target = OrgContext;
+
+    fn deref(&self) -> &OrgContext {
+        &self.0
+    }
+}
+
+impl<R: MinRole> FromRequestParts<AppState> for OrgGuard<R> {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> AppResult<Self> {
+        let context = OrgContext::from_request_parts(parts, state).await?;
+        context.require(R::ROLE)?;
+        Ok(OrgGuard(context, PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{header::AUTHORIZATION, StatusCode},
+        response::IntoResponse,
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::modules::auth::jwt::{self, Claims};
+    use crate::modules::organizations::service as organizations_service;
+    use crate::modules::users::{entity as users, service as users_service};
+    use crate::test_support::{test_state, unique_email};
+
+    async fn create_user(state: &AppState) -> users::Model {
+        users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn create_org(state: &AppState, owner: &users::Model) -> Uuid {
+        let name = format!("Context {}", Uuid::new_v4().simple());
+        organizations_service::create_organization(state, owner, &name, None)
+            .await
+            .unwrap()
+            .0
+            .id
+    }
+
+    /// Status and body of `GET uri` with `claims` and an optional `X-Org-Id`
+    async fn get_as(
+        state: &AppState,
+        claims: &Claims,
+        uri: &str,
+        org_header: Option<&str>,
+    ) -> (StatusCode, String) {
+        async fn context(org: OrgContext) -> String {
+            format!("{} {:?}", org.org_id, org.role)
+        }
+        async fn admin(org: OrgGuard<Admin>) -> impl IntoResponse {
+            org.org_id.to_string()
+        }
+        async fn owner(org: OrgGuard<Owner>) -> impl IntoResponse {
+            org.org_id.to_string()
+        }
+
+        let app = Router::new()
+            .route("/", get(context))
+            .route("/{org_id}", get(context))
+            .route("/guard/admin", get(admin))
+            .route("/guard/owner", get(owner))
+            .with_state(state.clone());
+        let token = jwt::encode_claims(&state.config, claims).unwrap();
+        let mut request = Request::builder()
+            .uri(uri)
+            .header(AUTHORIZATION, format!("Bearer {}", token));
+        if let Some(org_id) = org_header {
+            request = request.header(ORG_ID_HEADER, org_id);
+        }
+
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn requires_a_minimum_role() {
+        let context = OrgContext {
+            org_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            role: OrgRole::Admin,
+            custom_role_id: None,
+        };
+
+        assert!(context.require(OrgRole::Member).is_ok());
+        assert!(context.require(OrgRole::Admin).is_ok());
+        assert!(matches!(
+            context.require(OrgRole::Owner),
+            Err(AppError::Forbidden(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn selects_the_organization_by_path_header_or_token() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = create_user(&state).await;
+        let owned = create_org(&state, &user).await;
+        let joined = create_org(&state, &create_user(&state).await).await;
+        organizations_service::insert_membership(&state.db, joined, user.id, OrgRole::Member)
+            .await
+            .unwrap();
+        let owned_str = owned.to_string();
+        let joined_str = joined.to_string();
+
+        let unscoped = Claims::new(&state.config, user.id);
+        let scoped = Claims::new(&state.config, user.id).with_org(owned);
+
+        assert_eq!(
+            get_as(&state, &scoped, "/", None).await,
+            (StatusCode::OK, format!("{} Owner", owned))
+        );
+        assert_eq!(
+            get_as(&state, &unscoped, "/", Some(&joined_str)).await,
+            (StatusCode::OK, format!("{} Member", joined))
+        );
+        // The header overrides the token, and the path overrides both
+        assert_eq!(
+            get_as(&state, &scoped, "/", Some(&joined_str)).await,
+            (StatusCode::OK, format!("{} Member", joined))
+        );
+        assert_eq!(
+            get_as(&state, &scoped, &format!("/{}", joined), Some(&owned_str)).await,
+            (StatusCode::OK, format!("{} Member", joined))
+        );
+
+        assert_eq!(
+            get_as(&state, &unscoped, "/", None).await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            get_as(&state, &unscoped, "/", Some("not-a-uuid")).await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            get_as(&state, &unscoped, "/not-a-uuid", None).await.0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn hides_organizations_from_non_members() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let organization = create_org(&state, &create_user(&state).await).await;
+        let outsider = create_user(&state).await;
+        let claims = Claims::new(&state.config, outsider.id);
+
+        assert_eq!(
+            get_as(&state, &claims, &format!("/{}", organization), None)
+                .await
+                .0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get_as(&state, &claims, "/", Some(&Uuid::new_v4().to_string()))
+                .await
+                .0,
+            StatusCode::NOT_FOUND
+        );
+        // An organization-scoped token does not grant membership
+        let scoped = claims.with_org(organization);
+        assert_eq!(
+            get_as(&state, &scoped, "/", None).await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn guards_check_the_role() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let owner = create_user(&state).await;
+        let organization = create_org(&state, &owner).await;
+        let org_id = organization.to_string();
+
+        let mut users = vec![(owner, OrgRole::Owner)];
+        for role in [OrgRole::Admin, OrgRole::Member] {
+            let user = create_user(&state).await;
+            organizations_service::insert_membership(&state.db, organization, user.id, role)
+                .await
+                .unwrap();
+            users.push((user, role));
+        }
+
+        for (user, role) in users {
+            let claims = Claims::new(&state.config, user.id);
+            for (uri, min_role) in [
+                ("/guard/admin", OrgRole::Admin),
+                ("/guard/owner", OrgRole::Owner),
+            ] {
+                let (status, body) = get_as(&state, &claims, uri, Some(&org_id)).await;
+                if role >= min_role {
+                    assert_eq!((status, body), (StatusCode::OK, org_id.clone()));
+                } else {
+                    assert_eq!(status, StatusCode::FORBIDDEN, "{:?} on {}", role, uri);
+                }
+            }
+        }
+    }
+}
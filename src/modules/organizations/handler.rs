@@ -0,0 +1,454 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::{extractor::AuthUser, jwt};
+use crate::modules::mfa::service as mfa_service;
+use crate::modules::users::entity as users;
+use crate::state::AppState;
+
+use super::context::{Admin, Member, OrgContext, OrgGuard, Owner};
+use super::entity;
+use super::membership::{self, OrgRole};
+use super::service;
+use super::tenant::TenantDb;
+
+#[derive(serde::Deserialize)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+    pub slug: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateOrganizationRequest {
+    pub name: Option<String>,
+    pub require_mfa: Option<bool>,
+    pub allow_magic_links: Option<bool>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateMemberRequest {
+    pub role: OrgRole,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TransferOwnershipRequest {
+    pub user_id: Uuid,
+}
+
+#[derive(serde::Serialize)]
+pub struct OrganizationResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub slug: String,
+    pub require_mfa: bool,
+    pub allow_magic_links: bool,
+    pub role: OrgRole,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+    pub updated_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl OrganizationResponse {
+    fn new(model: entity::Model, role: OrgRole) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+            slug: model.slug,
+            require_mfa: model.require_mfa,
+            allow_magic_links: model.allow_magic_links,
+            role,
+            created_at: model.created_at,
+            updated_at: model.updated_at,
+        }
+    }
+}
+
+/// An organization with the number of seats its members take up
+#[derive(serde::Serialize)]
+pub struct OrganizationDetailResponse {
+    #[serde(flatten)]
+    pub organization: OrganizationResponse,
+    /// Members excluding service accounts
+    pub seats: u64,
+}
+
+#[derive(serde::Serialize)]
+pub struct AccessTokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct MemberResponse {
+    pub user_id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub role: OrgRole,
+    pub custom_role_id: Option<Uuid>,
+    pub service_account: bool,
+    pub joined_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl MemberResponse {
+    fn new(membership: membership::Model, user: users::Model) -> Self {
+        Self {
+            service_account: user.is_service_account(),
+            user_id: user.id,
+            email: user.email,
+            name: user.name,
+            role: membership.role,
+            custom_role_id: membership.custom_role_id,
+            joined_at: membership.created_at,
+        }
+    }
+}
+
+/// POST /api/orgs
+pub async fn create_organization(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<CreateOrganizationRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.require_verified_email(&state.config)?;
+
+    let (organization, membership) =
+        service::create_organization(&state, &auth.user, &payload.name, payload.slug.as_deref())
+            .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(OrganizationResponse::new(organization, membership.role)),
+    ))
+}
+
+/// GET /api/orgs
+pub async fn list_organizations(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let organizations = service::list_for_user(&state, auth.user.id).await?;
+    let responses: Vec<OrganizationResponse> = organizations
+        .into_iter()
+        .map(|(organization, role)| OrganizationResponse::new(organization, role))
+        .collect();
+
+    Ok(Json(responses))
+}
+
+/// GET /api/orgs/:org_id
+pub async fn get_organization(tenant: TenantDb) -> AppResult<impl IntoResponse> {
+    let organization = service::get_organization(&*tenant, tenant.org.org_id).await?;
+    let seats = service::count_seats(&*tenant, tenant.org.org_id).await?;
+    Ok(Json(OrganizationDetailResponse {
+        organization: OrganizationResponse::new(organization, tenant.org.role),
+        seats,
+    }))
+}
+
+/// PATCH /api/orgs/:org_id
+pub async fn update_organization(
+    tenant: TenantDb,
+    Json(payload): Json<UpdateOrganizationRequest>,
+) -> AppResult<impl IntoResponse> {
+    tenant.org.require(OrgRole::Admin)?;
+    if let Some(require_mfa) = payload.require_mfa {
+        tenant.org.require(OrgRole::Owner)?;
+
+        // Keep owners from locking themselves out of their own organization
+        if require_mfa && !mfa_service::has_second_factor(&*tenant, tenant.org.user_id).await? {
+            return Err(AppError::BadRequest(
+                "Enable two-factor authentication or add a passkey to your own account first"
+                    .to_string(),
+            ));
+        }
+    }
+    if payload.allow_magic_links.is_some() {
+        tenant.org.require(OrgRole::Owner)?;
+    }
+
+    let organization = service::update_organization(
+        &*tenant,
+        tenant.org.org_id,
+        payload.name,
+        payload.require_mfa,
+        payload.allow_magic_links,
+    )
+    .await?;
+    let role = tenant.org.role;
+    tenant.commit().await?;
+
+    Ok(Json(OrganizationResponse::new(organization, role)))
+}
+
+/// DELETE /api/orgs/:org_id
+pub async fn delete_organization(
+    State(state): State<AppState>,
+    org: OrgGuard<Owner>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::delete_organization(&state, org.org_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/orgs/:org_id/token
+///
+/// Issue an access token scoped to the organization, so later requests can
+/// omit the `X-Org-Id` header.
+///
+/// While impersonating, the new token stays tied to the impersonation and
+/// expires with it.
+pub async fn switch_organization(
+    State(state): State<AppState>,
+    org: OrgGuard<Member>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let claims = match auth.claims.act {
+        Some(_) => jwt::Claims {
+            iat: chrono::Utc::now().timestamp(),
+            ..auth.claims.clone()
+        }
+        .with_org(org.org_id),
+        None => jwt::Claims::new(&state.config, org.user_id).with_org(org.org_id),
+    };
+
+    Ok(Json(AccessTokenResponse {
+        access_token: jwt::encode_claims(&state.config, &claims)?,
+        token_type: "Bearer",
+        expires_in: claims.exp - claims.iat,
+    }))
+}
+
+/// GET /api/orgs/:org_id/members
+pub async fn list_members(tenant: TenantDb) -> AppResult<impl IntoResponse> {
+    let members = service::list_members(&*tenant, tenant.org.org_id).await?;
+    let responses: Vec<MemberResponse> = members
+        .into_iter()
+        .map(|(membership, user)| MemberResponse::new(membership, user))
+        .collect();
+
+    Ok(Json(responses))
+}
+
+/// PATCH /api/orgs/:org_id/members/:user_id
+pub async fn update_member(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    Path((_, user_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<UpdateMemberRequest>,
+) -> AppResult<impl IntoResponse> {
+    service::change_role(&state, &org, user_id, payload.role).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/orgs/:org_id/members/:user_id
+pub async fn remove_member(
+    State(state): State<AppState>,
+    org: OrgContext,
+    Path((_, user_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    service::remove_member(&state, &org, user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/orgs/:org_id/transfer-ownership
+pub async fn transfer_ownership(
+    State(state): State<AppState>,
+    org: OrgGuard<Owner>,
+    auth: AuthUser,
+    Json(payload): Json<TransferOwnershipRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::transfer_ownership(&state, &org, payload.user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Request, http::header, http::Method};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_state, unique_email};
+
+    async fn create_user(state: &AppState) -> users::Model {
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap();
+        users_service::mark_email_verified(&state.db, user)
+            .await
+            .unwrap()
+    }
+
+    /// `method uri` as `user`
+    async fn call(
+        state: &AppState,
+        user: &users::Model,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let claims = jwt::Claims::new(&state.config, user.id);
+        let token = jwt::encode_claims(&state.config, &claims).unwrap();
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token));
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+
+        let response = crate::app::rust_saas(state.clone())
+            .oneshot(request.unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn manages_organizations_by_role() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let owner = create_user(&state).await;
+        let admin = create_user(&state).await;
+        let member = create_user(&state).await;
+        let outsider = create_user(&state).await;
+
+        let name = format!("Handler {}", Uuid::new_v4().simple());
+        let (status, body) = call(
+            &state,
+            &owner,
+            Method::POST,
+            "/api/orgs",
+            Some(json!({ "name": name })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["role"], "owner");
+        let org_id: Uuid = body["id"].as_str().unwrap().parse().unwrap();
+        let uri = format!("/api/orgs/{}", org_id);
+
+        for (user, role) in [(&admin, OrgRole::Admin), (&member, OrgRole::Member)] {
+            service::insert_membership(&state.db, org_id, user.id, role)
+                .await
+                .unwrap();
+        }
+
+        let (status, body) = call(&state, &member, Method::GET, &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], name);
+        assert_eq!(body["role"], "member");
+        assert_eq!(body["seats"], 3);
+        let (status, _) = call(&state, &outsider, Method::GET, &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let rename = json!({ "name": "Renamed" });
+        let (status, _) = call(&state, &member, Method::PATCH, &uri, Some(rename.clone())).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, body) = call(&state, &admin, Method::PATCH, &uri, Some(rename)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "Renamed");
+
+        // Sign-in policies are for owners
+        let policy = json!({ "allow_magic_links": false });
+        let (status, _) = call(&state, &admin, Method::PATCH, &uri, Some(policy.clone())).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, body) = call(&state, &owner, Method::PATCH, &uri, Some(policy)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["allow_magic_links"], false);
+
+        // Without a second factor of their own, owners cannot require one
+        let mfa = json!({ "require_mfa": true });
+        let (status, _) = call(&state, &owner, Method::PATCH, &uri, Some(mfa)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = call(&state, &admin, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call(&state, &owner, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = call(&state, &owner, Method::GET, &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn admins_cannot_demote_the_owner() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let owner = create_user(&state).await;
+        let admin = create_user(&state).await;
+        let name = format!("Handler {}", Uuid::new_v4().simple());
+        let (organization, _) = service::create_organization(&state, &owner, &name, None)
+            .await
+            .unwrap();
+        service::insert_membership(&state.db, organization.id, admin.id, OrgRole::Admin)
+            .await
+            .unwrap();
+        let owner_uri = format!("/api/orgs/{}/members/{}", organization.id, owner.id);
+
+        let demote = json!({ "role": "admin" });
+        let (status, _) = call(&state, &admin, Method::PATCH, &owner_uri, Some(demote)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call(&state, &admin, Method::DELETE, &owner_uri, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // The sole owner cannot leave either
+        let (status, _) = call(&state, &owner, Method::DELETE, &owner_uri, None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let transfer = format!("/api/orgs/{}/transfer-ownership", organization.id);
+        let to_admin = json!({ "user_id": admin.id });
+        let (status, _) = call(
+            &state,
+            &admin,
+            Method::POST,
+            &transfer,
+            Some(to_admin.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call(&state, &owner, Method::POST, &transfer, Some(to_admin)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, members) = call(
+            &state,
+            &owner,
+            Method::GET,
+            &format!("/api/orgs/{}/members", organization.id),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let role = |id: Uuid| {
+            members
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|m| m["user_id"] == id.to_string())
+                .map(|m| m["role"].clone())
+        };
+        assert_eq!(role(admin.id), Some(json!("owner")));
+        assert_eq!(role(owner.id), Some(json!("admin")));
+    }
+}
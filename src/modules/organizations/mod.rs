@@ -0,0 +1,7 @@
+pub mod context;
+pub mod entity;
+pub mod handler;
+pub mod membership;
+pub mod routes;
+pub mod service;
+pub mod tenant;
@@ -0,0 +1,69 @@
+use axum::{
+    middleware,
+    routing::{get, patch, post},
+    Extension, Router,
+};
+
+use crate::modules::api_keys::{routes::org_api_key_routes, scope::ScopedResource};
+use crate::modules::auth::middleware::require_auth;
+use crate::modules::invitations::routes::org_invitation_routes;
+use crate::modules::oauth::routes::org_oauth_client_routes;
+use crate::modules::password_policy::routes::org_password_policy_routes;
+use crate::modules::permissions::routes::{
+    org_member_role_routes, org_permission_routes, org_role_routes,
+};
+use crate::modules::saml::routes::org_saml_routes;
+use crate::modules::scim::routes::org_scim_routes;
+use crate::modules::service_accounts::routes::org_service_account_routes;
+use crate::state::AppState;
+
+use super::handler;
+
+pub fn organization_routes(state: AppState) -> Router<AppState> {
+    let auth = middleware::from_fn_with_state(state, require_auth);
+
+    // Reachable with API keys holding an `orgs` scope. The scope is layered
+    // outside `require_auth` so the middleware sees it.
+    let scoped = Router::new()
+        .route(
+            "/",
+            get(handler::list_organizations).post(handler::create_organization),
+        )
+        .route(
+            "/{org_id}",
+            get(handler::get_organization)
+                .patch(handler::update_organization)
+                .delete(handler::delete_organization),
+        )
+        .route("/{org_id}/members", get(handler::list_members))
+        .route(
+            "/{org_id}/members/{user_id}",
+            patch(handler::update_member).delete(handler::remove_member),
+        )
+        .nest("/{org_id}/invitations", org_invitation_routes())
+        .route_layer(auth.clone())
+        .route_layer(Extension(ScopedResource::Organizations));
+
+    // Tokens, ownership, roles and identity settings need an interactive
+    // sign-in
+    Router::new()
+        .route("/{org_id}/token", post(handler::switch_organization))
+        .route(
+            "/{org_id}/transfer-ownership",
+            post(handler::transfer_ownership),
+        )
+        .nest("/{org_id}/roles", org_role_routes())
+        .nest(
+            "/{org_id}/members/{user_id}/custom-role",
+            org_member_role_routes(),
+        )
+        .nest("/{org_id}/permissions", org_permission_routes())
+        .nest("/{org_id}/saml", org_saml_routes())
+        .nest("/{org_id}/password-policy", org_password_policy_routes())
+        .nest("/{org_id}/scim", org_scim_routes())
+        .nest("/{org_id}/api-keys", org_api_key_routes())
+        .nest("/{org_id}/service-accounts", org_service_account_routes())
+        .nest("/{org_id}/oauth-clients", org_oauth_client_routes())
+        .route_layer(auth)
+        .merge(scoped)
+}
@@ -0,0 +1,735 @@
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::users::entity::{self as users, Entity as Users};
+use crate::state::AppState;
+
+use super::context::OrgContext;
+use super::entity::{self as organizations, Entity as Organizations};
+use super::membership::{self, Entity as Memberships, OrgRole};
+
+/// Turn an organization name into a URL-friendly slug
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.trim().to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug: String = slug.trim_matches('-').chars().take(64).collect();
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Create an organization with `owner` as its first owner
+pub async fn create_organization(
+    state: &AppState,
+    owner: &users::Model,
+    name: &str,
+    slug: Option<&str>,
+) -> AppResult<(organizations::Model, membership::Model)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::ValidationError(
+            "Organization name is required".to_string(),
+        ));
+    }
+
+    let slug = slugify(slug.unwrap_or(name));
+    if slug.is_empty() {
+        return Err(AppError::ValidationError(
+            "Organization slug must contain letters or digits".to_string(),
+        ));
+    }
+
+    let now = chrono::Utc::now().fixed_offset();
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    let organization = organizations::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        name: Set(name.to_string()),
+        slug: Set(slug),
+        require_mfa: Set(false),
+        allow_magic_links: Set(true),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(&txn)
+    .await
+    .map_err(AppError::from)?;
+
+    let membership = insert_membership(&txn, organization.id, owner.id, OrgRole::Owner).await?;
+    txn.commit().await.map_err(AppError::from)?;
+
+    Ok((organization, membership))
+}
+
+/// Organizations the user belongs to, with the user's role in each
+pub async fn list_for_user(
+    state: &AppState,
+    user_id: Uuid,
+) -> AppResult<Vec<(organizations::Model, OrgRole)>> {
+    let rows = Memberships::find()
+        .filter(membership::Column::UserId.eq(user_id))
+        .find_also_related(Organizations)
+        .order_by_asc(membership::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(membership, organization)| organization.map(|o| (o, membership.role)))
+        .collect())
+}
+
+pub async fn find_membership<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    user_id: Uuid,
+) -> AppResult<Option<membership::Model>> {
+    Memberships::find_by_id((organization_id, user_id))
+        .one(db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Require the user to be a member of the organization with at least `min_role`
+///
+/// Non-members get `404` so the existence of an organization is not revealed
+/// to outsiders; members with an insufficient role get `403`.
+pub async fn require_role(
+    state: &AppState,
+    organization_id: Uuid,
+    user_id: Uuid,
+    min_role: OrgRole,
+) -> AppResult<membership::Model> {
+    let membership = find_membership(&state.db, organization_id, user_id)
+        .await?
+        .ok_or_else(|| organization_not_found(organization_id))?;
+
+    if membership.role < min_role {
+        return Err(AppError::Forbidden(format!(
+            "This action requires the {:?} role or higher",
+            min_role
+        )));
+    }
+
+    Ok(membership)
+}
+
+pub async fn get_organization<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+) -> AppResult<organizations::Model> {
+    Organizations::find_by_id(organization_id)
+        .one(db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| organization_not_found(organization_id))
+}
+
+pub async fn update_organization<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    name: Option<String>,
+    require_mfa: Option<bool>,
+    allow_magic_links: Option<bool>,
+) -> AppResult<organizations::Model> {
+    let organization = get_organization(db, organization_id).await?;
+    let mut active: organizations::ActiveModel = organization.into();
+
+    if let Some(name) = name {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(AppError::ValidationError(
+                "Organization name is required".to_string(),
+            ));
+        }
+        active.name = Set(name);
+    }
+    if let Some(require_mfa) = require_mfa {
+        active.require_mfa = Set(require_mfa);
+    }
+    if let Some(allow_magic_links) = allow_magic_links {
+        active.allow_magic_links = Set(allow_magic_links);
+    }
+    active.updated_at = Set(chrono::Utc::now().fixed_offset());
+
+    active.update(db).await.map_err(AppError::from)
+}
+
+pub async fn delete_organization(state: &AppState, organization_id: Uuid) -> AppResult<()> {
+    let result = Organizations::delete_by_id(organization_id)
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    if result.rows_affected == 0 {
+        return Err(organization_not_found(organization_id));
+    }
+
+    Ok(())
+}
+
+/// Members of an organization with their user records
+pub async fn list_members<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+) -> AppResult<Vec<(membership::Model, users::Model)>> {
+    let rows = Memberships::find()
+        .filter(membership::Column::OrganizationId.eq(organization_id))
+        .find_also_related(Users)
+        .order_by_asc(membership::Column::CreatedAt)
+        .all(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(membership, user)| user.map(|u| (membership, u)))
+        .collect())
+}
+
+/// Members taking up a seat: people, not service accounts
+pub async fn count_seats<C: ConnectionTrait>(db: &C, organization_id: Uuid) -> AppResult<u64> {
+    Memberships::find()
+        .inner_join(Users)
+        .filter(membership::Column::OrganizationId.eq(organization_id))
+        .filter(users::Column::ServiceAccountOrganizationId.is_null())
+        .count(db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Change a member's role, keeping at least one owner
+pub async fn change_role(
+    state: &AppState,
+    actor: &OrgContext,
+    user_id: Uuid,
+    role: OrgRole,
+) -> AppResult<membership::Model> {
+    ensure_can_grant(actor, role)?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    lock_organization(&txn, actor.org_id).await?;
+
+    let target = find_membership(&txn, actor.org_id, user_id)
+        .await?
+        .ok_or_else(|| member_not_found(user_id))?;
+
+    if target.role == OrgRole::Owner && actor.role != OrgRole::Owner {
+        return Err(AppError::Forbidden(
+            "Only owners can change the role of another owner".to_string(),
+        ));
+    }
+    if target.role == OrgRole::Owner && role != OrgRole::Owner {
+        ensure_other_owner(&txn, actor.org_id, user_id).await?;
+    }
+    if role == OrgRole::Owner {
+        ensure_can_own(&txn, user_id).await?;
+    }
+
+    let mut active: membership::ActiveModel = target.into();
+    active.role = Set(role);
+    active.updated_at = Set(chrono::Utc::now().fixed_offset());
+    let membership = active.update(&txn).await.map_err(AppError::from)?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok(membership)
+}
+
+/// Remove a member, or let a member leave, keeping at least one owner
+pub async fn remove_member(state: &AppState, actor: &OrgContext, user_id: Uuid) -> AppResult<()> {
+    let leaving = actor.user_id == user_id;
+    if !leaving && actor.role < OrgRole::Admin {
+        return Err(AppError::Forbidden(
+            "Only admins can remove other members".to_string(),
+        ));
+    }
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    lock_organization(&txn, actor.org_id).await?;
+
+    let target = find_membership(&txn, actor.org_id, user_id)
+        .await?
+        .ok_or_else(|| member_not_found(user_id))?;
+
+    let is_service_account = Users::find_by_id(user_id)
+        .one(&txn)
+        .await
+        .map_err(AppError::from)?
+        .is_some_and(|user| user.is_service_account());
+    if is_service_account {
+        return Err(AppError::BadRequest(
+            "Service accounts cannot leave their organization; delete them instead".to_string(),
+        ));
+    }
+
+    if target.role == OrgRole::Owner {
+        if !leaving && actor.role != OrgRole::Owner {
+            return Err(AppError::Forbidden(
+                "Only owners can remove another owner".to_string(),
+            ));
+        }
+        ensure_other_owner(&txn, actor.org_id, user_id).await?;
+    }
+
+    Memberships::delete_by_id((actor.org_id, user_id))
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok(())
+}
+
+/// Hand ownership to another member; the current owner becomes an admin
+pub async fn transfer_ownership(
+    state: &AppState,
+    actor: &OrgContext,
+    new_owner_id: Uuid,
+) -> AppResult<()> {
+    if actor.role != OrgRole::Owner {
+        return Err(AppError::Forbidden(
+            "Only owners can transfer ownership".to_string(),
+        ));
+    }
+    if actor.user_id == new_owner_id {
+        return Err(AppError::BadRequest(
+            "You already own this organization".to_string(),
+        ));
+    }
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    lock_organization(&txn, actor.org_id).await?;
+
+    let target = find_membership(&txn, actor.org_id, new_owner_id)
+        .await?
+        .ok_or_else(|| member_not_found(new_owner_id))?;
+    ensure_can_own(&txn, new_owner_id).await?;
+
+    let now = chrono::Utc::now().fixed_offset();
+    let mut promoted: membership::ActiveModel = target.into();
+    promoted.role = Set(OrgRole::Owner);
+    promoted.updated_at = Set(now);
+    promoted.update(&txn).await.map_err(AppError::from)?;
+
+    let current = find_membership(&txn, actor.org_id, actor.user_id)
+        .await?
+        .ok_or_else(|| member_not_found(actor.user_id))?;
+    let mut demoted: membership::ActiveModel = current.into();
+    demoted.role = Set(OrgRole::Admin);
+    demoted.updated_at = Set(now);
+    demoted.update(&txn).await.map_err(AppError::from)?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok(())
+}
+
+pub async fn insert_membership<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    user_id: Uuid,
+    role: OrgRole,
+) -> AppResult<membership::Model> {
+    let now = chrono::Utc::now().fixed_offset();
+
+    membership::ActiveModel {
+        organization_id: Set(organization_id),
+        user_id: Set(user_id),
+        role: Set(role),
+        custom_role_id: Set(None),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(db)
+    .await
+    .map_err(AppError::from)
+}
+
+/// Check that `actor` may hand out `role`: admins manage members, only
+/// owners may grant the owner role
+pub fn ensure_can_grant(actor: &OrgContext, role: OrgRole) -> AppResult<()> {
+    if actor.role < OrgRole::Admin {
+        return Err(AppError::Forbidden(
+            "Only admins can manage members".to_string(),
+        ));
+    }
+    if role == OrgRole::Owner && actor.role != OrgRole::Owner {
+        return Err(AppError::Forbidden(
+            "Only owners can grant the owner role".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Serialize membership changes of an organization for the rest of the
+/// transaction, so two concurrent demotions cannot both pass the owner check
+async fn lock_organization<C: ConnectionTrait>(db: &C, organization_id: Uuid) -> AppResult<()> {
+    Organizations::find_by_id(organization_id)
+        .lock_exclusive()
+        .one(db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| organization_not_found(organization_id))?;
+
+    Ok(())
+}
+
+/// Service accounts act for an organization and cannot own one
+async fn ensure_can_own<C: ConnectionTrait>(db: &C, user_id: Uuid) -> AppResult<()> {
+    let user = Users::find_by_id(user_id)
+        .one(db)
+        .await
+        .map_err(AppError::from)?;
+
+    if user.is_some_and(|user| user.is_service_account()) {
+        return Err(AppError::BadRequest(
+            "Service accounts cannot own organizations".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn ensure_other_owner<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    excluding_user_id: Uuid,
+) -> AppResult<()> {
+    let owners = Memberships::find()
+        .filter(membership::Column::OrganizationId.eq(organization_id))
+        .filter(membership::Column::Role.eq(OrgRole::Owner))
+        .filter(membership::Column::UserId.ne(excluding_user_id))
+        .count(db)
+        .await
+        .map_err(AppError::from)?;
+
+    if owners == 0 {
+        return Err(AppError::Conflict(
+            "An organization must have at least one owner".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn organization_not_found(organization_id: Uuid) -> AppError {
+    AppError::NotFound(format!(
+        "Organization with id {} not found",
+        organization_id
+    ))
+}
+
+fn member_not_found(user_id: Uuid) -> AppError {
+    AppError::NotFound(format!("Member with user id {} not found", user_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_state, unique_email};
+
+    async fn create_user(state: &AppState) -> users::Model {
+        users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap()
+    }
+
+    /// An organization and its owner's context
+    async fn create_org(state: &AppState) -> (organizations::Model, OrgContext) {
+        let owner = create_user(state).await;
+        let name = format!("Organizations {}", Uuid::new_v4().simple());
+        let (organization, _) = create_organization(state, &owner, &name, None)
+            .await
+            .unwrap();
+        let context = OrgContext {
+            org_id: organization.id,
+            user_id: owner.id,
+            role: OrgRole::Owner,
+            custom_role_id: None,
+        };
+        (organization, context)
+    }
+
+    /// Add a new user to the organization with `role` and return their context
+    async fn join(state: &AppState, org_id: Uuid, role: OrgRole) -> OrgContext {
+        let user = create_user(state).await;
+        insert_membership(&state.db, org_id, user.id, role)
+            .await
+            .unwrap();
+        OrgContext {
+            org_id,
+            user_id: user.id,
+            role,
+            custom_role_id: None,
+        }
+    }
+
+    async fn role_of(state: &AppState, context: &OrgContext) -> Option<OrgRole> {
+        find_membership(&state.db, context.org_id, context.user_id)
+            .await
+            .unwrap()
+            .map(|membership| membership.role)
+    }
+
+    #[test]
+    fn slugifies_names() {
+        assert_eq!(slugify("  Acme, Inc.  "), "acme-inc");
+        assert_eq!(slugify("Hello   World!!"), "hello-world");
+        assert_eq!(slugify("--- !!! ---"), "");
+
+        let long = format!("{} b", "a".repeat(63));
+        assert_eq!(slugify(&long), "a".repeat(63));
+    }
+
+    #[tokio::test]
+    async fn creates_updates_and_deletes_organizations() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let owner = create_user(&state).await;
+        let suffix = Uuid::new_v4().simple().to_string();
+        let name = format!("  Acme {}  ", suffix);
+
+        let (organization, membership) = create_organization(&state, &owner, &name, None)
+            .await
+            .unwrap();
+        assert_eq!(organization.name, name.trim());
+        assert_eq!(organization.slug, format!("acme-{}", suffix));
+        assert!(!organization.require_mfa);
+        assert!(organization.allow_magic_links);
+        assert_eq!(membership.user_id, owner.id);
+        assert_eq!(membership.role, OrgRole::Owner);
+
+        let listed = list_for_user(&state, owner.id).await.unwrap();
+        assert!(listed
+            .iter()
+            .any(|(o, role)| o.id == organization.id && *role == OrgRole::Owner));
+
+        assert!(matches!(
+            create_organization(&state, &owner, "Copy", Some(&organization.slug)).await,
+            Err(AppError::Conflict(_))
+        ));
+        for (name, slug) in [("   ", None), ("Acme", Some("!!!"))] {
+            assert!(matches!(
+                create_organization(&state, &owner, name, slug).await,
+                Err(AppError::ValidationError(_))
+            ));
+        }
+
+        let updated = update_organization(
+            &state.db,
+            organization.id,
+            Some(" Renamed ".to_string()),
+            Some(true),
+            Some(false),
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated.name, "Renamed");
+        assert_eq!(updated.slug, organization.slug);
+        assert!(updated.require_mfa);
+        assert!(!updated.allow_magic_links);
+
+        let untouched = update_organization(&state.db, organization.id, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(untouched.name, "Renamed");
+        assert!(untouched.require_mfa);
+        assert!(matches!(
+            update_organization(
+                &state.db,
+                organization.id,
+                Some(" ".to_string()),
+                None,
+                None
+            )
+            .await,
+            Err(AppError::ValidationError(_))
+        ));
+
+        delete_organization(&state, organization.id).await.unwrap();
+        assert!(matches!(
+            get_organization(&state.db, organization.id).await,
+            Err(AppError::NotFound(_))
+        ));
+        assert!(find_membership(&state.db, organization.id, owner.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            delete_organization(&state, organization.id).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn admins_cannot_demote_or_remove_owners() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, owner) = create_org(&state).await;
+        let admin = join(&state, owner.org_id, OrgRole::Admin).await;
+        let member = join(&state, owner.org_id, OrgRole::Member).await;
+
+        for role in [OrgRole::Admin, OrgRole::Member] {
+            assert!(matches!(
+                change_role(&state, &admin, owner.user_id, role).await,
+                Err(AppError::Forbidden(_))
+            ));
+        }
+        assert!(matches!(
+            change_role(&state, &admin, member.user_id, OrgRole::Owner).await,
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            remove_member(&state, &admin, owner.user_id).await,
+            Err(AppError::Forbidden(_))
+        ));
+        assert_eq!(role_of(&state, &owner).await, Some(OrgRole::Owner));
+
+        change_role(&state, &admin, member.user_id, OrgRole::Admin)
+            .await
+            .unwrap();
+        assert_eq!(role_of(&state, &member).await, Some(OrgRole::Admin));
+        assert!(matches!(
+            change_role(&state, &admin, Uuid::new_v4(), OrgRole::Member).await,
+            Err(AppError::NotFound(_))
+        ));
+        remove_member(&state, &admin, member.user_id).await.unwrap();
+        assert_eq!(role_of(&state, &member).await, None);
+    }
+
+    #[tokio::test]
+    async fn members_only_leave_themselves() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, owner) = create_org(&state).await;
+        let member = join(&state, owner.org_id, OrgRole::Member).await;
+        let other = join(&state, owner.org_id, OrgRole::Member).await;
+
+        assert!(matches!(
+            change_role(&state, &member, member.user_id, OrgRole::Admin).await,
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            remove_member(&state, &member, other.user_id).await,
+            Err(AppError::Forbidden(_))
+        ));
+        assert_eq!(role_of(&state, &other).await, Some(OrgRole::Member));
+
+        remove_member(&state, &member, member.user_id)
+            .await
+            .unwrap();
+        assert_eq!(role_of(&state, &member).await, None);
+    }
+
+    #[tokio::test]
+    async fn keeps_at_least_one_owner() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, owner) = create_org(&state).await;
+        let admin = join(&state, owner.org_id, OrgRole::Admin).await;
+
+        assert!(matches!(
+            change_role(&state, &owner, owner.user_id, OrgRole::Admin).await,
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            remove_member(&state, &owner, owner.user_id).await,
+            Err(AppError::Conflict(_))
+        ));
+        assert_eq!(role_of(&state, &owner).await, Some(OrgRole::Owner));
+
+        change_role(&state, &owner, admin.user_id, OrgRole::Owner)
+            .await
+            .unwrap();
+        let co_owner = OrgContext {
+            role: OrgRole::Owner,
+            ..admin
+        };
+
+        // Owners can demote each other while another owner remains
+        change_role(&state, &co_owner, owner.user_id, OrgRole::Member)
+            .await
+            .unwrap();
+        assert!(matches!(
+            remove_member(&state, &co_owner, co_owner.user_id).await,
+            Err(AppError::Conflict(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn concurrent_demotions_keep_an_owner() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, first) = create_org(&state).await;
+        let second = join(&state, first.org_id, OrgRole::Owner).await;
+
+        let (a, b) = tokio::join!(
+            change_role(&state, &first, second.user_id, OrgRole::Admin),
+            change_role(&state, &second, first.user_id, OrgRole::Admin),
+        );
+        assert_eq!(a.is_ok() as u8 + b.is_ok() as u8, 1);
+        assert!([a, b]
+            .into_iter()
+            .any(|result| matches!(result, Err(AppError::Conflict(_)))));
+
+        let owners = list_members(&state.db, first.org_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|(membership, _)| membership.role == OrgRole::Owner)
+            .count();
+        assert_eq!(owners, 1);
+    }
+
+    #[tokio::test]
+    async fn transfers_ownership_to_a_member() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, owner) = create_org(&state).await;
+        let admin = join(&state, owner.org_id, OrgRole::Admin).await;
+        let member = join(&state, owner.org_id, OrgRole::Member).await;
+        let outsider = create_user(&state).await;
+
+        assert!(matches!(
+            transfer_ownership(&state, &admin, member.user_id).await,
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            transfer_ownership(&state, &owner, owner.user_id).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            transfer_ownership(&state, &owner, outsider.id).await,
+            Err(AppError::NotFound(_))
+        ));
+        assert_eq!(role_of(&state, &owner).await, Some(OrgRole::Owner));
+
+        transfer_ownership(&state, &owner, member.user_id)
+            .await
+            .unwrap();
+        assert_eq!(role_of(&state, &member).await, Some(OrgRole::Owner));
+        assert_eq!(role_of(&state, &owner).await, Some(OrgRole::Admin));
+    }
+}
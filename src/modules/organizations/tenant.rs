@@ -0,0 +1,56 @@
+use std::ops::Deref;
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use sea_orm::DatabaseTransaction;
+
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::state::AppState;
+
+use super::context::OrgContext;
+
+/// A database transaction scoped to the request's organization
+///
+/// Queries run through it are subject to the row-level security policies on
+/// tenant-owned tables (unless `TENANT_RLS=false`), so a missing
+/// `WHERE org_id = ?` filter returns no foreign rows instead of leaking them.
+/// The transaction rolls back when dropped; call [`TenantDb::commit`] after
+/// writes.
+///
+/// ```rust,ignore
+/// pub async fn handler(tenant: TenantDb) -> AppResult<impl IntoResponse> {
+///     let members = Memberships::find().all(&*tenant).await?;
+///     Ok(Json(members))
+/// }
+/// ```
+pub struct TenantDb {
+    pub org: OrgContext,
+    txn: DatabaseTransaction,
+}
+
+impl TenantDb {
+    pub async fn commit(self) -> AppResult<()> {
+        self.txn.commit().await.map_err(AppError::from)
+    }
+}
+
+impl Deref for TenantDb {
+    type Target = DatabaseTransaction;
+
+    fn deref(&self) -> &DatabaseTransaction {
+        &self.txn
+    }
+}
+
+impl FromRequestParts<AppState> for TenantDb {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> AppResult<Self> {
+        let org = OrgContext::from_request_parts(parts, state).await?;
+        let txn = db::begin_tenant_transaction(&state.db, org.org_id, state.config.tenant_rls)
+            .await
+            .map_err(AppError::from)?;
+
+        Ok(TenantDb { org, txn })
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use sha1::{Digest, Sha1};
+
+use crate::error::{AppError, AppResult};
+
+/// Leaked password hashes kept on local disk in the k-anonymity range format
+///
+/// The SHA-1 of each password is split into a five-character prefix, which
+/// names a file (`21BD1.txt`), and a suffix, looked up among that file's
+/// `SUFFIX:COUNT` lines. Such corpora can be downloaded once and refreshed
+/// offline, so checks never send anything about a password elsewhere.
+#[derive(Clone, Debug)]
+pub struct BreachCorpus {
+    dir: PathBuf,
+}
+
+impl BreachCorpus {
+    pub fn open(dir: impl Into<PathBuf>) -> AppResult<Self> {
+        let dir = dir.into();
+        if !dir.is_dir() {
+            return Err(AppError::internal(format!(
+                "Breached password corpus {} is not a directory",
+                dir.display()
+            )));
+        }
+
+        Ok(Self { dir })
+    }
+
+    /// Whether the password appears in the corpus
+    ///
+    /// Only the file for the hash prefix is read. A missing file counts as
+    /// no match, so partial corpora work; entries with a zero count are
+    /// padding and do not match either.
+    pub async fn contains(&self, password: &str) -> AppResult<bool> {
+        let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = digest.split_at(5);
+        let path = self.dir.join(format!("{}.txt", prefix));
+
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(err) => {
+                return Err(AppError::internal(format!(
+                    "Failed to read breached password corpus {}: {}",
+                    path.display(),
+                    err
+                )))
+            }
+        };
+
+        Ok(contents.lines().any(|line| {
+            let mut parts = line.trim().splitn(2, ':');
+            let matches = parts
+                .next()
+                .is_some_and(|entry| entry.eq_ignore_ascii_case(suffix));
+            let count = parts
+                .next()
+                .map_or(1, |count| count.trim().parse::<u64>().unwrap_or(1));
+            matches && count > 0
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// SHA-1 of "password", split into its range prefix and suffix
+    const PREFIX: &str = "5BAA6";
+    const SUFFIX: &str = "1E4C9B93F3F0682250B6CF8331B7EE68FD8";
+
+    /// A corpus directory holding one range file, removed when dropped
+    struct Corpus(PathBuf);
+
+    impl Corpus {
+        fn with_range(lines: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("breach-{}", Uuid::new_v4().simple()));
+            std::fs::create_dir(&dir).unwrap();
+            std::fs::write(dir.join(format!("{}.txt", PREFIX)), lines).unwrap();
+            Self(dir)
+        }
+
+        fn open(&self) -> BreachCorpus {
+            BreachCorpus::open(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for Corpus {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_passwords_in_their_range() {
+        let corpus = Corpus::with_range(&format!(
+            "0018A45C4D1DEF81644B54AB7F969B88D65:1\r\n{}:3861493\r\n",
+            SUFFIX.to_lowercase()
+        ));
+
+        assert!(corpus.open().contains("password").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn needs_the_whole_suffix_to_match() {
+        let corpus = Corpus::with_range(&format!(
+            "{}:1\n{}0:1\n",
+            &SUFFIX[..SUFFIX.len() - 1],
+            SUFFIX
+        ));
+
+        assert!(!corpus.open().contains("password").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn ignores_padding_entries() {
+        let corpus = Corpus::with_range(&format!("{}:0\n", SUFFIX));
+
+        assert!(!corpus.open().contains("password").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn treats_missing_ranges_as_no_match() {
+        let corpus = Corpus::with_range(&format!("{}:1\n", SUFFIX));
+
+        assert!(!corpus
+            .open()
+            .contains("correct horse battery staple")
+            .await
+            .unwrap());
+    }
+
+    #[test]
+    fn refuses_a_missing_directory() {
+        let missing = std::env::temp_dir().join(format!("breach-{}", Uuid::new_v4().simple()));
+
+        assert!(matches!(
+            BreachCorpus::open(missing),
+            Err(AppError::Internal(_))
+        ));
+    }
+}
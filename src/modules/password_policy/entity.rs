@@ -0,0 +1,41 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// An organization's password rules, applied to its members on top of the
+/// deployment's
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "password_policies")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub organization_id: Uuid,
+    pub min_length: Option<i32>,
+    pub min_score: Option<i16>,
+    /// Lowercase words passwords must not contain, one per line
+    pub banned_words: String,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl Model {
+    pub fn banned_words(&self) -> impl Iterator<Item = &str> {
+        self.banned_words.lines().filter(|word| !word.is_empty())
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::modules::organizations::entity::Entity",
+        from = "Column::OrganizationId",
+        to = "crate::modules::organizations::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Organization,
+}
+
+impl Related<crate::modules::organizations::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organization.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,88 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use uuid::Uuid;
+
+use crate::error::AppResult;
+use crate::modules::organizations::context::{Admin, OrgContext, OrgGuard};
+use crate::state::AppState;
+
+use super::service::{self, PolicySettings};
+
+#[derive(serde::Deserialize)]
+pub struct ConfigurePasswordPolicyRequest {
+    pub min_length: Option<usize>,
+    pub min_score: Option<u8>,
+    #[serde(default)]
+    pub banned_words: Vec<String>,
+}
+
+/// The rules members' passwords are held to, including the deployment's
+#[derive(serde::Serialize)]
+pub struct PasswordPolicyResponse {
+    pub organization_id: Uuid,
+    pub min_length: usize,
+    pub max_length: usize,
+    pub min_score: u8,
+    pub banned_words: Vec<String>,
+    /// Passwords found in the deployment's breached password corpus are
+    /// rejected
+    pub breach_check: bool,
+    /// Whether the organization has its own policy on top of the
+    /// deployment's
+    pub customized: bool,
+    pub updated_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+async fn policy_response(
+    state: &AppState,
+    organization_id: Uuid,
+) -> AppResult<PasswordPolicyResponse> {
+    let (rules, policy) = service::effective_rules(state, organization_id).await?;
+    Ok(PasswordPolicyResponse {
+        organization_id,
+        min_length: rules.min_length,
+        max_length: state.password_policy.max_length(),
+        min_score: rules.min_score,
+        banned_words: rules.banned_words,
+        breach_check: state.password_policy.breach_corpus().is_some(),
+        customized: policy.is_some(),
+        updated_at: policy.map(|policy| policy.updated_at),
+    })
+}
+
+/// GET /api/orgs/:org_id/password-policy
+///
+/// Open to every member, so password forms can show the rules up front.
+pub async fn get_policy(
+    State(state): State<AppState>,
+    org: OrgContext,
+) -> AppResult<impl IntoResponse> {
+    Ok(Json(policy_response(&state, org.org_id).await?))
+}
+
+/// PUT /api/orgs/:org_id/password-policy
+pub async fn configure_policy(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    Json(payload): Json<ConfigurePasswordPolicyRequest>,
+) -> AppResult<impl IntoResponse> {
+    service::configure(
+        &state,
+        org.org_id,
+        PolicySettings {
+            min_length: payload.min_length,
+            min_score: payload.min_score,
+            banned_words: payload.banned_words,
+        },
+    )
+    .await?;
+    Ok(Json(policy_response(&state, org.org_id).await?))
+}
+
+/// DELETE /api/orgs/:org_id/password-policy
+pub async fn delete_policy(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+) -> AppResult<impl IntoResponse> {
+    service::remove(&state, org.org_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
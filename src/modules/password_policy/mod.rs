@@ -0,0 +1,7 @@
+pub mod breach;
+pub mod entity;
+pub mod handler;
+pub mod policy;
+pub mod routes;
+pub mod service;
+pub mod strength;
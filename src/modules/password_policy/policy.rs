@@ -0,0 +1,331 @@
+use std::collections::HashSet;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+
+use super::breach::BreachCorpus;
+use super::entity;
+use super::strength;
+
+/// Passwords rejected everywhere, on top of `password_banned_list_path`
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// Name and email parts shorter than this are not looked for in passwords
+const MIN_PERSONAL_TOKEN: usize = 3;
+
+/// The deployment's password rules, loaded once at startup
+#[derive(Debug)]
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    min_score: u8,
+    banned: HashSet<String>,
+    breach_corpus: Option<BreachCorpus>,
+}
+
+/// The rules one password is held to: the deployment's, tightened by the
+/// policies of the organizations involved
+#[derive(Clone, Debug)]
+pub struct Rules {
+    pub min_length: usize,
+    pub min_score: u8,
+    /// Words that must not appear anywhere in the password
+    pub banned_words: Vec<String>,
+}
+
+impl Rules {
+    /// Apply an organization's policy; it can only make the rules stricter
+    pub fn tighten(&mut self, policy: &entity::Model) {
+        if let Some(min_length) = policy.min_length {
+            self.min_length = self.min_length.max(min_length as usize);
+        }
+        if let Some(min_score) = policy.min_score {
+            self.min_score = self.min_score.max(min_score as u8);
+        }
+        self.banned_words
+            .extend(policy.banned_words().map(str::to_string));
+    }
+}
+
+impl PasswordPolicy {
+    pub fn from_config(config: &AppConfig) -> AppResult<Self> {
+        if config.password_min_length == 0
+            || config.password_min_length > config.password_max_length
+        {
+            return Err(AppError::internal(
+                "PASSWORD_MIN_LENGTH must be between 1 and PASSWORD_MAX_LENGTH",
+            ));
+        }
+        if config.password_min_score > strength::MAX_SCORE {
+            return Err(AppError::internal("PASSWORD_MIN_SCORE must be at most 4"));
+        }
+
+        let mut banned: HashSet<String> = parse_list(COMMON_PASSWORDS).collect();
+        if let Some(path) = &config.password_banned_list_path {
+            let list = std::fs::read_to_string(path).map_err(|e| {
+                AppError::internal(format!(
+                    "Failed to read banned password list {}: {}",
+                    path, e
+                ))
+            })?;
+            banned.extend(parse_list(&list));
+        }
+
+        let breach_corpus = config
+            .password_breach_corpus_dir
+            .as_deref()
+            .map(BreachCorpus::open)
+            .transpose()?;
+
+        Ok(Self {
+            min_length: config.password_min_length,
+            max_length: config.password_max_length,
+            min_score: config.password_min_score,
+            banned,
+            breach_corpus,
+        })
+    }
+
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    pub fn breach_corpus(&self) -> Option<&BreachCorpus> {
+        self.breach_corpus.as_ref()
+    }
+
+    /// The deployment's rules, before any organization tightens them
+    pub fn rules(&self) -> Rules {
+        Rules {
+            min_length: self.min_length,
+            min_score: self.min_score,
+            banned_words: Vec::new(),
+        }
+    }
+
+    /// What is wrong with a password under `rules`, empty if nothing is
+    ///
+    /// `email` and `name` belong to the account, and passwords built from
+    /// them are rejected. The breach corpus is not consulted here.
+    pub fn violations(
+        &self,
+        password: &str,
+        rules: &Rules,
+        email: &str,
+        name: &str,
+    ) -> Vec<String> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+        if length < rules.min_length {
+            violations.push(format!(
+                "Password must be at least {} characters",
+                rules.min_length
+            ));
+        }
+        if length > self.max_length {
+            violations.push(format!(
+                "Password must be at most {} characters",
+                self.max_length
+            ));
+            // Everything below is pointless work on an oversized input
+            return violations;
+        }
+
+        let lowered = password.to_lowercase();
+        if self.is_common(&lowered) {
+            violations.push("Password is too common".to_string());
+        }
+        if personal_tokens(email, name).any(|token| lowered.contains(&token)) {
+            violations.push("Password must not contain your name or email address".to_string());
+        }
+        if rules
+            .banned_words
+            .iter()
+            .any(|word| lowered.contains(word.as_str()))
+        {
+            violations
+                .push("Password contains a word your organization does not allow".to_string());
+        }
+        if rules.min_score > 0
+            && strength::score(password, |candidate| self.banned.contains(candidate))
+                < rules.min_score
+        {
+            violations.push(
+                "Password is too easy to guess; try a longer phrase of unrelated words".to_string(),
+            );
+        }
+
+        violations
+    }
+
+    /// Whether the password, or its letters without surrounding digits and
+    /// symbols (`Password1!`), is on the banned list
+    fn is_common(&self, lowered: &str) -> bool {
+        let core = lowered.trim_matches(|c: char| !c.is_alphabetic());
+        self.banned.contains(lowered) || (core.chars().count() >= 4 && self.banned.contains(core))
+    }
+}
+
+fn parse_list(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.lines()
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Lowercase pieces of an account's email and name that passwords must not
+/// contain: the local part of the email and each word of the name
+fn personal_tokens<'a>(email: &'a str, name: &'a str) -> impl Iterator<Item = String> + 'a {
+    let local_part = email.split('@').next().unwrap_or_default();
+    std::iter::once(local_part)
+        .chain(name.split_whitespace())
+        .map(str::to_lowercase)
+        .filter(|token| token.chars().count() >= MIN_PERSONAL_TOKEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::test_support::test_config;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy::from_config(&test_config()).unwrap()
+    }
+
+    fn violations(password: &str, rules: &Rules) -> Vec<String> {
+        policy().violations(password, rules, "jane.doe@example.com", "Jane Al Doe")
+    }
+
+    fn organization_policy(
+        min_length: Option<i32>,
+        min_score: Option<i16>,
+        banned_words: &str,
+    ) -> entity::Model {
+        entity::Model {
+            organization_id: Uuid::new_v4(),
+            min_length,
+            min_score,
+            banned_words: banned_words.to_string(),
+            updated_at: chrono::Utc::now().fixed_offset(),
+        }
+    }
+
+    #[test]
+    fn accepts_a_reasonable_password() {
+        assert!(violations("violet-kettle-orbit", &policy().rules()).is_empty());
+    }
+
+    #[test]
+    fn enforces_length() {
+        let rules = policy().rules();
+
+        assert_eq!(
+            violations("kettle", &rules),
+            ["Password must be at least 8 characters"]
+        );
+        // Counted in characters, not bytes
+        assert!(violations("ĸettle€€", &rules).is_empty());
+        // Nothing else is checked on an oversized password
+        assert_eq!(
+            violations(&"password".repeat(20), &rules),
+            ["Password must be at most 128 characters"]
+        );
+    }
+
+    #[test]
+    fn rejects_common_passwords_and_their_decorations() {
+        let rules = policy().rules();
+
+        for password in ["password", "QWERTY123", "Password1!", "!!letmein2024"] {
+            assert_eq!(
+                violations(password, &rules),
+                ["Password is too common"],
+                "{}",
+                password
+            );
+        }
+        // Common words inside a longer password are left to the strength check
+        assert!(violations("monkey-kettle-orbit", &rules).is_empty());
+    }
+
+    #[test]
+    fn rejects_passwords_built_from_the_account() {
+        let rules = policy().rules();
+        let personal = ["Password must not contain your name or email address"];
+
+        assert_eq!(violations("Jane.Doe.2024", &rules), personal);
+        assert_eq!(violations("kettle-JANE-orbit", &rules), personal);
+        assert_eq!(violations("orbit-doe-kettle", &rules), personal);
+        // Name parts too short to matter are ignored
+        assert!(violations("kettle-al-orbit", &rules).is_empty());
+    }
+
+    #[test]
+    fn applies_organization_rules() {
+        let mut rules = policy().rules();
+        rules.tighten(&organization_policy(Some(24), Some(4), "violet\n\nacme\n"));
+
+        assert_eq!(
+            violations("violet-kettle-orbit", &rules),
+            [
+                "Password must be at least 24 characters",
+                "Password contains a word your organization does not allow"
+            ]
+        );
+        assert_eq!(
+            violations("aaaaaaaaaaaaaaaaaaaaaaaa", &rules),
+            ["Password is too easy to guess; try a longer phrase of unrelated words"]
+        );
+        assert!(violations("kettle-orbit-lantern-fjord", &rules).is_empty());
+    }
+
+    #[test]
+    fn organization_rules_only_tighten() {
+        let mut rules = policy().rules();
+        rules.min_score = 2;
+        rules.tighten(&organization_policy(Some(4), Some(1), ""));
+        assert_eq!((rules.min_length, rules.min_score), (8, 2));
+
+        rules.tighten(&organization_policy(None, None, "acme"));
+        rules.tighten(&organization_policy(Some(12), Some(3), "globex"));
+        assert_eq!((rules.min_length, rules.min_score), (12, 3));
+        assert_eq!(rules.banned_words, ["acme", "globex"]);
+    }
+
+    #[test]
+    fn extends_the_banned_list_from_a_file() {
+        let path = std::env::temp_dir().join(format!("banned-{}.txt", Uuid::new_v4().simple()));
+        std::fs::write(&path, "# house rules\n\n  Kettle-Orbit-Fjord \n").unwrap();
+        let mut config = test_config();
+        config.password_banned_list_path = Some(path.to_string_lossy().into_owned());
+
+        let policy = PasswordPolicy::from_config(&config);
+        std::fs::remove_file(&path).unwrap();
+        let policy = policy.unwrap();
+
+        assert_eq!(
+            policy.violations("kettle-orbit-fjord", &policy.rules(), "a@example.com", ""),
+            ["Password is too common"]
+        );
+    }
+
+    #[test]
+    fn refuses_inconsistent_settings() {
+        let broken: [fn(&mut AppConfig); 4] = [
+            |config| config.password_min_length = 0,
+            |config| config.password_min_length = config.password_max_length + 1,
+            |config| config.password_min_score = strength::MAX_SCORE + 1,
+            |config| config.password_banned_list_path = Some("/nonexistent/banned.txt".into()),
+        ];
+
+        for configure in broken {
+            let mut config = test_config();
+            configure(&mut config);
+            assert!(matches!(
+                PasswordPolicy::from_config(&config),
+                Err(AppError::Internal(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use axum::{routing::get, Router};
+
+use crate::state::AppState;
+
+use super::handler;
+
+/// An organization's password rules, nested under
+/// `/api/orgs/{org_id}/password-policy`
+pub fn org_password_policy_routes() -> Router<AppState> {
+    Router::new().route(
+        "/",
+        get(handler::get_policy)
+            .put(handler::configure_policy)
+            .delete(handler::delete_policy),
+    )
+}
@@ -0,0 +1,404 @@
+use sea_orm::{sea_query::OnConflict, ColumnTrait, EntityTrait, QueryFilter, QuerySelect, Set};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::organizations::membership::{self, Entity as Memberships};
+use crate::modules::users::entity as users;
+use crate::state::AppState;
+
+use super::entity::{self as password_policy, Entity as PasswordPolicies};
+use super::policy::Rules;
+use super::strength;
+
+/// Most words an organization can ban
+const MAX_BANNED_WORDS: usize = 200;
+/// Longest word an organization can ban
+const MAX_BANNED_WORD_LEN: usize = 64;
+
+/// The account a password is being set for
+#[derive(Clone, Copy, Debug)]
+pub struct PasswordOwner<'a> {
+    pub email: &'a str,
+    pub name: &'a str,
+    /// An existing account, held to the policies of its organizations
+    pub user_id: Option<Uuid>,
+    /// An organization being joined, whose policy applies as well
+    pub organization_id: Option<Uuid>,
+}
+
+impl<'a> PasswordOwner<'a> {
+    /// Someone signing up, without an account or organization yet
+    pub fn new(email: &'a str, name: &'a str) -> Self {
+        Self {
+            email,
+            name,
+            user_id: None,
+            organization_id: None,
+        }
+    }
+
+    pub fn user(user: &'a users::Model) -> Self {
+        Self {
+            user_id: Some(user.id),
+            ..Self::new(&user.email, &user.name)
+        }
+    }
+
+    pub fn joining(self, organization_id: Uuid) -> Self {
+        Self {
+            organization_id: Some(organization_id),
+            ..self
+        }
+    }
+}
+
+/// Fail with `422` unless the password satisfies every policy that applies
+/// to its owner, listing each rule it breaks
+pub async fn validate(
+    state: &AppState,
+    password: &str,
+    owner: &PasswordOwner<'_>,
+) -> AppResult<()> {
+    let policy = &state.password_policy;
+
+    let mut rules = policy.rules();
+    for organization_policy in policies_for(state, owner).await? {
+        rules.tighten(&organization_policy);
+    }
+
+    let mut violations = policy.violations(password, &rules, owner.email, owner.name);
+    if violations.is_empty() {
+        if let Some(corpus) = policy.breach_corpus() {
+            if corpus.contains(password).await? {
+                violations.push(
+                    "Password has appeared in a data breach; choose a different one".to_string(),
+                );
+            }
+        }
+    }
+
+    if !violations.is_empty() {
+        return Err(AppError::ValidationError(violations.join("; ")));
+    }
+    Ok(())
+}
+
+async fn policies_for(
+    state: &AppState,
+    owner: &PasswordOwner<'_>,
+) -> AppResult<Vec<password_policy::Model>> {
+    let mut organization_ids: Vec<Uuid> = owner.organization_id.into_iter().collect();
+    if let Some(user_id) = owner.user_id {
+        let memberships: Vec<Uuid> = Memberships::find()
+            .select_only()
+            .column(membership::Column::OrganizationId)
+            .filter(membership::Column::UserId.eq(user_id))
+            .into_tuple()
+            .all(&state.db)
+            .await
+            .map_err(AppError::from)?;
+        organization_ids.extend(memberships);
+    }
+    if organization_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    PasswordPolicies::find()
+        .filter(password_policy::Column::OrganizationId.is_in(organization_ids))
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// The rules members of an organization are held to, and whether the
+/// organization has its own policy
+pub async fn effective_rules(
+    state: &AppState,
+    organization_id: Uuid,
+) -> AppResult<(Rules, Option<password_policy::Model>)> {
+    let policy = PasswordPolicies::find_by_id(organization_id)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    let mut rules = state.password_policy.rules();
+    if let Some(policy) = &policy {
+        rules.tighten(policy);
+    }
+
+    Ok((rules, policy))
+}
+
+/// Settings for an organization's policy; unset limits fall back to the
+/// deployment's
+pub struct PolicySettings {
+    pub min_length: Option<usize>,
+    pub min_score: Option<u8>,
+    pub banned_words: Vec<String>,
+}
+
+/// Create or replace an organization's policy
+///
+/// Organizations can only tighten the deployment's rules, so limits below
+/// them are rejected rather than silently ignored.
+pub async fn configure(
+    state: &AppState,
+    organization_id: Uuid,
+    settings: PolicySettings,
+) -> AppResult<password_policy::Model> {
+    let deployment = state.password_policy.rules();
+
+    if let Some(min_length) = settings.min_length {
+        if min_length < deployment.min_length || min_length > state.password_policy.max_length() {
+            return Err(AppError::ValidationError(format!(
+                "min_length must be between {} and {}",
+                deployment.min_length,
+                state.password_policy.max_length()
+            )));
+        }
+    }
+    if let Some(min_score) = settings.min_score {
+        if min_score < deployment.min_score || min_score > strength::MAX_SCORE {
+            return Err(AppError::ValidationError(format!(
+                "min_score must be between {} and {}",
+                deployment.min_score,
+                strength::MAX_SCORE
+            )));
+        }
+    }
+
+    let mut banned_words: Vec<String> = Vec::new();
+    for word in &settings.banned_words {
+        let word = word.trim().to_lowercase();
+        if word.is_empty() || word.chars().count() > MAX_BANNED_WORD_LEN || word.contains('\n') {
+            return Err(AppError::ValidationError(format!(
+                "Banned words must be single lines of 1 to {} characters",
+                MAX_BANNED_WORD_LEN
+            )));
+        }
+        if !banned_words.contains(&word) {
+            banned_words.push(word);
+        }
+    }
+    if banned_words.len() > MAX_BANNED_WORDS {
+        return Err(AppError::ValidationError(format!(
+            "At most {} banned words are allowed",
+            MAX_BANNED_WORDS
+        )));
+    }
+
+    let policy = password_policy::ActiveModel {
+        organization_id: Set(organization_id),
+        min_length: Set(settings.min_length.map(|length| length as i32)),
+        min_score: Set(settings.min_score.map(i16::from)),
+        banned_words: Set(banned_words.join("\n")),
+        updated_at: Set(chrono::Utc::now().fixed_offset()),
+    };
+
+    PasswordPolicies::insert(policy)
+        .on_conflict(
+            OnConflict::column(password_policy::Column::OrganizationId)
+                .update_columns([
+                    password_policy::Column::MinLength,
+                    password_policy::Column::MinScore,
+                    password_policy::Column::BannedWords,
+                    password_policy::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_with_returning(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Drop an organization's policy, leaving the deployment's rules in force
+pub async fn remove(state: &AppState, organization_id: Uuid) -> AppResult<()> {
+    let result = PasswordPolicies::delete_by_id(organization_id)
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    if result.rows_affected == 0 {
+        return Err(AppError::NotFound(
+            "The organization has no password policy".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::DatabaseConnection;
+    use sha1::{Digest, Sha1};
+
+    use super::*;
+    use crate::modules::organizations::service as organizations_service;
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_config, test_db, test_state, unique_email};
+
+    fn settings(
+        min_length: Option<usize>,
+        min_score: Option<u8>,
+        words: &[&str],
+    ) -> PolicySettings {
+        PolicySettings {
+            min_length,
+            min_score,
+            banned_words: words.iter().map(|word| word.to_string()).collect(),
+        }
+    }
+
+    fn is_validation_error(result: AppResult<()>, expected: &str) -> bool {
+        matches!(result, Err(AppError::ValidationError(message)) if message.contains(expected))
+    }
+
+    /// A user owning a new organization
+    async fn owner(state: &AppState) -> (users::Model, Uuid) {
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Owner",
+        )
+        .await
+        .unwrap();
+        let name = format!("Policy {}", Uuid::new_v4().simple());
+        let (organization, _) =
+            organizations_service::create_organization(state, &user, &name, None)
+                .await
+                .unwrap();
+        (user, organization.id)
+    }
+
+    #[tokio::test]
+    async fn refuses_policies_looser_than_the_deployment() {
+        let mut config = test_config();
+        config.password_min_score = 2;
+        let state = AppState::new(DatabaseConnection::Disconnected, config);
+        let organization_id = Uuid::new_v4();
+
+        for (settings, expected) in [
+            (
+                settings(Some(7), None, &[]),
+                "min_length must be between 8 and 128",
+            ),
+            (
+                settings(Some(129), None, &[]),
+                "min_length must be between 8 and 128",
+            ),
+            (
+                settings(None, Some(1), &[]),
+                "min_score must be between 2 and 4",
+            ),
+            (
+                settings(None, Some(5), &[]),
+                "min_score must be between 2 and 4",
+            ),
+            (
+                settings(None, None, &["  "]),
+                "Banned words must be single lines",
+            ),
+            (
+                settings(None, None, &["a\nb"]),
+                "Banned words must be single lines",
+            ),
+        ] {
+            let result = configure(&state, organization_id, settings).await;
+            assert!(
+                is_validation_error(result.map(|_| ()), expected),
+                "{}",
+                expected
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn holds_members_to_their_organizations_policies() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (member, organization_id) = owner(&state).await;
+        let (_, other_organization_id) = owner(&state).await;
+
+        let policy = configure(
+            &state,
+            organization_id,
+            settings(Some(20), None, &[" Violet ", "violet"]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(policy.banned_words, "violet");
+
+        let password = "violet-kettle-orbit";
+        validate(
+            &state,
+            password,
+            &PasswordOwner::new(&unique_email(), "New"),
+        )
+        .await
+        .unwrap();
+        // Every broken rule is reported at once
+        assert!(is_validation_error(
+            validate(&state, password, &PasswordOwner::user(&member)).await,
+            "at least 20 characters; Password contains a word your organization does not allow"
+        ));
+        // Someone joining the organization is held to its policy before they
+        // are a member
+        assert!(is_validation_error(
+            validate(
+                &state,
+                password,
+                &PasswordOwner::new(&unique_email(), "New").joining(organization_id)
+            )
+            .await,
+            "does not allow"
+        ));
+        validate(
+            &state,
+            password,
+            &PasswordOwner::new(&unique_email(), "New").joining(other_organization_id),
+        )
+        .await
+        .unwrap();
+
+        remove(&state, organization_id).await.unwrap();
+        validate(&state, password, &PasswordOwner::user(&member))
+            .await
+            .unwrap();
+        assert!(matches!(
+            remove(&state, organization_id).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_breached_passwords() {
+        // SHA-1 of "violet-kettle-orbit" is looked up in its range file
+        let dir = std::env::temp_dir().join(format!("breach-{}", Uuid::new_v4().simple()));
+        std::fs::create_dir(&dir).unwrap();
+        let digest = hex::encode_upper(Sha1::digest(b"violet-kettle-orbit"));
+        let (prefix, suffix) = digest.split_at(5);
+        std::fs::write(
+            dir.join(format!("{}.txt", prefix)),
+            format!("{}:2\n", suffix),
+        )
+        .unwrap();
+
+        let mut config = test_config();
+        config.password_breach_corpus_dir = Some(dir.to_string_lossy().into_owned());
+        let Some(db) = test_db().await else {
+            std::fs::remove_dir_all(&dir).unwrap();
+            return;
+        };
+        let state = AppState::new(db, config);
+        let owner = PasswordOwner::new("jane@example.com", "Jane");
+
+        let breached = validate(&state, "violet-kettle-orbit", &owner).await;
+        let fresh = validate(&state, "violet-kettle-fjord", &owner).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(is_validation_error(breached, "appeared in a data breach"));
+        fresh.unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! A small zxcvbn-style strength estimate
+//!
+//! Guesses are modelled on how crackers work rather than on character-class
+//! rules: each character costs the size of the alphabet in play, except
+//! repeats and steps of a sequence (`aaa`, `abc`, `987`) which cost almost
+//! nothing, and any common password inside it costs a single dictionary
+//! lookup. The result uses zxcvbn's 0 to 4 scale.
+
+/// Guesses attributed to a dictionary hit, about the size of the lists
+/// crackers try first
+const DICTIONARY_GUESSES_LOG10: f64 = 4.0;
+
+/// Shortest run checked against the dictionary
+const MIN_DICTIONARY_MATCH: usize = 4;
+
+/// Highest score
+pub const MAX_SCORE: u8 = 4;
+
+/// Estimated strength from 0 (guessable within a thousand tries) to 4 (more
+/// than ten billion)
+pub fn score(password: &str, is_common: impl Fn(&str) -> bool) -> u8 {
+    match log10_guesses(password, is_common) {
+        guesses if guesses < 3.0 => 0,
+        guesses if guesses < 6.0 => 1,
+        guesses if guesses < 8.0 => 2,
+        guesses if guesses < 10.0 => 3,
+        _ => MAX_SCORE,
+    }
+}
+
+fn log10_guesses(password: &str, is_common: impl Fn(&str) -> bool) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let lowered: Vec<char> = password.to_lowercase().chars().collect();
+    // Lowercasing can change the length of some scripts; skip dictionary
+    // matching rather than slice at mismatched positions
+    let dictionary = lowered.len() == chars.len();
+    let per_char = (alphabet_size(&chars) as f64).log10();
+
+    let mut guesses = 0.0;
+    let mut i = 0;
+    while i < chars.len() {
+        if dictionary {
+            if let Some(len) = longest_common_at(&lowered, i, &is_common) {
+                guesses += DICTIONARY_GUESSES_LOG10;
+                // Capitalization only doubles the work
+                if chars[i..i + len] != lowered[i..i + len] {
+                    guesses += 2f64.log10();
+                }
+                i += len;
+                continue;
+            }
+        }
+
+        let predictable = i > 0 && (chars[i] == chars[i - 1] || continues_sequence(&chars[..=i]));
+        guesses += if predictable { 2f64.log10() } else { per_char };
+        i += 1;
+    }
+
+    guesses
+}
+
+fn longest_common_at(
+    lowered: &[char],
+    start: usize,
+    is_common: &impl Fn(&str) -> bool,
+) -> Option<usize> {
+    (MIN_DICTIONARY_MATCH..=lowered.len() - start)
+        .rev()
+        .find(|len| is_common(&lowered[start..start + len].iter().collect::<String>()))
+}
+
+/// Whether the last character steps from the one before it by the same
+/// amount as that one did (`ab` then `c`, `9` `8` then `7`)
+fn continues_sequence(chars: &[char]) -> bool {
+    let [.., a, b, c] = chars else {
+        return false;
+    };
+    let step = *b as i64 - *a as i64;
+    step.abs() == 1 && *c as i64 - *b as i64 == step
+}
+
+fn alphabet_size(chars: &[char]) -> u32 {
+    let mut size = 0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        size += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        size += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        size += 10;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') {
+        size += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        size += 100;
+    }
+    size.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn common(candidate: &str) -> bool {
+        matches!(candidate, "password" | "monkey" | "dragon")
+    }
+
+    #[test]
+    fn scores_guessable_passwords_low() {
+        for password in ["", "aaaa", "abcdefgh", "98765432", "password", "Password"] {
+            assert!(score(password, common) <= 1, "{}", password);
+        }
+    }
+
+    #[test]
+    fn scores_long_unpredictable_passwords_high() {
+        for password in ["correct horse battery staple", "Tr0ub4dor&3", "x7#Kp2!vQm"] {
+            assert_eq!(score(password, common), MAX_SCORE, "{}", password);
+        }
+    }
+
+    #[test]
+    fn charges_a_dictionary_word_as_one_guess() {
+        let dictionary = log10_guesses("xxpasswordxx", common);
+        let random = log10_guesses("xxqzvtrwkbxx", common);
+
+        assert!(dictionary < random - 5.0, "{} vs {}", dictionary, random);
+        // Capitalizing a word only doubles its cost
+        let capitalized = log10_guesses("Password", common) - log10_guesses("password", common);
+        assert!(capitalized > 0.0 && capitalized < 0.5, "{}", capitalized);
+    }
+
+    #[test]
+    fn charges_repeats_and_sequences_almost_nothing() {
+        let single = log10_guesses("k", common);
+
+        for predictable in ["kkkkkkkk", "klmnopqr", "kjihgfed"] {
+            let extra = log10_guesses(predictable, common) - single;
+            assert!(extra < 7.0 * 0.5, "{}: {}", predictable, extra);
+        }
+        // A step that changes direction is not a sequence
+        assert!(!continues_sequence(&['a', 'b', 'a']));
+        assert!(!continues_sequence(&['a', 'c', 'e']));
+    }
+
+    #[test]
+    fn does_not_panic_on_case_changing_scripts() {
+        // 'İ' lowercases to two characters
+        assert!(score("İİİİpassword", common) <= MAX_SCORE);
+    }
+}
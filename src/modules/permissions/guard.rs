@@ -0,0 +1,170 @@
+use std::{marker::PhantomData, ops::Deref};
+
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::request::Parts,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::extractor::AuthUser;
+use crate::modules::organizations::context::{selected_org_id, OrgContext};
+use crate::state::AppState;
+
+use super::permission::{Permission, PermissionSet};
+use super::service;
+
+/// The caller's permissions in the active organization
+///
+/// Resolved like [`OrgContext`], from the built-in role and any custom role
+/// of the membership. As an `Option`, it is `None` when the request selects
+/// no organization, for routes that also serve callers acting on their own
+/// resources.
+#[derive(Clone, Debug)]
+pub struct Permissions {
+    pub org: OrgContext,
+    granted: PermissionSet,
+}
+
+impl Permissions {
+    pub fn granted(&self) -> &PermissionSet {
+        &self.granted
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        self.granted.contains(permission)
+    }
+
+    /// Fail with `403 Forbidden`, naming the permission, unless it is granted
+    pub fn require(&self, permission: Permission) -> AppResult<()> {
+        if !self.allows(permission) {
+            return Err(missing(permission));
+        }
+
+        Ok(())
+    }
+}
+
+/// Fail unless the caller owns the resource or holds `permission` in the
+/// active organization
+///
+/// Members act on their own resources without any permission; acting on
+/// anyone else's needs one, and thus an organization to hold it in.
+pub fn require_owner_or(
+    auth: &AuthUser,
+    permissions: Option<&Permissions>,
+    permission: Permission,
+    owner_id: Uuid,
+) -> AppResult<()> {
+    if auth.user.id == owner_id {
+        return Ok(());
+    }
+
+    permissions
+        .ok_or_else(|| missing(permission))?
+        .require(permission)
+}
+
+fn missing(permission: Permission) -> AppError {
+    AppError::MissingPermission {
+        permission: permission.as_str(),
+    }
+}
+
+impl FromRequestParts<AppState> for Permissions {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> AppResult<Self> {
+        if let Some(permissions) = parts.extensions.get::<Permissions>() {
+            return Ok(permissions.clone());
+        }
+
+        let org = OrgContext::from_request_parts(parts, state).await?;
+        let mut granted = service::granted(&state.db, &org).await?;
+
+        // Clients acting for a user only get what their scopes allow
+        let auth = AuthUser::from_request_parts(parts, state).await?;
+        if let Some(cap) = auth.api_key.and_then(|grant| grant.permissions) {
+            granted.retain_within(&cap);
+        }
+
+        let permissions = Permissions { org, granted };
+        parts.extensions.insert(permissions.clone());
+        Ok(permissions)
+    }
+}
+
+impl OptionalFromRequestParts<AppState> for Permissions {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> AppResult<Option<Self>> {
+        let auth = AuthUser::from_request_parts(parts, state).await?;
+        if selected_org_id(parts, state, &auth).await?.is_none() {
+            return Ok(None);
+        }
+
+        <Permissions as FromRequestParts<AppState>>::from_request_parts(parts, state)
+            .await
+            .map(Some)
+    }
+}
+
+/// Permission required by an [`Authorized`]
+pub trait RequiredPermission: Send + Sync {
+    const PERMISSION: Permission;
+}
+
+/// Marker for [`Authorized`]: `users:read`
+pub struct ReadUsers;
+/// Marker for [`Authorized`]: `users:write`
+pub struct WriteUsers;
+/// Marker for [`Authorized`]: `roles:manage`
+pub struct ManageRoles;
+/// Marker for [`Authorized`]: `billing:manage`
+#[allow(dead_code)] // Part of public API; billing routes check it as they are added
+pub struct ManageBilling;
+
+impl RequiredPermission for ReadUsers {
+    const PERMISSION: Permission = Permission::UsersRead;
+}
+
+impl RequiredPermission for WriteUsers {
+    const PERMISSION: Permission = Permission::UsersWrite;
+}
+
+impl RequiredPermission for ManageRoles {
+    const PERMISSION: Permission = Permission::RolesManage;
+}
+
+impl RequiredPermission for ManageBilling {
+    const PERMISSION: Permission = Permission::BillingManage;
+}
+
+/// [`Permissions`] that include `P`, otherwise `403 Forbidden` with the
+/// missing permission in `details`
+///
+/// ```rust,ignore
+/// pub async fn handler(permissions: Authorized<WriteUsers>) -> AppResult<impl IntoResponse> {
+///     Ok(Json(permissions.org.org_id))
+/// }
+/// ```
+pub struct Authorized<P: RequiredPermission>(pub Permissions, PhantomData<P>);
+
+impl<P: RequiredPermission> Deref for Authorized<P> {
+    type Target = Permissions;
+
+    fn deref(&self) -> &Permissions {
+        &self.0
+    }
+}
+
+impl<P: RequiredPermission> FromRequestParts<AppState> for Authorized<P> {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> AppResult<Self> {
+        let permissions =
+            <Permissions as FromRequestParts<AppState>>::from_request_parts(parts, state).await?;
+        permissions.require(P::PERMISSION)?;
+        Ok(Authorized(permissions, PhantomData))
+    }
+}
@@ -0,0 +1,177 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::error::AppResult;
+use crate::modules::organizations::membership::OrgRole;
+use crate::state::AppState;
+
+use super::entity;
+use super::guard::{Authorized, ManageRoles, Permissions};
+use super::permission::{granted_by, Permission, PermissionSet};
+use super::service::{self, RoleSettings};
+
+#[derive(serde::Deserialize)]
+pub struct RoleRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+}
+
+impl RoleRequest {
+    fn into_settings(self) -> AppResult<RoleSettings> {
+        Ok(RoleSettings {
+            permissions: PermissionSet::parse_all(self.permissions.iter().map(String::as_str))?,
+            name: self.name,
+            description: self.description,
+        })
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct AssignRoleRequest {
+    /// `null` takes the member's custom role away
+    pub role_id: Option<Uuid>,
+}
+
+/// A built-in or custom role
+#[derive(serde::Serialize)]
+pub struct RoleResponse {
+    /// `None` for built-in roles
+    pub id: Option<Uuid>,
+    pub name: String,
+    pub description: Option<String>,
+    pub built_in: bool,
+    pub permissions: PermissionSet,
+    pub created_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub updated_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+impl RoleResponse {
+    fn built_in(role: OrgRole) -> Self {
+        Self {
+            id: None,
+            name: format!("{:?}", role).to_lowercase(),
+            description: None,
+            built_in: true,
+            permissions: granted_by(role).iter().copied().collect(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl From<entity::Model> for RoleResponse {
+    fn from(model: entity::Model) -> Self {
+        Self {
+            id: Some(model.id),
+            permissions: model.permissions(),
+            name: model.name,
+            description: model.description,
+            built_in: false,
+            created_at: Some(model.created_at),
+            updated_at: Some(model.updated_at),
+        }
+    }
+}
+
+/// The caller's access in an organization
+#[derive(serde::Serialize)]
+pub struct MyPermissionsResponse {
+    pub organization_id: Uuid,
+    pub role: OrgRole,
+    pub custom_role_id: Option<Uuid>,
+    pub permissions: PermissionSet,
+}
+
+#[derive(serde::Serialize)]
+pub struct MemberRoleResponse {
+    pub user_id: Uuid,
+    pub role: OrgRole,
+    pub custom_role_id: Option<Uuid>,
+}
+
+/// GET /api/permissions
+///
+/// Every permission roles can grant.
+pub async fn list_permissions() -> impl IntoResponse {
+    Json(Permission::ALL)
+}
+
+/// GET /api/orgs/:org_id/permissions
+pub async fn my_permissions(permissions: Permissions) -> AppResult<impl IntoResponse> {
+    Ok(Json(MyPermissionsResponse {
+        organization_id: permissions.org.org_id,
+        role: permissions.org.role,
+        custom_role_id: permissions.org.custom_role_id,
+        permissions: permissions.granted().clone(),
+    }))
+}
+
+/// GET /api/orgs/:org_id/roles
+///
+/// Built-in roles first, then the organization's own.
+pub async fn list_roles(
+    State(state): State<AppState>,
+    permissions: Permissions,
+) -> AppResult<impl IntoResponse> {
+    let custom = service::list_roles(&state, permissions.org.org_id).await?;
+    let roles: Vec<RoleResponse> = [OrgRole::Member, OrgRole::Admin, OrgRole::Owner]
+        .into_iter()
+        .map(RoleResponse::built_in)
+        .chain(custom.into_iter().map(RoleResponse::from))
+        .collect();
+
+    Ok(Json(roles))
+}
+
+/// POST /api/orgs/:org_id/roles
+pub async fn create_role(
+    State(state): State<AppState>,
+    permissions: Authorized<ManageRoles>,
+    Json(payload): Json<RoleRequest>,
+) -> AppResult<impl IntoResponse> {
+    let role = service::create_role(&state, &permissions, payload.into_settings()?).await?;
+    Ok((StatusCode::CREATED, Json(RoleResponse::from(role))))
+}
+
+/// PUT /api/orgs/:org_id/roles/:role_id
+pub async fn update_role(
+    State(state): State<AppState>,
+    permissions: Authorized<ManageRoles>,
+    Path((_, role_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<RoleRequest>,
+) -> AppResult<impl IntoResponse> {
+    let role =
+        service::update_role(&state, &permissions, role_id, payload.into_settings()?).await?;
+    Ok(Json(RoleResponse::from(role)))
+}
+
+/// DELETE /api/orgs/:org_id/roles/:role_id
+pub async fn delete_role(
+    State(state): State<AppState>,
+    permissions: Authorized<ManageRoles>,
+    Path((_, role_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    service::delete_role(&state, &permissions, role_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// PUT /api/orgs/:org_id/members/:user_id/custom-role
+pub async fn assign_role(
+    State(state): State<AppState>,
+    permissions: Authorized<ManageRoles>,
+    Path((_, user_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<AssignRoleRequest>,
+) -> AppResult<impl IntoResponse> {
+    let membership = service::assign_role(&state, &permissions, user_id, payload.role_id).await?;
+    Ok(Json(MemberRoleResponse {
+        user_id: membership.user_id,
+        role: membership.role,
+        custom_role_id: membership.custom_role_id,
+    }))
+}
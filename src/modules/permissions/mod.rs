@@ -0,0 +1,6 @@
+pub mod entity;
+pub mod guard;
+pub mod handler;
+pub mod permission;
+pub mod routes;
+pub mod service;
@@ -0,0 +1,219 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+use crate::modules::organizations::membership::OrgRole;
+
+/// Something a member may do in an organization
+///
+/// Written `resource:action`. Built-in roles grant fixed sets (see
+/// [`granted_by`]); custom roles add to them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Permission {
+    #[serde(rename = "users:read")]
+    UsersRead,
+    #[serde(rename = "users:write")]
+    UsersWrite,
+    #[serde(rename = "roles:manage")]
+    RolesManage,
+    #[serde(rename = "billing:manage")]
+    BillingManage,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 4] = [
+        Permission::UsersRead,
+        Permission::UsersWrite,
+        Permission::RolesManage,
+        Permission::BillingManage,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::UsersRead => "users:read",
+            Permission::UsersWrite => "users:write",
+            Permission::RolesManage => "roles:manage",
+            Permission::BillingManage => "billing:manage",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|permission| permission.as_str() == value)
+    }
+}
+
+/// Permissions that come with a built-in role
+pub fn granted_by(role: OrgRole) -> &'static [Permission] {
+    match role {
+        OrgRole::Member => &[Permission::UsersRead],
+        OrgRole::Admin => &[
+            Permission::UsersRead,
+            Permission::UsersWrite,
+            Permission::RolesManage,
+        ],
+        OrgRole::Owner => &Permission::ALL,
+    }
+}
+
+/// A set of permissions, stored space-separated like API key scopes
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct PermissionSet(BTreeSet<Permission>);
+
+impl PermissionSet {
+    /// Parse stored permissions, skipping any this build no longer knows
+    pub fn from_stored(value: &str) -> Self {
+        value
+            .split_whitespace()
+            .filter_map(Permission::parse)
+            .collect()
+    }
+
+    /// Parse permissions from a request, rejecting unknown ones
+    pub fn parse_all<'a>(values: impl IntoIterator<Item = &'a str>) -> AppResult<Self> {
+        values
+            .into_iter()
+            .map(|value| {
+                Permission::parse(value.trim()).ok_or_else(|| {
+                    AppError::ValidationError(format!("Unknown permission {}", value))
+                })
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, permission: Permission) -> bool {
+        self.0.contains(&permission)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The first permission of `self` that `other` lacks
+    pub fn first_missing_from(&self, other: &PermissionSet) -> Option<Permission> {
+        self.0.difference(&other.0).next().copied()
+    }
+
+    /// Drop the permissions `cap` does not include
+    pub fn retain_within(&mut self, cap: &PermissionSet) {
+        self.0.retain(|permission| cap.contains(*permission));
+    }
+
+    pub fn extend(&mut self, other: impl IntoIterator<Item = Permission>) {
+        self.0.extend(other);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Permission> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn to_stored(&self) -> String {
+        self.iter()
+            .map(Permission::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl FromIterator<Permission> for PermissionSet {
+    fn from_iter<I: IntoIterator<Item = Permission>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(permissions: &[Permission]) -> PermissionSet {
+        permissions.iter().copied().collect()
+    }
+
+    #[test]
+    fn round_trips_every_permission() {
+        for permission in Permission::ALL {
+            assert_eq!(Permission::parse(permission.as_str()), Some(permission));
+            assert_eq!(
+                serde_json::to_value(permission).unwrap(),
+                permission.as_str()
+            );
+        }
+        assert_eq!(Permission::parse("users:delete"), None);
+        assert_eq!(Permission::parse("USERS:READ"), None);
+    }
+
+    #[test]
+    fn stores_permissions_in_a_stable_order() {
+        let permissions = set(&[Permission::BillingManage, Permission::UsersRead]);
+
+        assert_eq!(permissions.to_stored(), "users:read billing:manage");
+        assert_eq!(
+            PermissionSet::from_stored(&permissions.to_stored()),
+            permissions
+        );
+        assert_eq!(PermissionSet::from_stored(""), PermissionSet::default());
+    }
+
+    #[test]
+    fn skips_stored_permissions_it_no_longer_knows() {
+        let stored = PermissionSet::from_stored("  users:read reports:export\tusers:write ");
+
+        assert_eq!(
+            stored,
+            set(&[Permission::UsersRead, Permission::UsersWrite])
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_requested_permissions() {
+        let parsed = PermissionSet::parse_all([" users:read ", "users:read", "roles:manage"]);
+        assert_eq!(
+            parsed.unwrap(),
+            set(&[Permission::UsersRead, Permission::RolesManage])
+        );
+
+        let result = PermissionSet::parse_all(["users:read", "reports:export"]);
+        assert!(
+            matches!(result, Err(AppError::ValidationError(message)) if message == "Unknown permission reports:export")
+        );
+    }
+
+    #[test]
+    fn finds_permissions_missing_from_another_set() {
+        let admin = set(granted_by(OrgRole::Admin));
+        let owner = set(granted_by(OrgRole::Owner));
+
+        assert_eq!(admin.first_missing_from(&owner), None);
+        assert_eq!(
+            owner.first_missing_from(&admin),
+            Some(Permission::BillingManage)
+        );
+        assert_eq!(PermissionSet::default().first_missing_from(&admin), None);
+    }
+
+    #[test]
+    fn retains_only_permissions_within_a_cap() {
+        let mut permissions = set(&[Permission::UsersWrite, Permission::BillingManage]);
+        permissions.retain_within(&set(granted_by(OrgRole::Admin)));
+
+        assert_eq!(permissions, set(&[Permission::UsersWrite]));
+
+        permissions.retain_within(&set(granted_by(OrgRole::Member)));
+        assert!(permissions.is_empty());
+    }
+
+    #[test]
+    fn built_in_roles_grant_nested_sets() {
+        let member = set(granted_by(OrgRole::Member));
+        let admin = set(granted_by(OrgRole::Admin));
+        let owner = set(granted_by(OrgRole::Owner));
+
+        assert_eq!(member.first_missing_from(&admin), None);
+        assert_eq!(admin.first_missing_from(&owner), None);
+        assert!(Permission::ALL.iter().all(|p| owner.contains(*p)));
+        assert!(!admin.contains(Permission::BillingManage));
+    }
+}
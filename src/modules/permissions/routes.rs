@@ -0,0 +1,34 @@
+use axum::{
+    routing::{get, put},
+    Router,
+};
+
+use crate::state::AppState;
+
+use super::handler;
+
+/// The permission catalog, nested under `/api/permissions`
+pub fn permission_routes() -> Router<AppState> {
+    Router::new().route("/", get(handler::list_permissions))
+}
+
+/// The caller's permissions, nested under `/api/orgs/{org_id}/permissions`
+pub fn org_permission_routes() -> Router<AppState> {
+    Router::new().route("/", get(handler::my_permissions))
+}
+
+/// Built-in and custom roles, nested under `/api/orgs/{org_id}/roles`
+pub fn org_role_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(handler::list_roles).post(handler::create_role))
+        .route(
+            "/{role_id}",
+            put(handler::update_role).delete(handler::delete_role),
+        )
+}
+
+/// A member's custom role, nested under
+/// `/api/orgs/{org_id}/members/{user_id}/custom-role`
+pub fn org_member_role_routes() -> Router<AppState> {
+    Router::new().route("/", put(handler::assign_role))
+}
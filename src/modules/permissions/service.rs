@@ -0,0 +1,238 @@
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::organizations::{
+    context::OrgContext,
+    membership::{self, OrgRole},
+    service as organizations_service,
+};
+use crate::state::AppState;
+
+use super::entity::{self as org_role, Entity as OrgRoles};
+use super::guard::Permissions;
+use super::permission::{granted_by, PermissionSet};
+
+/// Longest custom role name
+const MAX_ROLE_NAME_LEN: usize = 64;
+/// Longest custom role description
+const MAX_ROLE_DESCRIPTION_LEN: usize = 255;
+
+/// Everything a member may do: their built-in role's permissions plus their
+/// custom role's
+pub async fn granted<C: ConnectionTrait>(db: &C, org: &OrgContext) -> AppResult<PermissionSet> {
+    let mut granted: PermissionSet = granted_by(org.role).iter().copied().collect();
+
+    if let Some(role_id) = org.custom_role_id {
+        if let Some(role) = find_role(db, org.org_id, role_id).await? {
+            granted.extend(role.permissions().iter());
+        }
+    }
+
+    Ok(granted)
+}
+
+async fn find_role<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    role_id: Uuid,
+) -> AppResult<Option<org_role::Model>> {
+    OrgRoles::find_by_id(role_id)
+        .filter(org_role::Column::OrganizationId.eq(organization_id))
+        .one(db)
+        .await
+        .map_err(AppError::from)
+}
+
+fn role_not_found(role_id: Uuid) -> AppError {
+    AppError::NotFound(format!("Role with id {} not found", role_id))
+}
+
+pub async fn list_roles(
+    state: &AppState,
+    organization_id: Uuid,
+) -> AppResult<Vec<org_role::Model>> {
+    OrgRoles::find()
+        .filter(org_role::Column::OrganizationId.eq(organization_id))
+        .order_by_asc(org_role::Column::Name)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Details of a custom role to create or update
+pub struct RoleSettings {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: PermissionSet,
+}
+
+impl RoleSettings {
+    /// Check the settings, and that `actor` holds every permission they
+    /// grant, so no one can hand out more than they have
+    fn validate(mut self, actor: &Permissions) -> AppResult<Self> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() || self.name.chars().count() > MAX_ROLE_NAME_LEN {
+            return Err(AppError::ValidationError(format!(
+                "Name must be between 1 and {} characters",
+                MAX_ROLE_NAME_LEN
+            )));
+        }
+        if [OrgRole::Member, OrgRole::Admin, OrgRole::Owner]
+            .iter()
+            .any(|role| format!("{:?}", role).eq_ignore_ascii_case(&self.name))
+        {
+            return Err(AppError::Conflict(format!(
+                "{} is the name of a built-in role",
+                self.name
+            )));
+        }
+
+        self.description = self
+            .description
+            .map(|description| description.trim().to_string())
+            .filter(|description| !description.is_empty());
+        if self
+            .description
+            .as_ref()
+            .is_some_and(|description| description.chars().count() > MAX_ROLE_DESCRIPTION_LEN)
+        {
+            return Err(AppError::ValidationError(format!(
+                "Description must be at most {} characters",
+                MAX_ROLE_DESCRIPTION_LEN
+            )));
+        }
+
+        if self.permissions.is_empty() {
+            return Err(AppError::ValidationError(
+                "A role needs at least one permission".to_string(),
+            ));
+        }
+        ensure_holds(actor, &self.permissions)?;
+
+        Ok(self)
+    }
+}
+
+fn ensure_holds(actor: &Permissions, permissions: &PermissionSet) -> AppResult<()> {
+    if let Some(permission) = permissions.first_missing_from(actor.granted()) {
+        return Err(AppError::MissingPermission {
+            permission: permission.as_str(),
+        });
+    }
+
+    Ok(())
+}
+
+pub async fn create_role(
+    state: &AppState,
+    actor: &Permissions,
+    settings: RoleSettings,
+) -> AppResult<org_role::Model> {
+    let settings = settings.validate(actor)?;
+    let now = chrono::Utc::now().fixed_offset();
+
+    org_role::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        organization_id: Set(actor.org.org_id),
+        name: Set(settings.name),
+        description: Set(settings.description),
+        permissions: Set(settings.permissions.to_stored()),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(|err| match AppError::from(err) {
+        AppError::Conflict(_) => {
+            AppError::Conflict("A role with this name already exists".to_string())
+        }
+        err => err,
+    })
+}
+
+/// Replace a custom role's settings; members holding it are affected
+/// immediately
+pub async fn update_role(
+    state: &AppState,
+    actor: &Permissions,
+    role_id: Uuid,
+    settings: RoleSettings,
+) -> AppResult<org_role::Model> {
+    let role = find_role(&state.db, actor.org.org_id, role_id)
+        .await?
+        .ok_or_else(|| role_not_found(role_id))?;
+    // Taking permissions away is as sensitive as granting them
+    ensure_holds(actor, &role.permissions())?;
+    let settings = settings.validate(actor)?;
+
+    let mut active: org_role::ActiveModel = role.into();
+    active.name = Set(settings.name);
+    active.description = Set(settings.description);
+    active.permissions = Set(settings.permissions.to_stored());
+    active.updated_at = Set(chrono::Utc::now().fixed_offset());
+
+    active
+        .update(&state.db)
+        .await
+        .map_err(|err| match AppError::from(err) {
+            AppError::Conflict(_) => {
+                AppError::Conflict("A role with this name already exists".to_string())
+            }
+            err => err,
+        })
+}
+
+/// Delete a custom role; its members keep their built-in roles
+pub async fn delete_role(state: &AppState, actor: &Permissions, role_id: Uuid) -> AppResult<()> {
+    let role = find_role(&state.db, actor.org.org_id, role_id)
+        .await?
+        .ok_or_else(|| role_not_found(role_id))?;
+    ensure_holds(actor, &role.permissions())?;
+
+    OrgRoles::delete_by_id(role.id)
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+/// Give a member a custom role, or take it away with `None`
+pub async fn assign_role(
+    state: &AppState,
+    actor: &Permissions,
+    user_id: Uuid,
+    role_id: Option<Uuid>,
+) -> AppResult<membership::Model> {
+    let target = organizations_service::find_membership(&state.db, actor.org.org_id, user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Member with user id {} not found", user_id)))?;
+
+    if target.role > actor.org.role {
+        return Err(AppError::Forbidden(
+            "You cannot change the roles of members above your own role".to_string(),
+        ));
+    }
+
+    let current = match target.custom_role_id {
+        Some(current_id) => find_role(&state.db, actor.org.org_id, current_id).await?,
+        None => None,
+    };
+    if let Some(current) = &current {
+        ensure_holds(actor, &current.permissions())?;
+    }
+    if let Some(role_id) = role_id {
+        let role = find_role(&state.db, actor.org.org_id, role_id)
+            .await?
+            .ok_or_else(|| role_not_found(role_id))?;
+        ensure_holds(actor, &role.permissions())?;
+    }
+
+    let mut active: membership::ActiveModel = target.into();
+    active.custom_role_id = Set(role_id);
+    active.updated_at = Set(chrono::Utc::now().fixed_offset());
+    active.update(&state.db).await.map_err(AppError::from)
+}
@@ -0,0 +1,271 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::mailer::templates::app_link;
+use crate::modules::auth::{
+    extractor::AuthUser,
+    handler::{AuthResponse, MfaChallengeResponse},
+    verification,
+};
+use crate::modules::mfa::service as mfa_service;
+use crate::modules::oidc::handler::IdentityResponse;
+use crate::modules::organizations::{
+    context::{OrgContext, OrgGuard, Owner},
+    membership::OrgRole,
+    service as organizations_service,
+};
+use crate::modules::sessions::mode::SignInMode;
+use crate::state::AppState;
+
+use super::entity;
+use super::metadata;
+use super::request;
+use super::service;
+
+#[derive(serde::Deserialize)]
+pub struct ConfigureSamlRequest {
+    /// The identity provider's metadata document
+    pub metadata_xml: String,
+    #[serde(default = "default_role")]
+    pub default_role: OrgRole,
+}
+
+fn default_role() -> OrgRole {
+    OrgRole::Member
+}
+
+/// Fields the identity provider's page posts to the assertion consumer service
+#[derive(serde::Deserialize)]
+pub struct AcsForm {
+    #[serde(rename = "SAMLResponse")]
+    pub saml_response: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SsoLoginRequest {
+    pub token: String,
+}
+
+/// Where to post the `SAMLRequest` that starts a link, as a form field
+/// named `SAMLRequest`
+#[derive(serde::Serialize)]
+pub struct SamlAuthorizationResponse {
+    pub sso_url: String,
+    pub saml_request: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct SamlConnectionResponse {
+    pub organization_id: Uuid,
+    pub idp_entity_id: String,
+    pub idp_sso_url: String,
+    pub idp_certificate_count: usize,
+    pub default_role: OrgRole,
+    /// What to register at the identity provider
+    pub sp_entity_id: String,
+    pub acs_url: String,
+    /// Where members start signing in
+    pub login_url: String,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+    pub updated_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl SamlConnectionResponse {
+    fn new(model: entity::Model, sp: service::ServiceProvider) -> Self {
+        Self {
+            organization_id: model.organization_id,
+            idp_entity_id: model.idp_entity_id.clone(),
+            idp_sso_url: model.idp_sso_url.clone(),
+            idp_certificate_count: model.certificates().len(),
+            default_role: model.default_role,
+            sp_entity_id: sp.entity_id,
+            acs_url: sp.acs_url,
+            login_url: sp.login_url,
+            created_at: model.created_at,
+            updated_at: model.updated_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct SsoLoginResponse {
+    pub organization_id: Uuid,
+    #[serde(flatten)]
+    pub auth: AuthResponse,
+}
+
+/// GET /api/orgs/:org_id/saml
+pub async fn get_connection(
+    State(state): State<AppState>,
+    org: OrgGuard<Owner>,
+) -> AppResult<impl IntoResponse> {
+    let connection = service::get_connection(&state.db, org.org_id).await?;
+    let sp = service::service_provider(&state.config, org.org_id);
+    Ok(Json(SamlConnectionResponse::new(connection, sp)))
+}
+
+/// PUT /api/orgs/:org_id/saml
+pub async fn configure_connection(
+    State(state): State<AppState>,
+    org: OrgGuard<Owner>,
+    Json(payload): Json<ConfigureSamlRequest>,
+) -> AppResult<impl IntoResponse> {
+    organizations_service::ensure_can_grant(&org, payload.default_role)?;
+
+    let connection = service::configure(
+        &state,
+        org.org_id,
+        &payload.metadata_xml,
+        payload.default_role,
+    )
+    .await?;
+    let sp = service::service_provider(&state.config, org.org_id);
+    Ok(Json(SamlConnectionResponse::new(connection, sp)))
+}
+
+/// DELETE /api/orgs/:org_id/saml
+pub async fn delete_connection(
+    State(state): State<AppState>,
+    org: OrgGuard<Owner>,
+) -> AppResult<impl IntoResponse> {
+    service::remove(&state, org.org_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/saml/:org_id/metadata
+pub async fn sp_metadata(
+    State(state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    service::get_connection(&state.db, org_id).await?;
+    let sp = service::service_provider(&state.config, org_id);
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/samlmetadata+xml")],
+        metadata::sp_metadata(&sp.entity_id, &sp.acs_url),
+    ))
+}
+
+/// GET /api/saml/:org_id/login
+///
+/// Answers with a page that posts an authentication request to the
+/// organization's identity provider.
+pub async fn login(
+    State(state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let (sso_url, saml_request) = service::start_login(&state, org_id).await?;
+    Ok(Html(request::post_form(&sso_url, &saml_request)))
+}
+
+/// POST /api/saml/:org_id/acs
+///
+/// Validates the identity provider's response and redirects the browser to
+/// the app with a one-time code for `POST /api/auth/sso`, or for
+/// `POST /api/users/me/identities/saml` when a member is linking their
+/// account.
+pub async fn acs(
+    State(state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    Form(form): Form<AcsForm>,
+) -> AppResult<Response> {
+    let sign_in = match service::complete_response(&state, org_id, &form.saml_response).await? {
+        service::AcsOutcome::SignIn(sign_in) => sign_in,
+        service::AcsOutcome::Link { token } => {
+            return Ok(Redirect::to(&app_link(
+                &state.config.app_base_url,
+                "/auth/sso/link",
+                &token,
+            ))
+            .into_response());
+        }
+    };
+    let user = sign_in.user;
+
+    if !user.is_active {
+        return Err(AppError::Forbidden("Account is disabled".to_string()));
+    }
+
+    if sign_in.created && user.email_verified_at.is_none() {
+        if let Err(err) = verification::send_verification_email(&state, &user).await {
+            tracing::warn!(
+                "Failed to send verification email to user {}: {}",
+                user.id,
+                err
+            );
+        }
+    }
+
+    let token = service::issue_login_token(&state, user.id, org_id).await?;
+    Ok(Redirect::to(&app_link(
+        &state.config.app_base_url,
+        "/auth/sso/callback",
+        &token,
+    ))
+    .into_response())
+}
+
+/// POST /api/auth/sso
+///
+/// Accounts that need a second factor get an [`MfaChallengeResponse`] like
+/// a password login.
+pub async fn exchange(
+    State(state): State<AppState>,
+    mode: SignInMode,
+    Json(payload): Json<SsoLoginRequest>,
+) -> AppResult<Response> {
+    let (user, organization_id) = service::exchange_login_token(&state, &payload.token).await?;
+
+    if !user.is_active {
+        return Err(AppError::Forbidden("Account is disabled".to_string()));
+    }
+    verification::ensure_sign_in_allowed(&state.config, &user)?;
+
+    if mfa_service::requires_challenge(&state.db, user.id).await? {
+        return Ok(Json(MfaChallengeResponse::start(&state, user.id).await?).into_response());
+    }
+
+    let (cookies, auth) = AuthResponse::sign_in(&state, &mode, user).await?;
+    Ok((
+        cookies,
+        Json(SsoLoginResponse {
+            organization_id,
+            auth,
+        }),
+    )
+        .into_response())
+}
+
+/// POST /api/users/me/identities/saml/:org_id/authorize
+pub async fn authorize_link(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    org: OrgContext,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let (sso_url, saml_request) = service::start_link(&state, &auth.user, org.org_id).await?;
+    Ok(Json(SamlAuthorizationResponse {
+        sso_url,
+        saml_request,
+    }))
+}
+
+/// POST /api/users/me/identities/saml
+///
+/// Completes a link started with the authorize endpoint above; the body is
+/// the code the assertion consumer service redirected with.
+pub async fn link_identity(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<SsoLoginRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let identity = service::complete_link(&state, &auth.user, &payload.token).await?;
+    Ok((StatusCode::CREATED, Json(IdentityResponse::from(identity))))
+}
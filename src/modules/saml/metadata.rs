@@ -0,0 +1,112 @@
+//! SAML metadata: reading an identity provider's and describing ours
+
+use crate::error::{AppError, AppResult};
+
+use super::dsig;
+use super::xml::{self, BINDING_HTTP_POST, NS_DSIG, NS_METADATA};
+
+/// What we need from an identity provider's metadata
+pub struct IdpMetadata {
+    pub entity_id: String,
+    /// Single sign-on endpoint for the HTTP-POST binding
+    pub sso_url: String,
+    /// Signing certificates, base64 DER
+    pub certificates: Vec<String>,
+}
+
+/// Read an `EntityDescriptor` with an `IDPSSODescriptor`
+///
+/// The identity provider must offer the HTTP-POST binding and publish at
+/// least one signing certificate, since unsigned assertions are rejected.
+pub fn parse_idp_metadata(metadata_xml: &str) -> AppResult<IdpMetadata> {
+    let invalid =
+        |reason: &str| AppError::ValidationError(format!("Invalid IdP metadata: {}", reason));
+
+    let document = xml::parse(metadata_xml)?;
+    let root = document.root_element();
+
+    // Aggregates list several providers; only one can be used here
+    let entity = if xml::is(root, NS_METADATA, "EntityDescriptor") {
+        root
+    } else if xml::is(root, NS_METADATA, "EntitiesDescriptor") {
+        let mut entities = root
+            .children()
+            .filter(|node| xml::is(*node, NS_METADATA, "EntityDescriptor"))
+            .filter(|node| xml::child(*node, NS_METADATA, "IDPSSODescriptor").is_some());
+        let entity = entities
+            .next()
+            .ok_or_else(|| invalid("no identity provider found"))?;
+        if entities.next().is_some() {
+            return Err(invalid("more than one identity provider found"));
+        }
+        entity
+    } else {
+        return Err(invalid("expected an EntityDescriptor"));
+    };
+
+    let entity_id = entity
+        .attribute("entityID")
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| invalid("entityID is missing"))?
+        .to_string();
+    let descriptor = xml::child(entity, NS_METADATA, "IDPSSODescriptor")
+        .ok_or_else(|| invalid("no IDPSSODescriptor"))?;
+
+    let sso_url = xml::children(descriptor, NS_METADATA, "SingleSignOnService")
+        .find(|node| node.attribute("Binding") == Some(BINDING_HTTP_POST))
+        .and_then(|node| node.attribute("Location"))
+        .map(str::trim)
+        .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+        .ok_or_else(|| invalid("no single sign-on endpoint with the HTTP-POST binding"))?
+        .to_string();
+
+    let mut certificates = Vec::new();
+    for key in xml::children(descriptor, NS_METADATA, "KeyDescriptor") {
+        if key.attribute("use").is_some_and(|usage| usage != "signing") {
+            continue;
+        }
+        for certificate in key
+            .descendants()
+            .filter(|node| xml::is(*node, NS_DSIG, "X509Certificate"))
+        {
+            let certificate: String = xml::text(certificate)
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            dsig::check_certificate(&certificate)?;
+            if !certificates.contains(&certificate) {
+                certificates.push(certificate);
+            }
+        }
+    }
+    if certificates.is_empty() {
+        return Err(invalid("no signing certificate"));
+    }
+
+    Ok(IdpMetadata {
+        entity_id,
+        sso_url,
+        certificates,
+    })
+}
+
+/// Our service provider metadata, for the identity provider's administrator
+pub fn sp_metadata(entity_id: &str, acs_url: &str) -> String {
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<md:EntityDescriptor xmlns:md="{md}" entityID="{entity_id}">"#,
+            r#"<md:SPSSODescriptor AuthnRequestsSigned="false" WantAssertionsSigned="true" protocolSupportEnumeration="urn:oasis:names:tc:SAML:2.0:protocol">"#,
+            r#"<md:NameIDFormat>urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress</md:NameIDFormat>"#,
+            r#"<md:NameIDFormat>urn:oasis:names:tc:SAML:2.0:nameid-format:persistent</md:NameIDFormat>"#,
+            r#"<md:AssertionConsumerService Binding="{binding}" Location="{acs_url}" index="0" isDefault="true"/>"#,
+            r#"</md:SPSSODescriptor>"#,
+            r#"</md:EntityDescriptor>"#,
+        ),
+        md = NS_METADATA,
+        entity_id = xml::escape_attr(entity_id),
+        binding = BINDING_HTTP_POST,
+        acs_url = xml::escape_attr(acs_url),
+    )
+}
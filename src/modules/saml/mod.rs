@@ -0,0 +1,13 @@
+pub mod auth_request;
+pub mod dsig;
+pub mod entity;
+pub mod handler;
+pub mod metadata;
+pub mod request;
+pub mod response;
+pub mod routes;
+pub mod service;
+#[cfg(test)]
+mod test_idp;
+pub mod used_assertion;
+pub mod xml;
@@ -0,0 +1,54 @@
+//! Authentication requests sent to identity providers with the HTTP-POST
+//! binding
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use super::xml::{self, BINDING_HTTP_POST, NS_ASSERTION, NS_PROTOCOL};
+
+/// Format of SAML timestamps
+pub const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// A new `AuthnRequest` id; ids must not start with a digit
+pub fn new_request_id() -> String {
+    format!("_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// An `AuthnRequest` asking the identity provider to post its response to
+/// `acs_url`, base64-encoded for the `SAMLRequest` form field
+pub fn authn_request(id: &str, sp_entity_id: &str, acs_url: &str, sso_url: &str) -> String {
+    let xml = format!(
+        concat!(
+            r#"<samlp:AuthnRequest xmlns:samlp="{samlp}" xmlns:saml="{saml}" ID="{id}" Version="2.0" IssueInstant="{now}" Destination="{destination}" AssertionConsumerServiceURL="{acs_url}" ProtocolBinding="{binding}">"#,
+            r#"<saml:Issuer>{issuer}</saml:Issuer>"#,
+            r#"<samlp:NameIDPolicy AllowCreate="true"/>"#,
+            r#"</samlp:AuthnRequest>"#,
+        ),
+        samlp = NS_PROTOCOL,
+        saml = NS_ASSERTION,
+        id = xml::escape_attr(id),
+        now = chrono::Utc::now().format(TIME_FORMAT),
+        destination = xml::escape_attr(sso_url),
+        acs_url = xml::escape_attr(acs_url),
+        binding = BINDING_HTTP_POST,
+        issuer = xml::escape_text(sp_entity_id),
+    );
+
+    STANDARD.encode(xml)
+}
+
+/// A page that makes the browser post `SAMLRequest` to the identity provider
+pub fn post_form(sso_url: &str, saml_request: &str) -> String {
+    format!(
+        concat!(
+            "<!DOCTYPE html>\n",
+            r#"<html><head><meta charset="utf-8"><title>Signing in…</title></head>"#,
+            r#"<body onload="document.forms[0].submit()">"#,
+            r#"<form method="post" action="{action}">"#,
+            r#"<input type="hidden" name="SAMLRequest" value="{request}">"#,
+            r#"<noscript><button type="submit">Continue to sign in</button></noscript>"#,
+            r#"</form></body></html>"#,
+        ),
+        action = xml::escape_attr(sso_url),
+        request = xml::escape_attr(saml_request),
+    )
+}
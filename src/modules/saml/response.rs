@@ -0,0 +1,477 @@
+//! Validation of responses identity providers post to the assertion consumer
+//! service, following the Web Browser SSO profile
+//!
+//! Only responses to requests we sent are accepted (no IdP-initiated sign-in),
+//! with exactly one plain assertion. Encrypted assertions are not supported.
+
+use chrono::{DateTime, Utc};
+use roxmltree::Node;
+
+use crate::error::{AppError, AppResult};
+
+use super::dsig;
+use super::xml::{self, NS_ASSERTION, NS_DSIG, NS_PROTOCOL};
+
+const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const CONFIRMATION_BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+const NAME_ID_EMAIL: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress";
+
+/// Attribute names, matched case-insensitively against `Name` and
+/// `FriendlyName`, that identity providers commonly use
+const EMAIL_ATTRIBUTES: [&str; 4] = [
+    "email",
+    "mail",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/emailaddress",
+    "urn:oid:0.9.2342.19200300.100.1.3",
+];
+const NAME_ATTRIBUTES: [&str; 4] = [
+    "displayName",
+    "name",
+    "http://schemas.microsoft.com/identity/claims/displayname",
+    "urn:oid:2.16.840.1.113730.3.1.241",
+];
+const GIVEN_NAME_ATTRIBUTES: [&str; 4] = [
+    "givenName",
+    "firstName",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/givenname",
+    "urn:oid:2.5.4.42",
+];
+const SURNAME_ATTRIBUTES: [&str; 5] = [
+    "sn",
+    "surname",
+    "lastName",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/surname",
+    "urn:oid:2.5.4.4",
+];
+
+/// What a response must match: the organization's identity provider and our
+/// own endpoints
+pub struct Expected<'a> {
+    pub idp_entity_id: &'a str,
+    pub certificates: &'a [String],
+    pub sp_entity_id: &'a str,
+    pub acs_url: &'a str,
+    pub clock_skew: chrono::Duration,
+}
+
+/// A validated assertion about the signed-in user
+#[derive(Debug)]
+pub struct Assertion {
+    pub id: String,
+    /// Id of our `AuthnRequest` the response answers
+    pub in_response_to: String,
+    pub name_id: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    /// After this the assertion is rejected as expired, so it only needs to
+    /// be remembered for replay detection until then
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Decode and validate the `SAMLResponse` form field
+pub fn validate(saml_response: &str, expected: &Expected) -> AppResult<Assertion> {
+    let bytes = dsig::decode_base64(saml_response).map_err(|_| invalid("malformed encoding"))?;
+    let text = String::from_utf8(bytes).map_err(|_| invalid("malformed encoding"))?;
+    let document = xml::parse(&text)?;
+    let response = document.root_element();
+    let now = Utc::now();
+
+    if !xml::is(response, NS_PROTOCOL, "Response") || response.attribute("Version") != Some("2.0") {
+        return Err(invalid("not a SAML 2.0 response"));
+    }
+    if response
+        .attribute("Destination")
+        .is_some_and(|destination| destination != expected.acs_url)
+    {
+        return Err(invalid("wrong destination"));
+    }
+    if let Some(issuer) = xml::child(response, NS_ASSERTION, "Issuer") {
+        if xml::text(issuer) != expected.idp_entity_id {
+            return Err(invalid("wrong issuer"));
+        }
+    }
+
+    let status = xml::child(response, NS_PROTOCOL, "Status")
+        .and_then(|status| xml::child(status, NS_PROTOCOL, "StatusCode"))
+        .and_then(|code| code.attribute("Value"));
+    if status != Some(STATUS_SUCCESS) {
+        tracing::info!(
+            "Identity provider {} answered with status {:?}",
+            expected.idp_entity_id,
+            status
+        );
+        return Err(AppError::Unauthorized(
+            "The identity provider did not sign you in".to_string(),
+        ));
+    }
+
+    if xml::child(response, NS_ASSERTION, "EncryptedAssertion").is_some() {
+        return Err(invalid("encrypted assertions are not supported"));
+    }
+    let mut assertions = xml::children(response, NS_ASSERTION, "Assertion");
+    let assertion = assertions.next().ok_or_else(|| invalid("no assertion"))?;
+    if assertions.next().is_some() {
+        return Err(invalid("more than one assertion"));
+    }
+
+    // Either signature covers the assertion; at least one is required
+    let response_signed = xml::child(response, NS_DSIG, "Signature").is_some();
+    let assertion_signed = xml::child(assertion, NS_DSIG, "Signature").is_some();
+    if response_signed {
+        dsig::verify_enveloped(response, expected.certificates)?;
+    }
+    if assertion_signed || !response_signed {
+        dsig::verify_enveloped(assertion, expected.certificates)?;
+    }
+
+    let id = assertion
+        .attribute("ID")
+        .ok_or_else(|| invalid("assertion has no ID"))?
+        .to_string();
+    let issuer = xml::child(assertion, NS_ASSERTION, "Issuer").map(xml::text);
+    if issuer.as_deref() != Some(expected.idp_entity_id) {
+        return Err(invalid("wrong assertion issuer"));
+    }
+
+    let subject = xml::child(assertion, NS_ASSERTION, "Subject")
+        .ok_or_else(|| invalid("assertion has no subject"))?;
+    let name_id_node = xml::child(subject, NS_ASSERTION, "NameID")
+        .ok_or_else(|| invalid("subject has no NameID"))?;
+    let name_id = xml::text(name_id_node);
+    if name_id.is_empty() {
+        return Err(invalid("subject has an empty NameID"));
+    }
+
+    // Bearer confirmation: the response was meant for this request and this
+    // endpoint, and is recent
+    let in_response_to = response
+        .attribute("InResponseTo")
+        .ok_or_else(|| invalid("unsolicited responses are not accepted"))?;
+    let mut confirmed_until = None;
+    for confirmation in xml::children(subject, NS_ASSERTION, "SubjectConfirmation") {
+        if confirmation.attribute("Method") != Some(CONFIRMATION_BEARER) {
+            continue;
+        }
+        let Some(data) = xml::child(confirmation, NS_ASSERTION, "SubjectConfirmationData") else {
+            continue;
+        };
+        let not_on_or_after = match data.attribute("NotOnOrAfter") {
+            Some(value) => parse_time(value)?,
+            None => continue,
+        };
+        if data.attribute("Recipient") == Some(expected.acs_url)
+            && data.attribute("InResponseTo") == Some(in_response_to)
+            && now < not_on_or_after + expected.clock_skew
+        {
+            confirmed_until = Some(not_on_or_after);
+            break;
+        }
+    }
+    let confirmed_until =
+        confirmed_until.ok_or_else(|| invalid("no valid bearer subject confirmation"))?;
+
+    let conditions = xml::child(assertion, NS_ASSERTION, "Conditions")
+        .ok_or_else(|| invalid("assertion has no conditions"))?;
+    if let Some(not_before) = conditions.attribute("NotBefore") {
+        if now + expected.clock_skew < parse_time(not_before)? {
+            return Err(invalid("assertion is not yet valid"));
+        }
+    }
+    let mut expires_at = confirmed_until;
+    if let Some(not_on_or_after) = conditions.attribute("NotOnOrAfter") {
+        let not_on_or_after = parse_time(not_on_or_after)?;
+        if now >= not_on_or_after + expected.clock_skew {
+            return Err(invalid("assertion has expired"));
+        }
+        expires_at = expires_at.min(not_on_or_after);
+    }
+
+    let mut restrictions =
+        xml::children(conditions, NS_ASSERTION, "AudienceRestriction").peekable();
+    if restrictions.peek().is_none() {
+        return Err(invalid("assertion has no audience restriction"));
+    }
+    for restriction in restrictions {
+        let allowed = xml::children(restriction, NS_ASSERTION, "Audience")
+            .any(|audience| xml::text(audience) == expected.sp_entity_id);
+        if !allowed {
+            return Err(invalid("assertion is meant for another audience"));
+        }
+    }
+
+    let attributes = attributes(assertion);
+    let email = if name_id_node.attribute("Format") == Some(NAME_ID_EMAIL) || name_id.contains('@')
+    {
+        Some(name_id.clone())
+    } else {
+        first_attribute(&attributes, &EMAIL_ATTRIBUTES)
+    };
+    let name = first_attribute(&attributes, &NAME_ATTRIBUTES).or_else(|| {
+        let parts: Vec<String> = [
+            first_attribute(&attributes, &GIVEN_NAME_ATTRIBUTES),
+            first_attribute(&attributes, &SURNAME_ATTRIBUTES),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        (!parts.is_empty()).then(|| parts.join(" "))
+    });
+
+    Ok(Assertion {
+        id,
+        in_response_to: in_response_to.to_string(),
+        name_id,
+        email,
+        name,
+        expires_at: expires_at + expected.clock_skew,
+    })
+}
+
+/// Attribute names (and friendly names) with their first non-empty value
+fn attributes(assertion: Node) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    for statement in xml::children(assertion, NS_ASSERTION, "AttributeStatement") {
+        for attribute in xml::children(statement, NS_ASSERTION, "Attribute") {
+            let Some(value) = xml::children(attribute, NS_ASSERTION, "AttributeValue")
+                .map(xml::text)
+                .find(|value| !value.is_empty())
+            else {
+                continue;
+            };
+            for name in [
+                attribute.attribute("Name"),
+                attribute.attribute("FriendlyName"),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                attributes.push((name.to_string(), value.clone()));
+            }
+        }
+    }
+    attributes
+}
+
+/// The value of the first of `names` present, in the order of `names`
+fn first_attribute(attributes: &[(String, String)], names: &[&str]) -> Option<String> {
+    names.iter().find_map(|wanted| {
+        attributes
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(wanted))
+            .map(|(_, value)| value.clone())
+    })
+}
+
+fn parse_time(value: &str) -> AppResult<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| invalid("malformed timestamp"))
+}
+
+fn invalid(reason: &str) -> AppError {
+    AppError::Unauthorized(format!("Invalid SAML response: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::super::metadata;
+    use super::super::request;
+    use super::super::test_idp::{TestIdentityProvider, TestResponse};
+    use super::*;
+
+    const SP_ENTITY_ID: &str = "https://api.example.com/api/saml/org/metadata";
+    const ACS_URL: &str = "https://api.example.com/api/saml/org/acs";
+
+    fn idp() -> TestIdentityProvider {
+        TestIdentityProvider::new("https://idp.example.com", "https://idp.example.com/sso")
+    }
+
+    /// A response to a fresh request, ready to be changed and signed
+    fn response(idp: &TestIdentityProvider) -> TestResponse {
+        let saml_request = request::authn_request(
+            &request::new_request_id(),
+            SP_ENTITY_ID,
+            ACS_URL,
+            &idp.sso_url,
+        );
+        idp.respond_to(&saml_request, "u-123")
+            .unwrap()
+            .with_attribute("email", "alice@example.com")
+    }
+
+    fn check(idp: &TestIdentityProvider, saml_response: &str) -> AppResult<Assertion> {
+        let certificates = metadata::parse_idp_metadata(&idp.metadata())
+            .unwrap()
+            .certificates;
+        validate(
+            saml_response,
+            &Expected {
+                idp_entity_id: &idp.entity_id,
+                certificates: &certificates,
+                sp_entity_id: SP_ENTITY_ID,
+                acs_url: ACS_URL,
+                clock_skew: chrono::Duration::seconds(120),
+            },
+        )
+    }
+
+    fn rejection(result: AppResult<Assertion>) -> String {
+        match result {
+            Ok(assertion) => panic!("accepted assertion for {}", assertion.name_id),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// Edit the XML of a signed response
+    fn edit(saml_response: &str, change: impl FnOnce(String) -> String) -> String {
+        let xml = String::from_utf8(STANDARD.decode(saml_response).unwrap()).unwrap();
+        STANDARD.encode(change(xml))
+    }
+
+    #[test]
+    fn accepts_signed_response() {
+        let idp = idp();
+        let response = response(&idp);
+
+        let assertion = check(&idp, &response.sign(&idp)).unwrap();
+        assert_eq!(assertion.id, response.assertion_id);
+        assert_eq!(Some(assertion.in_response_to), response.in_response_to);
+        assert_eq!(assertion.name_id, "u-123");
+        assert_eq!(assertion.email.as_deref(), Some("alice@example.com"));
+    }
+
+    #[test]
+    fn rejects_unsigned_assertion() {
+        let idp = idp();
+        let mut response = response(&idp);
+        response.signed = false;
+
+        assert!(rejection(check(&idp, &response.sign(&idp))).contains("signature"));
+    }
+
+    #[test]
+    fn rejects_assertion_changed_after_signing() {
+        let idp = idp();
+        let signed = response(&idp).sign(&idp);
+
+        let tampered = edit(&signed, |xml| xml.replace(">u-123<", ">u-999<"));
+        assert!(rejection(check(&idp, &tampered)).contains("digest mismatch"));
+    }
+
+    #[test]
+    fn rejects_tampered_signature_value() {
+        let idp = idp();
+        let signed = response(&idp).sign(&idp);
+
+        let tampered = edit(&signed, |xml| {
+            let start = xml.find("<ds:SignatureValue>").unwrap() + "<ds:SignatureValue>".len();
+            let mut xml = xml.into_bytes();
+            xml[start] = if xml[start] == b'A' { b'B' } else { b'A' };
+            String::from_utf8(xml).unwrap()
+        });
+        assert!(rejection(check(&idp, &tampered)).contains("does not match"));
+    }
+
+    #[test]
+    fn rejects_forged_assertion_next_to_signed_one() {
+        let idp = idp();
+        let signed = response(&idp).sign(&idp);
+
+        let wrapped = edit(&signed, |xml| {
+            let start = xml.find("<saml:Assertion").unwrap();
+            let end = xml.find("</samlp:Response>").unwrap();
+            let forged = xml[start..end].replace(">u-123<", ">u-999<");
+            format!("{}{}{}", &xml[..start], forged, &xml[start..])
+        });
+        assert!(rejection(check(&idp, &wrapped)).contains("more than one assertion"));
+    }
+
+    #[test]
+    fn rejects_forged_assertion_wrapping_signed_one() {
+        let idp = idp();
+        let signed = response(&idp).sign(&idp);
+
+        // The signed original hides in the forgery's subject, where a
+        // validator looking the signed element up by ID would find it
+        let wrapped = edit(&signed, |xml| {
+            let start = xml.find("<saml:Assertion").unwrap();
+            let end = xml.find("</samlp:Response>").unwrap();
+            let original = &xml[start..end];
+            let forged = original.replace(">u-123<", ">u-999<").replacen(
+                "</saml:Subject>",
+                &format!("{}</saml:Subject>", original),
+                1,
+            );
+            format!("{}{}{}", &xml[..start], forged, &xml[end..])
+        });
+        assert!(rejection(check(&idp, &wrapped)).contains("duplicate ID"));
+    }
+
+    #[test]
+    fn rejects_other_audience() {
+        let idp = idp();
+        let mut response = response(&idp);
+        response.audience = "https://other.example.com/metadata".to_string();
+
+        assert!(rejection(check(&idp, &response.sign(&idp))).contains("audience"));
+    }
+
+    #[test]
+    fn rejects_other_issuer() {
+        let idp = idp();
+        let mut response = response(&idp);
+        response.issuer = "https://evil.example.com".to_string();
+
+        assert!(rejection(check(&idp, &response.sign(&idp))).contains("issuer"));
+    }
+
+    #[test]
+    fn rejects_other_destination() {
+        let idp = idp();
+        let mut response = response(&idp);
+        response.destination = "https://evil.example.com/acs".to_string();
+
+        assert!(rejection(check(&idp, &response.sign(&idp))).contains("destination"));
+    }
+
+    #[test]
+    fn rejects_unsolicited_response() {
+        let idp = idp();
+        let mut response = response(&idp);
+        response.in_response_to = None;
+
+        assert!(rejection(check(&idp, &response.sign(&idp))).contains("Invalid SAML response"));
+    }
+
+    #[test]
+    fn rejects_assertion_past_not_on_or_after() {
+        let idp = idp();
+        let mut response = response(&idp);
+        response.issued_at = chrono::Utc::now() - chrono::Duration::minutes(10);
+
+        assert!(rejection(check(&idp, &response.sign(&idp))).contains("subject confirmation"));
+    }
+
+    #[test]
+    fn rejects_assertion_before_not_before() {
+        let idp = idp();
+        let mut response = response(&idp);
+        response.issued_at = chrono::Utc::now() + chrono::Duration::minutes(10);
+
+        assert!(rejection(check(&idp, &response.sign(&idp))).contains("not yet valid"));
+    }
+
+    #[test]
+    fn tolerates_clock_skew() {
+        let idp = idp();
+        let mut response = response(&idp);
+        response.issued_at = chrono::Utc::now() + chrono::Duration::seconds(60);
+        assert!(check(&idp, &response.sign(&idp)).is_ok());
+
+        let mut response = self::response(&idp);
+        response.issued_at = chrono::Utc::now() - chrono::Duration::seconds(5 * 60 + 60);
+        assert!(check(&idp, &response.sign(&idp)).is_ok());
+    }
+}
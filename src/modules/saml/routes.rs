@@ -0,0 +1,40 @@
+use axum::{
+    routing::{get, post},
+    Router,
+};
+
+use crate::state::AppState;
+
+use super::handler;
+
+/// Endpoints browsers and identity providers use, nested under `/api/saml`
+pub fn saml_routes() -> Router<AppState> {
+    Router::new()
+        .route("/{org_id}/metadata", get(handler::sp_metadata))
+        .route("/{org_id}/login", get(handler::login))
+        .route("/{org_id}/acs", post(handler::acs))
+}
+
+/// Trading the code from the assertion consumer service for tokens, nested
+/// under `/api/auth/sso`
+pub fn sso_auth_routes() -> Router<AppState> {
+    Router::new().route("/", post(handler::exchange))
+}
+
+/// Linking a signed-in member's account at their organization's identity
+/// provider, merged into `/api/users/me/identities`
+pub fn saml_identity_routes() -> Router<AppState> {
+    Router::new()
+        .route("/saml", post(handler::link_identity))
+        .route("/saml/{org_id}/authorize", post(handler::authorize_link))
+}
+
+/// Identity provider settings, nested under `/api/orgs/{org_id}/saml`
+pub fn org_saml_routes() -> Router<AppState> {
+    Router::new().route(
+        "/",
+        get(handler::get_connection)
+            .put(handler::configure_connection)
+            .delete(handler::delete_connection),
+    )
+}
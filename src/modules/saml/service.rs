@@ -0,0 +1,755 @@
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, Set, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::modules::oidc::entity::{self as identities, Entity as Identities};
+use crate::modules::organizations::{membership::OrgRole, service as organizations_service};
+use crate::modules::user_tokens::{entity::TokenPurpose, service as user_tokens};
+use crate::modules::users::{entity as users, service as users_service};
+use crate::state::AppState;
+
+use super::auth_request::{self, Entity as AuthRequests};
+use super::entity::{self as connections, Entity as Connections};
+use super::metadata;
+use super::request;
+use super::response::{self, Assertion};
+use super::used_assertion::{self, Entity as UsedAssertions};
+
+/// Our endpoints for one organization, as registered with its identity
+/// provider
+pub struct ServiceProvider {
+    pub entity_id: String,
+    pub acs_url: String,
+    pub login_url: String,
+}
+
+/// Outcome of a sign-in through an organization's identity provider
+pub struct SsoSignIn {
+    pub user: users::Model,
+    /// Whether the sign-in created the account
+    pub created: bool,
+}
+
+/// What a response posted to the assertion consumer service completed
+pub enum AcsOutcome {
+    SignIn(SsoSignIn),
+    /// A member's link of their account, with the one-time code the app
+    /// redeems with [`complete_link`] while signed in as them
+    Link {
+        token: String,
+    },
+}
+
+/// The identity a member's link asserted, carried in an
+/// [`TokenPurpose::SsoLink`] token's payload
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PendingLink {
+    organization_id: Uuid,
+    subject: String,
+    email: Option<String>,
+}
+
+pub fn service_provider(config: &AppConfig, organization_id: Uuid) -> ServiceProvider {
+    let base = format!("{}/api/saml/{}", config.public_api_url(), organization_id);
+
+    ServiceProvider {
+        entity_id: format!("{}/metadata", base),
+        acs_url: format!("{}/acs", base),
+        login_url: format!("{}/login", base),
+    }
+}
+
+/// The `identities` provider name of an organization's identity provider
+pub fn identity_provider(organization_id: Uuid) -> String {
+    format!("saml:{}", organization_id)
+}
+
+pub async fn find_connection<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+) -> AppResult<Option<connections::Model>> {
+    Connections::find()
+        .filter(connections::Column::OrganizationId.eq(organization_id))
+        .one(db)
+        .await
+        .map_err(AppError::from)
+}
+
+pub async fn get_connection<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+) -> AppResult<connections::Model> {
+    find_connection(db, organization_id)
+        .await?
+        .ok_or(AppError::NotFound(
+            "Single sign-on is not configured for this organization".to_string(),
+        ))
+}
+
+/// Set up or replace the organization's identity provider from its metadata
+pub async fn configure(
+    state: &AppState,
+    organization_id: Uuid,
+    metadata_xml: &str,
+    default_role: OrgRole,
+) -> AppResult<connections::Model> {
+    let metadata = metadata::parse_idp_metadata(metadata_xml)?;
+    let now = chrono::Utc::now().fixed_offset();
+
+    match find_connection(&state.db, organization_id).await? {
+        Some(existing) => {
+            let mut connection: connections::ActiveModel = existing.into();
+            connection.idp_entity_id = Set(metadata.entity_id);
+            connection.idp_sso_url = Set(metadata.sso_url);
+            connection.idp_certificates = Set(metadata.certificates.join("\n"));
+            connection.default_role = Set(default_role);
+            connection.updated_at = Set(now);
+            connection.update(&state.db).await.map_err(AppError::from)
+        }
+        None => connections::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            organization_id: Set(organization_id),
+            idp_entity_id: Set(metadata.entity_id),
+            idp_sso_url: Set(metadata.sso_url),
+            idp_certificates: Set(metadata.certificates.join("\n")),
+            default_role: Set(default_role),
+            created_at: Set(now),
+            updated_at: Set(now),
+        }
+        .insert(&state.db)
+        .await
+        .map_err(AppError::from),
+    }
+}
+
+/// Turn single sign-on off; accounts it created keep their other ways to
+/// sign in, and their links are removed
+pub async fn remove(state: &AppState, organization_id: Uuid) -> AppResult<()> {
+    let connection = get_connection(&state.db, organization_id).await?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    Identities::delete_many()
+        .filter(identities::Column::Provider.eq(identity_provider(organization_id)))
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+    Connections::delete_by_id(connection.id)
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+    txn.commit().await.map_err(AppError::from)
+}
+
+/// Start a sign-in, returning the identity provider's endpoint and the
+/// `SAMLRequest` to post to it
+pub async fn start_login(state: &AppState, organization_id: Uuid) -> AppResult<(String, String)> {
+    start_request(state, organization_id, None).await
+}
+
+/// Start linking a signed-in member's account at the organization's
+/// identity provider, which they then sign in to like [`start_login`]
+pub async fn start_link(
+    state: &AppState,
+    user: &users::Model,
+    organization_id: Uuid,
+) -> AppResult<(String, String)> {
+    start_request(state, organization_id, Some(user.id)).await
+}
+
+async fn start_request(
+    state: &AppState,
+    organization_id: Uuid,
+    user_id: Option<Uuid>,
+) -> AppResult<(String, String)> {
+    let connection = get_connection(&state.db, organization_id).await?;
+    let sp = service_provider(&state.config, organization_id);
+    let now = chrono::Utc::now().fixed_offset();
+
+    // Abandoned sign-ins are cleaned up here rather than by a job
+    AuthRequests::delete_many()
+        .filter(auth_request::Column::ExpiresAt.lt(now))
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    let id = request::new_request_id();
+    auth_request::ActiveModel {
+        id: Set(id.clone()),
+        organization_id: Set(organization_id),
+        user_id: Set(user_id),
+        expires_at: Set(now + chrono::Duration::seconds(state.config.saml_request_ttl_secs)),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(AppError::from)?;
+
+    let saml_request =
+        request::authn_request(&id, &sp.entity_id, &sp.acs_url, &connection.idp_sso_url);
+    Ok((connection.idp_sso_url, saml_request))
+}
+
+/// Validate a response posted to the assertion consumer service and finish
+/// the sign-in or link its request started
+pub async fn complete_response(
+    state: &AppState,
+    organization_id: Uuid,
+    saml_response: &str,
+) -> AppResult<AcsOutcome> {
+    let connection = get_connection(&state.db, organization_id).await?;
+    let sp = service_provider(&state.config, organization_id);
+    let certificates = connection.certificates();
+
+    let assertion = response::validate(
+        saml_response,
+        &response::Expected {
+            idp_entity_id: &connection.idp_entity_id,
+            certificates: &certificates,
+            sp_entity_id: &sp.entity_id,
+            acs_url: &sp.acs_url,
+            clock_skew: chrono::Duration::seconds(state.config.saml_clock_skew_secs),
+        },
+    )
+    .inspect_err(|err| {
+        tracing::warn!(
+            "Rejected SAML response for organization {}: {}",
+            organization_id,
+            err
+        )
+    })?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let request = consume_request(&txn, organization_id, &assertion.in_response_to).await?;
+    record_assertion(&txn, organization_id, &assertion).await?;
+
+    let outcome = match request.user_id {
+        Some(user_id) => {
+            let token =
+                start_pending_link(state, &txn, user_id, organization_id, &assertion).await?;
+            AcsOutcome::Link { token }
+        }
+        None => AcsOutcome::SignIn(sign_in(&txn, &connection, &assertion).await?),
+    };
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok(outcome)
+}
+
+/// Sign in the user an assertion names, provisioning them just in time
+///
+/// A known subject signs in its user. An unknown one creates a passwordless
+/// account, unless its email address already belongs to an account: an
+/// organization's identity provider is not allowed to take over an existing
+/// account, whose owner has to sign in and link it instead. Either way the
+/// user is made a member with the connection's default role if they are not
+/// one.
+async fn sign_in<C: ConnectionTrait>(
+    db: &C,
+    connection: &connections::Model,
+    assertion: &Assertion,
+) -> AppResult<SsoSignIn> {
+    let organization_id = connection.organization_id;
+    let provider = identity_provider(organization_id);
+
+    let sign_in = match find_identity(db, organization_id, &assertion.name_id).await? {
+        Some(identity) => {
+            let user = users::Entity::find_by_id(identity.user_id)
+                .one(db)
+                .await
+                .map_err(AppError::from)?
+                .ok_or(AppError::NotFound(format!(
+                    "User with id {} not found",
+                    identity.user_id
+                )))?;
+
+            let mut active: identities::ActiveModel = identity.into();
+            active.email = Set(assertion.email.clone());
+            active.last_used_at = Set(Some(chrono::Utc::now().fixed_offset()));
+            active.update(db).await.map_err(AppError::from)?;
+
+            SsoSignIn {
+                user,
+                created: false,
+            }
+        }
+        None => {
+            let email = assertion.email.as_deref().ok_or(AppError::BadRequest(
+                "The identity provider did not share an email address".to_string(),
+            ))?;
+            if users_service::find_by_email_in(db, email).await?.is_some() {
+                return Err(AppError::Conflict(
+                    "An account with this email already exists; sign in and link single sign-on from your account settings"
+                        .to_string(),
+                ));
+            }
+
+            let name = assertion
+                .name
+                .as_deref()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| email.split('@').next().unwrap_or(email));
+            let user =
+                users_service::create_passwordless_user_in(db, Uuid::new_v4(), email, name).await?;
+
+            let now = chrono::Utc::now().fixed_offset();
+            identities::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                user_id: Set(user.id),
+                provider: Set(provider),
+                subject: Set(assertion.name_id.clone()),
+                email: Set(assertion.email.clone()),
+                created_at: Set(now),
+                last_used_at: Set(Some(now)),
+            }
+            .insert(db)
+            .await
+            .map_err(AppError::from)?;
+
+            SsoSignIn {
+                user,
+                created: true,
+            }
+        }
+    };
+
+    if organizations_service::find_membership(db, organization_id, sign_in.user.id)
+        .await?
+        .is_none()
+    {
+        organizations_service::insert_membership(
+            db,
+            organization_id,
+            sign_in.user.id,
+            connection.default_role,
+        )
+        .await?;
+    }
+
+    Ok(sign_in)
+}
+
+/// Hold on to the identity a member's link asserted until the app redeems
+/// it while signed in as that member
+///
+/// Finishing the link from the app rather than here keeps a member from
+/// being tricked into completing a link someone else started.
+async fn start_pending_link<C: ConnectionTrait>(
+    state: &AppState,
+    db: &C,
+    user_id: Uuid,
+    organization_id: Uuid,
+    assertion: &Assertion,
+) -> AppResult<String> {
+    ensure_unlinked(db, organization_id, &assertion.name_id, user_id).await?;
+
+    let payload = serde_json::to_string(&PendingLink {
+        organization_id,
+        subject: assertion.name_id.clone(),
+        email: assertion.email.clone(),
+    })
+    .map_err(|err| AppError::Internal(err.into()))?;
+
+    user_tokens::issue(
+        db,
+        user_id,
+        TokenPurpose::SsoLink,
+        Some(payload),
+        state.config.sso_login_token_ttl_secs,
+    )
+    .await
+}
+
+/// Link the identity from a code the assertion consumer service handed the
+/// member who started the link
+pub async fn complete_link(
+    state: &AppState,
+    user: &users::Model,
+    raw_token: &str,
+) -> AppResult<identities::Model> {
+    let invalid = || AppError::BadRequest("Invalid or expired link code".to_string());
+
+    let pending = user_tokens::find_valid(&state.db, TokenPurpose::SsoLink, raw_token)
+        .await
+        .map_err(|_| invalid())?;
+    if pending.user_id != user.id {
+        return Err(invalid());
+    }
+    let link: PendingLink = pending
+        .payload
+        .as_deref()
+        .and_then(|payload| serde_json::from_str(payload).ok())
+        .ok_or_else(invalid)?;
+
+    organizations_service::require_role(state, link.organization_id, user.id, OrgRole::Member)
+        .await?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    user_tokens::consume(&txn, TokenPurpose::SsoLink, raw_token)
+        .await
+        .map_err(|_| invalid())?;
+    ensure_unlinked(&txn, link.organization_id, &link.subject, user.id).await?;
+
+    let now = chrono::Utc::now().fixed_offset();
+    let identity = identities::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user.id),
+        provider: Set(identity_provider(link.organization_id)),
+        subject: Set(link.subject),
+        email: Set(link.email),
+        created_at: Set(now),
+        last_used_at: Set(Some(now)),
+    }
+    .insert(&txn)
+    .await
+    .map_err(AppError::from)?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok(identity)
+}
+
+async fn find_identity<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    subject: &str,
+) -> AppResult<Option<identities::Model>> {
+    Identities::find()
+        .filter(identities::Column::Provider.eq(identity_provider(organization_id)))
+        .filter(identities::Column::Subject.eq(subject))
+        .one(db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Fail with `409 Conflict` if the subject is already linked
+async fn ensure_unlinked<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    subject: &str,
+    user_id: Uuid,
+) -> AppResult<()> {
+    match find_identity(db, organization_id, subject).await? {
+        Some(identity) => Err(AppError::Conflict(if identity.user_id == user_id {
+            "This account is already linked".to_string()
+        } else {
+            "This account is linked to another user".to_string()
+        })),
+        None => Ok(()),
+    }
+}
+
+/// Issue the one-time code the browser carries back to the app, which trades
+/// it for tokens with [`exchange_login_token`]
+pub async fn issue_login_token(
+    state: &AppState,
+    user_id: Uuid,
+    organization_id: Uuid,
+) -> AppResult<String> {
+    user_tokens::issue(
+        &state.db,
+        user_id,
+        TokenPurpose::SsoLogin,
+        Some(organization_id.to_string()),
+        state.config.sso_login_token_ttl_secs,
+    )
+    .await
+}
+
+/// Redeem a code from [`issue_login_token`], returning the user and the
+/// organization they signed in to
+pub async fn exchange_login_token(
+    state: &AppState,
+    raw_token: &str,
+) -> AppResult<(users::Model, Uuid)> {
+    let token = user_tokens::consume(&state.db, TokenPurpose::SsoLogin, raw_token)
+        .await
+        .map_err(|_| AppError::Unauthorized("Invalid or expired sign-in code".to_string()))?;
+    let organization_id = token
+        .payload
+        .as_deref()
+        .and_then(|payload| Uuid::parse_str(payload).ok())
+        .ok_or(AppError::Unauthorized(
+            "Invalid or expired sign-in code".to_string(),
+        ))?;
+
+    let user = users_service::find_by_id(state, token.user_id).await?;
+    Ok((user, organization_id))
+}
+
+/// Take the request a response answers out of the store, so each request
+/// is answered at most once
+async fn consume_request<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    request_id: &str,
+) -> AppResult<auth_request::Model> {
+    let invalid = || AppError::BadRequest("Invalid or expired sign-in request".to_string());
+
+    let request = AuthRequests::find_by_id(request_id.to_string())
+        .filter(auth_request::Column::OrganizationId.eq(organization_id))
+        .filter(auth_request::Column::ExpiresAt.gt(chrono::Utc::now().fixed_offset()))
+        .one(db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(invalid)?;
+
+    // Only one of two concurrent responses gets to delete it
+    let result = AuthRequests::delete_by_id(request.id.clone())
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+    if result.rows_affected == 0 {
+        return Err(invalid());
+    }
+
+    Ok(request)
+}
+
+/// Remember an assertion until it expires so it cannot be used twice
+async fn record_assertion<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    assertion: &Assertion,
+) -> AppResult<()> {
+    UsedAssertions::delete_many()
+        .filter(used_assertion::Column::ExpiresAt.lt(chrono::Utc::now().fixed_offset()))
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    used_assertion::ActiveModel {
+        organization_id: Set(organization_id),
+        assertion_id: Set(assertion.id.clone()),
+        expires_at: Set(assertion.expires_at.fixed_offset()),
+    }
+    .insert(db)
+    .await
+    .map_err(|err| match AppError::from(err) {
+        AppError::Conflict(_) => {
+            AppError::Unauthorized("Invalid SAML response: assertion was already used".to_string())
+        }
+        err => err,
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_idp::TestIdentityProvider;
+    use super::*;
+    use crate::test_support::{test_state, unique_email};
+
+    async fn create_user(state: &AppState) -> users::Model {
+        users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap()
+    }
+
+    /// An organization whose identity provider is `idp`, with its owner
+    async fn organization(state: &AppState, idp: &TestIdentityProvider) -> (Uuid, users::Model) {
+        let owner = create_user(state).await;
+        let name = format!("SSO {}", Uuid::new_v4().simple());
+        let (organization, _) =
+            organizations_service::create_organization(state, &owner, &name, None)
+                .await
+                .unwrap();
+        configure(state, organization.id, &idp.metadata(), OrgRole::Member)
+            .await
+            .unwrap();
+        (organization.id, owner)
+    }
+
+    fn idp() -> TestIdentityProvider {
+        TestIdentityProvider::new("https://idp.example.com", "https://idp.example.com/sso")
+    }
+
+    fn signed_in(outcome: AcsOutcome) -> SsoSignIn {
+        match outcome {
+            AcsOutcome::SignIn(sign_in) => sign_in,
+            AcsOutcome::Link { .. } => panic!("expected a sign-in"),
+        }
+    }
+
+    #[tokio::test]
+    async fn provisions_unknown_subject_then_signs_it_in() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let idp = idp();
+        let (org_id, _) = organization(&state, &idp).await;
+        let email = unique_email();
+
+        let (_, saml_request) = start_login(&state, org_id).await.unwrap();
+        let response = idp
+            .respond_to(&saml_request, "u-1")
+            .unwrap()
+            .with_attribute("email", &email);
+        let created = signed_in(
+            complete_response(&state, org_id, &response.sign(&idp))
+                .await
+                .unwrap(),
+        );
+        assert!(created.created);
+        assert_eq!(created.user.email, email);
+        assert!(
+            organizations_service::find_membership(&state.db, org_id, created.user.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let (_, saml_request) = start_login(&state, org_id).await.unwrap();
+        let response = idp.respond_to(&saml_request, "u-1").unwrap();
+        let again = signed_in(
+            complete_response(&state, org_id, &response.sign(&idp))
+                .await
+                .unwrap(),
+        );
+        assert!(!again.created);
+        assert_eq!(again.user.id, created.user.id);
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_response() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let idp = idp();
+        let (org_id, _) = organization(&state, &idp).await;
+
+        let (_, saml_request) = start_login(&state, org_id).await.unwrap();
+        let response = idp
+            .respond_to(&saml_request, "u-1")
+            .unwrap()
+            .with_attribute("email", &unique_email());
+        let signed = response.sign(&idp);
+        complete_response(&state, org_id, &signed).await.unwrap();
+
+        let err = complete_response(&state, org_id, &signed).await.err();
+        assert!(matches!(err, Some(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_assertion_reused_for_another_request() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let idp = idp();
+        let (org_id, _) = organization(&state, &idp).await;
+
+        let (_, saml_request) = start_login(&state, org_id).await.unwrap();
+        let first = idp
+            .respond_to(&saml_request, "u-1")
+            .unwrap()
+            .with_attribute("email", &unique_email());
+        complete_response(&state, org_id, &first.sign(&idp))
+            .await
+            .unwrap();
+
+        let (_, saml_request) = start_login(&state, org_id).await.unwrap();
+        let mut second = idp.respond_to(&saml_request, "u-1").unwrap();
+        second.assertion_id = first.assertion_id.clone();
+        let err = complete_response(&state, org_id, &second.sign(&idp))
+            .await
+            .err();
+        assert!(matches!(err, Some(AppError::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn refuses_to_take_over_existing_account_by_email() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let idp = idp();
+        let (org_id, owner) = organization(&state, &idp).await;
+
+        let (_, saml_request) = start_login(&state, org_id).await.unwrap();
+        let response = idp
+            .respond_to(&saml_request, "attacker")
+            .unwrap()
+            .with_attribute("email", &owner.email);
+        let err = complete_response(&state, org_id, &response.sign(&idp))
+            .await
+            .err();
+        assert!(matches!(err, Some(AppError::Conflict(_))));
+        assert!(find_identity(&state.db, org_id, "attacker")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn links_identity_for_the_member_who_started_the_link() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let idp = idp();
+        let (org_id, owner) = organization(&state, &idp).await;
+        let outsider = create_user(&state).await;
+
+        let (_, saml_request) = start_link(&state, &owner, org_id).await.unwrap();
+        let response = idp
+            .respond_to(&saml_request, "owner-subject")
+            .unwrap()
+            .with_attribute("email", &owner.email);
+        let token = match complete_response(&state, org_id, &response.sign(&idp))
+            .await
+            .unwrap()
+        {
+            AcsOutcome::Link { token } => token,
+            AcsOutcome::SignIn(_) => panic!("expected a link"),
+        };
+
+        // Someone else cannot redeem the code, nor sign in with it
+        assert!(complete_link(&state, &outsider, &token).await.is_err());
+        assert!(exchange_login_token(&state, &token).await.is_err());
+
+        let identity = complete_link(&state, &owner, &token).await.unwrap();
+        assert_eq!(identity.user_id, owner.id);
+        assert_eq!(identity.provider, identity_provider(org_id));
+        assert!(complete_link(&state, &owner, &token).await.is_err());
+
+        let (_, saml_request) = start_login(&state, org_id).await.unwrap();
+        let response = idp.respond_to(&saml_request, "owner-subject").unwrap();
+        let sign_in = signed_in(
+            complete_response(&state, org_id, &response.sign(&idp))
+                .await
+                .unwrap(),
+        );
+        assert_eq!(sign_in.user.id, owner.id);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_link_subject_of_another_user() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let idp = idp();
+        let (org_id, owner) = organization(&state, &idp).await;
+
+        let (_, saml_request) = start_login(&state, org_id).await.unwrap();
+        let response = idp
+            .respond_to(&saml_request, "taken")
+            .unwrap()
+            .with_attribute("email", &unique_email());
+        complete_response(&state, org_id, &response.sign(&idp))
+            .await
+            .unwrap();
+
+        let (_, saml_request) = start_link(&state, &owner, org_id).await.unwrap();
+        let response = idp.respond_to(&saml_request, "taken").unwrap();
+        let err = complete_response(&state, org_id, &response.sign(&idp))
+            .await
+            .err();
+        assert!(matches!(err, Some(AppError::Conflict(_))));
+    }
+}
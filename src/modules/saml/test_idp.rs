@@ -0,0 +1,287 @@
+//! A SAML identity provider for tests, signing assertions with a fixture key
+//!
+//! ```rust,ignore
+//! let idp = TestIdentityProvider::new("https://idp.test", "https://idp.test/sso");
+//! // configure the organization with idp.metadata(), then
+//! let (_, saml_request) = service::start_login(&state, org_id).await?;
+//! let response = idp.respond_to(&saml_request, "alice@example.com")?;
+//! service::complete_response(&state, org_id, &response.sign(&idp)).await?;
+//! ```
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    rand::SystemRandom,
+    signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use sha2::{Digest, Sha256};
+
+use crate::error::{AppError, AppResult};
+
+use super::dsig::{self, ALG_RSA_SHA256, ALG_SHA256};
+use super::request::TIME_FORMAT;
+use super::xml::{
+    self, BINDING_HTTP_POST, NS_ASSERTION, NS_DSIG, NS_EXC_C14N, NS_METADATA, NS_PROTOCOL,
+};
+
+const KEY_PEM: &str = include_str!("fixtures/idp-key.pem");
+const CERTIFICATE_PEM: &str = include_str!("fixtures/idp-cert.pem");
+
+pub struct TestIdentityProvider {
+    pub entity_id: String,
+    pub sso_url: String,
+    key: RsaKeyPair,
+}
+
+/// The response the identity provider is about to send; fields can be
+/// changed before signing to produce invalid responses
+pub struct TestResponse {
+    /// Defaults to the identity provider's entity id
+    pub issuer: String,
+    pub in_response_to: Option<String>,
+    /// The assertion consumer service
+    pub destination: String,
+    pub audience: String,
+    pub assertion_id: String,
+    pub name_id: String,
+    /// Attribute name and value pairs
+    pub attributes: Vec<(String, String)>,
+    pub issued_at: chrono::DateTime<chrono::Utc>,
+    pub lifetime: chrono::Duration,
+    /// Whether to sign the assertion
+    pub signed: bool,
+}
+
+impl TestIdentityProvider {
+    pub fn new(entity_id: &str, sso_url: &str) -> Self {
+        let key = RsaKeyPair::from_pkcs8(&pem_body(KEY_PEM)).expect("invalid fixture key");
+
+        Self {
+            entity_id: entity_id.to_string(),
+            sso_url: sso_url.to_string(),
+            key,
+        }
+    }
+
+    /// The signing certificate, base64 DER
+    pub fn certificate() -> String {
+        STANDARD.encode(pem_body(CERTIFICATE_PEM))
+    }
+
+    /// Metadata to configure the identity provider with
+    pub fn metadata(&self) -> String {
+        format!(
+            concat!(
+                r#"<md:EntityDescriptor xmlns:md="{md}" xmlns:ds="{ds}" entityID="{entity_id}">"#,
+                r#"<md:IDPSSODescriptor protocolSupportEnumeration="{samlp}">"#,
+                r#"<md:KeyDescriptor use="signing"><ds:KeyInfo><ds:X509Data><ds:X509Certificate>{certificate}</ds:X509Certificate></ds:X509Data></ds:KeyInfo></md:KeyDescriptor>"#,
+                r#"<md:SingleSignOnService Binding="{binding}" Location="{sso_url}"/>"#,
+                r#"</md:IDPSSODescriptor>"#,
+                r#"</md:EntityDescriptor>"#,
+            ),
+            md = NS_METADATA,
+            ds = NS_DSIG,
+            samlp = NS_PROTOCOL,
+            entity_id = xml::escape_attr(&self.entity_id),
+            certificate = Self::certificate(),
+            binding = BINDING_HTTP_POST,
+            sso_url = xml::escape_attr(&self.sso_url),
+        )
+    }
+
+    /// A successful response to a `SAMLRequest` for the user `name_id`
+    pub fn respond_to(&self, saml_request: &str, name_id: &str) -> AppResult<TestResponse> {
+        let bytes = dsig::decode_base64(saml_request)?;
+        let text = String::from_utf8(bytes)
+            .map_err(|_| AppError::BadRequest("Malformed SAMLRequest".to_string()))?;
+        let document = xml::parse(&text)?;
+        let request = document.root_element();
+        let missing = |what: &str| AppError::BadRequest(format!("AuthnRequest has no {}", what));
+
+        Ok(TestResponse {
+            issuer: self.entity_id.clone(),
+            in_response_to: Some(
+                request
+                    .attribute("ID")
+                    .ok_or_else(|| missing("ID"))?
+                    .to_string(),
+            ),
+            destination: request
+                .attribute("AssertionConsumerServiceURL")
+                .ok_or_else(|| missing("AssertionConsumerServiceURL"))?
+                .to_string(),
+            audience: xml::child(request, NS_ASSERTION, "Issuer")
+                .map(xml::text)
+                .ok_or_else(|| missing("Issuer"))?,
+            assertion_id: format!("_{}", uuid::Uuid::new_v4().simple()),
+            name_id: name_id.to_string(),
+            attributes: Vec::new(),
+            issued_at: chrono::Utc::now(),
+            lifetime: chrono::Duration::minutes(5),
+            signed: true,
+        })
+    }
+}
+
+impl TestResponse {
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        self.attributes.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The `SAMLResponse` form field, with the assertion signed unless
+    /// `signed` was turned off
+    pub fn sign(&self, idp: &TestIdentityProvider) -> String {
+        let issued_at = self.issued_at.format(TIME_FORMAT).to_string();
+        let expires_at = (self.issued_at + self.lifetime)
+            .format(TIME_FORMAT)
+            .to_string();
+        let in_response_to = self
+            .in_response_to
+            .as_deref()
+            .map(|id| format!(r#" InResponseTo="{}""#, xml::escape_attr(id)))
+            .unwrap_or_default();
+
+        let attributes: String = self
+            .attributes
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    r#"<saml:Attribute Name="{}"><saml:AttributeValue>{}</saml:AttributeValue></saml:Attribute>"#,
+                    xml::escape_attr(name),
+                    xml::escape_text(value)
+                )
+            })
+            .collect();
+        let attribute_statement = if attributes.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "<saml:AttributeStatement>{}</saml:AttributeStatement>",
+                attributes
+            )
+        };
+
+        let assertion = |signature: &str| {
+            format!(
+                concat!(
+                    r#"<saml:Assertion xmlns:saml="{saml}" ID="{id}" Version="2.0" IssueInstant="{issued_at}">"#,
+                    r#"<saml:Issuer>{issuer}</saml:Issuer>"#,
+                    "{signature}",
+                    r#"<saml:Subject><saml:NameID Format="urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified">{name_id}</saml:NameID>"#,
+                    r#"<saml:SubjectConfirmation Method="urn:oasis:names:tc:SAML:2.0:cm:bearer">"#,
+                    r#"<saml:SubjectConfirmationData{in_response_to} NotOnOrAfter="{expires_at}" Recipient="{destination}"/>"#,
+                    r#"</saml:SubjectConfirmation></saml:Subject>"#,
+                    r#"<saml:Conditions NotBefore="{issued_at}" NotOnOrAfter="{expires_at}">"#,
+                    r#"<saml:AudienceRestriction><saml:Audience>{audience}</saml:Audience></saml:AudienceRestriction>"#,
+                    r#"</saml:Conditions>"#,
+                    r#"<saml:AuthnStatement AuthnInstant="{issued_at}"><saml:AuthnContext>"#,
+                    r#"<saml:AuthnContextClassRef>urn:oasis:names:tc:SAML:2.0:ac:classes:PasswordProtectedTransport</saml:AuthnContextClassRef>"#,
+                    r#"</saml:AuthnContext></saml:AuthnStatement>"#,
+                    "{attribute_statement}",
+                    r#"</saml:Assertion>"#,
+                ),
+                saml = NS_ASSERTION,
+                id = xml::escape_attr(&self.assertion_id),
+                issued_at = issued_at,
+                issuer = xml::escape_text(&self.issuer),
+                signature = signature,
+                name_id = xml::escape_text(&self.name_id),
+                in_response_to = in_response_to,
+                expires_at = expires_at,
+                destination = xml::escape_attr(&self.destination),
+                audience = xml::escape_text(&self.audience),
+                attribute_statement = attribute_statement,
+            )
+        };
+
+        let assertion = if self.signed {
+            let unsigned = assertion("");
+            let signature = idp.signature(&self.assertion_id, &unsigned);
+            assertion(&signature)
+        } else {
+            assertion("")
+        };
+
+        let response = format!(
+            concat!(
+                r#"<samlp:Response xmlns:samlp="{samlp}" xmlns:saml="{saml}" ID="_{id}" Version="2.0" IssueInstant="{issued_at}" Destination="{destination}"{in_response_to}>"#,
+                r#"<saml:Issuer>{issuer}</saml:Issuer>"#,
+                r#"<samlp:Status><samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Success"/></samlp:Status>"#,
+                "{assertion}",
+                r#"</samlp:Response>"#,
+            ),
+            samlp = NS_PROTOCOL,
+            saml = NS_ASSERTION,
+            id = uuid::Uuid::new_v4().simple(),
+            issued_at = issued_at,
+            destination = xml::escape_attr(&self.destination),
+            in_response_to = in_response_to,
+            issuer = xml::escape_text(&self.issuer),
+            assertion = assertion,
+        );
+
+        STANDARD.encode(response)
+    }
+}
+
+impl TestIdentityProvider {
+    /// An enveloped signature over the element `element_xml` with id `id`
+    fn signature(&self, id: &str, element_xml: &str) -> String {
+        let document = xml::parse(element_xml).expect("test assertion is well-formed");
+        let canonical = xml::canonicalize(document.root_element(), None, &[]);
+        let digest = STANDARD.encode(Sha256::digest(canonical.as_bytes()));
+
+        let signed_info = format!(
+            concat!(
+                r#"<ds:SignedInfo xmlns:ds="{ds}">"#,
+                r#"<ds:CanonicalizationMethod Algorithm="{c14n}"/>"#,
+                r#"<ds:SignatureMethod Algorithm="{rsa_sha256}"/>"#,
+                r##"<ds:Reference URI="#{id}"><ds:Transforms>"##,
+                r#"<ds:Transform Algorithm="http://www.w3.org/2000/09/xmldsig#enveloped-signature"/>"#,
+                r#"<ds:Transform Algorithm="{c14n}"/>"#,
+                r#"</ds:Transforms><ds:DigestMethod Algorithm="{sha256}"/>"#,
+                r#"<ds:DigestValue>{digest}</ds:DigestValue></ds:Reference>"#,
+                r#"</ds:SignedInfo>"#,
+            ),
+            ds = NS_DSIG,
+            c14n = NS_EXC_C14N,
+            rsa_sha256 = ALG_RSA_SHA256,
+            id = xml::escape_attr(id),
+            sha256 = ALG_SHA256,
+            digest = digest,
+        );
+        let document = xml::parse(&signed_info).expect("signed info is well-formed");
+        let canonical = xml::canonicalize(document.root_element(), None, &[]);
+
+        let mut signature = vec![0; self.key.public().modulus_len()];
+        self.key
+            .sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                canonical.as_bytes(),
+                &mut signature,
+            )
+            .expect("signing with the fixture key");
+
+        format!(
+            concat!(
+                r#"<ds:Signature xmlns:ds="{ds}">{signed_info}"#,
+                r#"<ds:SignatureValue>{signature}</ds:SignatureValue>"#,
+                r#"<ds:KeyInfo><ds:X509Data><ds:X509Certificate>{certificate}</ds:X509Certificate></ds:X509Data></ds:KeyInfo>"#,
+                r#"</ds:Signature>"#,
+            ),
+            ds = NS_DSIG,
+            signed_info = signed_info,
+            signature = STANDARD.encode(signature),
+            certificate = Self::certificate(),
+        )
+    }
+}
+
+fn pem_body(pem: &str) -> Vec<u8> {
+    let body: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    STANDARD.decode(body).expect("invalid fixture PEM")
+}
@@ -0,0 +1,236 @@
+//! XML helpers for SAML: namespaces, lookups, escaping and Exclusive XML
+//! Canonicalization 1.0 (without comments), which signatures are computed over
+//!
+//! Documents are parsed with DTDs disabled, so entity expansion attacks and
+//! external entities are rejected by the parser.
+
+use std::collections::BTreeMap;
+
+use roxmltree::{Document, Node, NodeId};
+
+use crate::error::{AppError, AppResult};
+
+pub const NS_PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+pub const NS_ASSERTION: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+pub const NS_METADATA: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+pub const NS_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+pub const NS_EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+
+pub const BINDING_HTTP_POST: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+
+pub fn parse(xml: &str) -> AppResult<Document<'_>> {
+    Document::parse(xml).map_err(|err| AppError::BadRequest(format!("Malformed XML: {}", err)))
+}
+
+pub fn is(node: Node, namespace: &str, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().namespace() == Some(namespace)
+        && node.tag_name().name() == name
+}
+
+/// The first child element with the given name
+pub fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    namespace: &str,
+    name: &str,
+) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| is(*n, namespace, name))
+}
+
+pub fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    namespace: &'a str,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |n| is(*n, namespace, name))
+}
+
+/// The element's text content with surrounding whitespace removed
+pub fn text(node: Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Escape a value for use inside a double-quoted attribute
+pub fn escape_attr(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escape character data
+pub fn escape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Canonicalize the subtree rooted at `root`, leaving out `exclude` and its
+/// descendants (the enveloped signature)
+///
+/// `inclusive_prefixes` is the `PrefixList` of an `InclusiveNamespaces`
+/// element; `#default` stands for the default namespace.
+pub fn canonicalize(root: Node, exclude: Option<NodeId>, inclusive_prefixes: &[&str]) -> String {
+    let mut out = String::new();
+    write_node(
+        root,
+        exclude,
+        inclusive_prefixes,
+        &BTreeMap::new(),
+        &mut out,
+    );
+    out
+}
+
+fn write_node(
+    node: Node,
+    exclude: Option<NodeId>,
+    inclusive_prefixes: &[&str],
+    rendered: &BTreeMap<String, String>,
+    out: &mut String,
+) {
+    if Some(node.id()) == exclude {
+        return;
+    }
+
+    if node.is_text() {
+        out.push_str(&escape_text(node.text().unwrap_or_default()));
+        return;
+    }
+    if let Some(pi) = node.pi() {
+        out.push_str("<?");
+        out.push_str(pi.target);
+        if let Some(value) = pi.value {
+            out.push(' ');
+            out.push_str(value);
+        }
+        out.push_str("?>");
+        return;
+    }
+    if !node.is_element() {
+        // Comments are dropped
+        return;
+    }
+
+    let qname = element_qname(node);
+    let prefix = prefix_of(qname);
+
+    // Namespaces visibly utilized by the element and its attributes, plus
+    // the inclusive ones, each with its value in scope here
+    let mut utilized: BTreeMap<String, String> = BTreeMap::new();
+    utilized.insert(prefix.to_string(), namespace_in_scope(node, prefix));
+    for attribute in node.attributes() {
+        let attribute_prefix = prefix_of(attribute_qname(node, &attribute));
+        if !attribute_prefix.is_empty() && attribute_prefix != "xml" {
+            utilized.insert(
+                attribute_prefix.to_string(),
+                attribute.namespace().unwrap_or_default().to_string(),
+            );
+        }
+    }
+    for inclusive in inclusive_prefixes {
+        let inclusive = if *inclusive == "#default" {
+            ""
+        } else {
+            inclusive
+        };
+        let in_scope = namespace_in_scope(node, inclusive);
+        if !in_scope.is_empty() || inclusive.is_empty() {
+            utilized.insert(inclusive.to_string(), in_scope);
+        }
+    }
+
+    let mut now_rendered = rendered.clone();
+    out.push('<');
+    out.push_str(qname);
+
+    // BTreeMap order sorts declarations by prefix, the default namespace first
+    for (ns_prefix, uri) in &utilized {
+        let previous = rendered.get(ns_prefix).map(String::as_str).unwrap_or("");
+        if previous == uri {
+            continue;
+        }
+        if ns_prefix.is_empty() {
+            out.push_str(&format!(" xmlns=\"{}\"", escape_attr(uri)));
+        } else {
+            out.push_str(&format!(" xmlns:{}=\"{}\"", ns_prefix, escape_attr(uri)));
+        }
+        now_rendered.insert(ns_prefix.clone(), uri.clone());
+    }
+
+    let mut attributes: Vec<_> = node.attributes().collect();
+    attributes.sort_by(|a, b| {
+        (a.namespace().unwrap_or_default(), a.name())
+            .cmp(&(b.namespace().unwrap_or_default(), b.name()))
+    });
+    for attribute in attributes {
+        out.push(' ');
+        out.push_str(attribute_qname(node, &attribute));
+        out.push_str("=\"");
+        out.push_str(&escape_attr(attribute.value()));
+        out.push('"');
+    }
+    out.push('>');
+
+    for child in node.children() {
+        write_node(child, exclude, inclusive_prefixes, &now_rendered, out);
+    }
+
+    out.push_str("</");
+    out.push_str(qname);
+    out.push('>');
+}
+
+/// The element name as written in the source, with its prefix
+fn element_qname<'input>(node: Node<'_, 'input>) -> &'input str {
+    let source = &node.document().input_text()[node.range().start + 1..];
+    let end = source
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(source.len());
+    &source[..end]
+}
+
+fn attribute_qname<'input>(
+    node: Node<'_, 'input>,
+    attribute: &roxmltree::Attribute<'_, 'input>,
+) -> &'input str {
+    &node.document().input_text()[attribute.range_qname()]
+}
+
+fn prefix_of(qname: &str) -> &str {
+    qname
+        .split_once(':')
+        .map(|(prefix, _)| prefix)
+        .unwrap_or("")
+}
+
+/// The namespace URI a prefix is bound to at `node`, empty when unbound
+fn namespace_in_scope(node: Node, prefix: &str) -> String {
+    let prefix = (!prefix.is_empty()).then_some(prefix);
+    node.namespaces()
+        .find(|ns| ns.name() == prefix)
+        .map(|ns| ns.uri().to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,131 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::AppError;
+
+use super::extractor::ScimJson;
+
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
+/// An error in the shape SCIM clients expect (RFC 7644, section 3.12)
+#[derive(Debug)]
+pub struct ScimError {
+    pub status: StatusCode,
+    /// Machine-readable error type for 400 responses, e.g. `invalidFilter`
+    pub scim_type: Option<&'static str>,
+    pub detail: String,
+}
+
+pub type ScimResult<T> = Result<T, ScimError>;
+
+impl ScimError {
+    pub fn bad_request(scim_type: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            scim_type: Some(scim_type),
+            detail: detail.into(),
+        }
+    }
+
+    pub fn invalid_filter(detail: impl Into<String>) -> Self {
+        Self::bad_request("invalidFilter", detail)
+    }
+
+    pub fn invalid_value(detail: impl Into<String>) -> Self {
+        Self::bad_request("invalidValue", detail)
+    }
+
+    pub fn invalid_syntax(detail: impl Into<String>) -> Self {
+        Self::bad_request("invalidSyntax", detail)
+    }
+
+    pub fn invalid_path(detail: impl Into<String>) -> Self {
+        Self::bad_request("invalidPath", detail)
+    }
+
+    pub fn mutability(detail: impl Into<String>) -> Self {
+        Self::bad_request("mutability", detail)
+    }
+
+    pub fn uniqueness(detail: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            scim_type: Some("uniqueness"),
+            detail: detail.into(),
+        }
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            scim_type: None,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn forbidden(detail: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            scim_type: None,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn unauthorized() -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            scim_type: None,
+            detail: "Invalid or missing bearer token".to_string(),
+        }
+    }
+}
+
+impl From<AppError> for ScimError {
+    fn from(err: AppError) -> Self {
+        let status = err.status_code();
+        let (scim_type, detail) = match err {
+            AppError::Conflict(detail) => (Some("uniqueness"), detail),
+            AppError::ValidationError(detail) => (Some("invalidValue"), detail),
+            AppError::BadRequest(detail)
+            | AppError::Unauthorized(detail)
+            | AppError::Forbidden(detail)
+            | AppError::NotFound(detail) => (None, detail),
+            err if status.is_server_error() => {
+                err.log_error();
+                (None, "Internal server error".to_string())
+            }
+            err => (None, err.to_string()),
+        };
+
+        Self {
+            status,
+            scim_type,
+            detail,
+        }
+    }
+}
+
+impl From<sea_orm::DbErr> for ScimError {
+    fn from(err: sea_orm::DbErr) -> Self {
+        AppError::from(err).into()
+    }
+}
+
+impl IntoResponse for ScimError {
+    fn into_response(self) -> Response {
+        tracing::debug!("SCIM error {}: {}", self.status, self.detail);
+
+        let mut body = serde_json::json!({
+            "schemas": [ERROR_SCHEMA],
+            "status": self.status.as_u16().to_string(),
+            "detail": self.detail,
+        });
+        if let Some(scim_type) = self.scim_type {
+            body["scimType"] = scim_type.into();
+        }
+
+        (self.status, ScimJson(body)).into_response()
+    }
+}
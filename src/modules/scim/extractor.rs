@@ -0,0 +1,114 @@
+use std::ops::Deref;
+
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        request::Parts,
+        HeaderValue,
+    },
+    response::{IntoResponse, Response},
+};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, Set};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use crate::db;
+use crate::modules::auth::token::hash_token;
+use crate::state::AppState;
+
+use super::error::{ScimError, ScimResult};
+use super::token::{self, Entity as Tokens};
+
+pub const CONTENT_TYPE_SCIM: &str = "application/scim+json";
+
+/// The directory of the organization whose SCIM token authenticated the
+/// request
+///
+/// Derefs to a transaction scoped to that organization, like
+/// [`TenantDb`](crate::modules::organizations::tenant::TenantDb); call
+/// [`Directory::commit`] after writes.
+pub struct Directory {
+    pub organization_id: Uuid,
+    txn: DatabaseTransaction,
+}
+
+impl Directory {
+    pub async fn commit(self) -> ScimResult<()> {
+        self.txn.commit().await.map_err(ScimError::from)
+    }
+}
+
+impl Deref for Directory {
+    type Target = DatabaseTransaction;
+
+    fn deref(&self) -> &DatabaseTransaction {
+        &self.txn
+    }
+}
+
+impl FromRequestParts<AppState> for Directory {
+    type Rejection = ScimError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> ScimResult<Self> {
+        let raw_token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim())
+            .filter(|token| !token.is_empty())
+            .ok_or_else(ScimError::unauthorized)?;
+
+        let token = Tokens::find()
+            .filter(token::Column::TokenHash.eq(hash_token(raw_token)))
+            .one(&state.db)
+            .await?
+            .ok_or_else(ScimError::unauthorized)?;
+
+        let organization_id = token.organization_id;
+        let mut active: token::ActiveModel = token.into();
+        active.last_used_at = Set(Some(chrono::Utc::now().fixed_offset()));
+        active.update(&state.db).await?;
+
+        let txn = db::begin_tenant_transaction(&state.db, organization_id, state.config.tenant_rls)
+            .await?;
+
+        Ok(Directory {
+            organization_id,
+            txn,
+        })
+    }
+}
+
+/// JSON body with the `application/scim+json` media type
+///
+/// As an extractor it accepts either media type and reports malformed
+/// bodies as SCIM errors.
+pub struct ScimJson<T>(pub T);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for ScimJson<T> {
+    type Rejection = ScimError;
+
+    async fn from_request(req: Request, state: &S) -> ScimResult<Self> {
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|err| ScimError::invalid_syntax(err.body_text()))?;
+
+        serde_json::from_slice(&bytes)
+            .map(ScimJson)
+            .map_err(|err| ScimError::invalid_syntax(format!("Invalid request body: {}", err)))
+    }
+}
+
+impl<T: Serialize> IntoResponse for ScimJson<T> {
+    fn into_response(self) -> Response {
+        let mut response = axum::Json(self.0).into_response();
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE_SCIM));
+        response
+    }
+}
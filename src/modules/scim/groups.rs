@@ -0,0 +1,522 @@
+//! The `Groups` resource: groups pushed by the directory, whose members must
+//! be members of the organization
+//!
+//! Admins can give a group an organization role, after which the directory
+//! manages the roles of the members it provisioned (see [`sync_roles`]).
+
+use std::collections::{BTreeSet, HashMap};
+
+use sea_orm::{
+    sea_query::{Expr, Query, SimpleExpr},
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, QuerySelect, QueryTrait, Set,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::organizations::membership::{self, Entity as Memberships, OrgRole};
+use crate::modules::users::entity as users;
+
+use super::error::{ScimError, ScimResult};
+use super::extractor::Directory;
+use super::filter::{self, attribute_name, Attribute, Filter, Op};
+use super::group::{self, Entity as Groups};
+use super::group_member::{self, Entity as GroupMembers};
+use super::provisioned_user::Entity as ProvisionedUsers;
+use super::resource::{
+    optional_string, string, GroupResource, ListQuery, ListResponse, PatchKind, PatchRequest,
+};
+
+/// Columns behind the attributes clients can filter on
+fn attribute(name: &str) -> Option<Attribute> {
+    let column = |column: group::Column| SimpleExpr::from(Expr::col((Groups, column)));
+
+    Some(match name {
+        "id" => Attribute::Id(column(group::Column::Id)),
+        "displayname" => Attribute::Text {
+            expr: column(group::Column::DisplayName),
+            case_exact: false,
+        },
+        "externalid" => Attribute::Text {
+            expr: column(group::Column::ExternalId),
+            case_exact: true,
+        },
+        "members" | "members.value" => Attribute::Reference(|user_id| {
+            Expr::col((Groups, group::Column::Id)).in_subquery(
+                Query::select()
+                    .column(group_member::Column::GroupId)
+                    .from(GroupMembers)
+                    .and_where(Expr::col(group_member::Column::UserId).eq(user_id))
+                    .to_owned(),
+            )
+        }),
+        "meta.created" => Attribute::DateTime(column(group::Column::CreatedAt)),
+        "meta.lastmodified" => Attribute::DateTime(column(group::Column::UpdatedAt)),
+        _ => return None,
+    })
+}
+
+pub async fn list(
+    dir: &Directory,
+    base: &str,
+    query: &ListQuery,
+) -> ScimResult<ListResponse<GroupResource>> {
+    let mut select = Groups::find().filter(group::Column::OrganizationId.eq(dir.organization_id));
+    if let Some(expression) = &query.filter {
+        select = select.filter(filter::to_condition(
+            &filter::parse(expression)?,
+            &attribute,
+        )?);
+    }
+
+    let page = query.page();
+    let total = select.clone().count(&**dir).await?;
+    let groups = select
+        .order_by_asc(group::Column::CreatedAt)
+        .order_by_asc(group::Column::Id)
+        .offset(page.offset())
+        .limit(page.count)
+        .all(&**dir)
+        .await?;
+
+    let mut members = if query.excludes("members") {
+        None
+    } else {
+        let ids: Vec<Uuid> = groups.iter().map(|group| group.id).collect();
+        Some(load_members(dir, &ids).await?)
+    };
+
+    let resources = groups
+        .into_iter()
+        .map(|group| {
+            let group_members = members
+                .as_mut()
+                .map(|members| members.remove(&group.id).unwrap_or_default());
+            GroupResource::new(base, group, group_members)
+        })
+        .collect();
+    Ok(ListResponse::new(&page, total, resources))
+}
+
+pub async fn get(dir: &Directory, base: &str, id: Uuid) -> ScimResult<GroupResource> {
+    let group = find(dir, id).await?;
+    resource(dir, base, group).await
+}
+
+pub async fn create(dir: &Directory, base: &str, body: &Value) -> ScimResult<GroupResource> {
+    let changes = GroupChanges::from_resource(body)?;
+    let display_name = changes
+        .display_name
+        .ok_or_else(|| ScimError::invalid_value("displayName is required"))?;
+    let now = chrono::Utc::now().fixed_offset();
+
+    let group = group::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        organization_id: Set(dir.organization_id),
+        display_name: Set(display_name),
+        external_id: Set(changes.external_id.flatten()),
+        role: Set(None),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(&**dir)
+    .await?;
+
+    write_members(
+        dir,
+        &group,
+        &BTreeSet::new(),
+        &changes.members.unwrap_or_default(),
+    )
+    .await?;
+    resource(dir, base, group).await
+}
+
+/// Replace a group with a new representation
+pub async fn replace(
+    dir: &Directory,
+    base: &str,
+    id: Uuid,
+    body: &Value,
+) -> ScimResult<GroupResource> {
+    let group = find(dir, id).await?;
+    let changes = GroupChanges::from_resource(body)?;
+    let current = member_ids(dir, id).await?;
+    save(dir, base, group, current, changes).await
+}
+
+pub async fn patch(
+    dir: &Directory,
+    base: &str,
+    id: Uuid,
+    request: &PatchRequest,
+) -> ScimResult<GroupResource> {
+    let group = find(dir, id).await?;
+    let current = member_ids(dir, id).await?;
+    let changes = GroupChanges::from_patch(request, &current)?;
+    save(dir, base, group, current, changes).await
+}
+
+pub async fn remove(dir: &Directory, id: Uuid) -> ScimResult<()> {
+    let group = find(dir, id).await?;
+    let former = member_ids(dir, id).await?;
+
+    Groups::delete_by_id(group.id).exec(&**dir).await?;
+    if group.role.is_some() {
+        sync_roles(&**dir, dir.organization_id, former).await?;
+    }
+    Ok(())
+}
+
+/// Group attributes taken from a request body or patch operations; `None`
+/// leaves an attribute as it is
+#[derive(Default)]
+struct GroupChanges {
+    display_name: Option<String>,
+    external_id: Option<Option<String>>,
+    /// The complete new member list
+    members: Option<BTreeSet<Uuid>>,
+}
+
+impl GroupChanges {
+    fn from_resource(body: &Value) -> ScimResult<Self> {
+        let Value::Object(attributes) = body else {
+            return Err(ScimError::invalid_syntax("Expected a Group resource"));
+        };
+
+        let mut changes = Self::default();
+        for (key, value) in attributes {
+            changes.set(
+                &attribute_name(key),
+                value,
+                &BTreeSet::new(),
+                PatchKind::Replace,
+            )?;
+        }
+        changes.external_id.get_or_insert(None);
+        changes.members.get_or_insert_with(BTreeSet::new);
+        Ok(changes)
+    }
+
+    fn from_patch(request: &PatchRequest, current: &BTreeSet<Uuid>) -> ScimResult<Self> {
+        let mut changes = Self::default();
+
+        for operation in &request.operations {
+            let kind = operation.kind()?;
+            match (kind, operation.path.as_deref()) {
+                (PatchKind::Remove, None) => {
+                    return Err(ScimError::bad_request("noTarget", "remove requires a path"))
+                }
+                (PatchKind::Remove, Some(path)) => {
+                    let (name, selected) = split_value_path(path)?;
+                    match name.as_str() {
+                        "members" => {
+                            let members = changes.members.get_or_insert_with(|| current.clone());
+                            match (selected, &operation.value) {
+                                (Some(selected), _) => members.retain(|id| !selected.contains(id)),
+                                (None, Some(value)) => {
+                                    for id in member_refs(value)? {
+                                        members.remove(&id);
+                                    }
+                                }
+                                (None, None) => members.clear(),
+                            }
+                        }
+                        "externalid" => changes.external_id = Some(None),
+                        "displayname" => {
+                            return Err(ScimError::mutability("displayName cannot be removed"))
+                        }
+                        _ => {}
+                    }
+                }
+                (_, Some(path)) => changes.set(
+                    &attribute_name(path),
+                    operation.required_value()?,
+                    current,
+                    kind,
+                )?,
+                (_, None) => {
+                    let Value::Object(attributes) = operation.required_value()? else {
+                        return Err(ScimError::invalid_value(
+                            "A patch without a path needs an object value",
+                        ));
+                    };
+                    for (key, value) in attributes {
+                        changes.set(&attribute_name(key), value, current, kind)?;
+                    }
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Record one attribute of an `add` or `replace`; ones this service does
+    /// not store are ignored
+    fn set(
+        &mut self,
+        attribute: &str,
+        value: &Value,
+        current: &BTreeSet<Uuid>,
+        kind: PatchKind,
+    ) -> ScimResult<()> {
+        match attribute {
+            "displayname" => self.display_name = Some(string(value, "displayName")?),
+            "externalid" => self.external_id = Some(optional_string(value, "externalId")?),
+            "members" => {
+                let refs = member_refs(value)?;
+                match kind {
+                    PatchKind::Add => self
+                        .members
+                        .get_or_insert_with(|| current.clone())
+                        .extend(refs),
+                    _ => self.members = Some(refs.into_iter().collect()),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Split a path such as `members[value eq "…"]` into the attribute and the
+/// member ids its filter selects
+fn split_value_path(path: &str) -> ScimResult<(String, Option<BTreeSet<Uuid>>)> {
+    let Some((name, rest)) = path.split_once('[') else {
+        return Ok((attribute_name(path), None));
+    };
+    let expression = rest
+        .trim_end()
+        .strip_suffix(']')
+        .ok_or_else(|| ScimError::invalid_path(format!("Malformed path {}", path)))?;
+
+    let mut selected = BTreeSet::new();
+    collect_selected(&filter::parse(expression)?, &mut selected)?;
+    Ok((attribute_name(name), Some(selected)))
+}
+
+/// Member ids of a filter made of `value eq "…"` terms joined by `or`
+fn collect_selected(filter: &Filter, selected: &mut BTreeSet<Uuid>) -> ScimResult<()> {
+    match filter {
+        Filter::Or(left, right) => {
+            collect_selected(left, selected)?;
+            collect_selected(right, selected)
+        }
+        Filter::Compare {
+            attribute,
+            op: Op::Eq,
+            value,
+        } if attribute == "value" => {
+            // Ids that are not UUIDs select no member
+            if let Some(id) = value.as_str().and_then(|id| Uuid::parse_str(id).ok()) {
+                selected.insert(id);
+            }
+            Ok(())
+        }
+        _ => Err(ScimError::invalid_filter(
+            "Members can only be selected with value eq",
+        )),
+    }
+}
+
+/// User ids of a `members` value: one member or a list of them
+fn member_refs(value: &Value) -> ScimResult<Vec<Uuid>> {
+    let member_ref = |member: &Value| {
+        member
+            .get("value")
+            .and_then(Value::as_str)
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| ScimError::invalid_value("members need a user id as value"))
+    };
+
+    match value {
+        Value::Array(members) => members.iter().map(member_ref).collect(),
+        Value::Null => Ok(Vec::new()),
+        member => Ok(vec![member_ref(member)?]),
+    }
+}
+
+async fn find(dir: &Directory, id: Uuid) -> ScimResult<group::Model> {
+    Groups::find_by_id(id)
+        .filter(group::Column::OrganizationId.eq(dir.organization_id))
+        .one(&**dir)
+        .await?
+        .ok_or_else(|| ScimError::not_found(format!("Group {} not found", id)))
+}
+
+async fn member_ids(dir: &Directory, group_id: Uuid) -> ScimResult<BTreeSet<Uuid>> {
+    Ok(GroupMembers::find()
+        .filter(group_member::Column::GroupId.eq(group_id))
+        .all(&**dir)
+        .await?
+        .into_iter()
+        .map(|member| member.user_id)
+        .collect())
+}
+
+/// Members of each group that are still members of the organization
+async fn load_members(
+    dir: &Directory,
+    group_ids: &[Uuid],
+) -> ScimResult<HashMap<Uuid, Vec<users::Model>>> {
+    let rows = GroupMembers::find()
+        .filter(group_member::Column::GroupId.is_in(group_ids.iter().copied()))
+        .filter(
+            group_member::Column::UserId.in_subquery(
+                Memberships::find()
+                    .select_only()
+                    .column(membership::Column::UserId)
+                    .filter(membership::Column::OrganizationId.eq(dir.organization_id))
+                    .into_query(),
+            ),
+        )
+        .find_also_related(users::Entity)
+        .order_by_asc(group_member::Column::UserId)
+        .all(&**dir)
+        .await?;
+
+    let mut members: HashMap<Uuid, Vec<users::Model>> = HashMap::new();
+    for (member, user) in rows {
+        if let Some(user) = user {
+            members.entry(member.group_id).or_default().push(user);
+        }
+    }
+    Ok(members)
+}
+
+async fn resource(dir: &Directory, base: &str, group: group::Model) -> ScimResult<GroupResource> {
+    let mut members = load_members(dir, &[group.id]).await?;
+    let group_members = members.remove(&group.id).unwrap_or_default();
+    Ok(GroupResource::new(base, group, Some(group_members)))
+}
+
+async fn save(
+    dir: &Directory,
+    base: &str,
+    group: group::Model,
+    current: BTreeSet<Uuid>,
+    changes: GroupChanges,
+) -> ScimResult<GroupResource> {
+    let mut active: group::ActiveModel = group.clone().into();
+    if let Some(display_name) = changes.display_name {
+        active.display_name = Set(display_name);
+    }
+    if let Some(external_id) = changes.external_id {
+        active.external_id = Set(external_id);
+    }
+    active.updated_at = Set(chrono::Utc::now().fixed_offset());
+    let group = active.update(&**dir).await?;
+
+    if let Some(members) = changes.members {
+        write_members(dir, &group, &current, &members).await?;
+    }
+    resource(dir, base, group).await
+}
+
+/// Bring a group's members from `current` to `members`
+async fn write_members(
+    dir: &Directory,
+    group: &group::Model,
+    current: &BTreeSet<Uuid>,
+    members: &BTreeSet<Uuid>,
+) -> ScimResult<()> {
+    let added: Vec<Uuid> = members.difference(current).copied().collect();
+    let removed: Vec<Uuid> = current.difference(members).copied().collect();
+
+    if !added.is_empty() {
+        let count = Memberships::find()
+            .filter(membership::Column::OrganizationId.eq(dir.organization_id))
+            .filter(membership::Column::UserId.is_in(added.iter().copied()))
+            .count(&**dir)
+            .await?;
+        if count != added.len() as u64 {
+            return Err(ScimError::invalid_value(
+                "Group members must be members of the organization",
+            ));
+        }
+
+        GroupMembers::insert_many(added.iter().map(|user_id| group_member::ActiveModel {
+            group_id: Set(group.id),
+            user_id: Set(*user_id),
+        }))
+        .exec(&**dir)
+        .await?;
+    }
+    if !removed.is_empty() {
+        GroupMembers::delete_many()
+            .filter(group_member::Column::GroupId.eq(group.id))
+            .filter(group_member::Column::UserId.is_in(removed.iter().copied()))
+            .exec(&**dir)
+            .await?;
+    }
+
+    if group.role.is_some() {
+        sync_roles(
+            &**dir,
+            dir.organization_id,
+            added.into_iter().chain(removed),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Give members the highest role among the role-mapped groups they are in,
+/// or the member role if they are in none
+///
+/// Called for users joining or leaving a group that has (or had) a role, so
+/// members promoted by hand are left alone otherwise. Only members the
+/// directory provisioned are changed, and owners keep their role.
+pub async fn sync_roles<C: ConnectionTrait>(
+    db: &C,
+    organization_id: Uuid,
+    user_ids: impl IntoIterator<Item = Uuid>,
+) -> AppResult<()> {
+    let mapped: HashMap<Uuid, OrgRole> = Groups::find()
+        .filter(group::Column::OrganizationId.eq(organization_id))
+        .filter(group::Column::Role.is_not_null())
+        .all(db)
+        .await
+        .map_err(AppError::from)?
+        .into_iter()
+        .filter_map(|group| group.role.map(|role| (group.id, role)))
+        .collect();
+
+    for user_id in user_ids {
+        let Some(membership) = Memberships::find_by_id((organization_id, user_id))
+            .one(db)
+            .await
+            .map_err(AppError::from)?
+        else {
+            continue;
+        };
+        let provisioned = ProvisionedUsers::find_by_id((organization_id, user_id))
+            .one(db)
+            .await
+            .map_err(AppError::from)?;
+        if membership.role == OrgRole::Owner || provisioned.is_none() {
+            continue;
+        }
+
+        let role = GroupMembers::find()
+            .filter(group_member::Column::UserId.eq(user_id))
+            .filter(group_member::Column::GroupId.is_in(mapped.keys().copied()))
+            .all(db)
+            .await
+            .map_err(AppError::from)?
+            .into_iter()
+            .filter_map(|member| mapped.get(&member.group_id).copied())
+            .max()
+            .unwrap_or(OrgRole::Member);
+
+        if role != membership.role {
+            let mut active: membership::ActiveModel = membership.into();
+            active.role = Set(role);
+            active.updated_at = Set(chrono::Utc::now().fixed_offset());
+            active.update(db).await.map_err(AppError::from)?;
+        }
+    }
+
+    Ok(())
+}
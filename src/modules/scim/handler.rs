@@ -0,0 +1,329 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::error::AppResult;
+use crate::modules::organizations::{
+    context::{Admin, OrgGuard},
+    membership::OrgRole,
+};
+use crate::state::AppState;
+
+use super::error::{ScimError, ScimResult};
+use super::extractor::{Directory, ScimJson};
+use super::group;
+use super::groups;
+use super::resource::{self, ListQuery, ListResponse, Page, PatchRequest};
+use super::service;
+use super::token;
+use super::users::{self, UserChanges};
+
+#[derive(serde::Deserialize)]
+pub struct CreateScimTokenRequest {
+    pub name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateScimGroupRequest {
+    /// `null` stops the group from managing its members' roles
+    pub role: Option<OrgRole>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ScimTokenResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub created_by: Option<Uuid>,
+    pub last_used_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl From<token::Model> for ScimTokenResponse {
+    fn from(model: token::Model) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+            created_by: model.created_by,
+            last_used_at: model.last_used_at,
+            created_at: model.created_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct CreatedScimTokenResponse {
+    #[serde(flatten)]
+    pub token: ScimTokenResponse,
+    /// Shown only once
+    pub secret: String,
+    /// What to configure in the directory alongside the secret
+    pub scim_url: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ScimGroupResponse {
+    pub id: Uuid,
+    pub display_name: String,
+    pub external_id: Option<String>,
+    pub role: Option<OrgRole>,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+    pub updated_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl From<group::Model> for ScimGroupResponse {
+    fn from(model: group::Model) -> Self {
+        Self {
+            id: model.id,
+            display_name: model.display_name,
+            external_id: model.external_id,
+            role: model.role,
+            created_at: model.created_at,
+            updated_at: model.updated_at,
+        }
+    }
+}
+
+/// GET /api/orgs/:org_id/scim/tokens
+pub async fn list_scim_tokens(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+) -> AppResult<impl IntoResponse> {
+    let tokens = service::list_tokens(&state, org.org_id).await?;
+    Ok(Json(
+        tokens
+            .into_iter()
+            .map(ScimTokenResponse::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// POST /api/orgs/:org_id/scim/tokens
+pub async fn create_scim_token(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    Json(payload): Json<CreateScimTokenRequest>,
+) -> AppResult<impl IntoResponse> {
+    let (token, secret) =
+        service::create_token(&state, org.org_id, &payload.name, org.user_id).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedScimTokenResponse {
+            token: token.into(),
+            secret,
+            scim_url: service::endpoint(&state.config),
+        }),
+    ))
+}
+
+/// DELETE /api/orgs/:org_id/scim/tokens/:token_id
+pub async fn revoke_scim_token(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    Path((_, token_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    service::revoke_token(&state, org.org_id, token_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/orgs/:org_id/scim/groups
+pub async fn list_scim_groups(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+) -> AppResult<impl IntoResponse> {
+    let groups = service::list_groups(&state, org.org_id).await?;
+    Ok(Json(
+        groups
+            .into_iter()
+            .map(ScimGroupResponse::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// PATCH /api/orgs/:org_id/scim/groups/:group_id
+pub async fn update_scim_group(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    Path((_, group_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<UpdateScimGroupRequest>,
+) -> AppResult<impl IntoResponse> {
+    let group = service::set_group_role(&state, org.org_id, group_id, payload.role).await?;
+    Ok(Json(ScimGroupResponse::from(group)))
+}
+
+/// GET /scim/v2/ServiceProviderConfig
+pub async fn service_provider_config(State(state): State<AppState>) -> impl IntoResponse {
+    ScimJson(resource::service_provider_config(&service::endpoint(
+        &state.config,
+    )))
+}
+
+/// GET /scim/v2/ResourceTypes
+pub async fn resource_types(State(state): State<AppState>) -> impl IntoResponse {
+    let types = resource::resource_types(&service::endpoint(&state.config));
+    let page = Page {
+        start_index: 1,
+        count: types.len() as u64,
+    };
+    ScimJson(ListResponse::new(&page, types.len() as u64, types))
+}
+
+/// GET /scim/v2/Users
+pub async fn list_users(
+    State(state): State<AppState>,
+    dir: Directory,
+    Query(query): Query<ListQuery>,
+) -> ScimResult<impl IntoResponse> {
+    let list = users::list(&dir, &service::endpoint(&state.config), &query).await?;
+    Ok(ScimJson(list))
+}
+
+/// POST /scim/v2/Users
+pub async fn create_user(
+    State(state): State<AppState>,
+    dir: Directory,
+    ScimJson(body): ScimJson<Value>,
+) -> ScimResult<impl IntoResponse> {
+    let changes = UserChanges::from_resource(&body)?;
+    let user = users::create(&state, &dir, &service::endpoint(&state.config), changes).await?;
+    dir.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, user.meta.location.clone())],
+        ScimJson(user),
+    ))
+}
+
+/// GET /scim/v2/Users/:id
+pub async fn get_user(
+    State(state): State<AppState>,
+    dir: Directory,
+    Path(id): Path<String>,
+) -> ScimResult<impl IntoResponse> {
+    let id = resource_id(&id, "User")?;
+    let user = users::get(&dir, &service::endpoint(&state.config), id).await?;
+    Ok(ScimJson(user))
+}
+
+/// PUT /scim/v2/Users/:id
+pub async fn replace_user(
+    State(state): State<AppState>,
+    dir: Directory,
+    Path(id): Path<String>,
+    ScimJson(body): ScimJson<Value>,
+) -> ScimResult<impl IntoResponse> {
+    let id = resource_id(&id, "User")?;
+    let changes = UserChanges::from_resource(&body)?;
+    let user = users::update(&state, &dir, &service::endpoint(&state.config), id, changes).await?;
+    dir.commit().await?;
+    Ok(ScimJson(user))
+}
+
+/// PATCH /scim/v2/Users/:id
+pub async fn patch_user(
+    State(state): State<AppState>,
+    dir: Directory,
+    Path(id): Path<String>,
+    ScimJson(request): ScimJson<PatchRequest>,
+) -> ScimResult<impl IntoResponse> {
+    let id = resource_id(&id, "User")?;
+    let changes = UserChanges::from_patch(&request)?;
+    let user = users::update(&state, &dir, &service::endpoint(&state.config), id, changes).await?;
+    dir.commit().await?;
+    Ok(ScimJson(user))
+}
+
+/// DELETE /scim/v2/Users/:id
+pub async fn delete_user(
+    State(state): State<AppState>,
+    dir: Directory,
+    Path(id): Path<String>,
+) -> ScimResult<impl IntoResponse> {
+    let id = resource_id(&id, "User")?;
+    users::remove(&state, &dir, id).await?;
+    dir.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /scim/v2/Groups
+pub async fn list_groups(
+    State(state): State<AppState>,
+    dir: Directory,
+    Query(query): Query<ListQuery>,
+) -> ScimResult<impl IntoResponse> {
+    let list = groups::list(&dir, &service::endpoint(&state.config), &query).await?;
+    Ok(ScimJson(list))
+}
+
+/// POST /scim/v2/Groups
+pub async fn create_group(
+    State(state): State<AppState>,
+    dir: Directory,
+    ScimJson(body): ScimJson<Value>,
+) -> ScimResult<impl IntoResponse> {
+    let group = groups::create(&dir, &service::endpoint(&state.config), &body).await?;
+    dir.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, group.meta.location.clone())],
+        ScimJson(group),
+    ))
+}
+
+/// GET /scim/v2/Groups/:id
+pub async fn get_group(
+    State(state): State<AppState>,
+    dir: Directory,
+    Path(id): Path<String>,
+) -> ScimResult<impl IntoResponse> {
+    let id = resource_id(&id, "Group")?;
+    let group = groups::get(&dir, &service::endpoint(&state.config), id).await?;
+    Ok(ScimJson(group))
+}
+
+/// PUT /scim/v2/Groups/:id
+pub async fn replace_group(
+    State(state): State<AppState>,
+    dir: Directory,
+    Path(id): Path<String>,
+    ScimJson(body): ScimJson<Value>,
+) -> ScimResult<impl IntoResponse> {
+    let id = resource_id(&id, "Group")?;
+    let group = groups::replace(&dir, &service::endpoint(&state.config), id, &body).await?;
+    dir.commit().await?;
+    Ok(ScimJson(group))
+}
+
+/// PATCH /scim/v2/Groups/:id
+pub async fn patch_group(
+    State(state): State<AppState>,
+    dir: Directory,
+    Path(id): Path<String>,
+    ScimJson(request): ScimJson<PatchRequest>,
+) -> ScimResult<impl IntoResponse> {
+    let id = resource_id(&id, "Group")?;
+    let group = groups::patch(&dir, &service::endpoint(&state.config), id, &request).await?;
+    dir.commit().await?;
+    Ok(ScimJson(group))
+}
+
+/// DELETE /scim/v2/Groups/:id
+pub async fn delete_group(dir: Directory, Path(id): Path<String>) -> ScimResult<impl IntoResponse> {
+    let id = resource_id(&id, "Group")?;
+    groups::remove(&dir, id).await?;
+    dir.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Ids are UUIDs; anything else names no resource
+fn resource_id(id: &str, resource_type: &str) -> ScimResult<Uuid> {
+    Uuid::parse_str(id)
+        .map_err(|_| ScimError::not_found(format!("{} {} not found", resource_type, id)))
+}
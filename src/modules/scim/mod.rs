@@ -0,0 +1,13 @@
+pub mod error;
+pub mod extractor;
+pub mod filter;
+pub mod group;
+pub mod group_member;
+pub mod groups;
+pub mod handler;
+pub mod provisioned_user;
+pub mod resource;
+pub mod routes;
+pub mod service;
+pub mod token;
+pub mod users;
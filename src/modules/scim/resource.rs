@@ -0,0 +1,323 @@
+//! SCIM wire format: resource representations, list responses and the
+//! discovery documents
+
+use sea_orm::prelude::DateTimeWithTimeZone;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::modules::users::entity as users;
+
+use super::error::{ScimError, ScimResult};
+use super::group;
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const SERVICE_PROVIDER_CONFIG_SCHEMA: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+const RESOURCE_TYPE_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:ResourceType";
+
+/// Page size when the client does not ask for one
+pub const DEFAULT_PAGE_SIZE: u64 = 100;
+/// Largest page a client can ask for
+pub const MAX_PAGE_SIZE: u64 = 1000;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    pub resource_type: &'static str,
+    pub created: DateTimeWithTimeZone,
+    pub last_modified: DateTimeWithTimeZone,
+    pub location: String,
+}
+
+#[derive(Serialize)]
+pub struct Name {
+    pub formatted: String,
+}
+
+#[derive(Serialize)]
+pub struct Email {
+    pub value: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub primary: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserResource {
+    pub schemas: [&'static str; 1],
+    pub id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    pub user_name: String,
+    pub name: Name,
+    pub display_name: String,
+    pub emails: Vec<Email>,
+    pub active: bool,
+    pub meta: Meta,
+}
+
+impl UserResource {
+    /// `base` is the SCIM endpoint, e.g. `https://api.example.com/scim/v2`
+    pub fn new(base: &str, user: users::Model, external_id: Option<String>) -> Self {
+        Self {
+            schemas: [USER_SCHEMA],
+            id: user.id,
+            external_id,
+            user_name: user.email.clone(),
+            name: Name {
+                formatted: user.name.clone(),
+            },
+            display_name: user.name,
+            emails: vec![Email {
+                value: user.email,
+                kind: "work",
+                primary: true,
+            }],
+            active: user.is_active,
+            meta: Meta {
+                resource_type: "User",
+                created: user.created_at,
+                last_modified: user.updated_at,
+                location: user_location(base, user.id),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MemberRef {
+    pub value: Uuid,
+    pub display: String,
+    #[serde(rename = "$ref")]
+    pub reference: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupResource {
+    pub schemas: [&'static str; 1],
+    pub id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    pub display_name: String,
+    /// Left out when the client excludes it, which directories do to avoid
+    /// loading large groups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<MemberRef>>,
+    pub meta: Meta,
+}
+
+impl GroupResource {
+    pub fn new(base: &str, group: group::Model, members: Option<Vec<users::Model>>) -> Self {
+        Self {
+            schemas: [GROUP_SCHEMA],
+            id: group.id,
+            external_id: group.external_id,
+            display_name: group.display_name,
+            members: members.map(|members| {
+                members
+                    .into_iter()
+                    .map(|user| MemberRef {
+                        value: user.id,
+                        reference: user_location(base, user.id),
+                        display: user.name,
+                    })
+                    .collect()
+            }),
+            meta: Meta {
+                resource_type: "Group",
+                created: group.created_at,
+                last_modified: group.updated_at,
+                location: format!("{}/Groups/{}", base, group.id),
+            },
+        }
+    }
+}
+
+fn user_location(base: &str, id: Uuid) -> String {
+    format!("{}/Users/{}", base, id)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponse<T> {
+    pub schemas: [&'static str; 1],
+    pub total_results: u64,
+    pub start_index: u64,
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+impl<T> ListResponse<T> {
+    pub fn new(page: &Page, total_results: u64, resources: Vec<T>) -> Self {
+        Self {
+            schemas: [LIST_RESPONSE_SCHEMA],
+            total_results,
+            start_index: page.start_index,
+            items_per_page: resources.len(),
+            resources,
+        }
+    }
+}
+
+/// Query parameters of list requests
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListQuery {
+    pub filter: Option<String>,
+    pub start_index: Option<i64>,
+    pub count: Option<i64>,
+    pub excluded_attributes: Option<String>,
+}
+
+impl ListQuery {
+    pub fn page(&self) -> Page {
+        Page {
+            start_index: self.start_index.unwrap_or(1).max(1) as u64,
+            count: self
+                .count
+                .map_or(DEFAULT_PAGE_SIZE, |count| count.max(0) as u64)
+                .min(MAX_PAGE_SIZE),
+        }
+    }
+
+    pub fn excludes(&self, attribute: &str) -> bool {
+        self.excluded_attributes.as_deref().is_some_and(|excluded| {
+            excluded
+                .split(',')
+                .any(|name| super::filter::attribute_name(name) == attribute)
+        })
+    }
+}
+
+/// A 1-based window into a list, as SCIM counts
+pub struct Page {
+    pub start_index: u64,
+    pub count: u64,
+}
+
+impl Page {
+    pub fn offset(&self) -> u64 {
+        self.start_index - 1
+    }
+}
+
+/// Body of a PATCH request (RFC 7644, section 3.5.2)
+#[derive(Deserialize)]
+pub struct PatchRequest {
+    #[serde(rename = "Operations")]
+    pub operations: Vec<PatchOperation>,
+}
+
+#[derive(Deserialize)]
+pub struct PatchOperation {
+    pub op: String,
+    pub path: Option<String>,
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PatchKind {
+    Add,
+    Replace,
+    Remove,
+}
+
+impl PatchOperation {
+    pub fn kind(&self) -> ScimResult<PatchKind> {
+        match self.op.to_ascii_lowercase().as_str() {
+            "add" => Ok(PatchKind::Add),
+            "replace" => Ok(PatchKind::Replace),
+            "remove" => Ok(PatchKind::Remove),
+            other => Err(ScimError::invalid_syntax(format!(
+                "Unknown patch operation {}",
+                other
+            ))),
+        }
+    }
+
+    /// The value of an `add` or `replace`
+    pub fn required_value(&self) -> ScimResult<&Value> {
+        self.value
+            .as_ref()
+            .ok_or_else(|| ScimError::invalid_value(format!("{} requires a value", self.op)))
+    }
+}
+
+/// A string attribute value
+pub fn string(value: &Value, attribute: &str) -> ScimResult<String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| ScimError::invalid_value(format!("{} must be a string", attribute)))
+}
+
+/// A string attribute value that may be cleared with `null`
+pub fn optional_string(value: &Value, attribute: &str) -> ScimResult<Option<String>> {
+    match value {
+        Value::Null => Ok(None),
+        value => string(value, attribute).map(Some),
+    }
+}
+
+/// A boolean attribute value; some directories send `"True"` and `"False"`
+pub fn boolean(value: &Value, attribute: &str) -> ScimResult<bool> {
+    match value {
+        Value::Bool(flag) => Ok(*flag),
+        Value::String(text) if text.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(text) if text.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(ScimError::invalid_value(format!(
+            "{} must be a boolean",
+            attribute
+        ))),
+    }
+}
+
+pub fn service_provider_config(base: &str) -> Value {
+    serde_json::json!({
+        "schemas": [SERVICE_PROVIDER_CONFIG_SCHEMA],
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": MAX_PAGE_SIZE },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": false },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "OAuth Bearer Token",
+            "description": "A SCIM token created by an owner of the organization",
+            "primary": true,
+        }],
+        "meta": {
+            "resourceType": "ServiceProviderConfig",
+            "location": format!("{}/ServiceProviderConfig", base),
+        },
+    })
+}
+
+pub fn resource_types(base: &str) -> Vec<Value> {
+    [
+        ("User", "/Users", USER_SCHEMA),
+        ("Group", "/Groups", GROUP_SCHEMA),
+    ]
+    .into_iter()
+    .map(|(name, endpoint, schema)| {
+        serde_json::json!({
+            "schemas": [RESOURCE_TYPE_SCHEMA],
+            "id": name,
+            "name": name,
+            "endpoint": endpoint,
+            "schema": schema,
+            "meta": {
+                "resourceType": "ResourceType",
+                "location": format!("{}/ResourceTypes/{}", base, name),
+            },
+        })
+    })
+    .collect()
+}
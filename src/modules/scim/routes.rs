@@ -0,0 +1,52 @@
+use axum::{
+    routing::{delete, get, patch},
+    Router,
+};
+
+use crate::state::AppState;
+
+use super::handler;
+
+/// The SCIM 2.0 API for directories, nested under `/scim/v2`
+pub fn scim_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/ServiceProviderConfig",
+            get(handler::service_provider_config),
+        )
+        .route("/ResourceTypes", get(handler::resource_types))
+        .route(
+            "/Users",
+            get(handler::list_users).post(handler::create_user),
+        )
+        .route(
+            "/Users/{id}",
+            get(handler::get_user)
+                .put(handler::replace_user)
+                .patch(handler::patch_user)
+                .delete(handler::delete_user),
+        )
+        .route(
+            "/Groups",
+            get(handler::list_groups).post(handler::create_group),
+        )
+        .route(
+            "/Groups/{id}",
+            get(handler::get_group)
+                .put(handler::replace_group)
+                .patch(handler::patch_group)
+                .delete(handler::delete_group),
+        )
+}
+
+/// Directory tokens and group roles, nested under `/api/orgs/{org_id}/scim`
+pub fn org_scim_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/tokens",
+            get(handler::list_scim_tokens).post(handler::create_scim_token),
+        )
+        .route("/tokens/{token_id}", delete(handler::revoke_scim_token))
+        .route("/groups", get(handler::list_scim_groups))
+        .route("/groups/{group_id}", patch(handler::update_scim_group))
+}
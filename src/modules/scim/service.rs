@@ -0,0 +1,141 @@
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::token::{generate_token, hash_token};
+use crate::modules::organizations::membership::OrgRole;
+use crate::state::AppState;
+
+use super::group::{self, Entity as Groups};
+use super::group_member::{self, Entity as GroupMembers};
+use super::groups::sync_roles;
+use super::token::{self, Entity as Tokens};
+
+/// Where directories reach the SCIM API, e.g. `https://api.example.com/scim/v2`
+pub fn endpoint(config: &AppConfig) -> String {
+    format!("{}/scim/v2", config.public_api_url())
+}
+
+pub async fn list_tokens(state: &AppState, organization_id: Uuid) -> AppResult<Vec<token::Model>> {
+    Tokens::find()
+        .filter(token::Column::OrganizationId.eq(organization_id))
+        .order_by_asc(token::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Create a token for the organization's directory, returning it with the
+/// raw value, which is not stored and cannot be shown again
+pub async fn create_token(
+    state: &AppState,
+    organization_id: Uuid,
+    name: &str,
+    created_by: Uuid,
+) -> AppResult<(token::Model, String)> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > 100 {
+        return Err(AppError::ValidationError(
+            "Name must be between 1 and 100 characters".to_string(),
+        ));
+    }
+
+    let raw_token = generate_token();
+    let token = token::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        organization_id: Set(organization_id),
+        name: Set(name.to_string()),
+        token_hash: Set(hash_token(&raw_token)),
+        created_by: Set(Some(created_by)),
+        last_used_at: Set(None),
+        created_at: Set(chrono::Utc::now().fixed_offset()),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(AppError::from)?;
+
+    Ok((token, raw_token))
+}
+
+pub async fn revoke_token(
+    state: &AppState,
+    organization_id: Uuid,
+    token_id: Uuid,
+) -> AppResult<()> {
+    let result = Tokens::delete_many()
+        .filter(token::Column::Id.eq(token_id))
+        .filter(token::Column::OrganizationId.eq(organization_id))
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    if result.rows_affected == 0 {
+        return Err(AppError::NotFound(format!(
+            "SCIM token with id {} not found",
+            token_id
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn list_groups(state: &AppState, organization_id: Uuid) -> AppResult<Vec<group::Model>> {
+    Groups::find()
+        .filter(group::Column::OrganizationId.eq(organization_id))
+        .order_by_asc(group::Column::DisplayName)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Give a directory group's members an organization role, or stop doing so
+/// with `None`
+pub async fn set_group_role(
+    state: &AppState,
+    organization_id: Uuid,
+    group_id: Uuid,
+    role: Option<OrgRole>,
+) -> AppResult<group::Model> {
+    if role == Some(OrgRole::Owner) {
+        return Err(AppError::BadRequest(
+            "Groups cannot grant the owner role".to_string(),
+        ));
+    }
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let group = Groups::find_by_id(group_id)
+        .filter(group::Column::OrganizationId.eq(organization_id))
+        .one(&txn)
+        .await
+        .map_err(AppError::from)?
+        .ok_or(AppError::NotFound(format!(
+            "Group with id {} not found",
+            group_id
+        )))?;
+
+    let previous_role = group.role;
+    let mut active: group::ActiveModel = group.into();
+    active.role = Set(role);
+    active.updated_at = Set(chrono::Utc::now().fixed_offset());
+    let group = active.update(&txn).await.map_err(AppError::from)?;
+
+    if previous_role.is_some() || role.is_some() {
+        let members = GroupMembers::find()
+            .filter(group_member::Column::GroupId.eq(group_id))
+            .all(&txn)
+            .await
+            .map_err(AppError::from)?;
+        sync_roles(
+            &txn,
+            organization_id,
+            members.into_iter().map(|member| member.user_id),
+        )
+        .await?;
+    }
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok(group)
+}
@@ -0,0 +1,518 @@
+//! The `Users` resource: members of the organization, backed by `users` and
+//! `organization_members`
+//!
+//! `userName` is the account's email address and cannot be changed by the
+//! directory. Deprovisioning never deletes accounts: it removes the
+//! membership and turns `is_active` off.
+
+use sea_orm::{
+    sea_query::{Expr, IntoCondition, SimpleExpr},
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, JoinType, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, QueryTrait, RelationTrait, Select, Set,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::modules::auth::service as auth_service;
+use crate::modules::organizations::{
+    membership::{self, Entity as Memberships, OrgRole},
+    service as organizations_service,
+};
+use crate::modules::users::{entity as users, service as users_service};
+use crate::state::AppState;
+
+use super::error::{ScimError, ScimResult};
+use super::extractor::Directory;
+use super::filter::{self, attribute_name, Attribute};
+use super::group::{self, Entity as Groups};
+use super::group_member::{self, Entity as GroupMembers};
+use super::provisioned_user::{self, Entity as ProvisionedUsers};
+use super::resource::{
+    boolean, optional_string, string, ListQuery, ListResponse, PatchKind, PatchRequest,
+    UserResource,
+};
+
+/// User attributes taken from a request body or patch operations; `None`
+/// leaves an attribute as it is
+#[derive(Default)]
+pub struct UserChanges {
+    user_name: Option<String>,
+    display_name: Option<String>,
+    formatted: Option<String>,
+    given_name: Option<String>,
+    family_name: Option<String>,
+    active: Option<bool>,
+    external_id: Option<Option<String>>,
+}
+
+impl UserChanges {
+    /// From a full representation, as sent to create or replace a user
+    pub fn from_resource(body: &Value) -> ScimResult<Self> {
+        let Value::Object(attributes) = body else {
+            return Err(ScimError::invalid_syntax("Expected a User resource"));
+        };
+
+        let mut changes = Self::default();
+        for (key, value) in attributes {
+            changes.set(&attribute_name(key), value)?;
+        }
+        // Replacing a resource clears what the new representation leaves out
+        changes.external_id.get_or_insert(None);
+        Ok(changes)
+    }
+
+    pub fn from_patch(request: &PatchRequest) -> ScimResult<Self> {
+        let mut changes = Self::default();
+
+        for operation in &request.operations {
+            match operation.kind()? {
+                PatchKind::Add | PatchKind::Replace => {
+                    let value = operation.required_value()?;
+                    match &operation.path {
+                        Some(path) => changes.set(&attribute_name(path), value)?,
+                        None => {
+                            let Value::Object(attributes) = value else {
+                                return Err(ScimError::invalid_value(
+                                    "A patch without a path needs an object value",
+                                ));
+                            };
+                            for (key, value) in attributes {
+                                changes.set(&attribute_name(key), value)?;
+                            }
+                        }
+                    }
+                }
+                PatchKind::Remove => {
+                    let path = operation
+                        .path
+                        .as_deref()
+                        .map(attribute_name)
+                        .ok_or_else(|| {
+                            ScimError::bad_request("noTarget", "remove requires a path")
+                        })?;
+                    match path.as_str() {
+                        "externalid" => changes.external_id = Some(None),
+                        "username" | "active" => {
+                            return Err(ScimError::mutability(format!(
+                                "{} cannot be removed",
+                                path
+                            )))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Record one attribute; ones this service does not store are ignored
+    fn set(&mut self, attribute: &str, value: &Value) -> ScimResult<()> {
+        match attribute {
+            "username" => self.user_name = Some(string(value, "userName")?),
+            "displayname" => self.display_name = optional_string(value, "displayName")?,
+            "name" => match value {
+                Value::Object(name) => {
+                    for (key, value) in name {
+                        self.set(&format!("name.{}", attribute_name(key)), value)?;
+                    }
+                }
+                Value::Null => {}
+                _ => return Err(ScimError::invalid_value("name must be an object")),
+            },
+            "name.formatted" => self.formatted = optional_string(value, "name.formatted")?,
+            "name.givenname" => self.given_name = optional_string(value, "name.givenName")?,
+            "name.familyname" => self.family_name = optional_string(value, "name.familyName")?,
+            "active" => self.active = Some(boolean(value, "active")?),
+            "externalid" => self.external_id = Some(optional_string(value, "externalId")?),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The account name: `displayName`, else the formatted name, else the
+    /// given and family names
+    fn name(&self) -> Option<String> {
+        let joined = [&self.given_name, &self.family_name]
+            .into_iter()
+            .flatten()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        [&self.display_name, &self.formatted]
+            .into_iter()
+            .flatten()
+            .map(|name| name.trim().to_string())
+            .chain(Some(joined))
+            .find(|name| !name.is_empty())
+    }
+}
+
+/// Columns behind the attributes clients can filter on
+fn attribute(name: &str) -> Option<Attribute> {
+    let column = |column: users::Column| SimpleExpr::from(Expr::col((users::Entity, column)));
+
+    Some(match name {
+        "id" => Attribute::Id(column(users::Column::Id)),
+        "username" | "emails" | "emails.value" => Attribute::Text {
+            expr: column(users::Column::Email),
+            case_exact: false,
+        },
+        "emails.type" => Attribute::Text {
+            expr: Expr::val("work").into(),
+            case_exact: false,
+        },
+        "emails.primary" => Attribute::Boolean(Expr::val(true).into()),
+        "displayname" | "name.formatted" => Attribute::Text {
+            expr: column(users::Column::Name),
+            case_exact: false,
+        },
+        "externalid" => Attribute::Text {
+            expr: Expr::col((ProvisionedUsers, provisioned_user::Column::ExternalId)).into(),
+            case_exact: true,
+        },
+        "active" => Attribute::Boolean(column(users::Column::IsActive)),
+        "meta.created" => Attribute::DateTime(column(users::Column::CreatedAt)),
+        "meta.lastmodified" => Attribute::DateTime(column(users::Column::UpdatedAt)),
+        _ => return None,
+    })
+}
+
+/// Members of the organization, joined with what the directory knows of them
+///
+/// Service accounts belong to the organization rather than the directory,
+/// so they are left out.
+fn members(organization_id: Uuid) -> Select<users::Entity> {
+    users::Entity::find()
+        .join(JoinType::InnerJoin, membership::Relation::User.def().rev())
+        .join(
+            JoinType::LeftJoin,
+            provisioned_user::Relation::User
+                .def()
+                .rev()
+                .on_condition(move |_, provisioned| {
+                    Expr::col((provisioned, provisioned_user::Column::OrganizationId))
+                        .eq(organization_id)
+                        .into_condition()
+                }),
+        )
+        .filter(membership::Column::OrganizationId.eq(organization_id))
+        .filter(users::Column::ServiceAccountOrganizationId.is_null())
+}
+
+pub async fn list(
+    dir: &Directory,
+    base: &str,
+    query: &ListQuery,
+) -> ScimResult<ListResponse<UserResource>> {
+    let mut select = members(dir.organization_id);
+    if let Some(expression) = &query.filter {
+        select = select.filter(filter::to_condition(
+            &filter::parse(expression)?,
+            &attribute,
+        )?);
+    }
+
+    let page = query.page();
+    let total = select.clone().count(&**dir).await?;
+    let rows = select
+        .order_by_asc(users::Column::CreatedAt)
+        .order_by_asc(users::Column::Id)
+        .offset(page.offset())
+        .limit(page.count)
+        .select_also(ProvisionedUsers)
+        .all(&**dir)
+        .await?;
+
+    let resources = rows
+        .into_iter()
+        .map(|(user, provisioned)| {
+            UserResource::new(base, user, provisioned.and_then(|p| p.external_id))
+        })
+        .collect();
+    Ok(ListResponse::new(&page, total, resources))
+}
+
+pub async fn get(dir: &Directory, base: &str, id: Uuid) -> ScimResult<UserResource> {
+    let member = find(dir, id).await?;
+    Ok(member.into_resource(base))
+}
+
+/// Provision a user
+///
+/// A new email address gets a passwordless account that joins as a member.
+/// An existing account is taken over only if it is already a member, or was
+/// provisioned by this directory before and deprovisioned since.
+pub async fn create(
+    state: &AppState,
+    dir: &Directory,
+    base: &str,
+    mut changes: UserChanges,
+) -> ScimResult<UserResource> {
+    let organization_id = dir.organization_id;
+    let user_name = changes
+        .user_name
+        .as_deref()
+        .ok_or_else(|| ScimError::invalid_value("userName is required"))?;
+    let email = users_service::validate_email(user_name)?;
+    changes.active.get_or_insert(true);
+
+    let member = match users_service::find_by_email_in(&**dir, &email).await? {
+        Some(user) => {
+            let membership =
+                organizations_service::find_membership(&**dir, organization_id, user.id).await?;
+            let provisioned = ProvisionedUsers::find_by_id((organization_id, user.id))
+                .one(&**dir)
+                .await?;
+
+            let membership = match (membership, &provisioned) {
+                (Some(_), Some(_)) => {
+                    return Err(ScimError::uniqueness(
+                        "A user with this userName already exists",
+                    ))
+                }
+                (Some(membership), None) => membership,
+                (None, Some(_)) => {
+                    organizations_service::insert_membership(
+                        &**dir,
+                        organization_id,
+                        user.id,
+                        OrgRole::Member,
+                    )
+                    .await?
+                }
+                (None, None) => {
+                    return Err(ScimError::uniqueness(
+                        "An account with this email already exists and is not a member of this organization",
+                    ))
+                }
+            };
+
+            Member {
+                user,
+                membership,
+                provisioned,
+            }
+        }
+        None => {
+            let name = changes
+                .name()
+                .unwrap_or_else(|| email.split('@').next().unwrap_or(&email).to_string());
+            let user =
+                users_service::create_passwordless_user_in(&**dir, Uuid::new_v4(), &email, &name)
+                    .await?;
+            let membership = organizations_service::insert_membership(
+                &**dir,
+                organization_id,
+                user.id,
+                OrgRole::Member,
+            )
+            .await?;
+
+            Member {
+                user,
+                membership,
+                provisioned: None,
+            }
+        }
+    };
+
+    let member = apply(state, dir, member, changes).await?;
+    Ok(member.into_resource(base))
+}
+
+/// Apply a replacement or patch to a member
+pub async fn update(
+    state: &AppState,
+    dir: &Directory,
+    base: &str,
+    id: Uuid,
+    changes: UserChanges,
+) -> ScimResult<UserResource> {
+    let member = find(dir, id).await?;
+    let member = apply(state, dir, member, changes).await?;
+    Ok(member.into_resource(base))
+}
+
+/// Deprovision a member: they leave the organization and its groups, and
+/// their account is deactivated unless it belongs to other organizations
+pub async fn remove(state: &AppState, dir: &Directory, id: Uuid) -> ScimResult<()> {
+    let organization_id = dir.organization_id;
+    let member = find(dir, id).await?;
+    if member.membership.role == OrgRole::Owner {
+        return Err(ScimError::forbidden(
+            "Owners cannot be deprovisioned by the directory",
+        ));
+    }
+
+    GroupMembers::delete_many()
+        .filter(group_member::Column::UserId.eq(id))
+        .filter(
+            group_member::Column::GroupId.in_subquery(
+                Groups::find()
+                    .select_only()
+                    .column(group::Column::Id)
+                    .filter(group::Column::OrganizationId.eq(organization_id))
+                    .into_query(),
+            ),
+        )
+        .exec(&**dir)
+        .await?;
+    Memberships::delete_by_id((organization_id, id))
+        .exec(&**dir)
+        .await?;
+
+    if member.user.is_active && !belongs_elsewhere(state, organization_id, id).await? {
+        set_active(&**dir, member.user, false).await?;
+    }
+    Ok(())
+}
+
+/// A member with what the directory knows of them
+struct Member {
+    user: users::Model,
+    membership: membership::Model,
+    provisioned: Option<provisioned_user::Model>,
+}
+
+impl Member {
+    fn into_resource(self, base: &str) -> UserResource {
+        UserResource::new(
+            base,
+            self.user,
+            self.provisioned
+                .and_then(|provisioned| provisioned.external_id),
+        )
+    }
+}
+
+async fn find(dir: &Directory, id: Uuid) -> ScimResult<Member> {
+    let not_found = || ScimError::not_found(format!("User {} not found", id));
+
+    let membership = organizations_service::find_membership(&**dir, dir.organization_id, id)
+        .await?
+        .ok_or_else(not_found)?;
+    let user = users::Entity::find_by_id(id)
+        .one(&**dir)
+        .await?
+        .filter(|user| !user.is_service_account())
+        .ok_or_else(not_found)?;
+    let provisioned = ProvisionedUsers::find_by_id((dir.organization_id, id))
+        .one(&**dir)
+        .await?;
+
+    Ok(Member {
+        user,
+        membership,
+        provisioned,
+    })
+}
+
+/// Write `changes`, and mark the user as known to the directory
+async fn apply(
+    state: &AppState,
+    dir: &Directory,
+    member: Member,
+    changes: UserChanges,
+) -> ScimResult<Member> {
+    let Member {
+        mut user,
+        membership,
+        provisioned,
+    } = member;
+
+    if let Some(user_name) = &changes.user_name {
+        if users_service::normalize_email(user_name) != user.email {
+            return Err(ScimError::mutability(
+                "userName is the account's email address and cannot be changed",
+            ));
+        }
+    }
+
+    if let Some(name) = changes.name().filter(|name| *name != user.name) {
+        let mut active: users::ActiveModel = user.into();
+        active.name = Set(name);
+        active.updated_at = Set(chrono::Utc::now().fixed_offset());
+        user = active.update(&**dir).await?;
+    }
+
+    if let Some(is_active) = changes.active.filter(|active| *active != user.is_active) {
+        if membership.role == OrgRole::Owner {
+            return Err(ScimError::forbidden(
+                "Owners cannot be deactivated by the directory",
+            ));
+        }
+        if belongs_elsewhere(state, dir.organization_id, user.id).await? {
+            return Err(ScimError::forbidden(
+                "The account also belongs to other organizations, so the directory cannot change whether it is active",
+            ));
+        }
+        user = set_active(&**dir, user, is_active).await?;
+    }
+
+    let now = chrono::Utc::now().fixed_offset();
+    let provisioned = match provisioned {
+        Some(provisioned) => match changes.external_id {
+            Some(external_id) if external_id != provisioned.external_id => {
+                let mut active: provisioned_user::ActiveModel = provisioned.into();
+                active.external_id = Set(external_id);
+                active.updated_at = Set(now);
+                active.update(&**dir).await?
+            }
+            _ => provisioned,
+        },
+        None => {
+            provisioned_user::ActiveModel {
+                organization_id: Set(dir.organization_id),
+                user_id: Set(user.id),
+                external_id: Set(changes.external_id.flatten()),
+                created_at: Set(now),
+                updated_at: Set(now),
+            }
+            .insert(&**dir)
+            .await?
+        }
+    };
+
+    Ok(Member {
+        user,
+        membership,
+        provisioned: Some(provisioned),
+    })
+}
+
+/// Turn the account on or off; turning it off also ends its sessions
+async fn set_active<C: ConnectionTrait>(
+    db: &C,
+    user: users::Model,
+    is_active: bool,
+) -> ScimResult<users::Model> {
+    let user_id = user.id;
+    let mut active: users::ActiveModel = user.into();
+    active.is_active = Set(is_active);
+    active.updated_at = Set(chrono::Utc::now().fixed_offset());
+    let user = active.update(db).await?;
+
+    if !is_active {
+        auth_service::revoke_all_for_user(db, user_id).await?;
+    }
+    Ok(user)
+}
+
+/// Whether the user is a member of another organization, whose rows the
+/// tenant transaction cannot see
+async fn belongs_elsewhere(
+    state: &AppState,
+    organization_id: Uuid,
+    user_id: Uuid,
+) -> ScimResult<bool> {
+    let count = Memberships::find()
+        .filter(membership::Column::UserId.eq(user_id))
+        .filter(membership::Column::OrganizationId.ne(organization_id))
+        .count(&state.db)
+        .await?;
+    Ok(count > 0)
+}
@@ -0,0 +1,119 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::error::AppResult;
+use crate::modules::auth::client::ClientInfo;
+use crate::modules::organizations::{
+    context::{Admin, OrgGuard},
+    membership::OrgRole,
+};
+use crate::state::AppState;
+
+use super::service::{self, ServiceAccount, ServiceAccountChanges};
+
+#[derive(serde::Deserialize)]
+pub struct CreateServiceAccountRequest {
+    pub name: String,
+    #[serde(default = "default_role")]
+    pub role: OrgRole,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateServiceAccountRequest {
+    pub name: Option<String>,
+    pub role: Option<OrgRole>,
+    pub is_active: Option<bool>,
+}
+
+fn default_role() -> OrgRole {
+    OrgRole::Member
+}
+
+#[derive(serde::Serialize)]
+pub struct ServiceAccountResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub role: OrgRole,
+    pub custom_role_id: Option<Uuid>,
+    pub is_active: bool,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+    pub updated_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl From<ServiceAccount> for ServiceAccountResponse {
+    fn from((user, membership): ServiceAccount) -> Self {
+        Self {
+            id: user.id,
+            name: user.name,
+            role: membership.role,
+            custom_role_id: membership.custom_role_id,
+            is_active: user.is_active,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+/// GET /api/orgs/:org_id/service-accounts
+pub async fn list_service_accounts(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+) -> AppResult<impl IntoResponse> {
+    let accounts = service::list(&state, org.org_id).await?;
+    let responses: Vec<ServiceAccountResponse> = accounts
+        .into_iter()
+        .map(ServiceAccountResponse::from)
+        .collect();
+    Ok(Json(responses))
+}
+
+/// POST /api/orgs/:org_id/service-accounts
+///
+/// The account can do nothing until it is given an API key.
+pub async fn create_service_account(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    client: ClientInfo,
+    Json(payload): Json<CreateServiceAccountRequest>,
+) -> AppResult<impl IntoResponse> {
+    let account = service::create(&state, &org, &client, &payload.name, payload.role).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(ServiceAccountResponse::from(account)),
+    ))
+}
+
+/// PATCH /api/orgs/:org_id/service-accounts/:service_account_id
+///
+/// Deactivating an account stops its API keys from working.
+pub async fn update_service_account(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    client: ClientInfo,
+    Path((_, service_account_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<UpdateServiceAccountRequest>,
+) -> AppResult<impl IntoResponse> {
+    let changes = ServiceAccountChanges {
+        name: payload.name,
+        role: payload.role,
+        is_active: payload.is_active,
+    };
+    let account = service::update(&state, &org, &client, service_account_id, changes).await?;
+    Ok(Json(ServiceAccountResponse::from(account)))
+}
+
+/// DELETE /api/orgs/:org_id/service-accounts/:service_account_id
+pub async fn delete_service_account(
+    State(state): State<AppState>,
+    org: OrgGuard<Admin>,
+    client: ClientInfo,
+    Path((_, service_account_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    service::delete(&state, &org, &client, service_account_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,27 @@
+use axum::{
+    routing::{get, patch},
+    Router,
+};
+
+use crate::modules::api_keys::routes::service_account_api_key_routes;
+use crate::state::AppState;
+
+use super::handler;
+
+/// An organization's service accounts, nested under
+/// `/api/orgs/{org_id}/service-accounts`
+pub fn org_service_account_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(handler::list_service_accounts).post(handler::create_service_account),
+        )
+        .route(
+            "/{service_account_id}",
+            patch(handler::update_service_account).delete(handler::delete_service_account),
+        )
+        .nest(
+            "/{service_account_id}/api-keys",
+            service_account_api_key_routes(),
+        )
+}
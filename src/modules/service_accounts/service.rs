@@ -0,0 +1,469 @@
+//! Service accounts: non-login principals owned by an organization
+//!
+//! A service account is a `users` row with `service_account_organization_id`
+//! set and a membership in that organization, so its role, custom role and
+//! permissions are checked like anyone else's. It has no password and a
+//! placeholder email address that lookups by email never match; it acts
+//! only through API keys, and is deleted with its organization.
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::audit::service::{self as audit, AuditEvent};
+use crate::modules::auth::client::ClientInfo;
+use crate::modules::organizations::{
+    context::OrgContext,
+    membership::{self, Entity as Memberships, OrgRole},
+    service as organizations_service,
+};
+use crate::modules::users::entity::{self as users, Entity as Users};
+use crate::state::AppState;
+
+/// Domain of service account email addresses; reserved, so no person can
+/// sign up with one
+pub const EMAIL_DOMAIN: &str = "service-accounts.invalid";
+
+/// Longest service account name
+const MAX_NAME_LEN: usize = 100;
+
+/// A service account with its membership in its organization
+pub type ServiceAccount = (users::Model, membership::Model);
+
+pub async fn list(state: &AppState, organization_id: Uuid) -> AppResult<Vec<ServiceAccount>> {
+    let rows = Memberships::find()
+        .filter(membership::Column::OrganizationId.eq(organization_id))
+        .find_also_related(Users)
+        .filter(users::Column::ServiceAccountOrganizationId.eq(organization_id))
+        .order_by_asc(membership::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(membership, user)| user.map(|u| (u, membership)))
+        .collect())
+}
+
+/// A service account of the organization, or `404`
+pub async fn find(
+    state: &AppState,
+    organization_id: Uuid,
+    service_account_id: Uuid,
+) -> AppResult<ServiceAccount> {
+    let user = Users::find_by_id(service_account_id)
+        .filter(users::Column::ServiceAccountOrganizationId.eq(organization_id))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?;
+    let membership = match &user {
+        Some(_) => {
+            organizations_service::find_membership(&state.db, organization_id, service_account_id)
+                .await?
+        }
+        None => None,
+    };
+
+    user.zip(membership).ok_or_else(|| {
+        AppError::NotFound(format!(
+            "Service account with id {} not found",
+            service_account_id
+        ))
+    })
+}
+
+fn validate_name(name: &str) -> AppResult<String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::ValidationError(format!(
+            "Name must be between 1 and {} characters",
+            MAX_NAME_LEN
+        )));
+    }
+
+    Ok(name.to_string())
+}
+
+/// Check that `actor` may give a service account `role`; like any member's,
+/// except that service accounts never own organizations
+fn ensure_can_grant(actor: &OrgContext, role: OrgRole) -> AppResult<()> {
+    organizations_service::ensure_can_grant(actor, role)?;
+    if role == OrgRole::Owner {
+        return Err(AppError::BadRequest(
+            "Service accounts cannot own organizations".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn create(
+    state: &AppState,
+    actor: &OrgContext,
+    client: &ClientInfo,
+    name: &str,
+    role: OrgRole,
+) -> AppResult<ServiceAccount> {
+    let name = validate_name(name)?;
+    ensure_can_grant(actor, role)?;
+
+    let id = Uuid::new_v4();
+    let now = chrono::Utc::now().fixed_offset();
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    let user = users::ActiveModel {
+        id: Set(id),
+        email: Set(format!("{}@{}", id, EMAIL_DOMAIN)),
+        name: Set(name),
+        password_hash: Set(None),
+        is_active: Set(true),
+        is_platform_admin: Set(false),
+        service_account_organization_id: Set(Some(actor.org_id)),
+        email_verified_at: Set(None),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(&txn)
+    .await
+    .map_err(AppError::from)?;
+    let membership = organizations_service::insert_membership(&txn, actor.org_id, id, role).await?;
+
+    audit::record(
+        &txn,
+        AuditEvent::new("service_account.created")
+            .actor(actor.user_id)
+            .organization(actor.org_id)
+            .target_service_account(id)
+            .ip_address(client.ip)
+            .details(serde_json::json!({ "name": user.name, "role": role })),
+    )
+    .await?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok((user, membership))
+}
+
+/// Changes to a service account; unset fields are left alone
+pub struct ServiceAccountChanges {
+    pub name: Option<String>,
+    pub role: Option<OrgRole>,
+    pub is_active: Option<bool>,
+}
+
+pub async fn update(
+    state: &AppState,
+    actor: &OrgContext,
+    client: &ClientInfo,
+    service_account_id: Uuid,
+    changes: ServiceAccountChanges,
+) -> AppResult<ServiceAccount> {
+    let (mut user, mut membership) = find(state, actor.org_id, service_account_id).await?;
+    let name = changes.name.as_deref().map(validate_name).transpose()?;
+    if let Some(role) = changes.role {
+        ensure_can_grant(actor, role)?;
+    }
+    if membership.role > actor.role {
+        return Err(AppError::Forbidden(
+            "You cannot modify members above your own role".to_string(),
+        ));
+    }
+
+    let now = chrono::Utc::now().fixed_offset();
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+
+    if name.is_some() || changes.is_active.is_some() {
+        let mut active: users::ActiveModel = user.into();
+        if let Some(name) = name {
+            active.name = Set(name);
+        }
+        if let Some(is_active) = changes.is_active {
+            active.is_active = Set(is_active);
+        }
+        active.updated_at = Set(now);
+        user = active.update(&txn).await.map_err(AppError::from)?;
+    }
+
+    if let Some(role) = changes.role {
+        let mut active: membership::ActiveModel = membership.into();
+        active.role = Set(role);
+        active.updated_at = Set(now);
+        membership = active.update(&txn).await.map_err(AppError::from)?;
+    }
+
+    audit::record(
+        &txn,
+        AuditEvent::new("service_account.updated")
+            .actor(actor.user_id)
+            .organization(actor.org_id)
+            .target_service_account(user.id)
+            .ip_address(client.ip)
+            .details(serde_json::json!({
+                "name": user.name,
+                "role": membership.role,
+                "is_active": user.is_active,
+            })),
+    )
+    .await?;
+
+    txn.commit().await.map_err(AppError::from)?;
+    Ok((user, membership))
+}
+
+/// Delete a service account along with its membership and API keys
+pub async fn delete(
+    state: &AppState,
+    actor: &OrgContext,
+    client: &ClientInfo,
+    service_account_id: Uuid,
+) -> AppResult<()> {
+    let (user, membership) = find(state, actor.org_id, service_account_id).await?;
+    organizations_service::ensure_can_grant(actor, membership.role)?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    Users::delete_by_id(user.id)
+        .exec(&txn)
+        .await
+        .map_err(AppError::from)?;
+
+    audit::record(
+        &txn,
+        AuditEvent::new("service_account.deleted")
+            .actor(actor.user_id)
+            .organization(actor.org_id)
+            .target_service_account(user.id)
+            .ip_address(client.ip)
+            .details(serde_json::json!({ "name": user.name })),
+    )
+    .await?;
+
+    txn.commit().await.map_err(AppError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{header, Method, StatusCode},
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::modules::api_keys::service::{self as api_keys, NewApiKey, Owner};
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_state, unique_email};
+
+    async fn create_organization(state: &AppState) -> OrgContext {
+        let owner = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Owner",
+        )
+        .await
+        .unwrap();
+        let name = format!("Robots {}", Uuid::new_v4().simple());
+        let (organization, _) =
+            organizations_service::create_organization(state, &owner, &name, None)
+                .await
+                .unwrap();
+        OrgContext {
+            org_id: organization.id,
+            user_id: owner.id,
+            role: OrgRole::Owner,
+            custom_role_id: None,
+        }
+    }
+
+    /// A service account of `actor`'s organization and a raw key for it
+    async fn create_with_key(state: &AppState, actor: &OrgContext) -> (users::Model, String) {
+        let (account, _) = create(
+            state,
+            actor,
+            &ClientInfo::default(),
+            "Deploy bot",
+            OrgRole::Member,
+        )
+        .await
+        .unwrap();
+        let (_, raw_key) = api_keys::create(
+            state,
+            Owner::ServiceAccount {
+                id: account.id,
+                created_by: actor.user_id,
+            },
+            NewApiKey {
+                name: "CI".to_string(),
+                scopes: vec!["orgs:read".to_string()],
+                expires_at: None,
+            },
+        )
+        .await
+        .unwrap();
+        (account, raw_key)
+    }
+
+    async fn status(state: &AppState, request: Request<Body>) -> StatusCode {
+        crate::app::rust_saas(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    async fn get_with_key(state: &AppState, uri: &str, raw_key: &str) -> StatusCode {
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", raw_key))
+            .body(Body::empty())
+            .unwrap();
+        status(state, request).await
+    }
+
+    #[tokio::test]
+    async fn cannot_sign_in_with_a_password() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let actor = create_organization(&state).await;
+        let (account, _) = create(
+            &state,
+            &actor,
+            &ClientInfo::default(),
+            "Bot",
+            OrgRole::Member,
+        )
+        .await
+        .unwrap();
+        assert!(account.is_service_account());
+        assert!(account.password_hash.is_none());
+        assert!(account.email.ends_with(EMAIL_DOMAIN));
+
+        // Its placeholder address matches no lookup, so nobody can sign in,
+        // reset a password or get a sign-in link as it
+        assert!(users_service::find_by_email(&state, &account.email)
+            .await
+            .unwrap()
+            .is_none());
+        let login = Request::builder()
+            .method(Method::POST)
+            .uri("/api/auth/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({ "email": account.email, "password": "anything at all" }).to_string(),
+            ))
+            .unwrap();
+        assert_eq!(status(&state, login).await, StatusCode::UNAUTHORIZED);
+
+        // and no person can sign up with one
+        let result = users_service::create_user(
+            &state,
+            &format!("someone@{}", EMAIL_DOMAIN),
+            "Someone",
+            "a long enough passphrase".to_string(),
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn keys_only_reach_the_owning_organization() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let actor = create_organization(&state).await;
+        let other = create_organization(&state).await;
+        let (_, raw_key) = create_with_key(&state, &actor).await;
+
+        let own = format!("/api/orgs/{}", actor.org_id);
+        assert_eq!(get_with_key(&state, &own, &raw_key).await, StatusCode::OK);
+        let theirs = format!("/api/orgs/{}", other.org_id);
+        assert_eq!(
+            get_with_key(&state, &theirs, &raw_key).await,
+            StatusCode::NOT_FOUND
+        );
+
+        // The account only exists within its organization
+        let (account, _) = create_with_key(&state, &actor).await;
+        let result = find(&state, other.org_id, account.id).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+        let result = delete(&state, &other, &ClientInfo::default(), account.id).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn deleting_or_disabling_the_account_stops_its_keys() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let actor = create_organization(&state).await;
+
+        let (account, raw_key) = create_with_key(&state, &actor).await;
+        let (_, user) = api_keys::authenticate(&state, &raw_key).await.unwrap();
+        assert_eq!(user.id, account.id);
+
+        let changes = ServiceAccountChanges {
+            name: None,
+            role: None,
+            is_active: Some(false),
+        };
+        update(&state, &actor, &ClientInfo::default(), account.id, changes)
+            .await
+            .unwrap();
+        let result = api_keys::authenticate(&state, &raw_key).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+
+        let (account, raw_key) = create_with_key(&state, &actor).await;
+        delete(&state, &actor, &ClientInfo::default(), account.id)
+            .await
+            .unwrap();
+        let result = api_keys::authenticate(&state, &raw_key).await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+        let keys = api_keys::list(
+            &state,
+            Owner::ServiceAccount {
+                id: account.id,
+                created_by: actor.user_id,
+            },
+        )
+        .await
+        .unwrap();
+        assert!(keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn service_accounts_never_own_organizations() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let actor = create_organization(&state).await;
+
+        let result = create(
+            &state,
+            &actor,
+            &ClientInfo::default(),
+            "Bot",
+            OrgRole::Owner,
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        let member = OrgContext {
+            role: OrgRole::Member,
+            ..actor.clone()
+        };
+        let result = create(
+            &state,
+            &member,
+            &ClientInfo::default(),
+            "Bot",
+            OrgRole::Member,
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::Forbidden(_))));
+    }
+}
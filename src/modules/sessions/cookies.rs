@@ -0,0 +1,68 @@
+//! The cookies a session lives in
+//!
+//! The session cookie is HttpOnly. Its companion CSRF cookie is readable by
+//! scripts so the web app can echo it in the `X-CSRF-Token` header of
+//! unsafe requests; the value is also returned in the sign-in response for
+//! apps served from another domain.
+
+use axum::http::HeaderMap;
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+
+use crate::config::{AppConfig, CookieSameSite};
+
+use super::service::NewSession;
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Cookies signing the browser in to a new session
+///
+/// They outlive idle periods; the server decides when the session ends.
+pub fn session_cookies(config: &AppConfig, session: &NewSession) -> CookieJar {
+    let max_age = time::Duration::seconds(config.session_absolute_ttl_secs);
+
+    let mut session_cookie = cookie(config, config.session_cookie_name.clone());
+    session_cookie.set_value(session.token.clone());
+    session_cookie.set_http_only(true);
+    session_cookie.set_max_age(max_age);
+
+    let mut csrf_cookie = cookie(config, CSRF_COOKIE.to_string());
+    csrf_cookie.set_value(session.csrf_token.clone());
+    csrf_cookie.set_max_age(max_age);
+
+    CookieJar::new().add(session_cookie).add(csrf_cookie)
+}
+
+/// Cookies signing the browser out
+pub fn cleared_cookies(config: &AppConfig) -> CookieJar {
+    let mut session_cookie = cookie(config, config.session_cookie_name.clone());
+    session_cookie.make_removal();
+    let mut csrf_cookie = cookie(config, CSRF_COOKIE.to_string());
+    csrf_cookie.make_removal();
+
+    CookieJar::new().add(session_cookie).add(csrf_cookie)
+}
+
+/// The raw session token a request carries, if any
+pub fn session_token(config: &AppConfig, headers: &HeaderMap) -> Option<String> {
+    CookieJar::from_headers(headers)
+        .get(&config.session_cookie_name)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// An empty cookie with the attributes configured for session cookies
+pub(crate) fn cookie(config: &AppConfig, name: String) -> Cookie<'static> {
+    let mut cookie = Cookie::new(name, "");
+    cookie.set_path("/");
+    cookie.set_secure(config.session_cookie_secure);
+    cookie.set_same_site(match config.session_cookie_same_site {
+        CookieSameSite::Strict => SameSite::Strict,
+        CookieSameSite::Lax => SameSite::Lax,
+        CookieSameSite::None => SameSite::None,
+    });
+    if let Some(domain) = &config.session_cookie_domain {
+        cookie.set_domain(domain.clone());
+    }
+    cookie
+}
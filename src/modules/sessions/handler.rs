@@ -0,0 +1,125 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::extractor::AuthUser;
+use crate::state::AppState;
+
+use super::cookies;
+use super::entity;
+use super::service::{self, NewSession};
+
+/// Returned by sign-in in session mode; the session itself is in cookies
+#[derive(serde::Serialize)]
+pub struct SessionResponse {
+    pub session_id: Uuid,
+    /// Echo in the `X-CSRF-Token` header of unsafe requests
+    pub csrf_token: String,
+    pub expires_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl From<&NewSession> for SessionResponse {
+    fn from(new: &NewSession) -> Self {
+        Self {
+            session_id: new.session.id,
+            csrf_token: new.csrf_token.clone(),
+            expires_at: new.session.expires_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ActiveSessionResponse {
+    pub id: Uuid,
+    /// e.g. `Firefox on Windows`
+    pub device: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    /// Whether this is the session making the request
+    pub current: bool,
+    pub last_seen_at: chrono::DateTime<chrono::FixedOffset>,
+    pub expires_at: chrono::DateTime<chrono::FixedOffset>,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl ActiveSessionResponse {
+    fn new(model: entity::Model, current: Option<Uuid>) -> Self {
+        Self {
+            device: service::describe_device(model.user_agent.as_deref()),
+            current: current == Some(model.id),
+            id: model.id,
+            user_agent: model.user_agent,
+            ip_address: model.ip_address,
+            last_seen_at: model.last_seen_at,
+            expires_at: model.expires_at,
+            created_at: model.created_at,
+        }
+    }
+}
+
+/// DELETE /api/auth/session
+///
+/// Signs out the session the request is made with and clears its cookies.
+pub async fn sign_out(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let session_id = auth.session_id.ok_or(AppError::BadRequest(
+        "Not signed in with a session".to_string(),
+    ))?;
+    service::revoke(&state, auth.user.id, session_id).await?;
+
+    Ok((
+        cookies::cleared_cookies(&state.config),
+        StatusCode::NO_CONTENT,
+    ))
+}
+
+/// GET /api/users/me/sessions
+pub async fn list_my_sessions(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let sessions = service::list(&state, auth.user.id).await?;
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| ActiveSessionResponse::new(session, auth.session_id))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// DELETE /api/users/me/sessions
+///
+/// Signs out every session but the one making the request.
+pub async fn revoke_other_sessions(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::revoke_others(&state, auth.user.id, auth.session_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/users/me/sessions/:session_id
+pub async fn revoke_my_session(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(session_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::revoke(&state, auth.user.id, session_id).await?;
+
+    let cookies = if auth.session_id == Some(session_id) {
+        cookies::cleared_cookies(&state.config)
+    } else {
+        CookieJar::new()
+    };
+    Ok((cookies, StatusCode::NO_CONTENT))
+}
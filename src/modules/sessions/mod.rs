@@ -0,0 +1,6 @@
+pub mod cookies;
+pub mod entity;
+pub mod handler;
+pub mod mode;
+pub mod routes;
+pub mod service;
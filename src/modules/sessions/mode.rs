@@ -0,0 +1,39 @@
+use std::convert::Infallible;
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+
+use crate::modules::auth::client::ClientInfo;
+use crate::state::AppState;
+
+/// Header sign-in requests choose the [`SignInMode`] with
+pub const AUTH_MODE_HEADER: &str = "x-auth-mode";
+
+/// How a sign-in hands over credentials
+///
+/// Clients get an access/refresh token pair unless they send
+/// `X-Auth-Mode: session`, which signs the browser in with a session cookie
+/// instead.
+#[derive(Clone, Debug)]
+pub enum SignInMode {
+    Tokens,
+    Session(ClientInfo),
+}
+
+impl FromRequestParts<AppState> for SignInMode {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Infallible> {
+        let session = parts
+            .headers
+            .get(AUTH_MODE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|mode| mode.trim().eq_ignore_ascii_case("session"));
+
+        if !session {
+            return Ok(SignInMode::Tokens);
+        }
+
+        let Ok(client) = ClientInfo::from_request_parts(parts, state).await;
+        Ok(SignInMode::Session(client))
+    }
+}
@@ -0,0 +1,23 @@
+use axum::{
+    routing::{delete, get},
+    Router,
+};
+
+use crate::state::AppState;
+
+use super::handler;
+
+/// Signing out a session, nested under `/api/auth/session`
+pub fn session_auth_routes() -> Router<AppState> {
+    Router::new().route("/", delete(handler::sign_out))
+}
+
+/// The caller's sessions, nested under `/api/users/me/sessions`
+pub fn user_session_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/",
+            get(handler::list_my_sessions).delete(handler::revoke_other_sessions),
+        )
+        .route("/{session_id}", delete(handler::revoke_my_session))
+}
@@ -0,0 +1,389 @@
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::client::ClientInfo;
+use crate::modules::auth::token::{generate_token, hash_token};
+use crate::modules::users::entity::{self as users, Entity as Users};
+use crate::state::AppState;
+
+use super::entity::{self, Entity as Sessions};
+
+/// How stale `last_seen_at` may get before a request slides the expiry, so
+/// busy sessions do not write on every call
+const ACTIVITY_RESOLUTION_SECS: i64 = 60;
+
+/// A session just signed in, with the raw values that are not stored
+pub struct NewSession {
+    pub session: entity::Model,
+    /// Goes in the session cookie
+    pub token: String,
+    /// Unsafe requests must echo it in `X-CSRF-Token`
+    pub csrf_token: String,
+}
+
+pub async fn create(state: &AppState, user_id: Uuid, client: &ClientInfo) -> AppResult<NewSession> {
+    // Sessions that lapsed are otherwise only removed when presented again
+    Sessions::delete_many()
+        .filter(entity::Column::UserId.eq(user_id))
+        .filter(entity::Column::ExpiresAt.lte(chrono::Utc::now().fixed_offset()))
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    let token = generate_token();
+    let csrf_token = generate_token();
+    let now = chrono::Utc::now().fixed_offset();
+    let ttl = state
+        .config
+        .session_idle_ttl_secs
+        .min(state.config.session_absolute_ttl_secs);
+
+    let session = entity::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        token_hash: Set(hash_token(&token)),
+        csrf_token_hash: Set(hash_token(&csrf_token)),
+        user_agent: Set(client.user_agent.clone()),
+        ip_address: Set(client.ip.map(|ip| ip.to_string())),
+        expires_at: Set(now + chrono::Duration::seconds(ttl)),
+        last_seen_at: Set(now),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(NewSession {
+        session,
+        token,
+        csrf_token,
+    })
+}
+
+/// Resolve a session cookie to its session and user, pushing back the
+/// session's expiry
+pub async fn authenticate(
+    state: &AppState,
+    raw_token: &str,
+) -> AppResult<(entity::Model, users::Model)> {
+    let session = Sessions::find()
+        .filter(entity::Column::TokenHash.eq(hash_token(raw_token)))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or(AppError::Unauthorized("Invalid session".to_string()))?;
+
+    let now = chrono::Utc::now();
+    if session.expires_at <= now {
+        Sessions::delete_by_id(session.id)
+            .exec(&state.db)
+            .await
+            .map_err(AppError::from)?;
+        return Err(AppError::Unauthorized("Session has expired".to_string()));
+    }
+
+    let user = Users::find_by_id(session.user_id)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .filter(|user| user.is_active)
+        .ok_or(AppError::Unauthorized(
+            "Account is not available".to_string(),
+        ))?;
+
+    if (now - session.last_seen_at.to_utc()).num_seconds() < ACTIVITY_RESOLUTION_SECS {
+        return Ok((session, user));
+    }
+
+    let config = &state.config;
+    let expires_at = (now + chrono::Duration::seconds(config.session_idle_ttl_secs)).min(
+        session.created_at.to_utc() + chrono::Duration::seconds(config.session_absolute_ttl_secs),
+    );
+    let mut active: entity::ActiveModel = session.into();
+    active.last_seen_at = Set(now.fixed_offset());
+    active.expires_at = Set(expires_at.fixed_offset());
+    let session = active.update(&state.db).await.map_err(AppError::from)?;
+
+    Ok((session, user))
+}
+
+/// Fail with Forbidden unless `csrf_token` is the session's
+pub fn verify_csrf(session: &entity::Model, csrf_token: Option<&str>) -> AppResult<()> {
+    match csrf_token {
+        Some(token) if hash_token(token) == session.csrf_token_hash => Ok(()),
+        _ => Err(AppError::Forbidden(
+            "Missing or invalid CSRF token".to_string(),
+        )),
+    }
+}
+
+/// The user's unexpired sessions, most recently active first
+pub async fn list(state: &AppState, user_id: Uuid) -> AppResult<Vec<entity::Model>> {
+    Sessions::find()
+        .filter(entity::Column::UserId.eq(user_id))
+        .filter(entity::Column::ExpiresAt.gt(chrono::Utc::now().fixed_offset()))
+        .order_by_desc(entity::Column::LastSeenAt)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+pub async fn revoke(state: &AppState, user_id: Uuid, session_id: Uuid) -> AppResult<()> {
+    let result = Sessions::delete_many()
+        .filter(entity::Column::Id.eq(session_id))
+        .filter(entity::Column::UserId.eq(user_id))
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    if result.rows_affected == 0 {
+        return Err(AppError::NotFound(format!(
+            "Session with id {} not found",
+            session_id
+        )));
+    }
+
+    Ok(())
+}
+
+/// End every session of the user except `keep`
+pub async fn revoke_others(state: &AppState, user_id: Uuid, keep: Option<Uuid>) -> AppResult<()> {
+    let mut delete = Sessions::delete_many().filter(entity::Column::UserId.eq(user_id));
+    if let Some(keep) = keep {
+        delete = delete.filter(entity::Column::Id.ne(keep));
+    }
+    delete.exec(&state.db).await.map_err(AppError::from)?;
+
+    Ok(())
+}
+
+/// End every session of the user
+pub async fn revoke_all_for_user<C: ConnectionTrait>(db: &C, user_id: Uuid) -> AppResult<()> {
+    Sessions::delete_many()
+        .filter(entity::Column::UserId.eq(user_id))
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+/// A short description of the device behind a user agent, e.g.
+/// `Firefox on Windows`
+pub fn describe_device(user_agent: Option<&str>) -> String {
+    let Some(user_agent) = user_agent.filter(|agent| !agent.trim().is_empty()) else {
+        return "Unknown device".to_string();
+    };
+
+    // Order matters: most user agents also claim to be the browsers they
+    // were derived from
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("FxiOS/", "Firefox"),
+        ("CriOS/", "Chrome"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ]
+    .into_iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| name);
+
+    let system = [
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Android", "Android"),
+        ("Windows", "Windows"),
+        ("CrOS", "ChromeOS"),
+        ("Mac OS X", "macOS"),
+        ("Linux", "Linux"),
+    ]
+    .into_iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| name);
+
+    match (browser, system) {
+        (Some(browser), Some(system)) => format!("{} on {}", browser, system),
+        (Some(browser), None) => browser.to_string(),
+        // Not a browser, e.g. `curl/8.5.0`
+        _ => user_agent
+            .split(['/', ' '])
+            .next()
+            .unwrap_or(user_agent)
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_state, unique_email};
+
+    fn session_with_csrf(csrf_token: &str) -> entity::Model {
+        let now = chrono::Utc::now().fixed_offset();
+        entity::Model {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            token_hash: hash_token("session"),
+            csrf_token_hash: hash_token(csrf_token),
+            user_agent: None,
+            ip_address: None,
+            expires_at: now,
+            last_seen_at: now,
+            created_at: now,
+        }
+    }
+
+    async fn create_session(state: &AppState) -> (users::Model, NewSession) {
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap();
+        let client = ClientInfo {
+            ip: Some("203.0.113.7".parse().unwrap()),
+            user_agent: Some("curl/8.5.0".to_string()),
+        };
+        let session = create(state, user.id, &client).await.unwrap();
+        (user, session)
+    }
+
+    #[test]
+    fn describes_common_devices() {
+        let cases = [
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) \
+                 Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0",
+                "Edge on Windows",
+            ),
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:121.0) Gecko/20100101 Firefox/121.0",
+                "Firefox on Windows",
+            ),
+            (
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 \
+                 (KHTML, like Gecko) Version/17.2 Safari/605.1.15",
+                "Safari on macOS",
+            ),
+            (
+                "Mozilla/5.0 (iPhone; CPU iPhone OS 17_2 like Mac OS X) AppleWebKit/605.1.15 \
+                 (KHTML, like Gecko) CriOS/120.0.6099.119 Mobile/15E148 Safari/604.1",
+                "Chrome on iOS",
+            ),
+            (
+                "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) \
+                 Chrome/120.0.0.0 Mobile Safari/537.36",
+                "Chrome on Android",
+            ),
+            (
+                "Mozilla/5.0 (X11; CrOS x86_64 14541.0.0) AppleWebKit/537.36 (KHTML, like Gecko) \
+                 Chrome/120.0.0.0 Safari/537.36 OPR/106.0.0.0",
+                "Opera on ChromeOS",
+            ),
+            ("curl/8.5.0", "curl"),
+            ("PostmanRuntime/7.36.0", "PostmanRuntime"),
+        ];
+
+        for (user_agent, expected) in cases {
+            assert_eq!(
+                describe_device(Some(user_agent)),
+                expected,
+                "{}",
+                user_agent
+            );
+        }
+        assert_eq!(describe_device(None), "Unknown device");
+        assert_eq!(describe_device(Some("  ")), "Unknown device");
+    }
+
+    #[test]
+    fn checks_csrf_token() {
+        let session = session_with_csrf("csrf");
+
+        assert!(verify_csrf(&session, Some("csrf")).is_ok());
+        for token in [None, Some(""), Some("other"), Some("CSRF")] {
+            assert!(matches!(
+                verify_csrf(&session, token),
+                Err(AppError::Forbidden(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticates_session_token() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (user, new) = create_session(&state).await;
+
+        let (session, found) = authenticate(&state, &new.token).await.unwrap();
+        assert_eq!(session.id, new.session.id);
+        assert_eq!(found.id, user.id);
+        assert_eq!(session.ip_address.as_deref(), Some("203.0.113.7"));
+        assert!(verify_csrf(&session, Some(&new.csrf_token)).is_ok());
+        // The CSRF token is not a session token
+        assert!(authenticate(&state, &new.csrf_token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn slides_expiry_up_to_absolute_lifetime() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, new) = create_session(&state).await;
+        let created_at = new.session.created_at;
+
+        // Active a while ago, close to the absolute limit
+        let absolute = state.config.session_absolute_ttl_secs;
+        let mut active: entity::ActiveModel = new.session.into();
+        active.created_at = Set(created_at - chrono::Duration::seconds(absolute - 10));
+        active.last_seen_at = Set(created_at - chrono::Duration::seconds(120));
+        active.update(&state.db).await.unwrap();
+
+        let (session, _) = authenticate(&state, &new.token).await.unwrap();
+        assert!(session.last_seen_at > created_at - chrono::Duration::seconds(60));
+        assert!(session.expires_at <= session.created_at + chrono::Duration::seconds(absolute));
+        assert!(session.expires_at < chrono::Utc::now() + chrono::Duration::seconds(60));
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_session_and_deletes_it() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (user, new) = create_session(&state).await;
+        let mut active: entity::ActiveModel = new.session.into();
+        active.expires_at = Set(chrono::Utc::now().fixed_offset());
+        active.update(&state.db).await.unwrap();
+
+        assert!(matches!(
+            authenticate(&state, &new.token).await,
+            Err(AppError::Unauthorized(_))
+        ));
+        assert!(list(&state, user.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn revokes_only_the_users_sessions() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (user, first) = create_session(&state).await;
+        let (other, theirs) = create_session(&state).await;
+
+        let not_theirs = revoke(&state, other.id, first.session.id).await;
+        assert!(matches!(not_theirs, Err(AppError::NotFound(_))));
+
+        revoke(&state, user.id, first.session.id).await.unwrap();
+        assert!(authenticate(&state, &first.token).await.is_err());
+        assert!(authenticate(&state, &theirs.token).await.is_ok());
+    }
+}
@@ -0,0 +1,72 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// What a single-use user token authorizes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(32))")]
+pub enum TokenPurpose {
+    /// Confirms a change of email address; the payload holds the new address
+    #[sea_orm(string_value = "email_change")]
+    EmailChange,
+    /// Verifies the address the account was registered with; the payload
+    /// holds that address so a later email change invalidates the token
+    #[sea_orm(string_value = "email_verification")]
+    EmailVerification,
+    /// Allows setting a new password without knowing the current one
+    #[sea_orm(string_value = "password_reset")]
+    PasswordReset,
+    /// Proves the password step of a login that still needs a second factor
+    #[sea_orm(string_value = "mfa_challenge")]
+    MfaChallenge,
+    /// Hands a SAML sign-in over from the assertion consumer service to the
+    /// app; the payload holds the organization id
+    #[sea_orm(string_value = "sso_login")]
+    SsoLogin,
+    /// Hands a member's link of their SAML account over from the assertion
+    /// consumer service to the app; the payload holds the asserted identity
+    #[sea_orm(string_value = "sso_link")]
+    SsoLink,
+    /// Lifts a lockout after too many failed sign-ins
+    #[sea_orm(string_value = "account_unlock")]
+    AccountUnlock,
+    /// Signs in without a password from the browser that asked for it; the
+    /// payload holds the hash of the nonce handed to that browser
+    #[sea_orm(string_value = "magic_link")]
+    MagicLink,
+}
+
+/// Single-use token mailed to a user, stored as a SHA-256 hash
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "user_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub purpose: TokenPurpose,
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub payload: Option<String>,
+    pub expires_at: DateTimeWithTimeZone,
+    pub consumed_at: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::modules::users::entity::Entity",
+        from = "Column::UserId",
+        to = "crate::modules::users::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<crate::modules::users::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
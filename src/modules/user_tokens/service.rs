@@ -0,0 +1,236 @@
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait,
+    EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::token;
+
+use super::entity::{self as user_tokens, Entity as UserTokens, TokenPurpose};
+
+/// Issue a single-use token, invalidating any outstanding token of the same
+/// purpose for the user
+///
+/// Returns the raw token; only its hash is stored.
+pub async fn issue<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    purpose: TokenPurpose,
+    payload: Option<String>,
+    ttl_secs: i64,
+) -> AppResult<String> {
+    UserTokens::delete_many()
+        .filter(user_tokens::Column::UserId.eq(user_id))
+        .filter(user_tokens::Column::Purpose.eq(purpose))
+        .filter(user_tokens::Column::ConsumedAt.is_null())
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    let raw_token = token::generate_token();
+    let now = chrono::Utc::now().fixed_offset();
+
+    user_tokens::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        purpose: Set(purpose),
+        token_hash: Set(token::hash_token(&raw_token)),
+        payload: Set(payload),
+        expires_at: Set(now + chrono::Duration::seconds(ttl_secs)),
+        consumed_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(db)
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(raw_token)
+}
+
+/// When the user's most recent token of this purpose was issued, if any
+pub async fn last_issued_at<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    purpose: TokenPurpose,
+) -> AppResult<Option<DateTimeWithTimeZone>> {
+    let token = UserTokens::find()
+        .filter(user_tokens::Column::UserId.eq(user_id))
+        .filter(user_tokens::Column::Purpose.eq(purpose))
+        .order_by_desc(user_tokens::Column::CreatedAt)
+        .one(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(token.map(|token| token.created_at))
+}
+
+/// Look up a token that is unexpired and unused without consuming it
+pub async fn find_valid<C: ConnectionTrait>(
+    db: &C,
+    purpose: TokenPurpose,
+    raw_token: &str,
+) -> AppResult<user_tokens::Model> {
+    UserTokens::find()
+        .filter(user_tokens::Column::TokenHash.eq(token::hash_token(raw_token)))
+        .filter(user_tokens::Column::Purpose.eq(purpose))
+        .filter(user_tokens::Column::ConsumedAt.is_null())
+        .filter(user_tokens::Column::ExpiresAt.gt(chrono::Utc::now().fixed_offset()))
+        .one(db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(invalid_token)
+}
+
+/// Consume a token, returning it if it was valid, unexpired and unused
+///
+/// The token is marked consumed with a conditional update so concurrent
+/// attempts to use it cannot both succeed.
+pub async fn consume<C: ConnectionTrait>(
+    db: &C,
+    purpose: TokenPurpose,
+    raw_token: &str,
+) -> AppResult<user_tokens::Model> {
+    let now = chrono::Utc::now().fixed_offset();
+
+    let token = UserTokens::find()
+        .filter(user_tokens::Column::TokenHash.eq(token::hash_token(raw_token)))
+        .filter(user_tokens::Column::Purpose.eq(purpose))
+        .one(db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(invalid_token)?;
+
+    let result = UserTokens::update_many()
+        .col_expr(user_tokens::Column::ConsumedAt, Expr::value(now))
+        .filter(user_tokens::Column::Id.eq(token.id))
+        .filter(user_tokens::Column::ConsumedAt.is_null())
+        .filter(user_tokens::Column::ExpiresAt.gt(now))
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+
+    if result.rows_affected == 0 {
+        return Err(invalid_token());
+    }
+
+    Ok(token)
+}
+
+fn invalid_token() -> AppError {
+    AppError::BadRequest("Invalid or expired token".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_db, unique_email};
+
+    async fn create_user<C: ConnectionTrait>(db: &C) -> Uuid {
+        users_service::create_passwordless_user_in(db, Uuid::new_v4(), &unique_email(), "Test")
+            .await
+            .unwrap()
+            .id
+    }
+
+    fn is_invalid(result: AppResult<user_tokens::Model>) -> bool {
+        matches!(result, Err(AppError::BadRequest(_)))
+    }
+
+    #[tokio::test]
+    async fn tokens_are_single_use_and_bound_to_their_purpose() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let user_id = create_user(&db).await;
+        let payload = Some("payload".to_string());
+        let raw = issue(&db, user_id, TokenPurpose::PasswordReset, payload, 600)
+            .await
+            .unwrap();
+
+        assert!(is_invalid(
+            consume(&db, TokenPurpose::EmailVerification, &raw).await
+        ));
+        let found = find_valid(&db, TokenPurpose::PasswordReset, &raw)
+            .await
+            .unwrap();
+        assert_eq!(found.user_id, user_id);
+        assert_eq!(found.token_hash, token::hash_token(&raw));
+
+        let consumed = consume(&db, TokenPurpose::PasswordReset, &raw)
+            .await
+            .unwrap();
+        assert_eq!(consumed.id, found.id);
+        assert_eq!(consumed.payload.as_deref(), Some("payload"));
+
+        assert!(is_invalid(
+            consume(&db, TokenPurpose::PasswordReset, &raw).await
+        ));
+        assert!(is_invalid(
+            find_valid(&db, TokenPurpose::PasswordReset, &raw).await
+        ));
+        assert!(is_invalid(
+            consume(&db, TokenPurpose::PasswordReset, "made-up").await
+        ));
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_refused() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let user_id = create_user(&db).await;
+        let raw = issue(&db, user_id, TokenPurpose::EmailVerification, None, -1)
+            .await
+            .unwrap();
+
+        assert!(is_invalid(
+            find_valid(&db, TokenPurpose::EmailVerification, &raw).await
+        ));
+        assert!(is_invalid(
+            consume(&db, TokenPurpose::EmailVerification, &raw).await
+        ));
+    }
+
+    #[tokio::test]
+    async fn issuing_replaces_the_outstanding_token() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let user_id = create_user(&db).await;
+        let purpose = TokenPurpose::EmailVerification;
+
+        let first = issue(&db, user_id, purpose, None, 600).await.unwrap();
+        let first_issued = last_issued_at(&db, user_id, purpose).await.unwrap();
+        let second = issue(&db, user_id, purpose, None, 600).await.unwrap();
+        assert!(is_invalid(consume(&db, purpose, &first).await));
+        assert!(last_issued_at(&db, user_id, purpose).await.unwrap() > first_issued);
+
+        // Tokens of other purposes are left alone
+        let reset = issue(&db, user_id, TokenPurpose::PasswordReset, None, 600)
+            .await
+            .unwrap();
+        consume(&db, purpose, &second).await.unwrap();
+        consume(&db, TokenPurpose::PasswordReset, &reset)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_consumers_cannot_both_succeed() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let user_id = create_user(&db).await;
+        let raw = issue(&db, user_id, TokenPurpose::PasswordReset, None, 600)
+            .await
+            .unwrap();
+
+        let (a, b) = tokio::join!(
+            consume(&db, TokenPurpose::PasswordReset, &raw),
+            consume(&db, TokenPurpose::PasswordReset, &raw)
+        );
+        assert_eq!([a.is_ok(), b.is_ok()].iter().filter(|ok| **ok).count(), 1);
+    }
+}
@@ -9,9 +9,16 @@ pub struct Model {
     #[sea_orm(unique)]
     pub email: String,
     pub name: String,
+    /// `None` for passkey-only accounts
     #[serde(skip_serializing)]
-    pub password_hash: String,
+    pub password_hash: Option<String>,
     pub is_active: bool,
+    /// Staff of the deployment, allowed to impersonate other users
+    pub is_platform_admin: bool,
+    /// Set for service accounts: non-login principals owned by this
+    /// organization and used through API keys
+    pub service_account_organization_id: Option<Uuid>,
+    pub email_verified_at: Option<DateTimeWithTimeZone>,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: DateTimeWithTimeZone,
 }
@@ -19,4 +26,10 @@ pub struct Model {
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
+impl Model {
+    pub fn is_service_account(&self) -> bool {
+        self.service_account_organization_id.is_some()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
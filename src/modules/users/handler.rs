@@ -4,13 +4,25 @@ use axum::{
     response::IntoResponse,
     Json,
 };
-use sea_orm::{ActiveModelTrait, EntityTrait, Set};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, Set, TransactionTrait,
+};
 use uuid::Uuid;
 
 use crate::error::{AppError, AppResult};
+use crate::modules::auth::{extractor::AuthUser, service as auth_service};
+use crate::modules::organizations::{
+    membership::{self, Entity as Memberships, OrgRole},
+    service as organizations_service,
+};
+use crate::modules::permissions::{
+    guard::{require_owner_or, Authorized, Permissions, ReadUsers, WriteUsers},
+    permission::Permission,
+};
 use crate::state::AppState;
 
 use super::entity::{self, Entity as Users};
+use super::service;
 
 #[derive(serde::Deserialize)]
 pub struct CreateUserRequest {
@@ -21,17 +33,38 @@ pub struct CreateUserRequest {
 
 #[derive(serde::Deserialize)]
 pub struct UpdateUserRequest {
-    pub email: Option<String>,
     pub name: Option<String>,
     pub is_active: Option<bool>,
 }
 
+#[derive(serde::Deserialize)]
+pub struct UpdateMeRequest {
+    pub name: Option<String>,
+    pub current_password: Option<String>,
+    pub new_password: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeRequest {
+    pub email: String,
+    pub current_password: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmEmailChangeRequest {
+    pub token: String,
+}
+
 #[derive(serde::Serialize)]
 pub struct UserResponse {
     pub id: Uuid,
     pub email: String,
     pub name: String,
     pub is_active: bool,
+    pub is_platform_admin: bool,
+    /// `false` for passkey-only accounts
+    pub has_password: bool,
+    pub email_verified_at: Option<chrono::DateTime<chrono::FixedOffset>>,
     pub created_at: chrono::DateTime<chrono::FixedOffset>,
     pub updated_at: chrono::DateTime<chrono::FixedOffset>,
 }
@@ -43,6 +76,9 @@ impl From<entity::Model> for UserResponse {
             email: model.email,
             name: model.name,
             is_active: model.is_active,
+            is_platform_admin: model.is_platform_admin,
+            has_password: model.password_hash.is_some(),
+            email_verified_at: model.email_verified_at,
             created_at: model.created_at,
             updated_at: model.updated_at,
         }
@@ -50,80 +86,279 @@ impl From<entity::Model> for UserResponse {
 }
 
 /// POST /api/users
+///
+/// Creates the account as a member of the active organization.
 pub async fn create_user(
     State(state): State<AppState>,
+    permissions: Authorized<WriteUsers>,
     Json(payload): Json<CreateUserRequest>,
 ) -> AppResult<impl IntoResponse> {
-    // TODO: Hash password with bcrypt/argon2 before storing
-    let user = entity::ActiveModel {
-        id: Set(Uuid::new_v4()),
-        email: Set(payload.email),
-        name: Set(payload.name),
-        password_hash: Set(payload.password),
-        is_active: Set(true),
-        created_at: Set(chrono::Utc::now().fixed_offset()),
-        updated_at: Set(chrono::Utc::now().fixed_offset()),
-    };
+    let org_id = permissions.org.org_id;
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let user = service::create_user_in(
+        &state,
+        &txn,
+        &payload.email,
+        &payload.name,
+        payload.password,
+        Some(org_id),
+    )
+    .await?;
+    organizations_service::insert_membership(&txn, org_id, user.id, OrgRole::Member).await?;
+    txn.commit().await.map_err(AppError::from)?;
 
-    let user = user.insert(&state.db).await.map_err(AppError::from)?;
     Ok((StatusCode::CREATED, Json(UserResponse::from(user))))
 }
 
 /// GET /api/users
-pub async fn list_users(State(state): State<AppState>) -> AppResult<impl IntoResponse> {
-    let users = Users::find().all(&state.db).await.map_err(AppError::from)?;
-    let responses: Vec<UserResponse> = users.into_iter().map(UserResponse::from).collect();
+///
+/// Members of the active organization.
+pub async fn list_users(
+    State(state): State<AppState>,
+    permissions: Authorized<ReadUsers>,
+) -> AppResult<impl IntoResponse> {
+    let members = organizations_service::list_members(&state.db, permissions.org.org_id).await?;
+    let responses: Vec<UserResponse> = members
+        .into_iter()
+        .map(|(_, user)| UserResponse::from(user))
+        .collect();
     Ok(Json(responses))
 }
 
+/// GET /api/users/me
+pub async fn get_me(auth: AuthUser) -> AppResult<impl IntoResponse> {
+    Ok(Json(UserResponse::from(auth.user)))
+}
+
+/// PATCH /api/users/me
+///
+/// Changing the password requires `current_password` and signs out every
+/// other session.
+pub async fn update_me(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<UpdateMeRequest>,
+) -> AppResult<impl IntoResponse> {
+    if payload.new_password.is_some() {
+        auth.forbid_impersonation()?;
+    }
+    let mut user = auth.user;
+
+    if let Some(new_password) = payload.new_password {
+        let current_password = payload.current_password.ok_or(AppError::BadRequest(
+            "current_password is required to change the password".to_string(),
+        ))?;
+        user = service::change_password(&state, user, &current_password, new_password).await?;
+    }
+
+    if let Some(name) = payload.name {
+        let mut active: entity::ActiveModel = user.into();
+        active.name = Set(name.trim().to_string());
+        active.updated_at = Set(chrono::Utc::now().fixed_offset());
+        user = active.update(&state.db).await.map_err(AppError::from)?;
+    }
+
+    Ok(Json(UserResponse::from(user)))
+}
+
+/// POST /api/users/me/email
+///
+/// Sends a confirmation token to the new address; the email is not changed
+/// until the token is confirmed.
+pub async fn request_email_change(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<EmailChangeRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::request_email_change(&state, auth.user, &payload.email, &payload.current_password)
+        .await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// POST /api/users/me/email/confirm
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<ConfirmEmailChangeRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let user = service::confirm_email_change(&state, auth.user, &payload.token).await?;
+    Ok(Json(UserResponse::from(user)))
+}
+
+/// The user behind `id`, who must be the caller or a member of the active
+/// organization
+///
+/// Users outside the organization get `404`, as if they did not exist.
+async fn find_visible_user(
+    state: &AppState,
+    auth: &AuthUser,
+    permissions: Option<&Permissions>,
+    id: Uuid,
+) -> AppResult<(entity::Model, Option<membership::Model>)> {
+    let membership = match permissions {
+        Some(permissions) => {
+            organizations_service::find_membership(&state.db, permissions.org.org_id, id).await?
+        }
+        None => None,
+    };
+    if auth.user.id != id && membership.is_none() {
+        return Err(AppError::NotFound(format!("User with id {} not found", id)));
+    }
+
+    let user = service::find_by_id(state, id).await?;
+    Ok((user, membership))
+}
+
+/// Members holding `users:write` can only act on members below their own
+/// role
+fn ensure_outranks(actor: &Permissions, target: &membership::Model) -> AppResult<()> {
+    if target.role >= actor.org.role {
+        return Err(AppError::Forbidden(
+            "You can only modify members below your own role".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether the account is active is shared by every organization it belongs
+/// to, so one organization may only change it for accounts that are its
+/// alone, and never for its owner
+async fn ensure_can_set_active(
+    state: &AppState,
+    actor: &Permissions,
+    user_id: Uuid,
+    membership: Option<&membership::Model>,
+) -> AppResult<()> {
+    if membership.is_some_and(|membership| membership.role == OrgRole::Owner) {
+        return Err(AppError::Forbidden(
+            "The organization's owner cannot be deactivated".to_string(),
+        ));
+    }
+
+    let elsewhere = Memberships::find()
+        .filter(membership::Column::UserId.eq(user_id))
+        .filter(membership::Column::OrganizationId.ne(actor.org.org_id))
+        .count(&state.db)
+        .await
+        .map_err(AppError::from)?;
+    if elsewhere > 0 {
+        return Err(AppError::Forbidden(
+            "The user belongs to other organizations; remove them from this one instead"
+                .to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
 /// GET /api/users/:id
+///
+/// Anyone may read their own account; other members need `users:read`.
 pub async fn get_user(
     State(state): State<AppState>,
+    auth: AuthUser,
+    permissions: Option<Permissions>,
     Path(id): Path<Uuid>,
 ) -> AppResult<impl IntoResponse> {
-    let user = Users::find_by_id(id)
-        .one(&state.db)
-        .await
-        .map_err(AppError::from)?
-        .ok_or(AppError::NotFound(format!("User with id {} not found", id)))?;
+    require_owner_or(&auth, permissions.as_ref(), Permission::UsersRead, id)?;
+    let (user, _) = find_visible_user(&state, &auth, permissions.as_ref(), id).await?;
 
     Ok(Json(UserResponse::from(user)))
 }
 
 /// PUT /api/users/:id
+///
+/// Anyone may rename their own account. Changing the active flag, or
+/// anything about another member, needs `users:write`, and members at or
+/// above the caller's role are off limits. Owners and accounts that belong
+/// to other organizations cannot be deactivated from here. Email addresses
+/// only change through `POST /api/users/me/email`, which the new address
+/// must confirm.
 pub async fn update_user(
     State(state): State<AppState>,
+    auth: AuthUser,
+    permissions: Option<Permissions>,
     Path(id): Path<Uuid>,
     Json(payload): Json<UpdateUserRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let user = Users::find_by_id(id)
-        .one(&state.db)
-        .await
-        .map_err(AppError::from)?
-        .ok_or(AppError::NotFound(format!("User with id {} not found", id)))?;
+    require_owner_or(&auth, permissions.as_ref(), Permission::UsersWrite, id)?;
+    if payload.is_active.is_some() {
+        permissions
+            .as_ref()
+            .ok_or(AppError::MissingPermission {
+                permission: Permission::UsersWrite.as_str(),
+            })?
+            .require(Permission::UsersWrite)?;
+    }
+
+    let (user, membership) = find_visible_user(&state, &auth, permissions.as_ref(), id).await?;
+    if let (Some(permissions), Some(membership)) = (&permissions, &membership) {
+        if auth.user.id != id {
+            ensure_outranks(permissions, membership)?;
+        }
+    }
+    let deactivated = payload.is_active == Some(false) && user.is_active;
+    if let (Some(permissions), Some(is_active)) = (&permissions, payload.is_active) {
+        if is_active != user.is_active {
+            ensure_can_set_active(&state, permissions, id, membership.as_ref()).await?;
+        }
+    }
 
     let mut user: entity::ActiveModel = user.into();
 
-    if let Some(email) = payload.email {
-        user.email = Set(email);
-    }
     if let Some(name) = payload.name {
-        user.name = Set(name);
+        user.name = Set(name.trim().to_string());
     }
     if let Some(is_active) = payload.is_active {
         user.is_active = Set(is_active);
     }
     user.updated_at = Set(chrono::Utc::now().fixed_offset());
 
-    let user = user.update(&state.db).await.map_err(AppError::from)?;
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let user = user.update(&txn).await.map_err(AppError::from)?;
+    if deactivated {
+        auth_service::revoke_all_for_user(&txn, user.id).await?;
+    }
+    txn.commit().await.map_err(AppError::from)?;
+
     Ok(Json(UserResponse::from(user)))
 }
 
 /// DELETE /api/users/:id
+///
+/// Needs `users:write`. Accounts that belong to other organizations as well
+/// cannot be deleted from here; remove them from this one instead.
 pub async fn delete_user(
     State(state): State<AppState>,
+    auth: AuthUser,
+    permissions: Authorized<WriteUsers>,
     Path(id): Path<Uuid>,
 ) -> AppResult<impl IntoResponse> {
+    let (_, membership) = find_visible_user(&state, &auth, Some(&permissions), id).await?;
+    let membership =
+        membership.ok_or_else(|| AppError::NotFound(format!("User with id {} not found", id)))?;
+    ensure_outranks(&permissions, &membership)?;
+    if membership.role == OrgRole::Owner {
+        return Err(AppError::Forbidden(
+            "Owners must transfer ownership before their account is deleted".to_string(),
+        ));
+    }
+
+    let organizations = Memberships::find()
+        .filter(membership::Column::UserId.eq(id))
+        .count(&state.db)
+        .await
+        .map_err(AppError::from)?;
+    if organizations > 1 {
+        return Err(AppError::Forbidden(
+            "The user belongs to other organizations; remove them from this one instead"
+                .to_string(),
+        ));
+    }
+
     let result = Users::delete_by_id(id)
         .exec(&state.db)
         .await
@@ -135,3 +370,298 @@ pub async fn delete_user(
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Request, http::header, http::Method};
+    use sea_orm::DatabaseConnection;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::modules::auth::jwt::{self, Claims};
+    use crate::modules::organizations::context::ORG_ID_HEADER;
+    use crate::test_support::{test_config, test_state, unique_email};
+
+    /// An organization with an owner, an admin and two members
+    struct Org {
+        id: Uuid,
+        owner: entity::Model,
+        admin: entity::Model,
+        member: entity::Model,
+        other_member: entity::Model,
+    }
+
+    async fn create_user(state: &AppState) -> entity::Model {
+        let user = service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap();
+        service::mark_email_verified(&state.db, user).await.unwrap()
+    }
+
+    async fn join(state: &AppState, org_id: Uuid, role: OrgRole) -> entity::Model {
+        let user = create_user(state).await;
+        organizations_service::insert_membership(&state.db, org_id, user.id, role)
+            .await
+            .unwrap();
+        user
+    }
+
+    async fn create_org(state: &AppState) -> Org {
+        let owner = create_user(state).await;
+        let name = format!("Users {}", Uuid::new_v4().simple());
+        let (organization, _) =
+            organizations_service::create_organization(state, &owner, &name, None)
+                .await
+                .unwrap();
+        let id = organization.id;
+        Org {
+            id,
+            owner,
+            admin: join(state, id, OrgRole::Admin).await,
+            member: join(state, id, OrgRole::Member).await,
+            other_member: join(state, id, OrgRole::Member).await,
+        }
+    }
+
+    /// `method uri` as `user` within organization `org_id`
+    async fn call(
+        state: &AppState,
+        user: &entity::Model,
+        org_id: Uuid,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let token =
+            jwt::encode_claims(&state.config, &Claims::new(&state.config, user.id)).unwrap();
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(ORG_ID_HEADER, org_id.to_string());
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+
+        let response = crate::app::rust_saas(state.clone())
+            .oneshot(request.unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    async fn put(
+        state: &AppState,
+        actor: &entity::Model,
+        org: &Org,
+        target: &entity::Model,
+        body: Value,
+    ) -> StatusCode {
+        let uri = format!("/api/users/{}", target.id);
+        call(state, actor, org.id, Method::PUT, &uri, Some(body))
+            .await
+            .0
+    }
+
+    async fn delete(
+        state: &AppState,
+        actor: &entity::Model,
+        org: &Org,
+        target: &entity::Model,
+    ) -> StatusCode {
+        let uri = format!("/api/users/{}", target.id);
+        call(state, actor, org.id, Method::DELETE, &uri, None)
+            .await
+            .0
+    }
+
+    #[tokio::test]
+    async fn members_rename_only_themselves() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let org = create_org(&state).await;
+        let rename = json!({ "name": "  Renamed  " });
+
+        let uri = format!("/api/users/{}", org.member.id);
+        let (status, body) = call(
+            &state,
+            &org.member,
+            org.id,
+            Method::PUT,
+            &uri,
+            Some(rename.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "Renamed");
+
+        assert_eq!(
+            put(&state, &org.member, &org, &org.other_member, rename).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            put(
+                &state,
+                &org.member,
+                &org,
+                &org.member,
+                json!({ "is_active": false })
+            )
+            .await,
+            StatusCode::FORBIDDEN
+        );
+
+        let (status, _) = call(&state, &org.member, org.id, Method::GET, "/api/users", None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn admins_only_modify_members_below_them() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let org = create_org(&state).await;
+        let other_admin = join(&state, org.id, OrgRole::Admin).await;
+        let outsider = create_user(&state).await;
+        let rename = json!({ "name": "Renamed" });
+
+        assert_eq!(
+            put(&state, &org.admin, &org, &org.member, rename.clone()).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            put(&state, &org.admin, &org, &other_admin, rename.clone()).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            put(&state, &org.admin, &org, &org.owner, rename.clone()).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            put(&state, &org.admin, &org, &outsider, rename).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn deactivation_is_limited_to_accounts_the_organization_owns() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let org = create_org(&state).await;
+        let deactivate = json!({ "is_active": false });
+
+        let tokens = auth_service::issue_tokens(&state, &org.member)
+            .await
+            .unwrap();
+        let uri = format!("/api/users/{}", org.member.id);
+        let (status, body) = call(
+            &state,
+            &org.admin,
+            org.id,
+            Method::PUT,
+            &uri,
+            Some(deactivate.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["is_active"], false);
+        let refreshed = auth_service::rotate_refresh_token(&state, &tokens.refresh_token).await;
+        assert!(matches!(refreshed, Err(AppError::Unauthorized(_))));
+
+        // The owner, even by themselves
+        assert_eq!(
+            put(&state, &org.owner, &org, &org.owner, deactivate.clone()).await,
+            StatusCode::FORBIDDEN
+        );
+
+        // Accounts that other organizations share
+        let elsewhere = create_org(&state).await;
+        organizations_service::insert_membership(
+            &state.db,
+            elsewhere.id,
+            org.other_member.id,
+            OrgRole::Member,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            put(&state, &org.admin, &org, &org.other_member, deactivate).await,
+            StatusCode::FORBIDDEN
+        );
+        let shared = service::find_by_id(&state, org.other_member.id)
+            .await
+            .unwrap();
+        assert!(shared.is_active);
+    }
+
+    #[tokio::test]
+    async fn deleting_is_limited_to_accounts_the_organization_owns() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let org = create_org(&state).await;
+
+        assert_eq!(
+            delete(&state, &org.member, &org, &org.other_member).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            delete(&state, &org.admin, &org, &org.owner).await,
+            StatusCode::FORBIDDEN
+        );
+
+        let elsewhere = create_org(&state).await;
+        organizations_service::insert_membership(
+            &state.db,
+            elsewhere.id,
+            org.other_member.id,
+            OrgRole::Member,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            delete(&state, &org.admin, &org, &org.other_member).await,
+            StatusCode::FORBIDDEN
+        );
+
+        assert_eq!(
+            delete(&state, &org.admin, &org, &org.member).await,
+            StatusCode::NO_CONTENT
+        );
+        let result = service::find_by_id(&state, org.member.id).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn users_routes_need_a_signed_in_caller() {
+        let state = AppState::new(DatabaseConnection::Disconnected, test_config());
+
+        for (method, uri) in [(Method::GET, "/api/users/me"), (Method::GET, "/api/users")] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let response = crate::app::rust_saas(state.clone())
+                .oneshot(request)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+    }
+}
@@ -1,3 +1,5 @@
 pub mod entity;
 pub mod handler;
+pub mod password;
 pub mod routes;
+pub mod service;
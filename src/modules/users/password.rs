@@ -0,0 +1,276 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, SaltString},
+    Algorithm, Argon2, Params, PasswordHasher as _, PasswordVerifier as _, Version,
+};
+use sea_orm::{ActiveModelTrait, Set};
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::state::AppState;
+
+use super::entity;
+
+/// Outcome of checking a password against a stored hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordVerification {
+    /// The password does not match the hash
+    Invalid,
+    /// The password matches and the hash uses the current parameters
+    Valid,
+    /// The password matches but the hash was produced with outdated parameters
+    /// and should be replaced with a fresh one
+    ValidNeedsRehash,
+}
+
+/// Argon2id password hasher configured from `AppConfig`
+///
+/// Hashing is CPU and memory intensive, so the async methods run it on the
+/// blocking thread pool instead of stalling the runtime.
+#[derive(Clone, Debug)]
+pub struct PasswordHasher {
+    params: Params,
+}
+
+impl PasswordHasher {
+    pub fn from_config(config: &AppConfig) -> Result<Self, argon2::Error> {
+        let params = Params::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+            None,
+        )?;
+
+        Ok(Self { params })
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    /// Hash a password into a PHC string (`$argon2id$v=19$m=...`)
+    pub fn hash(&self, password: &str) -> AppResult<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.argon2()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AppError::internal(format!("Failed to hash password: {}", e)))
+    }
+
+    /// Verify a password against a stored PHC string
+    ///
+    /// Hashes produced by another algorithm, version or parameter set still
+    /// verify, but are reported as [`PasswordVerification::ValidNeedsRehash`].
+    pub fn verify(&self, password: &str, hash: &str) -> AppResult<PasswordVerification> {
+        let parsed = PasswordHash::new(hash)
+            .map_err(|e| AppError::internal(format!("Invalid stored password hash: {}", e)))?;
+
+        match self.argon2().verify_password(password.as_bytes(), &parsed) {
+            Ok(()) if self.is_current(&parsed) => Ok(PasswordVerification::Valid),
+            Ok(()) => Ok(PasswordVerification::ValidNeedsRehash),
+            Err(argon2::password_hash::Error::Password) => Ok(PasswordVerification::Invalid),
+            Err(e) => Err(AppError::internal(format!(
+                "Failed to verify password: {}",
+                e
+            ))),
+        }
+    }
+
+    fn is_current(&self, parsed: &PasswordHash<'_>) -> bool {
+        if parsed.algorithm != Algorithm::Argon2id.ident()
+            || parsed.version != Some(Version::V0x13.into())
+        {
+            return false;
+        }
+
+        match Params::try_from(parsed) {
+            Ok(params) => {
+                params.m_cost() == self.params.m_cost()
+                    && params.t_cost() == self.params.t_cost()
+                    && params.p_cost() == self.params.p_cost()
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Hash a password on the blocking thread pool
+    pub async fn hash_async(&self, password: String) -> AppResult<String> {
+        let hasher = self.clone();
+        tokio::task::spawn_blocking(move || hasher.hash(&password))
+            .await
+            .map_err(|e| AppError::internal(format!("Password hashing task failed: {}", e)))?
+    }
+
+    /// Verify a password on the blocking thread pool
+    pub async fn verify_async(
+        &self,
+        password: String,
+        hash: String,
+    ) -> AppResult<PasswordVerification> {
+        let hasher = self.clone();
+        tokio::task::spawn_blocking(move || hasher.verify(&password, &hash))
+            .await
+            .map_err(|e| AppError::internal(format!("Password verification task failed: {}", e)))?
+    }
+}
+
+/// Check a user's password, upgrading the stored hash when the configured
+/// Argon2 parameters have changed since it was created
+///
+/// Returns the (possibly updated) user when the password is correct and
+/// `None` when it is not or the account has no password.
+pub async fn verify_user_password(
+    state: &AppState,
+    user: entity::Model,
+    password: &str,
+) -> AppResult<Option<entity::Model>> {
+    let Some(password_hash) = user.password_hash.clone() else {
+        // Passkey-only account; take as long as a real check anyway
+        state
+            .password_hasher
+            .hash_async(password.to_string())
+            .await?;
+        return Ok(None);
+    };
+
+    let verification = state
+        .password_hasher
+        .verify_async(password.to_string(), password_hash)
+        .await?;
+
+    match verification {
+        PasswordVerification::Invalid => Ok(None),
+        PasswordVerification::Valid => Ok(Some(user)),
+        PasswordVerification::ValidNeedsRehash => {
+            let new_hash = state
+                .password_hasher
+                .hash_async(password.to_string())
+                .await?;
+
+            let mut active: entity::ActiveModel = user.into();
+            active.password_hash = Set(Some(new_hash));
+            active.updated_at = Set(chrono::Utc::now().fixed_offset());
+
+            let user = active.update(&state.db).await.map_err(AppError::from)?;
+            tracing::info!("Upgraded password hash parameters for user {}", user.id);
+            Ok(Some(user))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::modules::users::service as users_service;
+    use crate::test_support::{test_config, test_state, unique_email};
+
+    fn hasher(memory_kib: u32, iterations: u32) -> PasswordHasher {
+        let mut config = test_config();
+        config.argon2_memory_kib = memory_kib;
+        config.argon2_iterations = iterations;
+        PasswordHasher::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn verifies_its_own_hashes() {
+        let hasher = hasher(1024, 1);
+        let hash = hasher.hash("correct horse").unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert_eq!(
+            hasher.verify("correct horse", &hash).unwrap(),
+            PasswordVerification::Valid
+        );
+        assert_eq!(
+            hasher.verify("wrong horse", &hash).unwrap(),
+            PasswordVerification::Invalid
+        );
+    }
+
+    #[test]
+    fn salts_every_hash() {
+        let hasher = hasher(1024, 1);
+
+        assert_ne!(
+            hasher.hash("correct horse").unwrap(),
+            hasher.hash("correct horse").unwrap()
+        );
+    }
+
+    #[test]
+    fn asks_for_rehash_when_parameters_change() {
+        let hash = hasher(1024, 1).hash("correct horse").unwrap();
+
+        for upgraded in [hasher(2048, 1), hasher(1024, 2)] {
+            assert_eq!(
+                upgraded.verify("correct horse", &hash).unwrap(),
+                PasswordVerification::ValidNeedsRehash
+            );
+            assert_eq!(
+                upgraded.verify("wrong horse", &hash).unwrap(),
+                PasswordVerification::Invalid
+            );
+        }
+    }
+
+    #[test]
+    fn asks_for_rehash_of_other_argon2_variants() {
+        let params = Params::new(1024, 1, 1, None).unwrap();
+        let salt = SaltString::generate(&mut OsRng);
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, params)
+            .hash_password(b"correct horse", &salt)
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            hasher(1024, 1).verify("correct horse", &argon2i).unwrap(),
+            PasswordVerification::ValidNeedsRehash
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_hash() {
+        let result = hasher(1024, 1).verify("correct horse", "correct horse");
+
+        assert!(matches!(result, Err(AppError::Internal(_))));
+    }
+
+    #[tokio::test]
+    async fn upgrades_stored_hash_on_login() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let old_hash = hasher(2048, 1).hash("correct horse").unwrap();
+        let user = users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap();
+        let mut active: entity::ActiveModel = user.into();
+        active.password_hash = Set(Some(old_hash.clone()));
+        let user = active.update(&state.db).await.unwrap();
+
+        let wrong = verify_user_password(&state, user.clone(), "wrong horse")
+            .await
+            .unwrap();
+        assert!(wrong.is_none());
+
+        let user = verify_user_password(&state, user, "correct horse")
+            .await
+            .unwrap()
+            .unwrap();
+        let new_hash = user.password_hash.unwrap();
+        assert_ne!(new_hash, old_hash);
+        assert_eq!(
+            state
+                .password_hasher
+                .verify("correct horse", &new_hash)
+                .unwrap(),
+            PasswordVerification::Valid
+        );
+    }
+}
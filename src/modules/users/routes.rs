@@ -1,16 +1,46 @@
-use axum::{routing::get, Router};
+use axum::{
+    middleware,
+    routing::{get, post},
+    Extension, Router,
+};
 
+use crate::modules::api_keys::{routes::user_api_key_routes, scope::ScopedResource};
+use crate::modules::auth::middleware::require_auth;
+use crate::modules::oauth::routes::user_consent_routes;
+use crate::modules::oidc::routes::identity_routes;
+use crate::modules::saml::routes::saml_identity_routes;
+use crate::modules::sessions::routes::user_session_routes;
 use crate::state::AppState;
 
 use super::handler;
 
-pub fn user_routes() -> Router<AppState> {
-    Router::new()
+pub fn user_routes(state: AppState) -> Router<AppState> {
+    let auth = middleware::from_fn_with_state(state, require_auth);
+
+    // Reachable with API keys holding a `users` scope. The scope is layered
+    // outside `require_auth` so the middleware sees it.
+    let scoped = Router::new()
         .route("/", get(handler::list_users).post(handler::create_user))
+        .route("/me", get(handler::get_me).patch(handler::update_me))
         .route(
             "/{id}",
             get(handler::get_user)
                 .put(handler::update_user)
                 .delete(handler::delete_user),
         )
+        .route_layer(auth.clone())
+        .route_layer(Extension(ScopedResource::Users));
+
+    Router::new()
+        .route("/me/email", post(handler::request_email_change))
+        .route("/me/email/confirm", post(handler::confirm_email_change))
+        .nest(
+            "/me/identities",
+            identity_routes().merge(saml_identity_routes()),
+        )
+        .nest("/me/api-keys", user_api_key_routes())
+        .nest("/me/sessions", user_session_routes())
+        .nest("/me/oauth-consents", user_consent_routes())
+        .route_layer(auth)
+        .merge(scoped)
 }
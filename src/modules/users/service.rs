@@ -0,0 +1,460 @@
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, Set};
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::mailer::templates::{app_link, ActionEmail};
+use crate::modules::auth::service as auth_service;
+use crate::modules::password_policy::service::{self as password_policy, PasswordOwner};
+use crate::modules::service_accounts::service as service_accounts;
+use crate::modules::user_tokens::{entity::TokenPurpose, service as user_tokens};
+use crate::state::AppState;
+
+use super::entity::{self, Entity as Users};
+use super::password;
+
+/// Longest address SMTP can deliver to
+const MAX_EMAIL_LEN: usize = 254;
+
+/// Normalize an email address for storage and lookup
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Create a user, hashing the plaintext password before it is stored
+///
+/// The password has to satisfy the deployment's password policy.
+pub async fn create_user(
+    state: &AppState,
+    email: &str,
+    name: &str,
+    password: String,
+) -> AppResult<entity::Model> {
+    create_user_in(state, &state.db, email, name, password, None).await
+}
+
+/// [`create_user`] on a specific connection, e.g. inside a transaction
+///
+/// With `organization_id`, the user is about to join that organization and
+/// the password has to satisfy its policy as well.
+pub async fn create_user_in<C: ConnectionTrait>(
+    state: &AppState,
+    db: &C,
+    email: &str,
+    name: &str,
+    password: String,
+    organization_id: Option<Uuid>,
+) -> AppResult<entity::Model> {
+    let email = validate_email(email)?;
+    let mut owner = PasswordOwner::new(&email, name);
+    if let Some(organization_id) = organization_id {
+        owner = owner.joining(organization_id);
+    }
+    password_policy::validate(state, &password, &owner).await?;
+
+    let password_hash = state.password_hasher.hash_async(password).await?;
+
+    insert_user(db, Uuid::new_v4(), email, name, Some(password_hash)).await
+}
+
+/// Create a passkey-only user without a password, with a caller-chosen id
+/// that the passkey was already bound to
+pub async fn create_passwordless_user_in<C: ConnectionTrait>(
+    db: &C,
+    id: Uuid,
+    email: &str,
+    name: &str,
+) -> AppResult<entity::Model> {
+    let email = validate_email(email)?;
+    insert_user(db, id, email, name, None).await
+}
+
+/// Normalize an email address and check it looks like one
+///
+/// That takes a single `@` between a non-empty local part and a domain with
+/// at least two non-empty labels, and no whitespace; whether mail actually
+/// arrives is what verification is for. Addresses in the domain reserved for
+/// service accounts are refused.
+pub fn validate_email(email: &str) -> AppResult<String> {
+    let email = normalize_email(email);
+    let valid = email.chars().count() <= MAX_EMAIL_LEN
+        && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+        && email.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && domain.split('.').all(|label| !label.is_empty())
+                && domain != service_accounts::EMAIL_DOMAIN
+        });
+    if !valid {
+        return Err(AppError::ValidationError(
+            "A valid email address is required".to_string(),
+        ));
+    }
+
+    Ok(email)
+}
+
+async fn insert_user<C: ConnectionTrait>(
+    db: &C,
+    id: Uuid,
+    email: String,
+    name: &str,
+    password_hash: Option<String>,
+) -> AppResult<entity::Model> {
+    let now = chrono::Utc::now().fixed_offset();
+
+    let user = entity::ActiveModel {
+        id: Set(id),
+        email: Set(email),
+        name: Set(name.trim().to_string()),
+        password_hash: Set(password_hash),
+        is_active: Set(true),
+        is_platform_admin: Set(false),
+        service_account_organization_id: Set(None),
+        email_verified_at: Set(None),
+        created_at: Set(now),
+        updated_at: Set(now),
+    };
+
+    user.insert(db).await.map_err(AppError::from)
+}
+
+/// Load a user by id
+pub async fn find_by_id(state: &AppState, id: Uuid) -> AppResult<entity::Model> {
+    Users::find_by_id(id)
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or(AppError::NotFound(format!("User with id {} not found", id)))
+}
+
+/// Look up a user by (normalized) email address
+///
+/// Service accounts are never found this way, which keeps them out of every
+/// sign-in, recovery and invitation flow.
+pub async fn find_by_email(state: &AppState, email: &str) -> AppResult<Option<entity::Model>> {
+    find_by_email_in(&state.db, email).await
+}
+
+/// [`find_by_email`] on a specific connection, e.g. inside a transaction
+pub async fn find_by_email_in<C: ConnectionTrait>(
+    db: &C,
+    email: &str,
+) -> AppResult<Option<entity::Model>> {
+    Users::find()
+        .filter(entity::Column::Email.eq(normalize_email(email)))
+        .filter(entity::Column::ServiceAccountOrganizationId.is_null())
+        .one(db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Change a user's password after re-checking the current one
+///
+/// Every refresh token of the user is revoked, so other devices have to sign
+/// in again with the new password.
+pub async fn change_password(
+    state: &AppState,
+    user: entity::Model,
+    current_password: &str,
+    new_password: String,
+) -> AppResult<entity::Model> {
+    let user = password::verify_user_password(state, user, current_password)
+        .await?
+        .ok_or(AppError::Unauthorized(
+            "Current password is incorrect".to_string(),
+        ))?;
+    password_policy::validate(state, &new_password, &PasswordOwner::user(&user)).await?;
+
+    let password_hash = state.password_hasher.hash_async(new_password).await?;
+    let mut active: entity::ActiveModel = user.into();
+    active.password_hash = Set(Some(password_hash));
+    active.updated_at = Set(chrono::Utc::now().fixed_offset());
+
+    let user = active.update(&state.db).await.map_err(AppError::from)?;
+    auth_service::revoke_all_for_user(&state.db, user.id).await?;
+
+    Ok(user)
+}
+
+/// Start an email change; the new address only takes effect once the
+/// confirmation token sent to it is redeemed
+pub async fn request_email_change(
+    state: &AppState,
+    user: entity::Model,
+    new_email: &str,
+    current_password: &str,
+) -> AppResult<()> {
+    let new_email = validate_email(new_email)?;
+    if new_email == user.email {
+        return Err(AppError::BadRequest(
+            "New email is the same as the current one".to_string(),
+        ));
+    }
+
+    let user = password::verify_user_password(state, user, current_password)
+        .await?
+        .ok_or(AppError::Unauthorized(
+            "Current password is incorrect".to_string(),
+        ))?;
+
+    if find_by_email(state, &new_email).await?.is_some() {
+        return Err(AppError::Conflict("Email is already in use".to_string()));
+    }
+
+    let token = user_tokens::issue(
+        &state.db,
+        user.id,
+        TokenPurpose::EmailChange,
+        Some(new_email.clone()),
+        state.config.email_change_token_ttl_secs,
+    )
+    .await?;
+
+    let email = ActionEmail {
+        subject: "Confirm your new email address".to_string(),
+        heading: "Confirm your new email address",
+        intro: format!(
+            "Someone asked to change the email address of the account {} to this address.",
+            user.email
+        ),
+        action_label: "Confirm email",
+        action_url: app_link(&state.config.app_base_url, "/account/confirm-email", &token),
+        outro: "If you did not request this change, you can ignore this email.".to_string(),
+    }
+    .render(&new_email);
+
+    state.mailer.send(email).await
+}
+
+/// Redeem an email change confirmation token issued to this user
+pub async fn confirm_email_change(
+    state: &AppState,
+    user: entity::Model,
+    raw_token: &str,
+) -> AppResult<entity::Model> {
+    let token = user_tokens::consume(&state.db, TokenPurpose::EmailChange, raw_token).await?;
+    if token.user_id != user.id {
+        return Err(AppError::BadRequest("Invalid or expired token".to_string()));
+    }
+
+    let new_email = token
+        .payload
+        .ok_or(AppError::internal("Email change token has no address"))?;
+
+    // Redeeming the token proves control of the new address
+    let now = chrono::Utc::now().fixed_offset();
+    let mut active: entity::ActiveModel = user.into();
+    active.email = Set(new_email);
+    active.email_verified_at = Set(Some(now));
+    active.updated_at = Set(now);
+
+    active.update(&state.db).await.map_err(AppError::from)
+}
+
+/// Record that the user has proven control of their email address
+pub async fn mark_email_verified<C: ConnectionTrait>(
+    db: &C,
+    user: entity::Model,
+) -> AppResult<entity::Model> {
+    if user.email_verified_at.is_some() {
+        return Ok(user);
+    }
+
+    let now = chrono::Utc::now().fixed_offset();
+    let mut active: entity::ActiveModel = user.into();
+    active.email_verified_at = Set(Some(now));
+    active.updated_at = Set(now);
+
+    active.update(db).await.map_err(AppError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mailer::InMemoryMailer;
+    use crate::test_support::{link_token, test_state, unique_email};
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    fn is_valid(email: &str) -> bool {
+        validate_email(email).is_ok()
+    }
+
+    #[test]
+    fn normalizes_valid_addresses() {
+        assert_eq!(
+            validate_email("  Alice.Smith+tag@Mail.Example.COM ").unwrap(),
+            "alice.smith+tag@mail.example.com"
+        );
+        assert!(is_valid("a@b.co"));
+        assert!(is_valid("o'brien@example.ie"));
+    }
+
+    #[test]
+    fn rejects_addresses_without_a_local_part_or_dotted_domain() {
+        for email in [
+            "",
+            "alice",
+            "@example.com",
+            "alice@",
+            "alice@localhost",
+            "alice@.example.com",
+            "alice@example.com.",
+            "alice@example..com",
+            "alice@bob@example.com",
+        ] {
+            assert!(!is_valid(email), "{:?}", email);
+        }
+    }
+
+    #[test]
+    fn rejects_whitespace_and_overlong_addresses() {
+        for email in [
+            "alice smith@example.com",
+            "alice@exa mple.com",
+            "alice@example.com\nBcc: eve@example.com",
+            "alice\t@example.com",
+        ] {
+            assert!(!is_valid(email), "{:?}", email);
+        }
+
+        let local = "a".repeat(64);
+        let domain = format!("{}.com", "d".repeat(MAX_EMAIL_LEN - 64 - 5));
+        assert!(is_valid(&format!("{}@{}", local, domain)));
+        assert!(!is_valid(&format!("{}@d{}", local, domain)));
+    }
+
+    #[test]
+    fn rejects_the_service_account_domain() {
+        assert!(!is_valid(&format!(
+            "someone@{}",
+            service_accounts::EMAIL_DOMAIN
+        )));
+        assert!(!is_valid(&format!(
+            "someone@{}",
+            service_accounts::EMAIL_DOMAIN.to_uppercase()
+        )));
+    }
+
+    #[tokio::test]
+    async fn changes_email_once_the_new_address_confirms() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let mailer = Arc::new(InMemoryMailer::new());
+        let state = state.with_mailer(mailer.clone());
+        let user = create_user(&state, &unique_email(), "Test", PASSWORD.to_string())
+            .await
+            .unwrap();
+        let taken = create_user(&state, &unique_email(), "Taken", PASSWORD.to_string())
+            .await
+            .unwrap();
+        let new_email = unique_email();
+
+        assert!(matches!(
+            request_email_change(&state, user.clone(), &new_email, "wrong password").await,
+            Err(AppError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            request_email_change(&state, user.clone(), &user.email, PASSWORD).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            request_email_change(&state, user.clone(), &taken.email, PASSWORD).await,
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            request_email_change(&state, user.clone(), "new@localhost", PASSWORD).await,
+            Err(AppError::ValidationError(_))
+        ));
+        assert!(mailer.sent().is_empty());
+
+        request_email_change(&state, user.clone(), &new_email.to_uppercase(), PASSWORD)
+            .await
+            .unwrap();
+        let token = link_token(&mailer.last_to(&new_email).unwrap());
+        assert_eq!(find_by_id(&state, user.id).await.unwrap().email, user.email);
+
+        let confirmed = confirm_email_change(&state, user.clone(), &token)
+            .await
+            .unwrap();
+        assert_eq!(confirmed.email, new_email);
+        assert!(confirmed.email_verified_at.is_some());
+        assert!(find_by_email(&state, &user.email).await.unwrap().is_none());
+
+        assert!(matches!(
+            confirm_email_change(&state, confirmed, &token).await,
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn email_change_tokens_only_work_for_their_account() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let mailer = Arc::new(InMemoryMailer::new());
+        let state = state.with_mailer(mailer.clone());
+        let user = create_user(&state, &unique_email(), "Test", PASSWORD.to_string())
+            .await
+            .unwrap();
+        let other = create_user(&state, &unique_email(), "Other", PASSWORD.to_string())
+            .await
+            .unwrap();
+        let new_email = unique_email();
+
+        request_email_change(&state, user.clone(), &new_email, PASSWORD)
+            .await
+            .unwrap();
+        let token = link_token(&mailer.last_to(&new_email).unwrap());
+
+        assert!(matches!(
+            confirm_email_change(&state, other.clone(), &token).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert_eq!(
+            find_by_id(&state, other.id).await.unwrap().email,
+            other.email
+        );
+        assert_eq!(find_by_id(&state, user.id).await.unwrap().email, user.email);
+    }
+
+    #[tokio::test]
+    async fn changing_the_password_needs_the_current_one() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = create_user(&state, &unique_email(), "Test", PASSWORD.to_string())
+            .await
+            .unwrap();
+        let new_password = "another long passphrase entirely";
+
+        assert!(matches!(
+            change_password(
+                &state,
+                user.clone(),
+                "wrong password",
+                new_password.to_string()
+            )
+            .await,
+            Err(AppError::Unauthorized(_))
+        ));
+
+        let user = change_password(&state, user, PASSWORD, new_password.to_string())
+            .await
+            .unwrap();
+        assert!(
+            password::verify_user_password(&state, user.clone(), PASSWORD)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(password::verify_user_password(&state, user, new_password)
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,212 @@
+//! A software passkey authenticator for tests
+//!
+//! It answers the options the service hands out the way a browser and
+//! platform authenticator would, so passkey ceremonies can be exercised
+//! without a browser or hardware:
+//!
+//! ```rust,ignore
+//! let mut authenticator = SoftwareAuthenticator::new("http://localhost:3000");
+//! let credential = authenticator.create(&options)?;
+//! let assertion = authenticator.get(&request_options)?;
+//! ```
+//!
+//! Keys are ES256, user verification is reported unless turned off, and the
+//! signature counter increases by one per assertion.
+
+use anyhow::{anyhow, Context, Result};
+use ciborium::value::Value;
+use rand::{rngs::OsRng, RngCore};
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use super::ceremony::{
+    decode_b64, encode_b64, COSE_ALG_ES256, FLAG_ATTESTED_CREDENTIAL, FLAG_USER_PRESENT,
+    FLAG_USER_VERIFIED,
+};
+
+/// A discoverable credential held by the authenticator
+pub struct SoftwareCredential {
+    pub id: Vec<u8>,
+    pub rp_id: String,
+    pub user_handle: Vec<u8>,
+    pub sign_count: u32,
+    key_pair: EcdsaKeyPair,
+}
+
+pub struct SoftwareAuthenticator {
+    /// The origin reported in client data
+    pub origin: String,
+    /// Whether the user verification flag is set
+    pub user_verified: bool,
+    rng: SystemRandom,
+    pub credentials: Vec<SoftwareCredential>,
+}
+
+impl SoftwareAuthenticator {
+    /// An authenticator used from pages served at `origin`
+    pub fn new(origin: impl Into<String>) -> Self {
+        Self {
+            origin: origin.into(),
+            user_verified: true,
+            rng: SystemRandom::new(),
+            credentials: Vec::new(),
+        }
+    }
+
+    /// Create a credential for creation options, returning the JSON the
+    /// browser would send back
+    pub fn create(&mut self, options: &serde_json::Value) -> Result<serde_json::Value> {
+        let options = options.get("publicKey").unwrap_or(options);
+        let challenge = str_field(options, "/challenge")?;
+        let rp_id = str_field(options, "/rp/id")?;
+        let user_handle = decode_b64(str_field(options, "/user/id")?)
+            .map_err(|_| anyhow!("user.id is not base64url"))?;
+
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &self.rng)
+            .map_err(|_| anyhow!("failed to generate key pair"))?;
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &self.rng)
+                .map_err(|_| anyhow!("failed to load key pair"))?;
+
+        let mut id = vec![0u8; 16];
+        OsRng.fill_bytes(&mut id);
+
+        // Uncompressed SEC1 point: 0x04 || x || y
+        let point = key_pair.public_key().as_ref();
+        let cose_key = Value::Map(vec![
+            (Value::from(1), Value::from(2)),
+            (Value::from(3), Value::from(COSE_ALG_ES256)),
+            (Value::from(-1), Value::from(1)),
+            (Value::from(-2), Value::Bytes(point[1..33].to_vec())),
+            (Value::from(-3), Value::Bytes(point[33..65].to_vec())),
+        ]);
+
+        let mut auth_data = authenticator_data(rp_id, self.flags() | FLAG_ATTESTED_CREDENTIAL, 0);
+        auth_data.extend_from_slice(&[0u8; 16]);
+        auth_data.extend_from_slice(&(id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&id);
+        ciborium::into_writer(&cose_key, &mut auth_data).context("failed to encode key")?;
+
+        let attestation_object = Value::Map(vec![
+            (Value::from("fmt"), Value::from("none")),
+            (Value::from("attStmt"), Value::Map(Vec::new())),
+            (Value::from("authData"), Value::Bytes(auth_data)),
+        ]);
+        let mut attestation_bytes = Vec::new();
+        ciborium::into_writer(&attestation_object, &mut attestation_bytes)
+            .context("failed to encode attestation object")?;
+
+        let client_data = self.client_data("webauthn.create", challenge);
+
+        self.credentials.push(SoftwareCredential {
+            id: id.clone(),
+            rp_id: rp_id.to_string(),
+            user_handle,
+            sign_count: 0,
+            key_pair,
+        });
+
+        Ok(json!({
+            "id": encode_b64(&id),
+            "rawId": encode_b64(&id),
+            "type": "public-key",
+            "authenticatorAttachment": "platform",
+            "response": {
+                "clientDataJSON": encode_b64(&client_data),
+                "attestationObject": encode_b64(&attestation_bytes),
+                "transports": ["internal"],
+            },
+            "clientExtensionResults": {},
+        }))
+    }
+
+    /// Sign an assertion for request options, using the first credential
+    /// for the relying party that the options allow
+    pub fn get(&mut self, options: &serde_json::Value) -> Result<serde_json::Value> {
+        let options = options.get("publicKey").unwrap_or(options);
+        let challenge = str_field(options, "/challenge")?.to_string();
+        let rp_id = str_field(options, "/rpId")?.to_string();
+        let allowed: Vec<Vec<u8>> = options
+            .get("allowCredentials")
+            .and_then(|list| list.as_array())
+            .map(|list| {
+                list.iter()
+                    .filter_map(|entry| entry.get("id")?.as_str())
+                    .filter_map(|id| decode_b64(id).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let client_data = self.client_data("webauthn.get", &challenge);
+        let flags = self.flags();
+        let credential = self
+            .credentials
+            .iter_mut()
+            .find(|credential| {
+                credential.rp_id == rp_id
+                    && (allowed.is_empty() || allowed.contains(&credential.id))
+            })
+            .ok_or_else(|| anyhow!("no credential for relying party {}", rp_id))?;
+
+        credential.sign_count += 1;
+        let auth_data = authenticator_data(&rp_id, flags, credential.sign_count);
+
+        let mut message = auth_data.clone();
+        message.extend_from_slice(&Sha256::digest(&client_data));
+        let signature = credential
+            .key_pair
+            .sign(&self.rng, &message)
+            .map_err(|_| anyhow!("failed to sign assertion"))?;
+
+        Ok(json!({
+            "id": encode_b64(&credential.id),
+            "rawId": encode_b64(&credential.id),
+            "type": "public-key",
+            "authenticatorAttachment": "platform",
+            "response": {
+                "clientDataJSON": encode_b64(&client_data),
+                "authenticatorData": encode_b64(&auth_data),
+                "signature": encode_b64(signature.as_ref()),
+                "userHandle": encode_b64(&credential.user_handle),
+            },
+            "clientExtensionResults": {},
+        }))
+    }
+
+    fn flags(&self) -> u8 {
+        if self.user_verified {
+            FLAG_USER_PRESENT | FLAG_USER_VERIFIED
+        } else {
+            FLAG_USER_PRESENT
+        }
+    }
+
+    fn client_data(&self, kind: &str, challenge: &str) -> Vec<u8> {
+        json!({
+            "type": kind,
+            "challenge": challenge,
+            "origin": self.origin,
+            "crossOrigin": false,
+        })
+        .to_string()
+        .into_bytes()
+    }
+}
+
+fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+    let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+    data.push(flags);
+    data.extend_from_slice(&sign_count.to_be_bytes());
+    data
+}
+
+fn str_field<'a>(value: &'a serde_json::Value, pointer: &str) -> Result<&'a str> {
+    value
+        .pointer(pointer)
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| anyhow!("options are missing {}", pointer))
+}
@@ -0,0 +1,318 @@
+//! Parsing and verification of WebAuthn ceremony responses
+//!
+//! Covers what a passkey relying party needs: `none` attestation (the
+//! attestation statement is not evaluated) and ES256, EdDSA or RS256
+//! credential keys. Binary fields travel as base64url strings, matching the
+//! browser's `PublicKeyCredential.toJSON()`.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::value::Value;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{AppError, AppResult};
+
+/// COSE algorithm: ECDSA with P-256 and SHA-256
+pub const COSE_ALG_ES256: i32 = -7;
+/// COSE algorithm: Ed25519
+pub const COSE_ALG_EDDSA: i32 = -8;
+/// COSE algorithm: RSASSA-PKCS1-v1_5 with SHA-256
+pub const COSE_ALG_RS256: i32 = -257;
+
+/// Algorithms offered to authenticators, in order of preference
+pub const SUPPORTED_ALGORITHMS: [i32; 3] = [COSE_ALG_ES256, COSE_ALG_EDDSA, COSE_ALG_RS256];
+
+pub const FLAG_USER_PRESENT: u8 = 0x01;
+pub const FLAG_USER_VERIFIED: u8 = 0x04;
+pub const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// Response to `navigator.credentials.create()`
+#[derive(Debug, Deserialize)]
+pub struct RegistrationCredential {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+/// Response to `navigator.credentials.get()`
+#[derive(Debug, Deserialize)]
+pub struct AuthenticationCredential {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+/// The client data the browser signs over
+#[derive(Debug, Deserialize)]
+pub struct CollectedClientData {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub challenge: String,
+    pub origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    pub cross_origin: bool,
+}
+
+/// Data the authenticator attests to or signs over
+#[derive(Debug)]
+pub struct AuthenticatorData {
+    pub rp_id_hash: [u8; 32],
+    pub flags: u8,
+    pub sign_count: u32,
+    pub attested_credential: Option<AttestedCredential>,
+}
+
+/// A newly created credential, present in registration responses
+#[derive(Debug)]
+pub struct AttestedCredential {
+    pub credential_id: Vec<u8>,
+    /// COSE-encoded public key
+    pub public_key: Vec<u8>,
+}
+
+impl AuthenticatorData {
+    /// Check the data was produced for this relying party with the user
+    /// present and verified
+    pub fn check(&self, rp_id: &str) -> AppResult<()> {
+        if self.rp_id_hash[..] != Sha256::digest(rp_id.as_bytes())[..] {
+            return Err(invalid("relying party id mismatch"));
+        }
+        if self.flags & FLAG_USER_PRESENT == 0 {
+            return Err(invalid("user presence flag not set"));
+        }
+        if self.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(invalid("user verification flag not set"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode a base64url field, tolerating padding
+pub fn decode_b64(value: &str) -> AppResult<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| invalid("malformed base64url field"))
+}
+
+pub fn encode_b64(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Parse client data and check its ceremony type and origin
+///
+/// The challenge is left to the caller, which looks it up by hash.
+pub fn parse_client_data(
+    raw: &[u8],
+    expected_type: &str,
+    expected_origin: &str,
+) -> AppResult<CollectedClientData> {
+    let client_data: CollectedClientData =
+        serde_json::from_slice(raw).map_err(|_| invalid("malformed client data"))?;
+
+    if client_data.kind != expected_type {
+        return Err(invalid("unexpected ceremony type"));
+    }
+    if client_data.origin != expected_origin {
+        return Err(invalid("origin mismatch"));
+    }
+    if client_data.cross_origin {
+        return Err(invalid("cross-origin requests are not allowed"));
+    }
+
+    Ok(client_data)
+}
+
+/// Extract the authenticator data from an attestation object
+pub fn parse_attestation_object(raw: &[u8]) -> AppResult<AuthenticatorData> {
+    let value: Value =
+        ciborium::from_reader(raw).map_err(|_| invalid("malformed attestation object"))?;
+    let map = value
+        .as_map()
+        .ok_or_else(|| invalid("malformed attestation object"))?;
+
+    let auth_data = map
+        .iter()
+        .find(|(key, _)| key.as_text() == Some("authData"))
+        .and_then(|(_, value)| value.as_bytes())
+        .ok_or_else(|| invalid("attestation object has no authenticator data"))?;
+
+    parse_authenticator_data(auth_data)
+}
+
+/// Parse the binary authenticator data structure
+pub fn parse_authenticator_data(raw: &[u8]) -> AppResult<AuthenticatorData> {
+    if raw.len() < 37 {
+        return Err(invalid("authenticator data is too short"));
+    }
+
+    let mut rp_id_hash = [0u8; 32];
+    rp_id_hash.copy_from_slice(&raw[..32]);
+    let flags = raw[32];
+    let sign_count = u32::from_be_bytes([raw[33], raw[34], raw[35], raw[36]]);
+
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        // AAGUID (16 bytes), credential id length (2 bytes), credential id,
+        // then the COSE key; extensions may follow the key
+        let rest = &raw[37..];
+        if rest.len() < 18 {
+            return Err(invalid("attested credential data is too short"));
+        }
+        let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+        let rest = &rest[18..];
+        if rest.len() < id_len {
+            return Err(invalid("attested credential data is too short"));
+        }
+        let (credential_id, rest) = rest.split_at(id_len);
+
+        let mut cursor = std::io::Cursor::new(rest);
+        let _: Value =
+            ciborium::from_reader(&mut cursor).map_err(|_| invalid("malformed public key"))?;
+        let key_len = cursor.position() as usize;
+
+        Some(AttestedCredential {
+            credential_id: credential_id.to_vec(),
+            public_key: rest[..key_len].to_vec(),
+        })
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash,
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+/// Validate a COSE public key and return its algorithm
+pub fn key_algorithm(public_key: &[u8]) -> AppResult<i32> {
+    let key = CoseKey::parse(public_key)?;
+    key.verifier().map(|_| key.alg)
+}
+
+/// Verify an assertion signature, made over the authenticator data followed
+/// by the SHA-256 of the client data
+pub fn verify_assertion(
+    public_key: &[u8],
+    authenticator_data: &[u8],
+    client_data_json: &[u8],
+    signature: &[u8],
+) -> AppResult<bool> {
+    let mut message = authenticator_data.to_vec();
+    message.extend_from_slice(&Sha256::digest(client_data_json));
+
+    let key = CoseKey::parse(public_key)?;
+    Ok(match key.verifier()? {
+        Verifier::Unparsed(algorithm, bytes) => UnparsedPublicKey::new(algorithm, bytes)
+            .verify(&message, signature)
+            .is_ok(),
+        Verifier::Rsa { n, e } => RsaPublicKeyComponents { n, e }
+            .verify(&signature::RSA_PKCS1_2048_8192_SHA256, &message, signature)
+            .is_ok(),
+    })
+}
+
+/// The parameters of a COSE key, keyed by their integer labels
+struct CoseKey {
+    alg: i32,
+    params: Vec<(i128, Value)>,
+}
+
+enum Verifier {
+    Unparsed(&'static dyn signature::VerificationAlgorithm, Vec<u8>),
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl CoseKey {
+    fn parse(raw: &[u8]) -> AppResult<Self> {
+        let value: Value =
+            ciborium::from_reader(raw).map_err(|_| invalid("malformed public key"))?;
+        let params: Vec<(i128, Value)> = value
+            .into_map()
+            .map_err(|_| invalid("malformed public key"))?
+            .into_iter()
+            .filter_map(|(key, value)| key.as_integer().map(|key| (i128::from(key), value)))
+            .collect();
+
+        let alg = params
+            .iter()
+            .find(|(key, _)| *key == 3)
+            .and_then(|(_, value)| value.as_integer())
+            .and_then(|alg| i32::try_from(i128::from(alg)).ok())
+            .ok_or_else(|| invalid("public key has no algorithm"))?;
+
+        Ok(Self { alg, params })
+    }
+
+    fn int(&self, label: i128) -> Option<i128> {
+        self.params
+            .iter()
+            .find(|(key, _)| *key == label)
+            .and_then(|(_, value)| value.as_integer())
+            .map(i128::from)
+    }
+
+    fn bytes(&self, label: i128) -> AppResult<Vec<u8>> {
+        self.params
+            .iter()
+            .find(|(key, _)| *key == label)
+            .and_then(|(_, value)| value.as_bytes())
+            .cloned()
+            .ok_or_else(|| invalid("public key is missing a parameter"))
+    }
+
+    fn verifier(&self) -> AppResult<Verifier> {
+        // Label 1 is the key type, -1 the curve (EC2/OKP) or modulus (RSA)
+        match (self.alg, self.int(1)) {
+            (COSE_ALG_ES256, Some(2)) if self.int(-1) == Some(1) => {
+                let (x, y) = (self.bytes(-2)?, self.bytes(-3)?);
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(invalid("malformed P-256 public key"));
+                }
+                let mut point = vec![0x04];
+                point.extend_from_slice(&x);
+                point.extend_from_slice(&y);
+                Ok(Verifier::Unparsed(
+                    &signature::ECDSA_P256_SHA256_ASN1,
+                    point,
+                ))
+            }
+            (COSE_ALG_EDDSA, Some(1)) if self.int(-1) == Some(6) => {
+                Ok(Verifier::Unparsed(&signature::ED25519, self.bytes(-2)?))
+            }
+            (COSE_ALG_RS256, Some(3)) => Ok(Verifier::Rsa {
+                n: self.bytes(-1)?,
+                e: self.bytes(-2)?,
+            }),
+            _ => Err(invalid("unsupported public key algorithm")),
+        }
+    }
+}
+
+fn invalid(reason: &str) -> AppError {
+    AppError::BadRequest(format!("Invalid passkey response: {}", reason))
+}
@@ -0,0 +1,53 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Which WebAuthn ceremony a challenge was issued for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+pub enum Ceremony {
+    #[sea_orm(string_value = "registration")]
+    Registration,
+    #[sea_orm(string_value = "authentication")]
+    Authentication,
+}
+
+/// Single-use challenge for a ceremony in progress, stored as a SHA-256 hash
+///
+/// Registration challenges belong either to a signed-in user adding a
+/// passkey (`user_id`) or to a new passkey-only signup (`email` and `name`,
+/// with the challenge id reserved as the new user's id). Authentication
+/// challenges are not tied to a user, since passkeys are discoverable.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "webauthn_challenges")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub challenge_hash: String,
+    pub ceremony: Ceremony,
+    pub user_id: Option<Uuid>,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub expires_at: DateTimeWithTimeZone,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::modules::users::entity::Entity",
+        from = "Column::UserId",
+        to = "crate::modules::users::entity::Column::Id",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<crate::modules::users::entity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
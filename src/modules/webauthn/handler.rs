@@ -0,0 +1,174 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::{extractor::AuthUser, handler::AuthResponse, verification};
+use crate::modules::sessions::mode::SignInMode;
+use crate::state::AppState;
+
+use super::ceremony::{AuthenticationCredential, RegistrationCredential};
+use super::entity;
+use super::service;
+
+#[derive(serde::Deserialize)]
+pub struct RegisterCredentialRequest {
+    pub credential: RegistrationCredential,
+    pub name: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SignupOptionsRequest {
+    pub email: String,
+    pub name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SignupRequest {
+    pub credential: RegistrationCredential,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasskeyLoginRequest {
+    pub credential: AuthenticationCredential,
+}
+
+#[derive(serde::Serialize)]
+pub struct CredentialResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub algorithm: i32,
+    pub sign_count: i64,
+    pub created_at: chrono::DateTime<chrono::FixedOffset>,
+    pub last_used_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+impl From<entity::Model> for CredentialResponse {
+    fn from(model: entity::Model) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+            algorithm: model.algorithm,
+            sign_count: model.sign_count,
+            created_at: model.created_at,
+            last_used_at: model.last_used_at,
+        }
+    }
+}
+
+/// POST /api/webauthn/credentials/options
+pub async fn registration_options(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let options = service::registration_options(&state, &auth.user).await?;
+    Ok(Json(options))
+}
+
+/// POST /api/webauthn/credentials
+pub async fn register_credential(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(payload): Json<RegisterCredentialRequest>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    let credential =
+        service::register_credential(&state, &auth.user, &payload.credential, payload.name).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CredentialResponse::from(credential)),
+    ))
+}
+
+/// GET /api/webauthn/credentials
+pub async fn list_credentials(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> AppResult<impl IntoResponse> {
+    let credentials = service::list_credentials(&state, auth.user.id).await?;
+    Ok(Json(
+        credentials
+            .into_iter()
+            .map(CredentialResponse::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// DELETE /api/webauthn/credentials/:id
+pub async fn delete_credential(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    auth.forbid_impersonation()?;
+    service::delete_credential(&state, &auth.user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/auth/passkey/signup/options
+pub async fn signup_options(
+    State(state): State<AppState>,
+    Json(payload): Json<SignupOptionsRequest>,
+) -> AppResult<impl IntoResponse> {
+    let options = service::signup_options(&state, &payload.email, &payload.name).await?;
+    Ok(Json(options))
+}
+
+/// POST /api/auth/passkey/signup
+///
+/// Creates a passkey-only account; it has no password until one is set
+/// through the password reset flow.
+pub async fn signup(
+    State(state): State<AppState>,
+    mode: SignInMode,
+    Json(payload): Json<SignupRequest>,
+) -> AppResult<impl IntoResponse> {
+    let user = service::complete_signup(&state, &payload.credential).await?;
+
+    if let Err(err) = verification::send_verification_email(&state, &user).await {
+        tracing::warn!(
+            "Failed to send verification email to user {}: {}",
+            user.id,
+            err
+        );
+    }
+
+    let (cookies, response) = match verification::ensure_sign_in_allowed(&state.config, &user) {
+        Ok(()) => AuthResponse::sign_in(&state, &mode, user).await?,
+        Err(_) => (CookieJar::new(), AuthResponse::pending(user)),
+    };
+
+    Ok((StatusCode::CREATED, cookies, Json(response)))
+}
+
+/// POST /api/auth/passkey/options
+pub async fn login_options(State(state): State<AppState>) -> AppResult<impl IntoResponse> {
+    let options = service::authentication_options(&state).await?;
+    Ok(Json(options))
+}
+
+/// POST /api/auth/passkey
+///
+/// A passkey verifies the user on the device, so no further MFA step is
+/// required.
+pub async fn login(
+    State(state): State<AppState>,
+    mode: SignInMode,
+    Json(payload): Json<PasskeyLoginRequest>,
+) -> AppResult<impl IntoResponse> {
+    let user = service::authenticate(&state, &payload.credential).await?;
+
+    if !user.is_active {
+        return Err(AppError::Forbidden("Account is disabled".to_string()));
+    }
+    verification::ensure_sign_in_allowed(&state.config, &user)?;
+
+    let (cookies, response) = AuthResponse::sign_in(&state, &mode, user).await?;
+    Ok((cookies, Json(response)))
+}
@@ -0,0 +1,8 @@
+#[cfg(test)]
+mod authenticator;
+pub mod ceremony;
+pub mod challenge;
+pub mod entity;
+pub mod handler;
+pub mod routes;
+pub mod service;
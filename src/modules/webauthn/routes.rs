@@ -0,0 +1,31 @@
+use axum::{
+    middleware,
+    routing::{delete, get, post},
+    Router,
+};
+
+use crate::modules::auth::middleware::require_auth;
+use crate::state::AppState;
+
+use super::handler;
+
+/// Passkey management for the signed-in user
+pub fn webauthn_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/credentials",
+            get(handler::list_credentials).post(handler::register_credential),
+        )
+        .route("/credentials/options", post(handler::registration_options))
+        .route("/credentials/{id}", delete(handler::delete_credential))
+        .route_layer(middleware::from_fn_with_state(state, require_auth))
+}
+
+/// Public passkey sign-up and sign-in, nested under `/api/auth/passkey`
+pub fn passkey_auth_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", post(handler::login))
+        .route("/options", post(handler::login_options))
+        .route("/signup", post(handler::signup))
+        .route("/signup/options", post(handler::signup_options))
+}
@@ -0,0 +1,803 @@
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::modules::auth::token;
+use crate::modules::mfa::service as mfa_service;
+use crate::modules::oidc::service as oidc_service;
+use crate::modules::users::{entity as users, service as users_service};
+use crate::state::AppState;
+
+use super::ceremony::{
+    self, AuthenticationCredential, CollectedClientData, RegistrationCredential,
+    SUPPORTED_ALGORITHMS,
+};
+use super::challenge::{self, Ceremony, Entity as Challenges};
+use super::entity::{self as credentials, Entity as Credentials};
+
+/// Default name for a passkey registered without one
+const DEFAULT_CREDENTIAL_NAME: &str = "Passkey";
+
+/// A registration response that passed verification
+struct VerifiedRegistration {
+    client_data: CollectedClientData,
+    credential_id: String,
+    public_key: Vec<u8>,
+    algorithm: i32,
+    sign_count: i64,
+}
+
+/// Creation options for a signed-in user adding a passkey
+pub async fn registration_options(
+    state: &AppState,
+    user: &users::Model,
+) -> AppResult<serde_json::Value> {
+    let existing = list_credentials(state, user.id).await?;
+    let challenge = create_challenge(
+        state,
+        Uuid::new_v4(),
+        Ceremony::Registration,
+        Some(user.id),
+        None,
+    )
+    .await?;
+
+    Ok(creation_options(
+        &state.config,
+        &challenge,
+        user.id,
+        &user.email,
+        &user.name,
+        &existing,
+    ))
+}
+
+/// Creation options for a new passkey-only account
+///
+/// The account is only created once the passkey is registered; until then
+/// the challenge reserves the new user's id.
+pub async fn signup_options(
+    state: &AppState,
+    email: &str,
+    name: &str,
+) -> AppResult<serde_json::Value> {
+    let email = users_service::validate_email(email)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::ValidationError("Name is required".to_string()));
+    }
+    if users_service::find_by_email(state, &email).await?.is_some() {
+        return Err(AppError::Conflict("Email is already in use".to_string()));
+    }
+
+    let user_id = Uuid::new_v4();
+    let challenge = create_challenge(
+        state,
+        user_id,
+        Ceremony::Registration,
+        None,
+        Some((email.clone(), name.to_string())),
+    )
+    .await?;
+
+    Ok(creation_options(
+        &state.config,
+        &challenge,
+        user_id,
+        &email,
+        name,
+        &[],
+    ))
+}
+
+/// Verify a registration response and store the passkey for the user
+pub async fn register_credential(
+    state: &AppState,
+    user: &users::Model,
+    credential: &RegistrationCredential,
+    name: Option<String>,
+) -> AppResult<credentials::Model> {
+    let verified = verify_registration(&state.config, credential)?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let challenge = consume_challenge(
+        &txn,
+        Ceremony::Registration,
+        &verified.client_data.challenge,
+    )
+    .await?;
+    if challenge.user_id != Some(user.id) {
+        return Err(invalid_challenge());
+    }
+
+    let credential = insert_credential(&txn, user.id, verified, name).await?;
+    txn.commit().await.map_err(AppError::from)?;
+
+    Ok(credential)
+}
+
+/// Verify a registration response for a passkey-only signup and create the
+/// account together with its passkey
+pub async fn complete_signup(
+    state: &AppState,
+    credential: &RegistrationCredential,
+) -> AppResult<users::Model> {
+    let verified = verify_registration(&state.config, credential)?;
+
+    let txn = state.db.begin().await.map_err(AppError::from)?;
+    let challenge = consume_challenge(
+        &txn,
+        Ceremony::Registration,
+        &verified.client_data.challenge,
+    )
+    .await?;
+    let (Some(email), Some(name)) = (challenge.email, challenge.name) else {
+        return Err(invalid_challenge());
+    };
+
+    let user = users_service::create_passwordless_user_in(&txn, challenge.id, &email, &name)
+        .await
+        .map_err(|err| match err {
+            AppError::Conflict(_) => AppError::Conflict("Email is already in use".to_string()),
+            err => err,
+        })?;
+    insert_credential(&txn, user.id, verified, None).await?;
+    txn.commit().await.map_err(AppError::from)?;
+
+    Ok(user)
+}
+
+/// Request options for signing in with a discoverable passkey
+pub async fn authentication_options(state: &AppState) -> AppResult<serde_json::Value> {
+    let challenge =
+        create_challenge(state, Uuid::new_v4(), Ceremony::Authentication, None, None).await?;
+
+    Ok(request_options(&state.config, &challenge, &[]))
+}
+
+/// Request options for the second step of a login, limited to the user's
+/// own passkeys
+pub async fn second_factor_options(
+    state: &AppState,
+    user_id: Uuid,
+) -> AppResult<serde_json::Value> {
+    let existing = list_credentials(state, user_id).await?;
+    if existing.is_empty() {
+        return Err(AppError::BadRequest(
+            "No passkey is registered for this account".to_string(),
+        ));
+    }
+
+    let challenge = create_challenge(
+        state,
+        Uuid::new_v4(),
+        Ceremony::Authentication,
+        Some(user_id),
+        None,
+    )
+    .await?;
+
+    Ok(request_options(&state.config, &challenge, &existing))
+}
+
+/// Verify an assertion and return the user it authenticates
+///
+/// The signature counter must increase unless the authenticator does not
+/// implement one (always zero); a counter that goes backwards suggests a
+/// cloned authenticator and the sign-in is refused.
+pub async fn authenticate(
+    state: &AppState,
+    credential: &AuthenticationCredential,
+) -> AppResult<users::Model> {
+    let failed = || AppError::Unauthorized("Passkey verification failed".to_string());
+
+    if credential.kind != "public-key" {
+        return Err(failed());
+    }
+
+    let client_data_json = ceremony::decode_b64(&credential.response.client_data_json)?;
+    let client_data = ceremony::parse_client_data(
+        &client_data_json,
+        "webauthn.get",
+        state.config.expected_webauthn_origin(),
+    )?;
+
+    let credential_id = ceremony::encode_b64(&ceremony::decode_b64(&credential.id)?);
+    let stored = Credentials::find()
+        .filter(credentials::Column::CredentialId.eq(credential_id))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(failed)?;
+
+    // A challenge issued for one user's second step only takes their passkeys
+    let challenge =
+        consume_challenge(&state.db, Ceremony::Authentication, &client_data.challenge).await?;
+    if challenge
+        .user_id
+        .is_some_and(|user_id| user_id != stored.user_id)
+    {
+        return Err(failed());
+    }
+
+    let authenticator_data = ceremony::decode_b64(&credential.response.authenticator_data)?;
+    let parsed = ceremony::parse_authenticator_data(&authenticator_data)?;
+    parsed.check(&state.config.webauthn_rp_id)?;
+
+    if let Some(user_handle) = &credential.response.user_handle {
+        if ceremony::decode_b64(user_handle)? != stored.user_id.as_bytes() {
+            return Err(failed());
+        }
+    }
+
+    let signature = ceremony::decode_b64(&credential.response.signature)?;
+    if !ceremony::verify_assertion(
+        &stored.public_key,
+        &authenticator_data,
+        &client_data_json,
+        &signature,
+    )? {
+        return Err(failed());
+    }
+
+    let sign_count = i64::from(parsed.sign_count);
+    if (sign_count != 0 || stored.sign_count != 0) && sign_count <= stored.sign_count {
+        tracing::warn!(
+            "Passkey {} signature counter went from {} to {}; possible cloned authenticator",
+            stored.id,
+            stored.sign_count,
+            sign_count
+        );
+        return Err(AppError::Unauthorized(
+            "Passkey signature counter did not increase".to_string(),
+        ));
+    }
+
+    // Conditional on the counter we read, so two concurrent sign-ins with the
+    // same assertion cannot both succeed
+    let result = Credentials::update_many()
+        .col_expr(credentials::Column::SignCount, Expr::value(sign_count))
+        .col_expr(
+            credentials::Column::LastUsedAt,
+            Expr::value(chrono::Utc::now().fixed_offset()),
+        )
+        .filter(credentials::Column::Id.eq(stored.id))
+        .filter(credentials::Column::SignCount.eq(stored.sign_count))
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+    if result.rows_affected == 0 {
+        return Err(failed());
+    }
+
+    users_service::find_by_id(state, stored.user_id).await
+}
+
+pub async fn list_credentials(
+    state: &AppState,
+    user_id: Uuid,
+) -> AppResult<Vec<credentials::Model>> {
+    Credentials::find()
+        .filter(credentials::Column::UserId.eq(user_id))
+        .order_by_asc(credentials::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Whether the user has at least one passkey
+pub async fn has_credentials<C: ConnectionTrait>(db: &C, user_id: Uuid) -> AppResult<bool> {
+    let count = Credentials::find()
+        .filter(credentials::Column::UserId.eq(user_id))
+        .count(db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(count > 0)
+}
+
+/// Remove one of the user's passkeys, keeping at least one way to sign in
+pub async fn delete_credential(
+    state: &AppState,
+    user: &users::Model,
+    credential_id: Uuid,
+) -> AppResult<()> {
+    let credential = Credentials::find_by_id(credential_id)
+        .filter(credentials::Column::UserId.eq(user.id))
+        .one(&state.db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or(AppError::NotFound(format!(
+            "Passkey with id {} not found",
+            credential_id
+        )))?;
+
+    let others = Credentials::find()
+        .filter(credentials::Column::UserId.eq(user.id))
+        .filter(credentials::Column::Id.ne(credential.id))
+        .count(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    if others == 0 {
+        if user.password_hash.is_none() && !oidc_service::has_identities(&state.db, user.id).await?
+        {
+            return Err(AppError::BadRequest(
+                "Cannot remove the only way to sign in to this account".to_string(),
+            ));
+        }
+        if mfa_service::required_by_organization(&state.db, user.id).await?
+            && !mfa_service::is_enabled(&state.db, user.id).await?
+        {
+            return Err(AppError::Forbidden(
+                "An organization you belong to requires two-factor authentication".to_string(),
+            ));
+        }
+    }
+
+    Credentials::delete_by_id(credential.id)
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(())
+}
+
+fn creation_options(
+    config: &AppConfig,
+    challenge: &str,
+    user_id: Uuid,
+    email: &str,
+    name: &str,
+    exclude: &[credentials::Model],
+) -> serde_json::Value {
+    let params: Vec<_> = SUPPORTED_ALGORITHMS
+        .iter()
+        .map(|alg| json!({ "type": "public-key", "alg": alg }))
+        .collect();
+    let exclude: Vec<_> = exclude
+        .iter()
+        .map(|credential| json!({ "type": "public-key", "id": credential.credential_id }))
+        .collect();
+
+    json!({
+        "publicKey": {
+            "challenge": challenge,
+            "rp": { "id": config.webauthn_rp_id, "name": config.webauthn_rp_name },
+            "user": {
+                "id": ceremony::encode_b64(user_id.as_bytes()),
+                "name": email,
+                "displayName": name,
+            },
+            "pubKeyCredParams": params,
+            "timeout": config.webauthn_challenge_ttl_secs * 1000,
+            "attestation": "none",
+            "authenticatorSelection": {
+                "residentKey": "required",
+                "requireResidentKey": true,
+                "userVerification": "required",
+            },
+            "excludeCredentials": exclude,
+        }
+    })
+}
+
+fn request_options(
+    config: &AppConfig,
+    challenge: &str,
+    allow: &[credentials::Model],
+) -> serde_json::Value {
+    let allow: Vec<_> = allow
+        .iter()
+        .map(|credential| json!({ "type": "public-key", "id": credential.credential_id }))
+        .collect();
+
+    json!({
+        "publicKey": {
+            "challenge": challenge,
+            "rpId": config.webauthn_rp_id,
+            "timeout": config.webauthn_challenge_ttl_secs * 1000,
+            "userVerification": "required",
+            "allowCredentials": allow,
+        }
+    })
+}
+
+fn verify_registration(
+    config: &AppConfig,
+    credential: &RegistrationCredential,
+) -> AppResult<VerifiedRegistration> {
+    if credential.kind != "public-key" {
+        return Err(AppError::BadRequest(
+            "Invalid passkey response: unexpected credential type".to_string(),
+        ));
+    }
+
+    let client_data_json = ceremony::decode_b64(&credential.response.client_data_json)?;
+    let client_data = ceremony::parse_client_data(
+        &client_data_json,
+        "webauthn.create",
+        config.expected_webauthn_origin(),
+    )?;
+
+    let attestation_object = ceremony::decode_b64(&credential.response.attestation_object)?;
+    let authenticator_data = ceremony::parse_attestation_object(&attestation_object)?;
+    authenticator_data.check(&config.webauthn_rp_id)?;
+
+    let attested = authenticator_data
+        .attested_credential
+        .ok_or(AppError::BadRequest(
+            "Invalid passkey response: no credential was created".to_string(),
+        ))?;
+    if ceremony::decode_b64(&credential.id)? != attested.credential_id {
+        return Err(AppError::BadRequest(
+            "Invalid passkey response: credential id mismatch".to_string(),
+        ));
+    }
+    let algorithm = ceremony::key_algorithm(&attested.public_key)?;
+
+    Ok(VerifiedRegistration {
+        client_data,
+        credential_id: ceremony::encode_b64(&attested.credential_id),
+        public_key: attested.public_key,
+        algorithm,
+        sign_count: i64::from(authenticator_data.sign_count),
+    })
+}
+
+async fn insert_credential<C: ConnectionTrait>(
+    db: &C,
+    user_id: Uuid,
+    verified: VerifiedRegistration,
+    name: Option<String>,
+) -> AppResult<credentials::Model> {
+    let name = name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_CREDENTIAL_NAME.to_string());
+
+    credentials::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        credential_id: Set(verified.credential_id),
+        public_key: Set(verified.public_key),
+        algorithm: Set(verified.algorithm),
+        sign_count: Set(verified.sign_count),
+        name: Set(name),
+        created_at: Set(chrono::Utc::now().fixed_offset()),
+        last_used_at: Set(None),
+    }
+    .insert(db)
+    .await
+    .map_err(|err| match AppError::from(err) {
+        AppError::Conflict(_) => {
+            AppError::Conflict("This passkey is already registered".to_string())
+        }
+        err => err,
+    })
+}
+
+/// Store a new challenge, returning the raw value sent to the client
+async fn create_challenge(
+    state: &AppState,
+    id: Uuid,
+    ceremony: Ceremony,
+    user_id: Option<Uuid>,
+    signup: Option<(String, String)>,
+) -> AppResult<String> {
+    let now = chrono::Utc::now().fixed_offset();
+
+    // Abandoned ceremonies are cleaned up here rather than by a job
+    Challenges::delete_many()
+        .filter(challenge::Column::ExpiresAt.lt(now))
+        .exec(&state.db)
+        .await
+        .map_err(AppError::from)?;
+
+    let raw_challenge = token::generate_token();
+    let (email, name) = signup.unzip();
+
+    challenge::ActiveModel {
+        id: Set(id),
+        challenge_hash: Set(token::hash_token(&raw_challenge)),
+        ceremony: Set(ceremony),
+        user_id: Set(user_id),
+        email: Set(email),
+        name: Set(name),
+        expires_at: Set(now + chrono::Duration::seconds(state.config.webauthn_challenge_ttl_secs)),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(AppError::from)?;
+
+    Ok(raw_challenge)
+}
+
+/// Take a challenge out of the store so it cannot be answered twice
+async fn consume_challenge<C: ConnectionTrait>(
+    db: &C,
+    ceremony: Ceremony,
+    raw_challenge: &str,
+) -> AppResult<challenge::Model> {
+    let challenge = Challenges::find()
+        .filter(challenge::Column::ChallengeHash.eq(token::hash_token(raw_challenge)))
+        .filter(challenge::Column::Ceremony.eq(ceremony))
+        .filter(challenge::Column::ExpiresAt.gt(chrono::Utc::now().fixed_offset()))
+        .one(db)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(invalid_challenge)?;
+
+    let result = Challenges::delete_by_id(challenge.id)
+        .exec(db)
+        .await
+        .map_err(AppError::from)?;
+    if result.rows_affected == 0 {
+        return Err(invalid_challenge());
+    }
+
+    Ok(challenge)
+}
+
+fn invalid_challenge() -> AppError {
+    AppError::BadRequest("Invalid or expired passkey challenge".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::authenticator::SoftwareAuthenticator;
+    use super::*;
+    use crate::test_support::{test_config, test_state, unique_email};
+
+    const ORIGIN: &str = "http://localhost:3000";
+
+    async fn create_user(state: &AppState) -> users::Model {
+        users_service::create_passwordless_user_in(
+            &state.db,
+            Uuid::new_v4(),
+            &unique_email(),
+            "Test",
+        )
+        .await
+        .unwrap()
+    }
+
+    /// A user with one passkey held by the returned authenticator
+    async fn user_with_passkey(state: &AppState) -> (users::Model, SoftwareAuthenticator) {
+        let user = create_user(state).await;
+        let mut authenticator = SoftwareAuthenticator::new(ORIGIN);
+
+        let options = registration_options(state, &user).await.unwrap();
+        let credential = authenticator.create(&options).unwrap();
+        register_credential(
+            state,
+            &user,
+            &serde_json::from_value(credential).unwrap(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        (user, authenticator)
+    }
+
+    async fn sign_in(
+        state: &AppState,
+        authenticator: &mut SoftwareAuthenticator,
+        options: &serde_json::Value,
+    ) -> AppResult<users::Model> {
+        let assertion = authenticator.get(options).unwrap();
+        authenticate(state, &serde_json::from_value(assertion).unwrap()).await
+    }
+
+    fn register(
+        config: &AppConfig,
+        authenticator: &mut SoftwareAuthenticator,
+    ) -> AppResult<VerifiedRegistration> {
+        let options = creation_options(
+            config,
+            "challenge",
+            Uuid::new_v4(),
+            "user@example.com",
+            "User",
+            &[],
+        );
+        let credential = authenticator.create(&options).unwrap();
+        verify_registration(config, &serde_json::from_value(credential).unwrap())
+    }
+
+    fn rejection<T>(result: AppResult<T>) -> String {
+        match result {
+            Ok(_) => panic!("expected the ceremony to fail"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn accepts_registration() {
+        let config = test_config();
+        let mut authenticator = SoftwareAuthenticator::new(ORIGIN);
+
+        let verified = register(&config, &mut authenticator).unwrap();
+        assert_eq!(verified.client_data.challenge, "challenge");
+        assert_eq!(verified.algorithm, ceremony::COSE_ALG_ES256);
+        assert_eq!(
+            verified.credential_id,
+            ceremony::encode_b64(&authenticator.credentials[0].id)
+        );
+    }
+
+    #[test]
+    fn registration_rejects_wrong_origin() {
+        let config = test_config();
+        let mut authenticator = SoftwareAuthenticator::new("https://evil.example.com");
+
+        assert!(rejection(register(&config, &mut authenticator)).contains("origin"));
+    }
+
+    #[test]
+    fn registration_rejects_wrong_rp_id() {
+        let mut config = test_config();
+        config.webauthn_rp_id = "evil.example.com".to_string();
+        let mut authenticator = SoftwareAuthenticator::new(ORIGIN);
+        let options = creation_options(
+            &config,
+            "challenge",
+            Uuid::new_v4(),
+            "user@example.com",
+            "User",
+            &[],
+        );
+        let credential = authenticator.create(&options).unwrap();
+
+        let result =
+            verify_registration(&test_config(), &serde_json::from_value(credential).unwrap());
+        assert!(rejection(result).contains("relying party id mismatch"));
+    }
+
+    #[test]
+    fn registration_rejects_missing_user_verification() {
+        let config = test_config();
+        let mut authenticator = SoftwareAuthenticator::new(ORIGIN);
+        authenticator.user_verified = false;
+
+        assert!(rejection(register(&config, &mut authenticator)).contains("user verification"));
+    }
+
+    #[tokio::test]
+    async fn registration_challenge_belongs_to_its_user() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let user = create_user(&state).await;
+        let other = create_user(&state).await;
+        let mut authenticator = SoftwareAuthenticator::new(ORIGIN);
+
+        let options = registration_options(&state, &user).await.unwrap();
+        let credential: RegistrationCredential =
+            serde_json::from_value(authenticator.create(&options).unwrap()).unwrap();
+        assert!(register_credential(&state, &other, &credential, None)
+            .await
+            .is_err());
+
+        register_credential(&state, &user, &credential, None)
+            .await
+            .unwrap();
+        assert!(register_credential(&state, &user, &credential, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn authenticates_with_registered_passkey() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (user, mut authenticator) = user_with_passkey(&state).await;
+
+        let options = authentication_options(&state).await.unwrap();
+        let signed_in = sign_in(&state, &mut authenticator, &options).await.unwrap();
+        assert_eq!(signed_in.id, user.id);
+
+        let stored = list_credentials(&state, user.id).await.unwrap();
+        assert_eq!(stored[0].sign_count, 1);
+        assert!(stored[0].last_used_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_assertion() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, mut authenticator) = user_with_passkey(&state).await;
+
+        let options = authentication_options(&state).await.unwrap();
+        let assertion: AuthenticationCredential =
+            serde_json::from_value(authenticator.get(&options).unwrap()).unwrap();
+        authenticate(&state, &assertion).await.unwrap();
+
+        assert!(rejection(authenticate(&state, &assertion).await).contains("challenge"));
+    }
+
+    #[tokio::test]
+    async fn rejects_sign_count_rollback() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, mut authenticator) = user_with_passkey(&state).await;
+
+        for _ in 0..2 {
+            let options = authentication_options(&state).await.unwrap();
+            sign_in(&state, &mut authenticator, &options).await.unwrap();
+        }
+
+        // A clone of the authenticator taken after the first sign-in
+        authenticator.credentials[0].sign_count = 0;
+        let options = authentication_options(&state).await.unwrap();
+        let result = sign_in(&state, &mut authenticator, &options).await;
+        assert!(rejection(result).contains("counter"));
+    }
+
+    #[tokio::test]
+    async fn assertion_rejects_wrong_origin() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, mut authenticator) = user_with_passkey(&state).await;
+        authenticator.origin = "https://evil.example.com".to_string();
+
+        let options = authentication_options(&state).await.unwrap();
+        let result = sign_in(&state, &mut authenticator, &options).await;
+        assert!(rejection(result).contains("origin"));
+    }
+
+    #[tokio::test]
+    async fn assertion_rejects_wrong_rp_id() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, mut authenticator) = user_with_passkey(&state).await;
+        authenticator.credentials[0].rp_id = "evil.example.com".to_string();
+
+        let mut options = authentication_options(&state).await.unwrap();
+        options["publicKey"]["rpId"] = json!("evil.example.com");
+        let result = sign_in(&state, &mut authenticator, &options).await;
+        assert!(rejection(result).contains("relying party id mismatch"));
+    }
+
+    #[tokio::test]
+    async fn assertion_rejects_missing_user_verification() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (_, mut authenticator) = user_with_passkey(&state).await;
+        authenticator.user_verified = false;
+
+        let options = authentication_options(&state).await.unwrap();
+        let result = sign_in(&state, &mut authenticator, &options).await;
+        assert!(rejection(result).contains("user verification"));
+    }
+
+    #[tokio::test]
+    async fn second_factor_challenge_only_takes_its_users_passkeys() {
+        let Some(state) = test_state().await else {
+            return;
+        };
+        let (user, mut authenticator) = user_with_passkey(&state).await;
+        let (_, mut other) = user_with_passkey(&state).await;
+
+        let mut options = second_factor_options(&state, user.id).await.unwrap();
+        options["publicKey"]["allowCredentials"] = json!([]);
+        assert!(sign_in(&state, &mut other, &options).await.is_err());
+
+        let options = second_factor_options(&state, user.id).await.unwrap();
+        let signed_in = sign_in(&state, &mut authenticator, &options).await.unwrap();
+        assert_eq!(signed_in.id, user.id);
+    }
+}
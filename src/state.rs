@@ -1,12 +1,49 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use sea_orm::DatabaseConnection;
 
+use crate::config::AppConfig;
+use crate::mailer::{self, Mailer};
+use crate::modules::oidc::client::OidcClient;
+use crate::modules::password_policy::policy::PasswordPolicy;
+use crate::modules::users::password::PasswordHasher;
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: DatabaseConnection,
+    pub config: Arc<AppConfig>,
+    pub password_hasher: PasswordHasher,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub mailer: Arc<dyn Mailer>,
+    pub oidc: Arc<OidcClient>,
 }
 
 impl AppState {
-    pub fn new(db: DatabaseConnection) -> Self {
-        Self { db }
+    pub fn new(db: DatabaseConnection, config: AppConfig) -> Self {
+        let password_hasher = PasswordHasher::from_config(&config)
+            .expect("Invalid Argon2 password hashing parameters");
+        let password_policy =
+            PasswordPolicy::from_config(&config).expect("Invalid password policy configuration");
+        let mailer = mailer::from_config(&config).expect("Invalid mail configuration");
+        let oidc = OidcClient::new(Duration::from_secs(config.oidc_cache_ttl_secs))
+            .expect("Failed to create OpenID Connect client");
+
+        Self {
+            db,
+            config: Arc::new(config),
+            password_hasher,
+            password_policy: Arc::new(password_policy),
+            mailer,
+            oidc: Arc::new(oidc),
+        }
+    }
+
+    /// Replace the mailer, e.g. with one that captures messages in tests
+    /// Part of public API for library users
+    #[allow(dead_code)]
+    pub fn with_mailer(mut self, mailer: Arc<dyn Mailer>) -> Self {
+        self.mailer = mailer;
+        self
     }
 }
@@ -0,0 +1,89 @@
+//! Setup shared by unit tests
+//!
+//! Tests that need Postgres take its URL from `TEST_DATABASE_URL` and skip
+//! themselves when it is unset, so `cargo test` passes without a database.
+
+use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use sea_orm_migration::MigratorTrait;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::config::AppConfig;
+use crate::mailer::{Email, InMemoryMailer};
+use crate::migration::Migrator;
+use crate::state::AppState;
+
+/// The default for every setting, whatever the environment holds
+pub fn test_config() -> AppConfig {
+    let mut config: AppConfig = config::Config::builder()
+        .build()
+        .and_then(|cfg| cfg.try_deserialize())
+        .expect("defaults for every setting");
+    // Hashing at production cost makes tests crawl
+    config.argon2_memory_kib = 1024;
+    config.argon2_iterations = 1;
+    config
+}
+
+/// A migrated connection to the test database, or `None` without one
+pub async fn test_db() -> Option<DatabaseConnection> {
+    static MIGRATED: OnceCell<()> = OnceCell::const_new();
+
+    let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL is not set, skipping");
+        return None;
+    };
+
+    // Each test runs on its own runtime, so each gets its own pool
+    let mut opt = ConnectOptions::new(url);
+    opt.max_connections(5).sqlx_logging(false);
+    let db = Database::connect(opt)
+        .await
+        .expect("connecting to TEST_DATABASE_URL");
+
+    MIGRATED
+        .get_or_init(|| async {
+            Migrator::up(&db, None)
+                .await
+                .expect("migrating the test database")
+        })
+        .await;
+
+    Some(db)
+}
+
+/// State on the test database with [`test_config`], or `None` without one
+pub async fn test_state() -> Option<AppState> {
+    Some(AppState::new(test_db().await?, test_config()))
+}
+
+/// An address no other test uses, since tests share the database
+pub fn unique_email() -> String {
+    format!("{}@example.com", Uuid::new_v4().simple())
+}
+
+/// The token in the call-to-action link of a mailed message
+pub fn link_token(email: &Email) -> String {
+    let (_, rest) = email
+        .text_body
+        .split_once("?token=")
+        .expect("a link with a token");
+    rest.split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// The latest message to `to` once more than `sent` messages went out,
+/// waiting a little for mail sent in the background
+pub async fn mail_after(mailer: &InMemoryMailer, sent: usize, to: &str) -> Email {
+    for _ in 0..100 {
+        if mailer.sent().len() > sent {
+            if let Some(email) = mailer.last_to(to) {
+                return email;
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("no email was sent to {}", to);
+}
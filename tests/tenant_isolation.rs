@@ -0,0 +1,252 @@
+//! Row-level security keeps a handler that forgets its `WHERE` clause from
+//! seeing other organizations' rows
+//!
+//! These tests need Postgres: they take its URL from `TEST_DATABASE_URL` and
+//! skip themselves when it is unset.
+
+use axum::{routing::get, Json, Router};
+use sea_orm::{
+    ActiveModelTrait, ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend,
+    EntityTrait, Set, Statement,
+};
+use sea_orm_migration::MigratorTrait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use rust_saas_boilerplate::migration::Migrator;
+use rust_saas_boilerplate::modules::auth::jwt;
+use rust_saas_boilerplate::modules::invitations::entity::{
+    self as invitations, Entity as Invitations,
+};
+use rust_saas_boilerplate::modules::organizations::{
+    entity::Entity as Organizations,
+    membership::{self, Entity as Memberships, OrgRole},
+    service as organizations_service,
+    tenant::TenantDb,
+};
+use rust_saas_boilerplate::modules::users::{entity as users, service as users_service};
+use rust_saas_boilerplate::{db, AppConfig, AppResult, AppState};
+
+/// Organization ids of every row a tenant transaction can see
+#[derive(Serialize, Deserialize)]
+struct Visible {
+    organizations: Vec<Uuid>,
+    memberships: Vec<Uuid>,
+    invitations: Vec<Uuid>,
+}
+
+/// A careless handler: it lists whole tables, relying on nothing but the
+/// tenant transaction to stay inside the caller's organization
+async fn unfiltered(tenant: TenantDb) -> AppResult<Json<Visible>> {
+    let organizations = Organizations::find().all(&*tenant).await?;
+    let memberships = Memberships::find().all(&*tenant).await?;
+    let invitations = Invitations::find().all(&*tenant).await?;
+
+    Ok(Json(Visible {
+        organizations: organizations.into_iter().map(|org| org.id).collect(),
+        memberships: memberships.into_iter().map(|m| m.organization_id).collect(),
+        invitations: invitations.into_iter().map(|i| i.organization_id).collect(),
+    }))
+}
+
+async fn test_db() -> Option<DatabaseConnection> {
+    static MIGRATED: OnceCell<()> = OnceCell::const_new();
+
+    let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL is not set, skipping");
+        return None;
+    };
+
+    let mut opt = ConnectOptions::new(url);
+    opt.max_connections(5).sqlx_logging(false);
+    let db = Database::connect(opt)
+        .await
+        .expect("connecting to TEST_DATABASE_URL");
+
+    MIGRATED
+        .get_or_init(|| async {
+            Migrator::up(&db, None)
+                .await
+                .expect("migrating the test database")
+        })
+        .await;
+
+    Some(db)
+}
+
+async fn test_state(tenant_rls: bool) -> Option<AppState> {
+    let mut config: AppConfig = config::Config::builder()
+        .build()
+        .and_then(|cfg| cfg.try_deserialize())
+        .expect("defaults for every setting");
+    config.tenant_rls = tenant_rls;
+
+    Some(AppState::new(test_db().await?, config))
+}
+
+/// An organization with an owner and a pending invitation
+async fn organization(state: &AppState) -> (Uuid, users::Model) {
+    let email = format!("{}@example.com", Uuid::new_v4().simple());
+    let owner =
+        users_service::create_passwordless_user_in(&state.db, Uuid::new_v4(), &email, "Owner")
+            .await
+            .unwrap();
+    let name = format!("Tenant {}", Uuid::new_v4().simple());
+    let (organization, _) = organizations_service::create_organization(state, &owner, &name, None)
+        .await
+        .unwrap();
+
+    let now = chrono::Utc::now().fixed_offset();
+    invitations::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        organization_id: Set(organization.id),
+        email: Set(format!("{}@example.com", Uuid::new_v4().simple())),
+        role: Set(OrgRole::Member),
+        token_hash: Set(Uuid::new_v4().simple().to_string()),
+        invited_by: Set(Some(owner.id)),
+        expires_at: Set(now + chrono::Duration::days(1)),
+        accepted_at: Set(None),
+        declined_at: Set(None),
+        revoked_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .unwrap();
+
+    (organization.id, owner)
+}
+
+/// Call [`unfiltered`] for `org_id` as `user`, through the same extractors
+/// real handlers use
+async fn visible_to(state: AppState, user: &users::Model, org_id: Uuid) -> Visible {
+    let token = jwt::issue_access_token(&state.config, user.id).unwrap();
+    let app = Router::new()
+        .route("/orgs/{org_id}/unfiltered", get(unfiltered))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/orgs/{}/unfiltered", addr, org_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success(), "{}", response.status());
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn unfiltered_handler_sees_only_its_organization() {
+    let Some(state) = test_state(true).await else {
+        return;
+    };
+    let (mine, owner) = organization(&state).await;
+    organization(&state).await;
+
+    let visible = visible_to(state, &owner, mine).await;
+
+    assert_eq!(visible.organizations, vec![mine]);
+    assert_eq!(visible.memberships, vec![mine]);
+    assert_eq!(visible.invitations, vec![mine]);
+}
+
+/// Without the policies the same handler leaks, so the test above is
+/// measuring row-level security rather than an empty database
+#[tokio::test]
+async fn unfiltered_handler_leaks_without_row_level_security() {
+    let Some(state) = test_state(false).await else {
+        return;
+    };
+    let (mine, owner) = organization(&state).await;
+    let (theirs, _) = organization(&state).await;
+
+    let visible = visible_to(state, &owner, mine).await;
+
+    assert!(visible.organizations.contains(&theirs));
+    assert!(visible.memberships.contains(&theirs));
+    assert!(visible.invitations.contains(&theirs));
+}
+
+#[tokio::test]
+async fn tenant_transaction_cannot_write_into_another_organization() {
+    let Some(state) = test_state(true).await else {
+        return;
+    };
+    let (mine, intruder) = organization(&state).await;
+    let (theirs, _) = organization(&state).await;
+
+    let txn = db::begin_tenant_transaction(&state.db, mine, true)
+        .await
+        .unwrap();
+    let now = chrono::Utc::now().fixed_offset();
+    let result = membership::ActiveModel {
+        organization_id: Set(theirs),
+        user_id: Set(intruder.id),
+        role: Set(OrgRole::Owner),
+        custom_role_id: Set(None),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+    .insert(&txn)
+    .await;
+
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("row-level security"), "{}", err);
+}
+
+/// Tables with an organization column that deliberately have no policy
+const EXEMPT_TABLES: [(&str, &str); 2] = [
+    (
+        "audit_events",
+        "the trail outlives organizations and also records platform events; \
+         it is only read through the regular connection",
+    ),
+    (
+        "users",
+        "accounts are shared between organizations; the column only marks \
+         service accounts",
+    ),
+];
+
+#[tokio::test]
+async fn every_organization_table_has_a_policy() {
+    let Some(db) = test_db().await else {
+        return;
+    };
+
+    let rows = db
+        .query_all(Statement::from_string(
+            DbBackend::Postgres,
+            "SELECT DISTINCT c.table_name \
+             FROM information_schema.columns c \
+             JOIN pg_class t ON t.relname = c.table_name \
+             JOIN pg_namespace n ON n.oid = t.relnamespace AND n.nspname = c.table_schema \
+             WHERE c.table_schema = 'public' \
+               AND c.column_name LIKE '%organization_id' \
+               AND NOT (t.relrowsecurity AND EXISTS ( \
+                   SELECT 1 FROM pg_policies p \
+                   WHERE p.schemaname = 'public' \
+                     AND p.tablename = c.table_name \
+                     AND p.policyname = 'tenant_isolation')) \
+             ORDER BY 1",
+        ))
+        .await
+        .unwrap();
+    let unprotected: Vec<String> = rows
+        .iter()
+        .map(|row| row.try_get("", "table_name").unwrap())
+        .filter(|table: &String| !EXEMPT_TABLES.iter().any(|(exempt, _)| exempt == table))
+        .collect();
+
+    assert!(
+        unprotected.is_empty(),
+        "tables without a tenant_isolation policy: {:?}; call enable_tenant_rls \
+         in a migration or add them to EXEMPT_TABLES with a reason",
+        unprotected
+    );
+}